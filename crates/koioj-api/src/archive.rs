@@ -0,0 +1,69 @@
+use std::io::{Cursor, Read, Write};
+
+use axum::{body::Bytes, http::StatusCode};
+use tokio::sync::mpsc;
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{Result, error::Error};
+
+/// pack the given `(path, content)` pairs into an in-memory zip file
+pub async fn write_zip(files: Vec<(String, Vec<u8>)>) -> Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (path, content) in files {
+            writer.start_file(path, options)?;
+            writer.write_all(&content)?;
+        }
+        Ok(writer.finish()?.into_inner())
+    })
+    .await
+    .map_err(|e| Error::msg(format!("failed to build archive: {}", e)))?
+}
+
+/// unpack the files of a zip one at a time, so only the one being handled is
+/// in memory; archives that inflate past `max_size` bytes end with an error
+pub fn read_zip(data: Bytes, max_size: u64) -> mpsc::Receiver<Result<(String, Vec<u8>)>> {
+    let (tx, rx) = mpsc::channel(1);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = unpack(data, max_size, &tx) {
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    rx
+}
+
+fn unpack(data: Bytes, max_size: u64, tx: &mpsc::Sender<Result<(String, Vec<u8>)>>) -> Result<()> {
+    let invalid = |e: zip::result::ZipError| {
+        Error::msg(format!("invalid archive: {}", e)).status_code(StatusCode::BAD_REQUEST)
+    };
+
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(invalid)?;
+    let mut total: u64 = 0;
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(invalid)?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = file.name().to_string();
+
+        // the declared size can lie, so cap what is actually read
+        let mut content = Vec::new();
+        file.take(max_size - total + 1)
+            .read_to_end(&mut content)
+            .map_err(|e| {
+                Error::msg(format!("invalid archive: {}", e)).status_code(StatusCode::BAD_REQUEST)
+            })?;
+        total += content.len() as u64;
+        if total > max_size {
+            return Err(
+                Error::msg("archive is too large").status_code(StatusCode::PAYLOAD_TOO_LARGE)
+            );
+        }
+        if tx.blocking_send(Ok((path, content))).is_err() {
+            // the reader gave up on the archive
+            return Ok(());
+        }
+    }
+    Ok(())
+}
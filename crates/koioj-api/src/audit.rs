@@ -0,0 +1,40 @@
+use crate::{
+    auth::Claims,
+    perm::{Action, Resource},
+};
+
+/// keep a trace of a privileged action once it succeeded; the action can't be
+/// undone at this point, so a failure is only logged
+pub async fn record(
+    pool: &sqlx::PgPool,
+    claims: &Claims,
+    action: Action,
+    resource: Resource,
+    detail: Option<String>,
+) {
+    let (resource_type, resource_id) = resource.parts();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO audit_log (actor_id, action, resource_type, resource_id, detail)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        claims.sub,
+        action.name(),
+        resource_type,
+        resource_id,
+        detail
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!(
+            "Failed to audit {} on {} {:?} by user {}: {}",
+            action.name(),
+            resource_type,
+            resource_id,
+            claims.sub,
+            e
+        );
+    }
+}
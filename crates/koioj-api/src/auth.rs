@@ -8,13 +8,17 @@ use argon2::{
 use argon2::{PasswordHash, PasswordHasher};
 use axum::{
     extract::{Request, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode, header},
     middleware::Next,
     response::Response,
 };
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use koioj_common::bail;
 use passwords::PasswordGenerator;
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -22,6 +26,9 @@ pub struct Claims {
     pub exp: usize,
     /// issued at
     pub iat: usize,
+    /// token id, used to revoke a single access token
+    #[serde(default)]
+    pub jti: String,
 }
 
 pub fn generate_jwt_token(
@@ -38,6 +45,7 @@ pub fn generate_jwt_token(
         sub: user_id.to_owned(),
         exp: expiration,
         iat: chrono::Utc::now().timestamp() as usize,
+        jti: Uuid::new_v4().to_string(),
     };
 
     encode(
@@ -61,9 +69,107 @@ pub fn verify_jwt_token(
     Ok(token_data.claims)
 }
 
-fn extract_and_verify_jwt(request: &Request, jwt_secret: String) -> Result<Option<Claims>, Error> {
-    let auth_header = request
-        .headers()
+fn revoked_jti_key(jti: &str) -> String {
+    format!("revoked_jti:{}", jti)
+}
+
+/// put the access token on the denylist until it would have expired anyway
+pub async fn revoke_jwt_token(state: &AppState, claims: &Claims) -> Result<()> {
+    if claims.jti.is_empty() {
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now().timestamp() as usize;
+    let ttl = claims.exp.saturating_sub(now).max(1) as u64;
+
+    let mut redis_conn = state.redis.clone();
+    let _: () = redis_conn
+        .set_ex(revoked_jti_key(&claims.jti), 1, ttl)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+    Ok(())
+}
+
+async fn is_jwt_token_revoked(state: &AppState, claims: &Claims) -> Result<bool> {
+    if claims.jti.is_empty() {
+        return Ok(false);
+    }
+
+    let mut redis_conn = state.redis.clone();
+    redis_conn
+        .exists(revoked_jti_key(&claims.jti))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))
+}
+
+/// Refresh tokens look like `{id}.{secret}`; only an argon2 hash of the secret is stored.
+pub async fn issue_refresh_token(state: &AppState, user_id: i32) -> Result<String> {
+    let secret = Uuid::new_v4().simple().to_string();
+    let secret_hash = hash_password(secret.clone())?;
+    let expires_at = chrono::Utc::now() + state.config.refresh_token_expiry;
+
+    let id: i32 = sqlx::query_scalar!(
+        r#"
+        INSERT INTO refresh_tokens (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+        user_id,
+        secret_hash,
+        expires_at
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    Ok(format!("{}.{}", id, secret))
+}
+
+/// Revokes every refresh token of the user, e.g. once their password changed.
+pub async fn revoke_refresh_tokens(executor: impl PgExecutor<'_>, user_id: i32) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE refresh_tokens SET revoked = TRUE WHERE user_id = $1 AND NOT revoked
+        "#,
+        user_id
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    Ok(())
+}
+
+/// Checks a refresh token and returns the id of its row and of its owner.
+pub async fn verify_refresh_token(state: &AppState, token: &str) -> Result<(i32, i32)> {
+    let invalid = || Error::msg("invalid refresh token").status_code(StatusCode::UNAUTHORIZED);
+
+    let (id, secret) = token.split_once('.').ok_or_else(invalid)?;
+    let id: i32 = id.parse().map_err(|_| invalid())?;
+
+    let record = sqlx::query!(
+        r#"
+        SELECT user_id, token_hash FROM refresh_tokens
+        WHERE id = $1 AND NOT revoked AND expires_at > NOW()
+        "#,
+        id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(invalid)?;
+
+    verify_password(secret.to_string(), record.token_hash).map_err(|_| invalid())?;
+
+    Ok((id, record.user_id))
+}
+
+async fn extract_and_verify_jwt(
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<Option<Claims>, Error> {
+    let auth_header = headers
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok());
 
@@ -73,9 +179,13 @@ fn extract_and_verify_jwt(request: &Request, jwt_secret: String) -> Result<Optio
                 .strip_prefix("Bearer ")
                 .ok_or(Error::msg("missing auth token").status_code(StatusCode::UNAUTHORIZED))?;
 
-            let claims = verify_jwt_token(token, jwt_secret)
+            let claims = verify_jwt_token(token, state.config.jwt_secret.clone())
                 .map_err(|_| Error::msg("invalid token").status_code(StatusCode::UNAUTHORIZED))?;
 
+            if is_jwt_token_revoked(state, &claims).await? {
+                bail!(@UNAUTHORIZED "token revoked");
+            }
+
             Ok(Some(claims))
         }
         None => Ok(None),
@@ -88,6 +198,7 @@ fn create_guest_claims() -> Claims {
         sub: -1,
         exp: now + 3600,
         iat: now,
+        jti: String::new(),
     }
 }
 
@@ -96,7 +207,8 @@ pub async fn jwt_auth_middleware(
     mut request: Request,
     next: Next,
 ) -> Result<Response> {
-    let claims = extract_and_verify_jwt(&request, state.config.jwt_secret.clone())?
+    let claims = extract_and_verify_jwt(request.headers(), &state)
+        .await?
         .ok_or(Error::msg("missing auth header").status_code(StatusCode::UNAUTHORIZED))?;
 
     request.extensions_mut().insert(claims);
@@ -108,7 +220,7 @@ pub async fn jwt_auth_accept_guest_middleware(
     mut request: Request,
     next: Next,
 ) -> Result<Response> {
-    let claims = match extract_and_verify_jwt(&request, state.config.jwt_secret.clone())? {
+    let claims = match extract_and_verify_jwt(request.headers(), &state).await? {
         Some(claims) => claims,
         None => create_guest_claims(),
     };
@@ -149,3 +261,19 @@ pub fn generate_strong_password() -> String {
 
     pg.generate_one().unwrap()
 }
+
+/// short code that's easy to read out loud, no lowercase or look-alike characters
+pub fn generate_join_code() -> String {
+    let pg = PasswordGenerator {
+        length: 8,
+        numbers: true,
+        lowercase_letters: false,
+        uppercase_letters: true,
+        symbols: false,
+        spaces: false,
+        exclude_similar_characters: true,
+        strict: true,
+    };
+
+    pg.generate_one().unwrap()
+}
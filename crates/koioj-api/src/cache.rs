@@ -0,0 +1,150 @@
+use std::{collections::HashMap, hash::Hash, sync::Mutex};
+
+/// small least-recently-used cache for data read back from disk, a capacity of
+/// 0 disables it
+pub struct LruCache<K, V> {
+    /// most total weight kept
+    capacity: usize,
+    weigh: fn(&V) -> usize,
+    inner: Mutex<LruInner<K, V>>,
+}
+
+struct LruInner<K, V> {
+    tick: u64,
+    /// bumped on every invalidation, so a value read from disk before a write
+    /// can't be inserted after it
+    generation: u64,
+    weight: usize,
+    entries: HashMap<K, (V, u64)>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> LruCache<K, V> {
+    /// keeps at most `capacity` values
+    pub fn new(capacity: usize) -> Self {
+        Self::weighted(capacity, |_| 1)
+    }
+
+    /// keeps values up to a total `weigh` of `capacity`, values heavier than
+    /// that on their own aren't kept
+    pub fn weighted(capacity: usize, weigh: fn(&V) -> usize) -> Self {
+        Self {
+            capacity,
+            weigh,
+            inner: Mutex::new(LruInner {
+                tick: 0,
+                generation: 0,
+                weight: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.entries.get_mut(key).map(|(value, used)| {
+            *used = tick;
+            value.clone()
+        })
+    }
+
+    /// take this before loading a value, and pass it back to `insert`
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    pub fn insert(&self, key: K, value: V, generation: u64) {
+        let weight = (self.weigh)(&value);
+        if weight > self.capacity {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return;
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+
+        inner.remove(&key, self.weigh);
+        while inner.weight + weight > self.capacity {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(oldest) => inner.remove(&oldest, self.weigh),
+                None => break,
+            }
+        }
+
+        inner.weight += weight;
+        inner.entries.insert(key, (value, tick));
+    }
+
+    pub fn invalidate(&self, key: &K) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.generation += 1;
+        inner.remove(key, self.weigh);
+    }
+}
+
+impl<K: Eq + Hash, V> LruInner<K, V> {
+    fn remove(&mut self, key: &K, weigh: fn(&V) -> usize) {
+        if let Some((value, _)) = self.entries.remove(key) {
+            self.weight -= weigh(&value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_goes_first() {
+        let cache = LruCache::new(2);
+        cache.insert(1, "a", cache.generation());
+        cache.insert(2, "b", cache.generation());
+        assert_eq!(cache.get(&1), Some("a"));
+        cache.insert(3, "c", cache.generation());
+
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3), Some("c"));
+    }
+
+    #[test]
+    fn weighted_cache_stays_within_its_capacity() {
+        let cache = LruCache::weighted(10, |value: &String| value.len());
+        cache.insert(1, "aaaa".to_string(), cache.generation());
+        cache.insert(2, "bbbb".to_string(), cache.generation());
+        cache.insert(3, "cccccc".to_string(), cache.generation());
+
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some("bbbb".to_string()));
+        assert_eq!(cache.get(&3), Some("cccccc".to_string()));
+
+        cache.insert(4, "d".repeat(11), cache.generation());
+        assert_eq!(cache.get(&4), None);
+        assert_eq!(cache.get(&3), Some("cccccc".to_string()));
+    }
+
+    #[test]
+    fn insert_after_an_invalidation_is_dropped() {
+        let cache = LruCache::new(2);
+        let generation = cache.generation();
+        cache.invalidate(&1);
+        cache.insert(1, "stale", generation);
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn zero_capacity_keeps_nothing() {
+        let cache = LruCache::new(0);
+        cache.insert(1, "a", cache.generation());
+        assert_eq!(cache.get(&1), None);
+    }
+}
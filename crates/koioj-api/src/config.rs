@@ -1,7 +1,10 @@
 use std::collections::HashMap;
 
 use chrono::Duration;
-use koioj_common::utils::deserialize_log_level;
+use koioj_common::{
+    error::{Error, Result},
+    utils::deserialize_log_level,
+};
 use serde::Deserialize;
 use tracing::Level;
 
@@ -13,11 +16,323 @@ pub struct Config {
     pub log_file: String,
     #[serde(deserialize_with = "deserialize_log_level")]
     pub log_level: Level,
+    /// size of the database pool, keep it below the `max_connections` of Postgres
     pub max_connections: u32,
+    /// how long a query may wait for a free database connection
+    #[serde(default = "default_db_acquire_timeout")]
+    pub db_acquire_timeout: Duration,
+    /// idle database connections are closed after this long
+    #[serde(default = "default_db_idle_timeout")]
+    pub db_idle_timeout: Duration,
     pub max_file_size_mb: f32,
+    /// origins allowed by CORS, `"*"` allows any; only debug builds allow any by default
+    #[serde(default = "default_allowed_origins")]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// body limit of test case uploads, overriding `max_file_size_mb`
+    #[serde(default = "default_max_testcase_upload_mb")]
+    pub max_testcase_upload_mb: f32,
+    /// body limit of problem imports, overriding `max_file_size_mb`
+    #[serde(default = "default_max_import_mb")]
+    pub max_import_mb: f32,
+    /// at least 32 bytes, `JWT_SECRET` overrides it
+    #[serde(default)]
     pub jwt_secret: String,
+    /// lifetime of access tokens, keep it short since they're only revocable one by one
     pub jwt_expiry: Duration,
+    #[serde(default = "default_refresh_token_expiry")]
+    pub refresh_token_expiry: Duration,
+    /// failed logins allowed per identifier and client IP within `login_attempt_window`
+    #[serde(default = "default_login_max_attempts")]
+    pub login_max_attempts: u32,
+    #[serde(default = "default_login_attempt_window")]
+    pub login_attempt_window: Duration,
+    /// how long a password reset token stays valid
+    #[serde(default = "default_password_reset_expiry")]
+    pub password_reset_expiry: Duration,
+    /// where reset tokens are posted for delivery to their owner, e.g. by a
+    /// mailer, passwords can't be reset while it's unset
+    pub password_reset_hook: Option<PasswordResetHook>,
+    /// reset requests allowed per identifier within `password_reset_window`
+    #[serde(default = "default_password_reset_max_requests")]
+    pub password_reset_max_requests: u32,
+    /// reset requests allowed per client IP within `password_reset_window`
+    #[serde(default = "default_password_reset_max_requests_per_ip")]
+    pub password_reset_max_requests_per_ip: u32,
+    #[serde(default = "default_password_reset_window")]
+    pub password_reset_window: Duration,
+    /// `ADMIN_PASSWORD` overrides it, a random one is generated when neither is set
     pub admin_password: Option<String>,
     pub data_dir: String,
+    /// gzip files written to `data_dir`, files are read either way
+    #[serde(default)]
+    pub compress_data: bool,
     pub judgers: HashMap<String, String>,
+    /// judges present it to open their connection, at least 32 bytes, `JUDGE_TOKEN`
+    /// overrides it
+    #[serde(default)]
+    pub judge_token: String,
+    /// test cases sent to a judge at a time, it asks for the next batch when
+    /// done with one
+    #[serde(default = "default_test_case_batch_size")]
+    pub test_case_batch_size: usize,
+    /// submissions are refused while fewer live judges are connected
+    #[serde(default = "default_min_judges")]
+    pub min_judges: usize,
+    /// number of problem statements kept in memory, 0 disables the cache
+    #[serde(default = "default_problem_cache_size")]
+    pub problem_cache_size: usize,
+    /// megabytes of test case input and output kept in memory, 0 disables the cache
+    #[serde(default = "default_test_case_cache_mb")]
+    pub test_case_cache_mb: usize,
+    /// longest description, note and the like, in characters
+    #[serde(default = "default_max_text_length")]
+    pub max_text_length: usize,
+    /// longest solution content, in characters
+    #[serde(default = "default_max_solution_length")]
+    pub max_solution_length: usize,
+    /// minimum time between two submissions of a student, zero disables it
+    #[serde(default = "default_submission_cooldown")]
+    pub submission_cooldown: Duration,
+    /// longest accepted source for problems without their own limit, in bytes
+    #[serde(default = "default_max_code_length")]
+    pub max_code_length: usize,
+    /// how long a sample run may wait for its result
+    #[serde(default = "default_run_timeout")]
+    pub run_timeout: Duration,
+    /// count compile errors as wrong attempts in contest penalties, as they
+    /// always were; ICPC doesn't
+    #[serde(default = "default_penalize_compile_errors")]
+    pub penalize_compile_errors: bool,
+    /// pruning removes older submissions that weren't accepted, made in a
+    /// contest or bookmarked, zero disables it
+    #[serde(default = "default_submission_retention")]
+    pub submission_retention: Duration,
+}
+
+/// receives `password_reset.requested` events, signed like webhooks are
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordResetHook {
+    pub url: String,
+    pub secret: String,
+}
+
+const MIN_JWT_SECRET_LENGTH: usize = 32;
+const MIN_ADMIN_PASSWORD_LENGTH: usize = 8;
+const MIN_JUDGE_TOKEN_LENGTH: usize = 32;
+
+/// secrets from templates and tutorials, which would make tokens forgeable
+const PLACEHOLDER_SECRETS: &[&str] = &[
+    "this-is-a-secret",
+    "leave-commented-to-generate",
+    "secret",
+    "changeme",
+    "change-me",
+    "password",
+    "admin",
+];
+
+fn is_placeholder(secret: &str) -> bool {
+    let secret = secret.trim().to_lowercase();
+    PLACEHOLDER_SECRETS.contains(&secret.as_str()) || secret.starts_with("your-")
+}
+
+impl Config {
+    /// take secrets from the environment, so that they needn't live in the file
+    pub fn load_secrets_from_env(&mut self) {
+        if let Ok(secret) = std::env::var("JWT_SECRET") {
+            self.jwt_secret = secret;
+        }
+        if let Ok(password) = std::env::var("ADMIN_PASSWORD") {
+            self.admin_password = Some(password);
+        }
+        if let Ok(token) = std::env::var("JUDGE_TOKEN") {
+            self.judge_token = token;
+        }
+    }
+
+    /// refuse to start with secrets that are short or copied from a template
+    pub fn check_secrets(&self) -> Result<()> {
+        check_jwt_secret(&self.jwt_secret)?;
+        check_judge_token(&self.judge_token)?;
+        if let Some(password) = &self.admin_password {
+            check_admin_password(password)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_jwt_secret(secret: &str) -> Result<()> {
+    if secret.len() < MIN_JWT_SECRET_LENGTH {
+        return Err(Error::msg(format!(
+            "jwtSecret must be at least {} bytes, set it in the config or JWT_SECRET",
+            MIN_JWT_SECRET_LENGTH
+        )));
+    }
+    if is_placeholder(secret) {
+        return Err(Error::msg("jwtSecret is a placeholder, replace it"));
+    }
+    Ok(())
+}
+
+fn check_judge_token(token: &str) -> Result<()> {
+    if token.len() < MIN_JUDGE_TOKEN_LENGTH {
+        return Err(Error::msg(format!(
+            "judgeToken must be at least {} bytes, set it in the config or JUDGE_TOKEN",
+            MIN_JUDGE_TOKEN_LENGTH
+        )));
+    }
+    if is_placeholder(token) {
+        return Err(Error::msg("judgeToken is a placeholder, replace it"));
+    }
+    Ok(())
+}
+
+fn check_admin_password(password: &str) -> Result<()> {
+    if password.len() < MIN_ADMIN_PASSWORD_LENGTH {
+        return Err(Error::msg(format!(
+            "adminPassword must be at least {} bytes",
+            MIN_ADMIN_PASSWORD_LENGTH
+        )));
+    }
+    if is_placeholder(password) {
+        return Err(Error::msg("adminPassword is a placeholder, replace it"));
+    }
+    Ok(())
+}
+
+fn default_min_judges() -> usize {
+    1
+}
+
+fn default_refresh_token_expiry() -> Duration {
+    Duration::days(30)
+}
+
+fn default_login_max_attempts() -> u32 {
+    5
+}
+
+fn default_login_attempt_window() -> Duration {
+    Duration::minutes(15)
+}
+
+fn default_password_reset_expiry() -> Duration {
+    Duration::minutes(30)
+}
+
+fn default_password_reset_max_requests() -> u32 {
+    3
+}
+
+fn default_password_reset_max_requests_per_ip() -> u32 {
+    20
+}
+
+fn default_password_reset_window() -> Duration {
+    Duration::hours(1)
+}
+
+fn default_problem_cache_size() -> usize {
+    256
+}
+
+fn default_test_case_cache_mb() -> usize {
+    256
+}
+
+fn default_max_text_length() -> usize {
+    64 * 1024
+}
+
+fn default_max_solution_length() -> usize {
+    256 * 1024
+}
+
+fn default_submission_cooldown() -> Duration {
+    Duration::seconds(5)
+}
+
+fn default_max_code_length() -> usize {
+    64 * 1024
+}
+
+fn default_run_timeout() -> Duration {
+    Duration::seconds(60)
+}
+
+fn default_max_testcase_upload_mb() -> f32 {
+    256.
+}
+
+fn default_max_import_mb() -> f32 {
+    256.
+}
+
+fn default_allowed_origins() -> Vec<String> {
+    if cfg!(debug_assertions) {
+        vec!["*".to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+fn default_allowed_methods() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_allowed_headers() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_db_acquire_timeout() -> Duration {
+    Duration::seconds(30)
+}
+
+fn default_db_idle_timeout() -> Duration {
+    Duration::minutes(10)
+}
+
+fn default_test_case_batch_size() -> usize {
+    16
+}
+
+fn default_penalize_compile_errors() -> bool {
+    true
+}
+
+fn default_submission_retention() -> Duration {
+    Duration::days(365)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_admin_password, check_jwt_secret};
+
+    fn message(result: koioj_common::error::Result<()>) -> String {
+        result.unwrap_err().1.unwrap().to_string()
+    }
+
+    #[test]
+    fn short_jwt_secret_is_rejected() {
+        let error = message(check_jwt_secret("at-least-32-random-bytes"));
+        assert!(error.contains("at least 32 bytes"), "{}", error);
+        assert!(check_jwt_secret("kq8Vd3LxN2pR7tYw0ZcF5mHj9sBgA4eU").is_ok());
+    }
+
+    #[test]
+    fn placeholder_secrets_are_rejected() {
+        let error = message(check_jwt_secret("your-jwt-secret-goes-here-0123456789"));
+        assert!(error.contains("placeholder"), "{}", error);
+
+        let error = message(check_admin_password("ChangeMe"));
+        assert!(error.contains("placeholder"), "{}", error);
+        let error = message(check_admin_password("admin"));
+        assert!(error.contains("at least 8 bytes"), "{}", error);
+        assert!(check_admin_password("correct horse battery").is_ok());
+    }
 }
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use koioj_common::judge::Language;
+use sha2::{Digest, Sha256};
+
+use crate::{AppState, Result, route::stored_language};
+
+/// what counts as a comment or a string literal in a language
+struct Syntax {
+    line_comments: &'static [&'static str],
+    /// tried before line comments, lua's `--[[` starts with `--`
+    block_comments: &'static [(&'static str, &'static str)],
+    quotes: &'static [char],
+}
+
+const C_LIKE: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"', '\'', '`'],
+};
+
+const HASH: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    quotes: &['"', '\''],
+};
+
+fn syntax(lang: Language) -> Syntax {
+    match lang {
+        Language::C
+        | Language::Cpp
+        | Language::Java
+        | Language::Go
+        | Language::JavaScript
+        | Language::TypeScript
+        | Language::CSharp
+        | Language::Swift
+        | Language::Kotlin
+        | Language::Scala
+        | Language::Dart
+        | Language::ObjectiveC => C_LIKE,
+        // lifetimes and char literals look alike, only strings are kept
+        Language::Rust => Syntax {
+            quotes: &['"'],
+            ..C_LIKE
+        },
+        Language::Python | Language::Ruby | Language::Perl | Language::R => HASH,
+        Language::Php => Syntax {
+            line_comments: &["//", "#"],
+            ..C_LIKE
+        },
+        Language::Haskell => Syntax {
+            line_comments: &["--"],
+            block_comments: &[("{-", "-}")],
+            quotes: &['"'],
+        },
+        Language::Lua => Syntax {
+            line_comments: &["--"],
+            block_comments: &[("--[[", "]]")],
+            quotes: &['"', '\''],
+        },
+    }
+}
+
+/// the code without comments and whitespace, string literals kept as written
+pub fn normalize(lang: Language, code: &str) -> String {
+    let syntax = syntax(lang);
+    let mut out = String::with_capacity(code.len());
+    let mut rest = code;
+
+    'outer: while let Some(c) = rest.chars().next() {
+        for (open, close) in syntax.block_comments {
+            if let Some(after) = rest.strip_prefix(open) {
+                rest = after.find(close).map_or("", |i| &after[i + close.len()..]);
+                continue 'outer;
+            }
+        }
+        for marker in syntax.line_comments {
+            if rest.starts_with(marker) {
+                rest = rest.find('\n').map_or("", |i| &rest[i..]);
+                continue 'outer;
+            }
+        }
+
+        if syntax.quotes.contains(&c) {
+            let mut escaped = false;
+            let end = rest[c.len_utf8()..]
+                .char_indices()
+                .find(|&(_, ch)| {
+                    let closes = !escaped && ch == c;
+                    escaped = !escaped && ch == '\\';
+                    closes
+                })
+                .map_or(rest.len(), |(i, _)| i + 2 * c.len_utf8());
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        if !c.is_whitespace() {
+            out.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+
+    out
+}
+
+/// hex SHA-256 of the normalized code, equal for copies that only differ in
+/// layout and comments
+pub fn fingerprint(lang: Language, code: &str) -> String {
+    format!("{:x}", Sha256::digest(normalize(lang, code).as_bytes()))
+}
+
+/// store the fingerprint of a judged submission in the background
+pub fn fingerprint_submission(state: &Arc<AppState>, submission_id: i32, lang: String) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = store_fingerprint(&state, submission_id, &lang).await {
+            tracing::warn!(
+                "Failed to fingerprint submission {}: {:?}",
+                submission_id,
+                e
+            );
+        }
+    });
+}
+
+async fn store_fingerprint(state: &AppState, submission_id: i32, lang: &str) -> Result<()> {
+    let Some(lang) = stored_language(lang) else {
+        return Ok(());
+    };
+    let code = state.read_submission_code(submission_id).await?.code;
+    let fingerprint = tokio::task::spawn_blocking(move || fingerprint(lang, &code)).await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE submissions SET fingerprint = $1 WHERE id = $2
+        "#,
+        fingerprint,
+        submission_id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use koioj_common::judge::Language;
+
+    use super::fingerprint;
+
+    #[test]
+    fn whitespace_only_changes_fingerprint_alike() {
+        let original = "int main() {\n    int a, b;\n    return a + b;\n}\n";
+        let reformatted = "int main(){\r\n\tint a,b;\r\n\treturn a+b;}";
+        assert_eq!(
+            fingerprint(Language::Cpp, original),
+            fingerprint(Language::Cpp, reformatted)
+        );
+
+        let python = "def f(a, b):\n    return a + b\n";
+        let reindented = "def f(a,b):\n  return a+b  \n\n";
+        assert_eq!(
+            fingerprint(Language::Python, python),
+            fingerprint(Language::Python, reindented)
+        );
+    }
+
+    #[test]
+    fn whitespace_inside_strings_still_counts() {
+        assert_ne!(
+            fingerprint(Language::Cpp, r#"puts("a b");"#),
+            fingerprint(Language::Cpp, r#"puts("ab");"#)
+        );
+    }
+}
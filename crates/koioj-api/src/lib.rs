@@ -1,33 +1,52 @@
+mod archive;
+mod audit;
 mod auth;
+mod cache;
 pub mod config;
+mod fingerprint;
 mod models;
 mod perm;
 pub mod route;
+#[cfg(test)]
+mod test_util;
+mod validation;
+mod webhook;
 
 use axum::{
     Extension,
-    extract::{DefaultBodyLimit, connect_info::MockConnectInfo},
+    extract::connect_info::MockConnectInfo,
+    http::{HeaderName, HeaderValue, Method},
 };
 use config::Config;
 use error::{Error, Result};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 use koioj_common::error;
 use redis::aio::ConnectionManager;
 use serde::{Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
 use sqlx::{
-    ConnectOptions, PgPool,
+    ConnectOptions, PgConnection, PgPool,
     postgres::{PgConnectOptions, PgPoolOptions},
 };
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+};
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, Mutex, atomic::AtomicI32},
     time::Instant,
 };
-use tokio::{fs, net::TcpListener, sync::RwLock};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    net::TcpListener,
+    sync::{RwLock, broadcast},
+};
 use tower::ServiceBuilder;
 use tower_http::{
-    cors::{self, CorsLayer},
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
     normalize_path::NormalizePathLayer,
     trace::TraceLayer,
 };
@@ -35,15 +54,22 @@ use uuid::Uuid;
 
 use crate::{
     auth::{generate_strong_password, hash_password},
+    cache::LruCache,
     models::{
         ContestContent, ProblemContent, SolutionContent, SubmissionCode, TestCaseData,
         TrainingPlanContent,
     },
-    route::judge::JudgeConnection,
+    route::{
+        contests::ranking_cache::RankingEvent,
+        judge::{JudgeConnection, RunSender},
+    },
 };
 
 pub type State = axum::extract::State<Arc<AppState>>;
 
+/// first bytes of any gzip stream, which no json starts with
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
 pub struct AppState {
     pub config: Arc<Config>,
     pool: PgPool,
@@ -51,28 +77,93 @@ pub struct AppState {
     pub started: Instant,
 
     pub judges: Arc<RwLock<HashMap<String, JudgeConnection>>>,
+    /// runs waiting for their result, by run id
+    runs: Mutex<HashMap<i32, RunSender>>,
+    next_run_id: AtomicI32,
+    /// test cases of dispatched submissions the judge hasn't asked for yet
+    queued_test_cases: Mutex<HashMap<i32, VecDeque<i32>>>,
+    /// live ranking updates by contest id, only while someone is subscribed
+    ranking_streams: Mutex<HashMap<i32, broadcast::Sender<RankingEvent>>>,
+
+    problem_cache: LruCache<i32, ProblemContent>,
+    test_case_cache: LruCache<i32, TestCaseData>,
+}
+
+/// write next to the target and rename over it, so readers never see a
+/// truncated file and concurrent writers don't interleave
+fn temp_path(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::msg("invalid data file path"))?;
+    Ok(path.with_file_name(format!(".{}.{}.tmp", file_name, Uuid::new_v4())))
+}
+
+/// storing and discarding the file of a test case take a lock on its hash,
+/// held until `conn`'s transaction ends, so that a file isn't removed between
+/// being found in place and its new row being committed
+async fn lock_test_case_hash(conn: &mut PgConnection, hash: &str) -> Result<()> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(hash)
+        .execute(conn)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    Ok(())
+}
+
+fn env_var(name: &str) -> Result<String> {
+    std::env::var(name).map_err(|e| match e {
+        std::env::VarError::NotPresent => Error::msg(format!("{} not set", name)),
+        std::env::VarError::NotUnicode(_) => Error::msg(format!("{} is not valid unicode", name)),
+    })
+}
+
+fn pool_options(config: &Config) -> Result<PgPoolOptions> {
+    if config.max_connections == 0 {
+        return Err(Error::msg("maxConnections must be positive"));
+    }
+    let duration = |what: &str, d: chrono::Duration| {
+        d.to_std()
+            .map_err(|_| Error::msg(format!("{} must not be negative", what)))
+    };
+
+    Ok(PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(duration("dbAcquireTimeout", config.db_acquire_timeout)?)
+        .idle_timeout(duration("dbIdleTimeout", config.db_idle_timeout)?))
 }
 
 impl AppState {
     pub async fn new(config: Arc<Config>) -> Result<Self> {
-        let opt = PgConnectOptions::from_str(&std::env::var("DATABASE_URL").unwrap())?
+        let opt = PgConnectOptions::from_str(&env_var("DATABASE_URL")?)
+            .map_err(|e| Error::msg(format!("invalid DATABASE_URL: {}", e)))?
             .disable_statement_logging();
-        let pool = PgPoolOptions::new()
-            .max_connections(32)
-            .connect_with(opt)
-            .await?;
+        let pool = pool_options(&config)?.connect_with(opt).await?;
 
-        let redis_url = std::env::var("REDIS_URL").unwrap();
-        let redis_client = redis::Client::open(redis_url).expect("Failed to create Redis client");
+        let redis_client = redis::Client::open(env_var("REDIS_URL")?)
+            .map_err(|e| Error::msg(format!("invalid REDIS_URL: {}", e)))?;
         let redis_manager = redis::aio::ConnectionManager::new(redis_client).await?;
 
-        Ok(Self {
+        Ok(Self::with_connections(config, pool, redis_manager))
+    }
+
+    fn with_connections(config: Arc<Config>, pool: PgPool, redis: ConnectionManager) -> Self {
+        Self {
+            problem_cache: LruCache::new(config.problem_cache_size),
+            test_case_cache: LruCache::weighted(
+                config.test_case_cache_mb * 1024 * 1024,
+                |test_case| test_case.input.len() + test_case.output.len(),
+            ),
             config: config,
             pool: pool,
-            redis: redis_manager,
+            redis: redis,
             started: Instant::now(),
             judges: Arc::new(RwLock::new(HashMap::new())),
-        })
+            runs: Mutex::new(HashMap::new()),
+            next_run_id: AtomicI32::new(-1),
+            queued_test_cases: Mutex::new(HashMap::new()),
+            ranking_streams: Mutex::new(HashMap::new()),
+        }
     }
 
     pub async fn create_admin_account(&self) -> Result<()> {
@@ -204,6 +295,55 @@ impl AppState {
         Ok(())
     }
 
+    /// test cases stored before they were deduplicated are kept under their id
+    /// and have no hash yet, move each one under the hash of its content
+    pub async fn migrate_test_case_files(&self) -> Result<()> {
+        let test_case_ids = sqlx::query_scalar!(
+            r#"
+        SELECT id FROM test_cases WHERE hash IS NULL ORDER BY id
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::msg(format!("check test case files failed: {}", e)))?;
+        if test_case_ids.is_empty() {
+            return Ok(());
+        }
+
+        tracing::warn!(
+            "{} test cases are stored by id, moving them",
+            test_case_ids.len()
+        );
+        for test_case_id in test_case_ids {
+            let legacy_path = self.get_data_path("test_cases", test_case_id);
+            let test_case: TestCaseData = self.read_json_data(legacy_path.clone()).await?;
+
+            let mut tx = self
+                .pool
+                .begin()
+                .await
+                .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+            let hash = self.write_test_cases(&mut tx, &test_case).await?;
+            sqlx::query!(
+                r#"
+            UPDATE test_cases SET hash = $1 WHERE id = $2
+            "#,
+                hash,
+                test_case_id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+            tx.commit()
+                .await
+                .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+            self.delete_json_data(legacy_path).await?;
+        }
+
+        Ok(())
+    }
+
     fn get_data_path(&self, subdir: &str, id: i32) -> PathBuf {
         PathBuf::from(&self.config.data_dir)
             .join(subdir)
@@ -217,30 +357,126 @@ impl AppState {
                 .map_err(|e| Error::msg(format!("failed to create directory: {}", e)))?;
         }
 
-        let json = serde_json::to_string_pretty(data)
-            .map_err(|e| Error::msg(format!("failed to serialize: {}", e)))?;
-
-        fs::write(&path, json)
+        let bytes = if self.config.compress_data {
+            let json = serde_json::to_vec(data)
+                .map_err(|e| Error::msg(format!("failed to serialize: {}", e)))?;
+            tokio::task::spawn_blocking(move || {
+                use std::io::Write;
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&json)?;
+                encoder.finish()
+            })
             .await
-            .map_err(|e| Error::msg(format!("failed to write file: {}", e)))?;
+            .map_err(|e| Error::msg(format!("failed to compress: {}", e)))?
+            .map_err(|e| Error::msg(format!("failed to compress: {}", e)))?
+        } else {
+            serde_json::to_vec_pretty(data)
+                .map_err(|e| Error::msg(format!("failed to serialize: {}", e)))?
+        };
+
+        let tmp_path = temp_path(&path)?;
+        let write_result = async {
+            let mut file = fs::File::create(&tmp_path).await?;
+            file.write_all(&bytes).await?;
+            file.sync_all().await?;
+            fs::rename(&tmp_path, &path).await
+        }
+        .await;
+
+        if let Err(e) = write_result {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(Error::msg(format!("failed to write file: {}", e)));
+        }
 
         Ok(())
     }
 
+    /// like `write_json_data`, but serializes straight into the file instead of
+    /// a buffer, for data that can get large. The data is handed back so that
+    /// callers needn't copy it to keep it
+    async fn write_json_data_streamed<T: Serialize + Send + 'static>(
+        &self,
+        path: PathBuf,
+        data: T,
+    ) -> Result<T> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| Error::msg(format!("failed to create directory: {}", e)))?;
+        }
+
+        let tmp_path = temp_path(&path)?;
+        let compress = self.config.compress_data;
+        let write_path = tmp_path.clone();
+        let (data, write_result) = tokio::task::spawn_blocking(move || {
+            let write = || -> std::io::Result<()> {
+                let mut writer = std::io::BufWriter::new(std::fs::File::create(&write_path)?);
+                if compress {
+                    let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+                    serde_json::to_writer(&mut encoder, &data)?;
+                    encoder.finish()?;
+                } else {
+                    serde_json::to_writer(&mut writer, &data)?;
+                }
+                let file = writer.into_inner().map_err(|e| e.into_error())?;
+                file.sync_all()?;
+                std::fs::rename(&write_path, &path)
+            };
+            let result = write();
+            (data, result)
+        })
+        .await
+        .map_err(|e| Error::msg(format!("failed to write file: {}", e)))?;
+
+        if let Err(e) = write_result {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(Error::msg(format!("failed to write file: {}", e)));
+        }
+
+        Ok(data)
+    }
+
     async fn read_json_data<T: DeserializeOwned>(&self, path: PathBuf) -> Result<T> {
-        let json = fs::read_to_string(&path)
+        let bytes = fs::read(&path)
             .await
             .map_err(|e| Error::msg(format!("failed to read file: {}", e)))?;
 
-        serde_json::from_str(&json).map_err(|e| Error::msg(format!("failed to deserialize: {}", e)))
+        // files written before compression was turned on are plain json
+        let json = if bytes.starts_with(&GZIP_MAGIC) {
+            tokio::task::spawn_blocking(move || {
+                use std::io::Read;
+                let mut json = Vec::new();
+                GzDecoder::new(bytes.as_slice()).read_to_end(&mut json)?;
+                Ok::<_, std::io::Error>(json)
+            })
+            .await
+            .map_err(|e| Error::msg(format!("failed to decompress: {}", e)))?
+            .map_err(|e| Error::msg(format!("failed to decompress: {}", e)))?
+        } else {
+            bytes
+        };
+
+        serde_json::from_slice(&json)
+            .map_err(|e| Error::msg(format!("failed to deserialize: {}", e)))
+    }
+
+    /// a file that is already gone counts as deleted
+    async fn delete_json_data(&self, path: PathBuf) -> Result<()> {
+        match fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::msg(format!("failed to delete file: {}", e))),
+        }
     }
 
     fn get_problem_content_path(&self, problem_id: i32) -> PathBuf {
         self.get_data_path("problems", problem_id)
     }
 
-    fn get_test_case_path(&self, test_case_id: i32) -> PathBuf {
-        self.get_data_path("test_cases", test_case_id)
+    fn get_test_case_path(&self, hash: &str) -> PathBuf {
+        PathBuf::from(&self.config.data_dir)
+            .join("test_cases")
+            .join(format!("{}.json", hash))
     }
 
     fn get_solution_content_path(&self, solution_id: i32) -> PathBuf {
@@ -265,26 +501,107 @@ impl AppState {
         content: &ProblemContent,
     ) -> Result<()> {
         let path = self.get_problem_content_path(problem_id);
-        self.write_json_data(path, content).await
+        let result = self.write_json_data(path, content).await;
+        self.problem_cache.invalidate(&problem_id);
+        result
     }
 
     pub async fn read_problem_content(&self, problem_id: i32) -> Result<ProblemContent> {
+        if let Some(content) = self.problem_cache.get(&problem_id) {
+            return Ok(content);
+        }
+
+        let generation = self.problem_cache.generation();
         let path = self.get_problem_content_path(problem_id);
-        self.read_json_data(path).await
+        let content: ProblemContent = self.read_json_data(path).await?;
+        self.problem_cache
+            .insert(problem_id, content.clone(), generation);
+        Ok(content)
+    }
+
+    pub async fn delete_problem_content(&self, problem_id: i32) -> Result<()> {
+        let path = self.get_problem_content_path(problem_id);
+        let result = self.delete_json_data(path).await;
+        self.problem_cache.invalidate(&problem_id);
+        result
     }
 
+    /// store a test case under the hash of its content, so that identical ones
+    /// share a file; the hash goes into its `test_cases` row, which has to be
+    /// written in the transaction of `tx`
     pub async fn write_test_cases(
         &self,
-        test_case_id: i32,
+        tx: &mut PgConnection,
         test_case: &TestCaseData,
-    ) -> Result<()> {
-        let path = self.get_test_case_path(test_case_id);
-        self.write_json_data(path, test_case).await
+    ) -> Result<String> {
+        let json = serde_json::to_vec(test_case)
+            .map_err(|e| Error::msg(format!("failed to serialize: {}", e)))?;
+        let hash = format!("{:x}", Sha256::digest(&json));
+
+        lock_test_case_hash(tx, &hash).await?;
+        let path = self.get_test_case_path(&hash);
+        if !fs::try_exists(&path).await.unwrap_or(false) {
+            self.write_json_data(path, test_case).await?;
+        }
+        Ok(hash)
     }
 
     pub async fn read_test_cases(&self, test_case_id: i32) -> Result<TestCaseData> {
-        let path = self.get_test_case_path(test_case_id);
-        self.read_json_data(path).await
+        if let Some(test_case) = self.test_case_cache.get(&test_case_id) {
+            return Ok(test_case);
+        }
+
+        let generation = self.test_case_cache.generation();
+        let hash = sqlx::query_scalar!(
+            r#"
+            SELECT hash FROM test_cases WHERE id = $1
+            "#,
+            test_case_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?
+        .ok_or_else(|| Error::msg(format!("test case {} not found", test_case_id)))?;
+        let path = self.get_test_case_path(&hash);
+        let test_case: TestCaseData = self.read_json_data(path).await?;
+        self.test_case_cache
+            .insert(test_case_id, test_case.clone(), generation);
+        Ok(test_case)
+    }
+
+    /// call once the row is gone, the file stays while other test cases share it
+    pub async fn delete_test_cases(&self, test_case_id: i32, hash: &str) -> Result<()> {
+        self.test_case_cache.invalidate(&test_case_id);
+        self.discard_test_cases(hash).await
+    }
+
+    /// remove a stored test case unless some row still uses it
+    pub async fn discard_test_cases(&self, hash: &str) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+        lock_test_case_hash(&mut tx, hash).await?;
+
+        let shared = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM test_cases WHERE hash = $1) as "exists!"
+            "#,
+            hash
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+        if shared {
+            return Ok(());
+        }
+
+        let path = self.get_test_case_path(hash);
+        self.delete_json_data(path).await?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))
     }
 
     pub async fn write_solution_content(
@@ -301,13 +618,18 @@ impl AppState {
         self.read_json_data(path).await
     }
 
-    pub async fn write_submission_code(
-        &self,
-        submission_id: i32,
-        code: &SubmissionCode,
-    ) -> Result<()> {
+    pub async fn delete_solution_content(&self, solution_id: i32) -> Result<()> {
+        let path = self.get_solution_content_path(solution_id);
+        self.delete_json_data(path).await
+    }
+
+    /// the code comes back once written, to be sent on to the judge
+    pub async fn write_submission_code(&self, submission_id: i32, code: String) -> Result<String> {
         let path = self.get_submission_code_path(submission_id);
-        self.write_json_data(path, code).await
+        let written = self
+            .write_json_data_streamed(path, SubmissionCode { code })
+            .await?;
+        Ok(written.code)
     }
 
     pub async fn read_submission_code(&self, submission_id: i32) -> Result<SubmissionCode> {
@@ -315,6 +637,11 @@ impl AppState {
         self.read_json_data(path).await
     }
 
+    pub async fn delete_submission_code(&self, submission_id: i32) -> Result<()> {
+        let path = self.get_submission_code_path(submission_id);
+        self.delete_json_data(path).await
+    }
+
     pub async fn write_contest_content(
         &self,
         contest_id: i32,
@@ -329,6 +656,11 @@ impl AppState {
         self.read_json_data(path).await
     }
 
+    pub async fn delete_contest_content(&self, contest_id: i32) -> Result<()> {
+        let path = self.get_contest_path(contest_id);
+        self.delete_json_data(path).await
+    }
+
     pub async fn write_training_plan_content(
         &self,
         training_plan_id: i32,
@@ -345,6 +677,55 @@ impl AppState {
         let path = self.get_training_plan_path(training_plan_id);
         self.read_json_data(path).await
     }
+
+    pub async fn delete_training_plan_content(&self, training_plan_id: i32) -> Result<()> {
+        let path = self.get_training_plan_path(training_plan_id);
+        self.delete_json_data(path).await
+    }
+}
+
+fn cors_layer(config: &Config) -> Result<CorsLayer> {
+    let wildcard = |list: &[String]| list.iter().any(|s| s == "*");
+    let invalid = |what: &str, value: &str| Error::msg(format!("invalid CORS {}: {}", what, value));
+
+    let origin = if wildcard(&config.allowed_origins) {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .map(|s| HeaderValue::from_str(s).map_err(|_| invalid("origin", s)))
+                .collect::<Result<Vec<_>>>()?,
+        )
+    };
+    let methods = if wildcard(&config.allowed_methods) {
+        AllowMethods::any()
+    } else {
+        AllowMethods::list(
+            config
+                .allowed_methods
+                .iter()
+                .map(|s| Method::from_str(s).map_err(|_| invalid("method", s)))
+                .collect::<Result<Vec<_>>>()?,
+        )
+    };
+    let headers = if wildcard(&config.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(
+            config
+                .allowed_headers
+                .iter()
+                .map(|s| HeaderName::from_str(s).map_err(|_| invalid("header", s)))
+                .collect::<Result<Vec<_>>>()?,
+        )
+    };
+
+    Ok(CorsLayer::new()
+        .allow_origin(origin)
+        .allow_methods(methods)
+        .allow_headers(headers))
 }
 
 pub async fn start_api(config: Config) -> Result<()> {
@@ -353,6 +734,7 @@ pub async fn start_api(config: Config) -> Result<()> {
 
     state.create_admin_account().await?;
     state.setup_phantom_training_plan().await?;
+    state.migrate_test_case_files().await?;
 
     let app = route::routes(state.clone())
         .layer(
@@ -371,15 +753,8 @@ pub async fn start_api(config: Config) -> Result<()> {
                         )
                     },
                 ))
-                .layer(
-                    CorsLayer::new()
-                        .allow_methods(cors::Any)
-                        .allow_headers(cors::Any)
-                        .allow_origin(cors::Any),
-                )
-                .layer(DefaultBodyLimit::max(
-                    (config.max_file_size_mb * 1024. * 1024.) as usize,
-                ))
+                .layer(cors_layer(&config)?)
+                .layer(route::body_limit(config.max_file_size_mb))
                 .layer(NormalizePathLayer::trim_trailing_slash()),
         )
         .with_state(Arc::clone(&state));
@@ -394,3 +769,299 @@ pub async fn start_api(config: Config) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode, header},
+    };
+    use serde::{
+        Serialize, Serializer,
+        ser::{Error as _, SerializeMap},
+    };
+    use serde_json::{Value, json};
+    use tempfile::TempDir;
+
+    use crate::{
+        models::TestCaseData,
+        test_util::{TestApp, test_config},
+    };
+
+    /// starts writing, then fails like a write cut short would
+    struct Interrupted;
+
+    impl Serialize for Interrupted {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            map.serialize_entry("code", "partial")?;
+            Err(S::Error::custom("interrupted"))
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn interrupted_write_keeps_the_old_file(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let state = &app.state;
+        let path = state.get_data_path("writes", 1);
+        let old = json!({ "code": "old code" });
+        state.write_json_data(path.clone(), &old).await.unwrap();
+
+        let interrupted = state.write_json_data(path.clone(), &Interrupted).await;
+        assert!(interrupted.is_err());
+
+        let read: Value = state.read_json_data(path.clone()).await.unwrap();
+        assert_eq!(read, old);
+        let files: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, ["1.json"]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn compressed_files_read_back(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| config.compress_data = true).await;
+        let state = &app.state;
+        let path = state.get_data_path("writes", 1);
+        let data = json!({ "input": "1 2\n".repeat(1000) });
+        state.write_json_data(path.clone(), &data).await.unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.starts_with(&super::GZIP_MAGIC));
+        assert!(bytes.len() < data.to_string().len());
+        let read: Value = state.read_json_data(path).await.unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn uncompressed_files_read_with_compression_on(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| config.compress_data = true).await;
+        let state = &app.state;
+        let path = state.get_data_path("writes", 1);
+        let data = json!({ "code": "written before compression" });
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, serde_json::to_vec_pretty(&data).unwrap()).unwrap();
+
+        let read: Value = state.read_json_data(path).await.unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn problem_reads_hit_the_cache_until_a_write(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let state = &app.state;
+
+        let mut content = state.read_problem_content(problem_id).await.unwrap();
+        let original = content.description.clone();
+        content.description = "changed behind the cache".to_string();
+        let path = state.get_problem_content_path(problem_id);
+        state.write_json_data(path, &content).await.unwrap();
+        let cached = state.read_problem_content(problem_id).await.unwrap();
+        assert_eq!(cached.description, original);
+
+        content.description = "rewritten".to_string();
+        state
+            .write_problem_content(problem_id, &content)
+            .await
+            .unwrap();
+        let read = state.read_problem_content(problem_id).await.unwrap();
+        assert_eq!(read.description, "rewritten");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_case_reads_hit_the_cache_until_deleted(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let state = &app.state;
+        let test_case = sqlx::query!(
+            "SELECT id, hash FROM test_cases WHERE problem_id = $1",
+            problem_id
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+
+        let read = state.read_test_cases(test_case.id).await.unwrap();
+        std::fs::remove_file(state.get_test_case_path(&test_case.hash)).unwrap();
+        let cached = state.read_test_cases(test_case.id).await.unwrap();
+        assert_eq!(cached.input, read.input);
+
+        state
+            .delete_test_cases(test_case.id, &test_case.hash)
+            .await
+            .unwrap();
+        assert!(state.read_test_cases(test_case.id).await.is_err());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn identical_test_cases_share_a_file_until_the_last_goes(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let first = app.problem(&teacher).await;
+        let second = app.problem(&teacher).await;
+        let hashes = sqlx::query_scalar!("SELECT hash FROM test_cases ORDER BY id")
+            .fetch_all(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(hashes[0], hashes[1]);
+        let path = app.state.get_test_case_path(&hashes[0]);
+
+        let uri = format!("/api/problems/{}", first);
+        let response = app.send(Method::DELETE, &uri, Some(&teacher), None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert!(path.exists());
+
+        let uri = format!("/api/problems/{}", second);
+        let response = app.send(Method::DELETE, &uri, Some(&teacher), None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert!(!path.exists());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_case_file_is_kept_while_its_row_is_being_written(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let state = app.state.clone();
+        let test_case = TestCaseData {
+            input: "5 6\n".to_string(),
+            output: "11\n".to_string(),
+        };
+
+        let mut tx = state.pool.begin().await.unwrap();
+        let hash = state.write_test_cases(&mut tx, &test_case).await.unwrap();
+        let discard = tokio::spawn({
+            let (state, hash) = (state.clone(), hash.clone());
+            async move { state.discard_test_cases(&hash).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!discard.is_finished());
+
+        sqlx::query!(
+            "INSERT INTO test_cases (problem_id, hash) VALUES ($1, $2)",
+            problem_id,
+            hash
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        discard.await.unwrap().unwrap();
+        assert!(state.get_test_case_path(&hash).exists());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_cases_stored_by_id_move_under_their_hash(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let state = &app.state;
+
+        // as left by a version that had no hashes
+        sqlx::query("ALTER TABLE test_cases ALTER COLUMN hash DROP NOT NULL")
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        let test_case_id = sqlx::query_scalar!(
+            "INSERT INTO test_cases (problem_id, hash) VALUES ($1, NULL) RETURNING id",
+            problem_id
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        let legacy_path = state.get_data_path("test_cases", test_case_id);
+        let test_case = TestCaseData {
+            input: "7 8\n".to_string(),
+            output: "15\n".to_string(),
+        };
+        state
+            .write_json_data(legacy_path.clone(), &test_case)
+            .await
+            .unwrap();
+
+        state.migrate_test_case_files().await.unwrap();
+        assert!(!legacy_path.exists());
+        let read = state.read_test_cases(test_case_id).await.unwrap();
+        assert_eq!(read.input, "7 8\n");
+        let hash: Option<String> = sqlx::query_scalar("SELECT hash FROM test_cases WHERE id = $1")
+            .bind(test_case_id)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+        assert!(state.get_test_case_path(&hash.unwrap()).exists());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn cors_answers_only_allowed_origins(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| {
+            config.allowed_origins = vec!["https://oj.example.com".to_string()];
+        })
+        .await;
+        let preflight = |origin: &str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/api/ping")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let allowed = app.request(preflight("https://oj.example.com")).await;
+        assert_eq!(allowed.status, StatusCode::OK);
+        assert_eq!(
+            allowed.headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://oj.example.com"
+        );
+
+        let disallowed = app.request(preflight("https://evil.example.com")).await;
+        assert!(
+            !disallowed
+                .headers
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+
+        let request = Request::builder()
+            .uri("/api/ping")
+            .header(header::ORIGIN, "https://evil.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.request(request).await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert!(
+            !res.headers
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[test]
+    fn pool_options_follow_the_config() {
+        let data_dir = TempDir::new().unwrap();
+        let mut config = test_config(&data_dir);
+        config.max_connections = 7;
+        config.db_acquire_timeout = chrono::Duration::seconds(3);
+        let options = super::pool_options(&config).unwrap();
+        assert_eq!(options.get_max_connections(), 7);
+        assert_eq!(
+            options.get_acquire_timeout(),
+            std::time::Duration::from_secs(3)
+        );
+
+        config.max_connections = 0;
+        assert!(super::pool_options(&config).is_err());
+    }
+
+    #[test]
+    fn missing_env_var_is_named_in_the_error() {
+        let error = super::env_var("KOIOJ_TEST_UNSET_VARIABLE").unwrap_err();
+        assert_eq!(
+            error.1.unwrap().to_string(),
+            "KOIOJ_TEST_UNSET_VARIABLE not set"
+        );
+    }
+}
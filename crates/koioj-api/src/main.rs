@@ -5,9 +5,11 @@ use std::fs::File;
 fn main() -> Result<()> {
     dotenvy::dotenv()?;
 
-    let config: Config =
+    let mut config: Config =
         serde_yaml::from_reader(File::open("config.yml").expect("failed to open the config file!"))
             .expect("failed to read the config!");
+    config.load_secrets_from_env();
+    config.check_secrets()?;
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(config.max_workers)
@@ -20,5 +22,7 @@ fn main() -> Result<()> {
 
     tracing::debug!("starting api with config {:?}", config);
 
-    runtime.block_on(start_api(config))
+    runtime
+        .block_on(start_api(config))
+        .inspect_err(|e| tracing::error!("failed to start api: {:?}", e))
 }
@@ -1,5 +1,7 @@
+use koioj_common::judge::Language;
 pub use koioj_common::judge::TestCaseData;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -9,6 +11,12 @@ pub struct ProblemContent {
     pub output_description: String,
     pub samples: Vec<TestCaseData>,
     pub note: Option<String>,
+    /// languages accepted by `submit`, any language when empty
+    #[serde(default)]
+    pub allowed_languages: Vec<Language>,
+    /// starter code to pre-fill the editor with
+    #[serde(default)]
+    pub templates: HashMap<Language, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,21 +24,69 @@ pub enum Action {
     PutProfile,
     GetProfile,
     DeleteUser,
+    ListUsers,
+    BulkCreateUsers,
     CreateProblem,
     PutProblem,
     DeleteProblem,
     GetTestCases,
     AddTestCases,
     CreateSolution,
+    PutSolution,
     DeleteSolution,
     GetSubmission,
     CreateContest,
     PutContest,
     DeleteContest,
     ViewOverallRanking,
+    RebuildRanking,
     CreateTrainingPlan,
     PutTrainingPlan,
     DeleteTrainingPlan,
+    ViewAuditLog,
+    ManageCollaborators,
+    ViewProblemStats,
+    ViewSimilarSubmissions,
+    PruneSubmissions,
+    ManageWebhooks,
+}
+
+impl Action {
+    /// stable name, as stored in the audit log
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::PutRole => "put_role",
+            Action::GetRole => "get_role",
+            Action::PutProfile => "put_profile",
+            Action::GetProfile => "get_profile",
+            Action::DeleteUser => "delete_user",
+            Action::ListUsers => "list_users",
+            Action::BulkCreateUsers => "bulk_create_users",
+            Action::CreateProblem => "create_problem",
+            Action::PutProblem => "put_problem",
+            Action::DeleteProblem => "delete_problem",
+            Action::GetTestCases => "get_test_cases",
+            Action::AddTestCases => "add_test_cases",
+            Action::CreateSolution => "create_solution",
+            Action::PutSolution => "put_solution",
+            Action::DeleteSolution => "delete_solution",
+            Action::GetSubmission => "get_submission",
+            Action::CreateContest => "create_contest",
+            Action::PutContest => "put_contest",
+            Action::DeleteContest => "delete_contest",
+            Action::ViewOverallRanking => "view_overall_ranking",
+            Action::RebuildRanking => "rebuild_ranking",
+            Action::CreateTrainingPlan => "create_training_plan",
+            Action::PutTrainingPlan => "put_training_plan",
+            Action::DeleteTrainingPlan => "delete_training_plan",
+            Action::ViewAuditLog => "view_audit_log",
+            Action::ManageCollaborators => "manage_collaborators",
+            Action::ViewProblemStats => "view_problem_stats",
+            Action::ViewSimilarSubmissions => "view_similar_submissions",
+            Action::PruneSubmissions => "prune_submissions",
+            Action::ManageWebhooks => "manage_webhooks",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -53,11 +101,31 @@ pub enum Resource {
 }
 
 impl Resource {
+    /// kind and id, as stored in the audit log
+    pub fn parts(self) -> (&'static str, Option<i32>) {
+        match self {
+            Resource::Global => ("global", None),
+            Resource::User(id) => ("user", Some(id)),
+            Resource::Problem(id) => ("problem", Some(id)),
+            Resource::Solution(id) => ("solution", Some(id)),
+            Resource::Submission(id) => ("submission", Some(id)),
+            Resource::Contest(id) => ("contest", Some(id)),
+            Resource::TrainingPlan(id) => ("training_plan", Some(id)),
+        }
+    }
+
     pub async fn owner_id(self, pool: &sqlx::PgPool) -> Result<i32> {
         match self {
             Resource::Global => Ok(1),
             Resource::User(id) => Ok(id),
-            Resource::Problem(_) => Ok(-1),
+            Resource::Problem(id) => {
+                let result =
+                    sqlx::query_scalar!("SELECT creator_id FROM problems WHERE id = $1", id)
+                        .fetch_one(pool)
+                        .await?;
+
+                Ok(result)
+            }
             Resource::Solution(id) => {
                 let result = sqlx::query_scalar!("SELECT author FROM solutions WHERE id = $1", id)
                     .fetch_one(pool)
@@ -91,6 +159,45 @@ impl Resource {
             }
         }
     }
+
+    /// owners and collaborators may both edit a problem or contest
+    pub async fn is_editor(self, pool: &sqlx::PgPool, user_id: i32) -> Result<bool> {
+        if self.owner_id(pool).await? == user_id {
+            return Ok(true);
+        }
+
+        let is_collaborator = match self {
+            Resource::Problem(id) => {
+                sqlx::query_scalar!(
+                    r#"
+                    SELECT EXISTS(
+                        SELECT 1 FROM problem_collaborators WHERE problem_id = $1 AND user_id = $2
+                    ) as "exists!"
+                    "#,
+                    id,
+                    user_id
+                )
+                .fetch_one(pool)
+                .await?
+            }
+            Resource::Contest(id) => {
+                sqlx::query_scalar!(
+                    r#"
+                    SELECT EXISTS(
+                        SELECT 1 FROM contest_collaborators WHERE contest_id = $1 AND user_id = $2
+                    ) as "exists!"
+                    "#,
+                    id,
+                    user_id
+                )
+                .fetch_one(pool)
+                .await?
+            }
+            _ => false,
+        };
+
+        Ok(is_collaborator)
+    }
 }
 
 pub async fn role_of_claims(pool: &sqlx::PgPool, claims: &Claims) -> Result<UserRole> {
@@ -135,24 +242,36 @@ pub async fn check_permission(
         (_, Action::PutProfile, Resource::User(id_to_put)) => claims.sub == id_to_put,
         (_, Action::DeleteUser, Resource::User(id_to_del)) => claims.sub == id_to_del,
 
+        (UserRole::Teacher, Action::ListUsers, _) => true,
+        (UserRole::Teacher, Action::BulkCreateUsers, _) => true,
+
         (UserRole::Teacher, Action::CreateProblem, _) => true,
-        (UserRole::Teacher, Action::PutProblem, _) => true,
-        (UserRole::Teacher, Action::DeleteProblem, _) => true,
+        (UserRole::Teacher, Action::PutProblem, problem) => {
+            problem.is_editor(pool, claims.sub).await?
+        }
+        (UserRole::Teacher, Action::DeleteProblem, problem) => {
+            problem.is_editor(pool, claims.sub).await?
+        }
 
-        (UserRole::Teacher, Action::AddTestCases, _) => true,
+        (UserRole::Teacher, Action::AddTestCases, problem) => {
+            problem.is_editor(pool, claims.sub).await?
+        }
         (UserRole::Teacher, Action::GetTestCases, _) => true,
 
         (UserRole::Teacher, Action::CreateSolution, _) => true,
+        (UserRole::Teacher, Action::PutSolution, solution) => {
+            claims.sub == solution.owner_id(pool).await?
+        }
         (UserRole::Teacher, Action::DeleteSolution, solution) => {
             claims.sub == solution.owner_id(pool).await?
         }
 
         (UserRole::Teacher, Action::CreateContest, _) => true,
         (UserRole::Teacher, Action::PutContest, contest) => {
-            claims.sub == contest.owner_id(pool).await?
+            contest.is_editor(pool, claims.sub).await?
         }
         (UserRole::Teacher, Action::DeleteContest, contest) => {
-            claims.sub == contest.owner_id(pool).await?
+            contest.is_editor(pool, claims.sub).await?
         }
         (UserRole::Teacher, Action::ViewOverallRanking, _) => true,
 
@@ -168,6 +287,15 @@ pub async fn check_permission(
         (UserRole::Teacher, Action::DeleteTrainingPlan, training_plan) => {
             claims.sub == training_plan.owner_id(pool).await?
         }
+        (UserRole::Teacher, Action::ManageCollaborators, resource) => {
+            claims.sub == resource.owner_id(pool).await?
+        }
+
+        (UserRole::Teacher, Action::ViewProblemStats, _) => true,
+        (UserRole::Student, Action::ViewProblemStats, problem) => {
+            problem.is_editor(pool, claims.sub).await?
+        }
+        (UserRole::Teacher, Action::ViewSimilarSubmissions, _) => true,
         _ => false,
     };
 
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json, Router, extract::Query, middleware};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    AppState, Result, State,
+    auth::{Claims, jwt_auth_middleware},
+    error::Error,
+    perm::{Action, Resource, check_permission},
+    route::{MAX_PAGE, Page},
+};
+
+pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    use axum::routing::*;
+    Router::new()
+        .route("/", get(list_audit_log))
+        .layer(middleware::from_fn_with_state(state, jwt_auth_middleware))
+}
+
+#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ListAuditLogQuery {
+    page: Option<i64>,
+    page_size: Option<i64>,
+    actor_id: Option<i32>,
+    /// e.g. `put_role` or `delete_user`
+    action: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditLogItem {
+    id: i32,
+    actor_id: i32,
+    actor_name: String,
+    action: String,
+    resource_type: String,
+    resource_id: Option<i32>,
+    detail: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/audit",
+    params(ListAuditLogQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = Page<AuditLogItem>),
+    ),
+    tag = "audit",
+)]
+async fn list_audit_log(
+    state: State,
+    claims: Extension<Claims>,
+    Query(q): Query<ListAuditLogQuery>,
+) -> Result<Json<Page<AuditLogItem>>> {
+    check_permission(&state.pool, &claims, Action::ViewAuditLog, Resource::Global).await?;
+
+    let page = q.page.unwrap_or(1).clamp(1, MAX_PAGE);
+    let page_size = q.page_size.unwrap_or(50).clamp(1, 200);
+    let offset = (page - 1) * page_size;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!" FROM audit_log
+        WHERE ($1::INTEGER IS NULL OR actor_id = $1)
+        AND ($2::VARCHAR IS NULL OR action = $2)
+        AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+        AND ($4::TIMESTAMPTZ IS NULL OR created_at < $4)
+        "#,
+        q.actor_id,
+        q.action,
+        q.from,
+        q.to
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let entries = sqlx::query!(
+        r#"
+        SELECT a.id, a.actor_id, u.username, a.action, a.resource_type, a.resource_id,
+               a.detail, a.created_at
+        FROM audit_log a
+        JOIN users u ON u.id = a.actor_id
+        WHERE ($1::INTEGER IS NULL OR a.actor_id = $1)
+        AND ($2::VARCHAR IS NULL OR a.action = $2)
+        AND ($3::TIMESTAMPTZ IS NULL OR a.created_at >= $3)
+        AND ($4::TIMESTAMPTZ IS NULL OR a.created_at < $4)
+        ORDER BY a.created_at DESC, a.id DESC
+        LIMIT $5 OFFSET $6
+        "#,
+        q.actor_id,
+        q.action,
+        q.from,
+        q.to,
+        page_size,
+        offset
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .into_iter()
+    .map(|row| AuditLogItem {
+        id: row.id,
+        actor_id: row.actor_id,
+        actor_name: row.username,
+        action: row.action,
+        resource_type: row.resource_type,
+        resource_id: row.resource_id,
+        detail: row.detail,
+        created_at: row.created_at,
+    })
+    .collect();
+
+    Ok(Json(Page::new(entries, total, page, page_size)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use crate::test_util::TestApp;
+
+    #[sqlx::test(migrations = false)]
+    async fn role_change_and_user_deletion_are_audited(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (admin_id, admin) = app.user("admin").await;
+        let (student_id, _) = app.user("student").await;
+        let (deleted_id, _) = app.user("student").await;
+
+        let uri = format!("/api/users/{}/role", student_id);
+        let body = json!({ "userRole": "teacher" });
+        let res = app.send(Method::PUT, &uri, Some(&admin), Some(body)).await;
+        assert_eq!(res.status, StatusCode::OK);
+        let uri = format!("/api/users/{}", deleted_id);
+        let res = app.send(Method::DELETE, &uri, Some(&admin), None).await;
+        assert_eq!(res.status, StatusCode::OK);
+
+        let uri = format!("/api/audit?actorId={}", admin_id);
+        let res = app.get(&uri, &admin).await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert_eq!(res.body["total"], 2);
+        let items = res.body["items"].as_array().unwrap();
+        let row = |action: &str| {
+            items
+                .iter()
+                .find(|item| item["action"] == action)
+                .unwrap_or_else(|| panic!("no {} row in {:?}", action, items))
+        };
+        let role = row("put_role");
+        assert_eq!(role["resourceType"], "user");
+        assert_eq!(role["resourceId"], student_id);
+        assert_eq!(role["detail"], "role: teacher");
+        let deletion = row("delete_user");
+        assert_eq!(deletion["resourceId"], deleted_id);
+
+        let res = app.get("/api/audit?action=delete_user", &admin).await;
+        assert_eq!(res.body["total"], 1);
+    }
+}
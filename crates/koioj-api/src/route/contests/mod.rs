@@ -1,30 +1,41 @@
 pub(crate) mod ranking_cache;
 
-pub use ranking_cache::ContestRankingItem;
+pub use ranking_cache::{ContestRankingItem, RankingEvent};
 
 use axum::{
     Extension, Json, Router,
     extract::{Path, Query},
-    http::StatusCode,
+    http::{StatusCode, header},
     middleware,
+    response::{
+        Response,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
 use axum_extra::extract::Query as ExtraQuery;
 use chrono::{DateTime, Utc};
+use futures::Stream;
 use koioj_common::{bail, judge::SubmissionResult};
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, Row};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::broadcast::error::RecvError;
 use utoipa::{IntoParams, ToSchema};
 
 use crate::{
-    AppState, Result, State,
+    AppState, Result, State, audit,
     auth::{
         Claims, hash_password, jwt_auth_accept_guest_middleware, jwt_auth_middleware,
         verify_password,
     },
-    error::Error,
+    error::{Error, ValidationError},
     models::ContestContent,
     perm::{Action, Resource, UserRole, check_permission, role_of_claims},
+    route::{
+        MAX_PAGE, Page, check_visible, not_found,
+        problems::{CollaboratorItem, ListCollaboratorsResponse, check_collaborator},
+    },
+    validation::check_max_length,
 };
 
 pub fn top_routes() -> Router<Arc<AppState>> {
@@ -39,6 +50,9 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
             Router::new()
                 .route("/", get(list_contests))
                 .route("/{contest_id}", get(get_contest))
+                .route("/{contest_id}/ranking", get(get_contest_ranking))
+                .route("/{contest_id}/ranking/stream", get(stream_contest_ranking))
+                .route("/overall-ranking", get(get_overall_ranking))
                 .layer(middleware::from_fn_with_state(
                     state.clone(),
                     jwt_auth_accept_guest_middleware,
@@ -49,10 +63,30 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
                 .route("/", post(create_contest))
                 .route("/{contest_id}", put(put_contest))
                 .route("/{contest_id}", delete(delete_contest))
+                .route("/{contest_id}/clone", post(clone_contest))
+                .route(
+                    "/{contest_id}/collaborators",
+                    get(list_contest_collaborators),
+                )
+                .route(
+                    "/{contest_id}/collaborators/{user_id}",
+                    put(add_contest_collaborator),
+                )
+                .route(
+                    "/{contest_id}/collaborators/{user_id}",
+                    delete(remove_contest_collaborator),
+                )
                 .route("/{contest_id}/join", post(join_contest))
                 .route("/{contest_id}/is-joined", get(get_is_joined))
-                .route("/{contest_id}/ranking", get(get_contest_ranking))
-                .route("/overall-ranking", get(get_overall_ranking))
+                .route("/{contest_id}/ranking/export", get(export_contest_ranking))
+                .route(
+                    "/{contest_id}/ranking/rebuild",
+                    post(rebuild_contest_ranking),
+                )
+                .route("/{contest_id}/virtual", post(start_virtual_contest))
+                .route("/{contest_id}/virtual-ranking", get(get_virtual_ranking))
+                .route("/{contest_id}/announcements", post(create_announcement))
+                .route("/{contest_id}/announcements", get(list_announcements))
                 .layer(middleware::from_fn_with_state(state, jwt_auth_middleware)),
         )
 }
@@ -73,6 +107,41 @@ pub enum ContestType {
     Private,
 }
 
+fn has_duplicates(problem_ids: &[i32]) -> bool {
+    let mut seen = std::collections::HashSet::new();
+    problem_ids.iter().any(|id| !seen.insert(id))
+}
+
+/// Rejects problem ids that don't exist, hidden problems are allowed since
+/// contests are usually prepared with them
+async fn check_contest_problems(pool: &PgPool, problem_ids: &[i32]) -> Result<()> {
+    let problems = sqlx::query!(
+        r#"SELECT id, status = 'hidden' as "hidden!" FROM problems WHERE id = ANY($1)"#,
+        problem_ids
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let missing: Vec<String> = problem_ids
+        .iter()
+        .filter(|id| !problems.iter().any(|problem| problem.id == **id))
+        .map(|id| id.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::invalid_field(
+            "problemIds",
+            format!("problems not found: {}", missing.join(", ")),
+        ));
+    }
+
+    for problem in problems.iter().filter(|problem| problem.hidden) {
+        tracing::warn!("hidden problem {} is added to a contest", problem.id);
+    }
+
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct CreateContestRequest {
@@ -84,6 +153,14 @@ pub(crate) struct CreateContestRequest {
     #[serde(rename = "type")]
     contest_type: ContestType,
     problem_ids: Vec<i32>,
+    /// joining is refused outside the registration window, either end may be open
+    registration_begin: Option<DateTime<Utc>>,
+    registration_end: Option<DateTime<Utc>>,
+    /// submissions allowed per problem and contestant, zero or absent for unlimited
+    max_attempts_per_problem: Option<i32>,
+    /// let contestants keep submitting after the end, without it counting
+    #[serde(default)]
+    allow_upsolving: bool,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -115,28 +192,47 @@ async fn create_contest(
     )
     .await?;
 
-    if p.name.is_empty() || p.description.is_empty() {
-        bail!(@BAD_REQUEST "name and description are required");
+    let mut errors = ValidationError::new();
+    if p.name.is_empty() {
+        errors.add("name", "name is required");
     }
-
-    if p.problem_ids.is_empty() {
-        bail!(@BAD_REQUEST "at least one problem is required");
+    if p.description.is_empty() {
+        errors.add("description", "description is required");
     }
-
-    if p.problem_ids.len() > 10 {
-        bail!(@BAD_REQUEST "contest can have at most 10 problems");
+    check_max_length(
+        &mut errors,
+        "description",
+        &p.description,
+        state.config.max_text_length,
+    );
+    if p.problem_ids.is_empty() {
+        errors.add("problemIds", "at least one problem is required");
+    } else if p.problem_ids.len() > 10 {
+        errors.add("problemIds", "contest can have at most 10 problems");
+    } else if has_duplicates(&p.problem_ids) {
+        errors.add("problemIds", "each problem can appear only once");
     }
-
     if p.begin_time >= p.end_time {
-        bail!(@BAD_REQUEST "begin time must be before end time");
+        errors.add("endTime", "begin time must be before end time");
     }
+    check_registration_window(
+        &mut errors,
+        p.registration_begin,
+        p.registration_end,
+        p.end_time,
+    );
+    check_max_attempts(&mut errors, p.max_attempts_per_problem);
+    errors.check()?;
+    check_contest_problems(&state.pool, &p.problem_ids).await?;
 
     let hashed_password = p.password.map(|p| hash_password(p)).transpose()?;
 
     let contest_id: i32 = sqlx::query_scalar!(
         r#"
-        INSERT INTO contests (creator_id, name, begin_time, end_time, password, type, status)
-        VALUES ($1, $2, $3, $4, $5, $6, 'active')
+        INSERT INTO contests
+            (creator_id, name, begin_time, end_time, password, type, status,
+             registration_begin, registration_end, max_attempts_per_problem, allow_upsolving)
+        VALUES ($1, $2, $3, $4, $5, $6, 'active', $7, $8, $9, $10)
         RETURNING id
         "#,
         claims.sub,
@@ -144,7 +240,11 @@ async fn create_contest(
         p.begin_time,
         p.end_time,
         hashed_password,
-        p.contest_type as ContestType
+        p.contest_type as ContestType,
+        p.registration_begin,
+        p.registration_end,
+        p.max_attempts_per_problem.filter(|max| *max > 0),
+        p.allow_upsolving
     )
     .fetch_one(&state.pool)
     .await
@@ -187,6 +287,79 @@ async fn create_contest(
     }))
 }
 
+fn check_registration_window(
+    errors: &mut ValidationError,
+    registration_begin: Option<DateTime<Utc>>,
+    registration_end: Option<DateTime<Utc>>,
+    end_time: DateTime<Utc>,
+) {
+    if let (Some(begin), Some(end)) = (registration_begin, registration_end) {
+        if begin >= end {
+            errors.add("registrationEnd", "registration must open before it closes");
+        }
+    }
+    if registration_end.is_some_and(|end| end > end_time) {
+        errors.add(
+            "registrationEnd",
+            "registration must close before the contest ends",
+        );
+    }
+}
+
+/// Participants enrolled through a training plan expect its contests to lie
+/// ahead, so changed times of a plan's contest can't be in the past
+async fn check_plan_contest_times(
+    pool: &PgPool,
+    errors: &mut ValidationError,
+    contest_id: i32,
+    new_begin_time: Option<DateTime<Utc>>,
+    new_end_time: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let plans = sqlx::query_scalar!(
+        r#"
+        SELECT tp.name FROM training_plan_contests tpc
+        JOIN training_plans tp ON tp.id = tpc.plan_id
+        WHERE tpc.contest_id = $1 AND tpc.plan_id <> 0
+        ORDER BY tp.id
+        "#,
+        contest_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    if plans.is_empty() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let plans = plans.join(", ");
+    if new_begin_time.is_some_and(|t| t < now) {
+        errors.add(
+            "beginTime",
+            format!(
+                "contest belongs to training plans ({}), its begin time can't be moved into the past",
+                plans
+            ),
+        );
+    }
+    if new_end_time.is_some_and(|t| t < now) {
+        errors.add(
+            "endTime",
+            format!(
+                "contest belongs to training plans ({}), its end time can't be moved into the past",
+                plans
+            ),
+        );
+    }
+    Ok(())
+}
+
+fn check_max_attempts(errors: &mut ValidationError, max_attempts: Option<i32>) {
+    if max_attempts.is_some_and(|max| max < 0) {
+        errors.add("maxAttemptsPerProblem", "max attempts must not be negative");
+    }
+}
+
 async fn check_contest_password(
     pool: &sqlx::PgPool,
     contest_id: i32,
@@ -233,19 +406,13 @@ pub(crate) struct ContestListItem {
     has_password: bool,
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct ListContestsResponse {
-    contests: Vec<ContestListItem>,
-    total: i64,
-}
-
 #[utoipa::path(
     get,
     path = "/api/contests",
+    security((), ("bearer_auth" = [])),
     params(ListContestsQuery),
     responses(
-        (status = 200, body = ListContestsResponse),
+        (status = 200, body = Page<ContestListItem>),
     ),
     tag = "contest"
 )]
@@ -253,9 +420,9 @@ async fn list_contests(
     state: State,
     claims: Extension<Claims>,
     Query(q): Query<ListContestsQuery>,
-) -> Result<Json<ListContestsResponse>> {
+) -> Result<Json<Page<ContestListItem>>> {
     let user_role = role_of_claims(&state.pool, &claims).await?;
-    let page = q.page.unwrap_or(1).max(1);
+    let page = q.page.unwrap_or(1).clamp(1, MAX_PAGE);
     let page_size = q.page_size.unwrap_or(20).clamp(1, 100);
     let offset = (page - 1) * page_size;
 
@@ -306,7 +473,7 @@ async fn list_contests(
             has_password: row.get::<bool, _>("has_password"),
         })
         .collect();
-    Ok(Json(ListContestsResponse { contests, total }))
+    Ok(Json(Page::new(contests, total, page, page_size)))
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -317,15 +484,41 @@ pub(crate) struct GetContestResponse {
     description: String,
     begin_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
+    registration_begin: Option<DateTime<Utc>>,
+    registration_end: Option<DateTime<Utc>>,
+    max_attempts_per_problem: Option<i32>,
+    allow_upsolving: bool,
     #[serde(rename = "type")]
     contest_type: ContestType,
     status: ContestStatus,
     problem_ids: Vec<i32>,
+    /// the same problems as `problem_ids`, with the label shown to contestants
+    problems: Vec<ContestProblemItem>,
     has_password: bool,
 }
 
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ContestProblemItem {
+    problem_id: i32,
+    number: i32,
+    /// the alias if one is set, otherwise a letter from the problem's position
+    label: String,
+}
+
+const MAX_ALIAS_LENGTH: usize = 32;
+
+/// A for the first problem, B for the second and so on
+fn default_problem_label(number: i32) -> String {
+    match u8::try_from(number) {
+        Ok(n) if n < 26 => ((b'A' + n) as char).to_string(),
+        _ => (number + 1).to_string(),
+    }
+}
+
 #[derive(Serialize, Deserialize, ToSchema, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub(crate) struct GetContestQuery {
     password: Option<String>,
 }
@@ -340,7 +533,7 @@ pub(crate) struct GetContestQuery {
     responses(
         (status = 200, body = GetContestResponse),
     ),
-    security(("bearer_auth" = [])),
+    security((), ("bearer_auth" = [])),
     tag = "contest"
 )]
 async fn get_contest(
@@ -351,7 +544,7 @@ async fn get_contest(
 ) -> Result<Json<GetContestResponse>> {
     let contest = sqlx::query!(
         r#"
-        SELECT id, name, begin_time, end_time, password, type as "type_: ContestType", status as "status_: ContestStatus", created_at
+        SELECT id, name, begin_time, end_time, registration_begin, registration_end, max_attempts_per_problem, allow_upsolving, password, type as "type_: ContestType", status as "status_: ContestStatus", created_at
         FROM contests
         WHERE id = $1
         "#,
@@ -364,13 +557,13 @@ async fn get_contest(
 
     let user_role = role_of_claims(&state.pool, &claims).await?;
 
-    // check hidden
-    if contest.status_ == ContestStatus::Hidden {
-        match user_role {
-            UserRole::Teacher | UserRole::Admin => {}
-            _ => bail!(@NOT_FOUND "contest not found"),
-        }
-    }
+    check_visible(
+        &state.pool,
+        &claims,
+        contest.status_ == ContestStatus::Hidden,
+        "contest",
+    )
+    .await?;
 
     check_contest_password(&state.pool, contest_id, query.password).await?;
 
@@ -392,9 +585,14 @@ async fn get_contest(
             } else {
                 let is_participant = sqlx::query!(
                     r#"
-                    SELECT EXISTS(
-                        SELECT 1 FROM contest_participants
-                        WHERE contest_id = $1 AND user_id = $2
+                    SELECT (
+                        EXISTS(
+                            SELECT 1 FROM contest_participants
+                            WHERE contest_id = $1 AND user_id = $2
+                        ) OR EXISTS(
+                            SELECT 1 FROM contest_virtual_participants
+                            WHERE contest_id = $1 AND user_id = $2
+                        )
                     ) as "exists!"
                     "#,
                     contest_id,
@@ -411,16 +609,31 @@ async fn get_contest(
     };
 
     // Get problem list
-    let problem_ids = match is_allowed {
-        true => sqlx::query_scalar!(
-            "SELECT problem_id FROM contest_problems WHERE contest_id = $1 ORDER BY number",
+    let problems: Vec<ContestProblemItem> = match is_allowed {
+        true => sqlx::query!(
+            r#"
+            SELECT problem_id, number, alias
+            FROM contest_problems
+            WHERE contest_id = $1
+            ORDER BY number
+            "#,
             contest_id
         )
         .fetch_all(&state.pool)
         .await
-        .map_err(|e| Error::msg(format!("database error: {}", e)))?,
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?
+        .into_iter()
+        .map(|row| ContestProblemItem {
+            problem_id: row.problem_id,
+            number: row.number,
+            label: row
+                .alias
+                .unwrap_or_else(|| default_problem_label(row.number)),
+        })
+        .collect(),
         false => vec![],
     };
+    let problem_ids = problems.iter().map(|problem| problem.problem_id).collect();
 
     Ok(Json(GetContestResponse {
         contest_id: contest.id,
@@ -428,10 +641,15 @@ async fn get_contest(
         description: content.description,
         begin_time: contest.begin_time,
         end_time: contest.end_time,
+        registration_begin: contest.registration_begin,
+        registration_end: contest.registration_end,
+        max_attempts_per_problem: contest.max_attempts_per_problem,
+        allow_upsolving: contest.allow_upsolving,
         has_password: contest.password.is_some(),
         contest_type: contest.type_,
         status: contest.status_,
         problem_ids,
+        problems,
     }))
 }
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -445,7 +663,17 @@ pub(crate) struct UpdateContestRequest {
     #[serde(rename = "type")]
     contest_type: Option<ContestType>,
     status: Option<ContestStatus>,
+    registration_begin: Option<DateTime<Utc>>,
+    registration_end: Option<DateTime<Utc>>,
+    /// drop the registration window, so that joining is always allowed
+    #[serde(default)]
+    clear_registration_window: bool,
+    /// zero lifts the limit
+    max_attempts_per_problem: Option<i32>,
+    allow_upsolving: Option<bool>,
     problem_ids: Option<Vec<i32>>,
+    /// labels keyed by problem id, an empty label restores the default letter
+    problem_aliases: Option<HashMap<i32, String>>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -496,21 +724,54 @@ async fn put_contest(
     }
 
     // Validate time constraints if both times are being updated
+    let mut errors = ValidationError::new();
     if let (Some(begin), Some(end)) = (&p.begin_time, &p.end_time) {
         if begin >= end {
-            bail!(@BAD_REQUEST "begin time must be before end time");
+            errors.add("endTime", "begin time must be before end time");
         }
     }
 
+    if let Some(ref description) = p.description {
+        check_max_length(
+            &mut errors,
+            "description",
+            description,
+            state.config.max_text_length,
+        );
+    }
+
     // Validate problem_ids length
     if let Some(ref problem_ids) = p.problem_ids {
         if problem_ids.is_empty() {
-            bail!(@BAD_REQUEST "at least one problem is required");
+            errors.add("problemIds", "at least one problem is required");
+        } else if problem_ids.len() > 10 {
+            errors.add("problemIds", "contest can have at most 10 problems");
+        } else if has_duplicates(problem_ids) {
+            errors.add("problemIds", "each problem can appear only once");
         }
-        if problem_ids.len() > 10 {
-            bail!(@BAD_REQUEST "contest can have at most 10 problems");
+    }
+    if let Some(ref aliases) = p.problem_aliases {
+        if aliases
+            .values()
+            .any(|alias| alias.trim().chars().count() > MAX_ALIAS_LENGTH)
+        {
+            errors.add(
+                "problemAliases",
+                format!("alias can have at most {} characters", MAX_ALIAS_LENGTH),
+            );
         }
     }
+    errors.check()?;
+    if let Some(ref problem_ids) = p.problem_ids {
+        check_contest_problems(&state.pool, problem_ids).await?;
+    }
+
+    // all or nothing, a bad alias must not leave the other changes in place
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
     // Update basic contest info
     if p.name.is_some()
@@ -519,16 +780,22 @@ async fn put_contest(
         || p.password.is_some()
         || p.contest_type.is_some()
         || p.status.is_some()
+        || p.registration_begin.is_some()
+        || p.registration_end.is_some()
+        || p.clear_registration_window
+        || p.max_attempts_per_problem.is_some()
+        || p.allow_upsolving.is_some()
     {
         let current = sqlx::query!(
             r#"
-            SELECT name, begin_time, end_time, password, type as "type_: ContestType", status as "status_: ContestStatus"
+            SELECT name, begin_time, end_time, password, type as "type_: ContestType", status as "status_: ContestStatus",
+                   registration_begin, registration_end, max_attempts_per_problem, allow_upsolving
             FROM contests
             WHERE id = $1
             "#,
             contest_id
         )
-        .fetch_one(&state.pool)
+        .fetch_one(&mut *tx)
         .await
         .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
@@ -542,16 +809,49 @@ async fn put_contest(
         };
         let contest_type = p.contest_type.as_ref().unwrap_or(&current.type_);
         let status = p.status.as_ref().unwrap_or(&current.status_);
+        let (registration_begin, registration_end) = if p.clear_registration_window {
+            (None, None)
+        } else {
+            (
+                p.registration_begin.or(current.registration_begin),
+                p.registration_end.or(current.registration_end),
+            )
+        };
 
         if begin_time >= end_time {
-            bail!(@BAD_REQUEST "begin time must be before end time");
+            return Err(Error::invalid_field(
+                "endTime",
+                "begin time must be before end time",
+            ));
+        }
+        let max_attempts_per_problem = match p.max_attempts_per_problem {
+            Some(max) => (max > 0).then_some(max),
+            None => current.max_attempts_per_problem,
+        };
+        let allow_upsolving = p.allow_upsolving.unwrap_or(current.allow_upsolving);
+
+        let mut errors = ValidationError::new();
+        if *begin_time != current.begin_time || *end_time != current.end_time {
+            check_plan_contest_times(
+                &state.pool,
+                &mut errors,
+                contest_id,
+                (*begin_time != current.begin_time).then_some(*begin_time),
+                (*end_time != current.end_time).then_some(*end_time),
+            )
+            .await?;
         }
+        check_max_attempts(&mut errors, p.max_attempts_per_problem);
+        check_registration_window(&mut errors, registration_begin, registration_end, *end_time);
+        errors.check()?;
 
         sqlx::query!(
             r#"
             UPDATE contests
-            SET name = $1, begin_time = $2, end_time = $3, password = $4, type = $5, status = $6, updated_at = NOW()
-            WHERE id = $7
+            SET name = $1, begin_time = $2, end_time = $3, password = $4, type = $5, status = $6,
+                registration_begin = $7, registration_end = $8, max_attempts_per_problem = $9,
+                allow_upsolving = $10, updated_at = NOW()
+            WHERE id = $11
             "#,
             name,
             begin_time,
@@ -559,9 +859,13 @@ async fn put_contest(
             password,
             contest_type as &ContestType,
             status as &ContestStatus,
+            registration_begin,
+            registration_end,
+            max_attempts_per_problem,
+            allow_upsolving,
             contest_id
         )
-        .execute(&state.pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| {
             if let sqlx::Error::Database(db_err) = &e {
@@ -574,47 +878,92 @@ async fn put_contest(
         })?;
     }
 
-    // Update description if provided
-    if let Some(description) = p.description {
-        let mut content = state
-            .read_contest_content(contest_id)
-            .await
-            .unwrap_or(ContestContent {
-                description: String::new(),
-            });
-        content.description = description;
-        state
-            .write_contest_content(contest_id, &content)
-            .await
-            .map_err(|e| Error::msg(format!("failed to write contest content: {:?}", e)))?;
-    }
-
     // Update problem list if provided
     if let Some(problem_ids) = p.problem_ids {
-        sqlx::query!(
-            "DELETE FROM contest_problems WHERE contest_id = $1",
+        // the order in the request is the display order
+        // problems that stay in the contest keep their aliases
+        let old_aliases: HashMap<i32, String> = sqlx::query!(
+            "DELETE FROM contest_problems WHERE contest_id = $1 RETURNING problem_id, alias",
             contest_id
         )
-        .execute(&state.pool)
+        .fetch_all(&mut *tx)
         .await
-        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?
+        .into_iter()
+        .filter_map(|row| row.alias.map(|alias| (row.problem_id, alias)))
+        .collect();
 
         for (i, problem_id) in problem_ids.iter().enumerate() {
             sqlx::query!(
                 r#"
-                INSERT INTO contest_problems (contest_id, problem_id, number)
-                VALUES ($1, $2, $3)
+                INSERT INTO contest_problems (contest_id, problem_id, number, alias)
+                VALUES ($1, $2, $3, $4)
                 "#,
                 contest_id,
                 problem_id,
-                i as i32
+                i as i32,
+                old_aliases.get(problem_id).map(String::as_str)
             )
-            .execute(&state.pool)
+            .execute(&mut *tx)
             .await
             .map_err(|e| Error::msg(format!("failed to add problem to contest: {}", e)))?;
         }
     }
 
+    // Update aliases if provided
+    if let Some(aliases) = p.problem_aliases {
+        for (problem_id, alias) in aliases {
+            let alias = alias.trim();
+            let updated = sqlx::query!(
+                r#"
+                UPDATE contest_problems SET alias = $1
+                WHERE contest_id = $2 AND problem_id = $3
+                "#,
+                (!alias.is_empty()).then_some(alias),
+                contest_id,
+                problem_id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+            if updated.rows_affected() == 0 {
+                return Err(Error::invalid_field(
+                    "problemAliases",
+                    format!("problem {} is not in the contest", problem_id),
+                ));
+            }
+        }
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    // Update description if provided
+    if let Some(description) = p.description {
+        let mut content = state
+            .read_contest_content(contest_id)
+            .await
+            .unwrap_or(ContestContent {
+                description: String::new(),
+            });
+        content.description = description;
+        state
+            .write_contest_content(contest_id, &content)
+            .await
+            .map_err(|e| Error::msg(format!("failed to write contest content: {:?}", e)))?;
+    }
+
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::PutContest,
+        Resource::Contest(contest_id),
+        None,
+    )
+    .await;
+
     Ok(Json(UpdateContestResponse {
         contest_id: contest_id,
     }))
@@ -680,84 +1029,114 @@ async fn delete_contest(
         .await
         .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    // Delete contest content file
-    // let _ = state.delete_contest_content(contest_id).await;
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::DeleteContest,
+        Resource::Contest(contest_id),
+        None,
+    )
+    .await;
+
+    // the row is gone already, so a leftover file is only worth a warning
+    if let Err(e) = state.delete_contest_content(contest_id).await {
+        tracing::warn!(
+            "Failed to delete content of contest {}: {:?}",
+            contest_id,
+            e
+        );
+    }
 
     Ok(Json(DeleteContestResponse {
         contest_id: contest_id,
     }))
 }
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct JoinContestRequest {
-    password: Option<String>,
+async fn contest_owner(state: &AppState, contest_id: i32) -> Result<i32> {
+    sqlx::query_scalar!("SELECT creator_id FROM contests WHERE id = $1", contest_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?
+        .ok_or_else(|| not_found("contest"))
 }
 
 #[utoipa::path(
-    post,
-    path = "/api/contests/{contest_id}/join",
+    get,
+    path = "/api/contests/{contest_id}/collaborators",
     params(
-        ("contest_id" = i32, Path, description = "Contest ID"),
+        ("contest_id" = i32, Path, description = "Contest ID")
     ),
-    request_body = JoinContestRequest,
     responses(
-        (status = 200, body = ()),
+        (status = 200, body = ListCollaboratorsResponse),
     ),
     security(("bearer_auth" = [])),
     tag = "contest"
 )]
-async fn join_contest(
+async fn list_contest_collaborators(
     state: State,
     claims: Extension<Claims>,
     Path(contest_id): Path<i32>,
-    Json(req): Json<JoinContestRequest>,
-) -> Result<()> {
-    let user_id = claims.sub;
+) -> Result<Json<ListCollaboratorsResponse>> {
+    contest_owner(&state, contest_id).await?;
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::PutContest,
+        Resource::Contest(contest_id),
+    )
+    .await?;
 
-    // Get contest info
-    let contest = sqlx::query!(
+    let collaborators = sqlx::query_as!(
+        CollaboratorItem,
         r#"
-        SELECT status as "status_: ContestStatus"
-        FROM contests
-        WHERE id = $1
+        SELECT u.id as user_id, u.username
+        FROM contest_collaborators c
+        JOIN users u ON u.id = c.user_id
+        WHERE c.contest_id = $1
+        ORDER BY c.created_at
         "#,
         contest_id
     )
-    .fetch_optional(&state.pool)
+    .fetch_all(&state.pool)
     .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
-
-    // Check if contest is hidden
-    if contest.status_ == ContestStatus::Hidden {
-        let user_role = role_of_claims(&state.pool, &claims).await?;
-        match user_role {
-            UserRole::Teacher | UserRole::Admin => {}
-            _ => bail!(@NOT_FOUND "contest not found"),
-        }
-    }
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    // Verify password
-    check_contest_password(&state.pool, contest_id, req.password).await?;
+    Ok(Json(ListCollaboratorsResponse { collaborators }))
+}
 
-    // Check if already joined
-    let already_joined = sqlx::query_scalar!(
-        "SELECT EXISTS(SELECT 1 FROM contest_participants WHERE contest_id = $1 AND user_id = $2)",
-        contest_id,
-        user_id
+#[utoipa::path(
+    put,
+    path = "/api/contests/{contest_id}/collaborators/{user_id}",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+        ("user_id" = i32, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, body = ()),
+    ),
+    security(("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn add_contest_collaborator(
+    state: State,
+    claims: Extension<Claims>,
+    Path((contest_id, user_id)): Path<(i32, i32)>,
+) -> Result<()> {
+    let owner_id = contest_owner(&state, contest_id).await?;
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::ManageCollaborators,
+        Resource::Contest(contest_id),
     )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .unwrap_or(false);
-
-    if already_joined {
-        bail!(@CONFLICT "already joined");
-    }
+    .await?;
+    check_collaborator(&state, owner_id, user_id).await?;
 
-    // Join contest
     sqlx::query!(
-        "INSERT INTO contest_participants (contest_id, user_id) VALUES ($1, $2)",
+        r#"
+        INSERT INTO contest_collaborators (contest_id, user_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
         contest_id,
         user_id
     )
@@ -765,248 +1144,980 @@ async fn join_contest(
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::ManageCollaborators,
+        Resource::Contest(contest_id),
+        Some(format!("add: {}", user_id)),
+    )
+    .await;
+
     Ok(())
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/contests/{contest_id}/is-joined",
+    delete,
+    path = "/api/contests/{contest_id}/collaborators/{user_id}",
     params(
         ("contest_id" = i32, Path, description = "Contest ID"),
+        ("user_id" = i32, Path, description = "User ID")
     ),
     responses(
-        (status = 200, body = bool),
+        (status = 200, body = ()),
     ),
     security(("bearer_auth" = [])),
     tag = "contest"
 )]
-async fn get_is_joined(
+async fn remove_contest_collaborator(
     state: State,
     claims: Extension<Claims>,
-    Path(contest_id): Path<i32>,
-) -> Result<Json<bool>> {
-    let user_id = claims.sub;
-
-    // Get contest info
-    let contest = sqlx::query!(
-        r#"
-        SELECT status as "status_: ContestStatus"
-        FROM contests
-        WHERE id = $1
-        "#,
-        contest_id
+    Path((contest_id, user_id)): Path<(i32, i32)>,
+) -> Result<()> {
+    contest_owner(&state, contest_id).await?;
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::ManageCollaborators,
+        Resource::Contest(contest_id),
     )
-    .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
-
-    // Check if contest is hidden
-    if contest.status_ == ContestStatus::Hidden {
-        let user_role = role_of_claims(&state.pool, &claims).await?;
-        match user_role {
-            UserRole::Teacher | UserRole::Admin => {}
-            _ => bail!(@NOT_FOUND "contest not found"),
-        }
-    }
+    .await?;
 
-    // Check if already joined
-    let is_joined = sqlx::query_scalar!(
-        "SELECT EXISTS(SELECT 1 FROM contest_participants WHERE contest_id = $1 AND user_id = $2)",
+    sqlx::query!(
+        r#"
+        DELETE FROM contest_collaborators WHERE contest_id = $1 AND user_id = $2
+        RETURNING user_id
+        "#,
         contest_id,
         user_id
     )
-    .fetch_one(&state.pool)
+    .fetch_optional(&state.pool)
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .unwrap_or(false);
+    .ok_or_else(|| not_found("collaborator"))?;
 
-    Ok(Json(is_joined))
-}
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::ManageCollaborators,
+        Resource::Contest(contest_id),
+        Some(format!("remove: {}", user_id)),
+    )
+    .await;
 
-pub struct ContestInfo {
-    id: i32,
-    begin_time: DateTime<Utc>,
-    end_time: DateTime<Utc>,
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct GetContestRankingResponse {
-    rankings: Vec<ContestRankingItem>,
+pub(crate) struct CloneContestRequest {
+    name: String,
+    begin_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
 }
 
+/// Copies the settings, description and problems of a contest, the
+/// registration window moves along with the begin time
 #[utoipa::path(
-    get,
-    path = "/api/contests/{contest_id}/ranking",
+    post,
+    path = "/api/contests/{contest_id}/clone",
     params(
-        ("contest_id" = i32, Path, description = "Contest ID"),
-        GetContestQuery
+        ("contest_id" = i32, Path, description = "Contest ID")
     ),
+    request_body = CloneContestRequest,
     responses(
-        (status = 200, body = GetContestRankingResponse),
+        (status = 200, body = CreateContestResponse),
     ),
     security(("bearer_auth" = [])),
     tag = "contest"
 )]
-async fn get_contest_ranking(
+async fn clone_contest(
     state: State,
     claims: Extension<Claims>,
     Path(contest_id): Path<i32>,
-    Query(query): Query<GetContestQuery>,
-) -> Result<Json<GetContestRankingResponse>> {
-    // Get contest info
-    let contest = sqlx::query!(
+    Json(p): Json<CloneContestRequest>,
+) -> Result<Json<CreateContestResponse>> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::CreateContest,
+        Resource::Global,
+    )
+    .await?;
+    contest_owner(&state, contest_id).await?;
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::PutContest,
+        Resource::Contest(contest_id),
+    )
+    .await?;
+
+    let source = sqlx::query!(
         r#"
-        SELECT id, begin_time, end_time, status as "status_: ContestStatus"
+        SELECT begin_time, password, type as "type_: ContestType", registration_begin,
+               registration_end, max_attempts_per_problem, allow_upsolving
         FROM contests
         WHERE id = $1
         "#,
         contest_id
     )
-    .fetch_optional(&state.pool)
+    .fetch_one(&state.pool)
     .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    let user_role = role_of_claims(&state.pool, &claims).await?;
+    let shift = p.begin_time - source.begin_time;
+    let registration_begin = source.registration_begin.map(|t| t + shift);
+    let registration_end = source.registration_end.map(|t| t + shift);
 
-    // Check hidden status
-    if contest.status_ == ContestStatus::Hidden {
-        match user_role {
-            UserRole::Teacher | UserRole::Admin => {}
-            _ => bail!(@NOT_FOUND "contest not found"),
-        }
+    let mut errors = ValidationError::new();
+    if p.name.is_empty() {
+        errors.add("name", "name is required");
     }
+    if p.begin_time >= p.end_time {
+        errors.add("endTime", "begin time must be before end time");
+    }
+    check_registration_window(
+        &mut errors,
+        registration_begin,
+        registration_end,
+        p.end_time,
+    );
+    errors.check()?;
 
-    // Verify password
-    check_contest_password(&state.pool, contest_id, query.password).await?;
-
-    // Check if non-admin/teacher users have joined the contest
-    match user_role {
-        UserRole::Admin | UserRole::Teacher => {}
-        _ => {
-            let is_participant = sqlx::query!(
-                r#"
-                SELECT EXISTS(
-                    SELECT 1 FROM contest_participants
-                    WHERE contest_id = $1 AND user_id = $2
-                ) as "exists!"
-                "#,
-                contest_id,
-                claims.sub
-            )
-            .fetch_one(&state.pool)
-            .await
-            .map_err(|e| Error::msg(format!("database error: {}", e)))?
-            .exists;
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-            if !is_participant {
-                bail!(@FORBIDDEN "you must join the contest to view rankings");
+    let new_contest_id: i32 = sqlx::query_scalar!(
+        r#"
+        INSERT INTO contests
+            (creator_id, name, begin_time, end_time, password, type, status,
+             registration_begin, registration_end, max_attempts_per_problem, allow_upsolving)
+        VALUES ($1, $2, $3, $4, $5, $6, 'active', $7, $8, $9, $10)
+        RETURNING id
+        "#,
+        claims.sub,
+        p.name,
+        p.begin_time,
+        p.end_time,
+        source.password,
+        source.type_ as ContestType,
+        registration_begin,
+        registration_end,
+        source.max_attempts_per_problem,
+        source.allow_upsolving
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        if let sqlx::Error::Database(db_err) = &e {
+            if db_err.is_unique_violation() {
+                return Error::msg("contest name already exists")
+                    .status_code(StatusCode::BAD_REQUEST);
             }
         }
-    }
+        Error::msg(format!("database error: {}", e))
+    })?;
 
-    let contest_info = ContestInfo {
-        id: contest.id,
-        begin_time: contest.begin_time,
-        end_time: contest.end_time,
-    };
+    sqlx::query!(
+        r#"
+        INSERT INTO contest_problems (contest_id, problem_id, number, alias)
+        SELECT $1, problem_id, number, alias FROM contest_problems WHERE contest_id = $2
+        "#,
+        new_contest_id,
+        contest_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| Error::msg(format!("failed to add problem to contest: {}", e)))?;
 
-    let rankings = ranking_cache::get_contest_ranking_cached(&state, &contest_info)
+    tx.commit()
         .await
-        .map_err(|e| {
-            tracing::error!("Failed to get contest ranking: {:?}", e);
-            Error::msg("Failed to get contest ranking")
-                .status_code(StatusCode::INTERNAL_SERVER_ERROR)
-        })?;
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    Ok(Json(GetContestRankingResponse { rankings }))
-}
+    let content = state
+        .read_contest_content(contest_id)
+        .await
+        .unwrap_or(ContestContent {
+            description: String::new(),
+        });
+    state
+        .write_contest_content(new_contest_id, &content)
+        .await
+        .map_err(|e| Error::msg(format!("failed to write contest content: {:?}", e)))?;
 
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct OverallRankingItem {
-    user_id: i32,
-    username: String,
-    contest_count: i32, // joined count
-    total_solved: i32,
-    total_penalty: i64,
+    Ok(Json(CreateContestResponse {
+        contest_id: new_contest_id,
+    }))
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct GetOverallRankingResponse {
-    rankings: Vec<OverallRankingItem>,
-}
-
-#[derive(Deserialize, ToSchema, IntoParams)]
-#[into_params(style = Form, parameter_in = Query)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct GetOverallRankingQuery {
-    contest_ids: Vec<i32>,
+pub(crate) struct JoinContestRequest {
+    password: Option<String>,
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/contests/overall-ranking",
-    params(GetOverallRankingQuery),
+    post,
+    path = "/api/contests/{contest_id}/join",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+    ),
+    request_body = JoinContestRequest,
     responses(
-        (status = 200, body = GetOverallRankingResponse),
+        (status = 200, body = ()),
     ),
     security(("bearer_auth" = [])),
     tag = "contest"
 )]
-async fn get_overall_ranking(
+async fn join_contest(
     state: State,
     claims: Extension<Claims>,
-    ExtraQuery(query): ExtraQuery<GetOverallRankingQuery>,
-) -> Result<Json<GetOverallRankingResponse>> {
-    check_permission(
+    Path(contest_id): Path<i32>,
+    Json(req): Json<JoinContestRequest>,
+) -> Result<()> {
+    let user_id = claims.sub;
+
+    // Get contest info
+    let contest = sqlx::query!(
+        r#"
+        SELECT status as "status_: ContestStatus", registration_begin, registration_end
+        FROM contests
+        WHERE id = $1
+        "#,
+        contest_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
+
+    check_visible(
         &state.pool,
         &claims,
-        Action::ViewOverallRanking,
-        Resource::Global,
+        contest.status_ == ContestStatus::Hidden,
+        "contest",
     )
     .await?;
 
-    // Parse contest IDs
-    let contest_ids: Vec<i32> = query.contest_ids;
-
-    if contest_ids.is_empty() {
-        bail!(@BAD_REQUEST "no valid contest IDs provided");
+    let now = Utc::now();
+    if contest.registration_begin.is_some_and(|begin| now < begin) {
+        bail!(@FORBIDDEN "registration is not open yet");
+    }
+    if contest.registration_end.is_some_and(|end| now > end) {
+        bail!(@FORBIDDEN "registration is closed");
     }
 
-    // Get all contests info
-    let contests = sqlx::query!(
-        r#"
-        SELECT id, begin_time, end_time
-        FROM contests
-        WHERE id = ANY($1)
-        "#,
-        &contest_ids
+    // Verify password
+    check_contest_password(&state.pool, contest_id, req.password).await?;
+
+    // Check if already joined
+    let already_joined = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM contest_participants WHERE contest_id = $1 AND user_id = $2)",
+        contest_id,
+        user_id
     )
-    .fetch_all(&state.pool)
+    .fetch_one(&state.pool)
     .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .unwrap_or(false);
 
-    if contests.is_empty() {
-        bail!(@NOT_FOUND "no contests found");
+    if already_joined {
+        bail!(@CONFLICT "already joined");
     }
 
-    // Calculate ranking for each contest and aggregate
-    let mut user_stats: std::collections::HashMap<i32, OverallRankingItem> =
-        std::collections::HashMap::new();
-
-    for contest in contests {
-        let contest_info = ContestInfo {
-            id: contest.id,
-            begin_time: contest.begin_time,
-            end_time: contest.end_time,
-        };
+    // Join contest
+    sqlx::query!(
+        "INSERT INTO contest_participants (contest_id, user_id) VALUES ($1, $2)",
+        contest_id,
+        user_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/contests/{contest_id}/is-joined",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+    ),
+    responses(
+        (status = 200, body = bool),
+    ),
+    security(("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn get_is_joined(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+) -> Result<Json<bool>> {
+    let user_id = claims.sub;
+
+    // Get contest info
+    let contest = sqlx::query!(
+        r#"
+        SELECT status as "status_: ContestStatus"
+        FROM contests
+        WHERE id = $1
+        "#,
+        contest_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
+
+    check_visible(
+        &state.pool,
+        &claims,
+        contest.status_ == ContestStatus::Hidden,
+        "contest",
+    )
+    .await?;
+
+    // Check if already joined
+    let is_joined = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM contest_participants WHERE contest_id = $1 AND user_id = $2)",
+        contest_id,
+        user_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .unwrap_or(false);
+
+    Ok(Json(is_joined))
+}
+
+pub struct ContestInfo {
+    id: i32,
+    begin_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetContestRankingResponse {
+    rankings: Vec<ContestRankingItem>,
+    total: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetContestRankingQuery {
+    password: Option<String>,
+    /// the whole ranking is returned when neither `page` nor `pageSize` is given
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+/// Checks that the user may view the ranking of the contest
+async fn check_ranking_access(
+    state: &AppState,
+    claims: &Claims,
+    contest_id: i32,
+    password: Option<String>,
+) -> Result<ContestInfo> {
+    // Get contest info
+    let contest = sqlx::query!(
+        r#"
+        SELECT id, begin_time, end_time, password IS NOT NULL as "has_password!",
+               type as "type_: ContestType", status as "status_: ContestStatus"
+        FROM contests
+        WHERE id = $1
+        "#,
+        contest_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
+
+    let user_role = role_of_claims(&state.pool, claims).await?;
+
+    check_visible(
+        &state.pool,
+        claims,
+        contest.status_ == ContestStatus::Hidden,
+        "contest",
+    )
+    .await?;
+
+    let info = ContestInfo {
+        id: contest.id,
+        begin_time: contest.begin_time,
+        end_time: contest.end_time,
+    };
+
+    // open contests can be spectated by anyone, signed in or not
+    if contest.type_ == ContestType::Public && !contest.has_password {
+        return Ok(info);
+    }
+    if user_role == UserRole::Guest {
+        bail!(@FORBIDDEN "sign in to view the ranking of this contest");
+    }
+
+    // Verify password
+    check_contest_password(&state.pool, contest_id, password).await?;
+
+    // Check if non-admin/teacher users have joined the contest
+    match user_role {
+        UserRole::Admin | UserRole::Teacher => {}
+        _ => {
+            let is_participant = sqlx::query!(
+                r#"
+                SELECT EXISTS(
+                    SELECT 1 FROM contest_participants
+                    WHERE contest_id = $1 AND user_id = $2
+                ) as "exists!"
+                "#,
+                contest_id,
+                claims.sub
+            )
+            .fetch_one(&state.pool)
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))?
+            .exists;
+
+            if !is_participant {
+                bail!(@FORBIDDEN "you must join the contest to view rankings");
+            }
+        }
+    }
+
+    Ok(info)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/contests/{contest_id}/ranking",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+        GetContestRankingQuery
+    ),
+    responses(
+        (status = 200, body = GetContestRankingResponse),
+    ),
+    security((), ("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn get_contest_ranking(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+    Query(query): Query<GetContestRankingQuery>,
+) -> Result<Json<GetContestRankingResponse>> {
+    let contest_info = check_ranking_access(&state, &claims, contest_id, query.password).await?;
+
+    let (offset, limit) = if query.page.is_none() && query.page_size.is_none() {
+        (0, None)
+    } else {
+        let page = query.page.unwrap_or(1).clamp(1, MAX_PAGE);
+        let page_size = query.page_size.unwrap_or(50).clamp(1, 200);
+        (((page - 1) * page_size) as usize, Some(page_size as usize))
+    };
+
+    let (rankings, total) =
+        ranking_cache::get_contest_ranking_page(&state, &contest_info, offset, limit)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to get contest ranking: {:?}", e);
+                Error::msg("Failed to get contest ranking")
+                    .status_code(StatusCode::INTERNAL_SERVER_ERROR)
+            })?;
+
+    Ok(Json(GetContestRankingResponse {
+        rankings,
+        total: total as i64,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(crate) struct StreamContestRankingQuery {
+    password: Option<String>,
+}
+
+/// Follow the ranking as submissions are judged. An `update` event carries the
+/// new row of a user as a `RankingUpdate`, a `reset` event means the whole
+/// ranking has to be fetched again
+#[utoipa::path(
+    get,
+    path = "/api/contests/{contest_id}/ranking/stream",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+        StreamContestRankingQuery
+    ),
+    responses(
+        (status = 200, body = String, content_type = "text/event-stream"),
+    ),
+    security((), ("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn stream_contest_ranking(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+    Query(query): Query<StreamContestRankingQuery>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, axum::Error>>>> {
+    check_ranking_access(&state, &claims, contest_id, query.password).await?;
+
+    let rx = state.subscribe_ranking(contest_id);
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        let event = match rx.recv().await {
+            Ok(RankingEvent::Update(update)) => Event::default().event("update").json_data(update),
+            // missed updates can't be replayed, start over instead
+            Ok(RankingEvent::Reset) | Err(RecvError::Lagged(_)) => {
+                Ok(Event::default().event("reset").data("{}"))
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((event, rx))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ExportContestRankingQuery {
+    password: Option<String>,
+    /// only `csv` is supported for now
+    format: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/contests/{contest_id}/ranking/export",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+        ExportContestRankingQuery
+    ),
+    responses(
+        (status = 200, body = String, content_type = "text/csv"),
+    ),
+    security(("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn export_contest_ranking(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+    Query(query): Query<ExportContestRankingQuery>,
+) -> Result<Response> {
+    match query.format.as_deref() {
+        None | Some("csv") => {}
+        Some(format) => bail!(@BAD_REQUEST "unsupported export format: {}", format),
+    }
+
+    let contest_info = check_ranking_access(&state, &claims, contest_id, query.password).await?;
+
+    let rankings = ranking_cache::get_contest_ranking_cached(&state, &contest_info)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to get contest ranking: {:?}", e);
+            Error::msg("Failed to get contest ranking")
+                .status_code(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "text/csv; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"contest_{}_ranking.csv\"",
+                contest_id
+            ),
+        )
+        .body(ranking_to_csv(&rankings).into())?)
+}
+
+/// Renders rankings as CSV, users with equal solved count and penalty share a
+/// rank
+fn ranking_to_csv(rankings: &[ContestRankingItem]) -> String {
+    fn field(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    let mut columns = vec![
+        "rank".to_string(),
+        "username".to_string(),
+        "solved".to_string(),
+        "penalty".to_string(),
+    ];
+    if let Some(first) = rankings.first() {
+        for pr in &first.problem_results {
+            columns.push(format!("problem {} attempts", pr.problem_id));
+            columns.push(format!("problem {} accepted at", pr.problem_id));
+        }
+    }
+
+    let mut csv = columns.join(",");
+    csv.push('\n');
+
+    let mut rank = 0;
+    for (index, item) in rankings.iter().enumerate() {
+        let tied = index > 0 && {
+            let prev = &rankings[index - 1];
+            prev.solved_count == item.solved_count && prev.total_penalty == item.total_penalty
+        };
+        if !tied {
+            rank = index + 1;
+        }
+
+        let mut row = vec![
+            rank.to_string(),
+            field(&item.username),
+            item.solved_count.to_string(),
+            item.total_penalty.to_string(),
+        ];
+        for pr in &item.problem_results {
+            row.push(pr.attempts.to_string());
+            row.push(
+                pr.accepted_time
+                    .map(|time| time.to_rfc3339())
+                    .unwrap_or_default(),
+            );
+        }
+
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RebuildContestRankingResponse {
+    /// mismatches found between the cache and the database before rebuilding
+    discrepancies: Vec<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/contests/{contest_id}/ranking/rebuild",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+    ),
+    responses(
+        (status = 200, body = RebuildContestRankingResponse),
+    ),
+    security(("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn rebuild_contest_ranking(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+) -> Result<Json<RebuildContestRankingResponse>> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::RebuildRanking,
+        Resource::Global,
+    )
+    .await?;
+
+    let contest_info = sqlx::query_as!(
+        ContestInfo,
+        "SELECT id, begin_time, end_time FROM contests WHERE id = $1",
+        contest_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
+
+    let discrepancies = ranking_cache::verify_ranking_cache(&state, &contest_info).await?;
+    ranking_cache::rebuild_ranking_cache(&state, &contest_info).await?;
+
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::RebuildRanking,
+        Resource::Contest(contest_id),
+        Some(format!("discrepancies: {}", discrepancies.len())),
+    )
+    .await;
+
+    tracing::info!(
+        "Rebuilt ranking cache for contest {} ({} discrepancies)",
+        contest_id,
+        discrepancies.len()
+    );
+
+    Ok(Json(RebuildContestRankingResponse { discrepancies }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StartVirtualContestResponse {
+    started_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/contests/{contest_id}/virtual",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+    ),
+    request_body = JoinContestRequest,
+    responses(
+        (status = 200, body = StartVirtualContestResponse),
+    ),
+    security(("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn start_virtual_contest(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+    Json(req): Json<JoinContestRequest>,
+) -> Result<Json<StartVirtualContestResponse>> {
+    let contest = sqlx::query!(
+        r#"
+        SELECT begin_time, end_time, status as "status_: ContestStatus"
+        FROM contests
+        WHERE id = $1
+        "#,
+        contest_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
+
+    check_visible(
+        &state.pool,
+        &claims,
+        contest.status_ == ContestStatus::Hidden,
+        "contest",
+    )
+    .await?;
+
+    if contest.end_time > Utc::now() {
+        bail!(@BAD_REQUEST "virtual participation is only available after the contest ends");
+    }
+
+    check_contest_password(&state.pool, contest_id, req.password).await?;
+
+    let started_at = sqlx::query_scalar!(
+        r#"
+        INSERT INTO contest_virtual_participants (contest_id, user_id)
+        VALUES ($1, $2)
+        RETURNING started_at
+        "#,
+        contest_id,
+        claims.sub
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| {
+        if let sqlx::Error::Database(db_err) = &e {
+            if db_err.is_unique_violation() {
+                return Error::msg("virtual participation already started")
+                    .status_code(StatusCode::CONFLICT);
+            }
+        }
+        Error::msg(format!("database error: {}", e))
+    })?;
+
+    Ok(Json(StartVirtualContestResponse {
+        started_at,
+        ends_at: started_at + (contest.end_time - contest.begin_time),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/contests/{contest_id}/virtual-ranking",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID"),
+        GetContestQuery
+    ),
+    responses(
+        (status = 200, body = GetContestRankingResponse),
+    ),
+    security(("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn get_virtual_ranking(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+    Query(query): Query<GetContestQuery>,
+) -> Result<Json<GetContestRankingResponse>> {
+    let contest = sqlx::query!(
+        r#"
+        SELECT id, begin_time, end_time, status as "status_: ContestStatus"
+        FROM contests
+        WHERE id = $1
+        "#,
+        contest_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
+
+    check_visible(
+        &state.pool,
+        &claims,
+        contest.status_ == ContestStatus::Hidden,
+        "contest",
+    )
+    .await?;
+
+    check_contest_password(&state.pool, contest_id, query.password).await?;
+
+    let contest_info = ContestInfo {
+        id: contest.id,
+        begin_time: contest.begin_time,
+        end_time: contest.end_time,
+    };
+
+    let rankings = ranking_cache::calculate_virtual_ranking_from_db(
+        &state.pool,
+        &contest_info,
+        state.config.penalize_compile_errors,
+    )
+    .await?;
+    let total = rankings.len() as i64;
+
+    Ok(Json(GetContestRankingResponse { rankings, total }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OverallRankingItem {
+    pub(crate) user_id: i32,
+    pub(crate) username: String,
+    pub(crate) contest_count: i32, // joined count
+    pub(crate) total_solved: i32,
+    pub(crate) total_penalty: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetOverallRankingResponse {
+    rankings: Vec<OverallRankingItem>,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetOverallRankingQuery {
+    contest_ids: Vec<i32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/contests/overall-ranking",
+    params(GetOverallRankingQuery),
+    responses(
+        (status = 200, body = GetOverallRankingResponse),
+    ),
+    security((), ("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn get_overall_ranking(
+    state: State,
+    claims: Extension<Claims>,
+    ExtraQuery(query): ExtraQuery<GetOverallRankingQuery>,
+) -> Result<Json<GetOverallRankingResponse>> {
+    // Parse contest IDs
+    let contest_ids: Vec<i32> = query.contest_ids;
+
+    if contest_ids.is_empty() {
+        bail!(@BAD_REQUEST "no valid contest IDs provided");
+    }
+
+    // anyone may sum up open contests, the others need the permission
+    if !are_open_contests(&state.pool, &contest_ids).await? {
+        check_permission(
+            &state.pool,
+            &claims,
+            Action::ViewOverallRanking,
+            Resource::Global,
+        )
+        .await?;
+    }
+
+    let rankings = aggregate_contest_rankings(&state, &contest_ids).await?;
+
+    Ok(Json(GetOverallRankingResponse { rankings }))
+}
+
+/// Whether every contest is public, passwordless and not hidden
+async fn are_open_contests(pool: &PgPool, contest_ids: &[i32]) -> Result<bool> {
+    let distinct: std::collections::HashSet<_> = contest_ids.iter().collect();
+    let open = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!" FROM contests
+        WHERE id = ANY($1) AND type = 'public' AND password IS NULL AND status = 'active'
+        "#,
+        contest_ids
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    Ok(open as usize == distinct.len())
+}
+
+/// Sums up the rankings of several contests per user, sorted like a contest
+/// ranking
+pub(crate) async fn aggregate_contest_rankings(
+    state: &Arc<AppState>,
+    contest_ids: &[i32],
+) -> Result<Vec<OverallRankingItem>> {
+    // Get all contests info
+    let contests = sqlx::query!(
+        r#"
+        SELECT id, begin_time, end_time
+        FROM contests
+        WHERE id = ANY($1)
+        "#,
+        contest_ids
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if contests.is_empty() {
+        bail!(@NOT_FOUND "no contests found");
+    }
+
+    // Calculate ranking for each contest and aggregate
+    let mut user_stats: std::collections::HashMap<i32, OverallRankingItem> =
+        std::collections::HashMap::new();
+
+    for contest in contests {
+        let contest_info = ContestInfo {
+            id: contest.id,
+            begin_time: contest.begin_time,
+            end_time: contest.end_time,
+        };
 
         // let rankings = calculate_contest_ranking(&state.pool, &contest_info).await?;
-        let rankings = ranking_cache::get_contest_ranking_cached(&state, &contest_info)
+        let rankings = ranking_cache::get_contest_ranking_cached(state, &contest_info)
             .await
             .map_err(|e| {
                 tracing::error!("Failed to get contest ranking: {:?}", e);
@@ -1027,90 +2138,1395 @@ async fn get_overall_ranking(
                     total_penalty: 0,
                 });
 
-            entry.total_solved += ranking.solved_count;
-            entry.total_penalty += ranking.total_penalty;
-        }
+            entry.total_solved += ranking.solved_count;
+            entry.total_penalty += ranking.total_penalty;
+        }
+    }
+
+    // Count actual participation and look up current usernames in bulk
+    let user_ids: Vec<i32> = user_stats.keys().copied().collect();
+
+    let participation: std::collections::HashMap<i32, i64> = sqlx::query!(
+        r#"
+        SELECT user_id, COUNT(DISTINCT contest_id) as "count!"
+        FROM contest_participants
+        WHERE contest_id = ANY($1) AND user_id = ANY($2)
+        GROUP BY user_id
+        "#,
+        contest_ids,
+        &user_ids
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .into_iter()
+    .map(|row| (row.user_id, row.count))
+    .collect();
+
+    let usernames: std::collections::HashMap<i32, String> = sqlx::query!(
+        "SELECT id, username FROM users WHERE id = ANY($1)",
+        &user_ids
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .into_iter()
+    .map(|row| (row.id, row.username))
+    .collect();
+
+    for user_entry in user_stats.values_mut() {
+        user_entry.contest_count =
+            participation.get(&user_entry.user_id).copied().unwrap_or(0) as i32;
+        if let Some(username) = usernames.get(&user_entry.user_id) {
+            user_entry.username = username.clone();
+        }
+    }
+
+    let mut overall_rankings: Vec<OverallRankingItem> = user_stats.into_values().collect();
+    sort_overall_rankings(&mut overall_rankings);
+
+    Ok(overall_rankings)
+}
+
+/// Sort by total_solved (desc), then by total_penalty (asc), then by contest_count (desc)
+pub(crate) fn sort_overall_rankings(rankings: &mut [OverallRankingItem]) {
+    rankings.sort_by(|a, b| {
+        b.total_solved
+            .cmp(&a.total_solved)
+            .then_with(|| a.total_penalty.cmp(&b.total_penalty))
+            .then_with(|| b.contest_count.cmp(&a.contest_count))
+    });
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreateAnnouncementRequest {
+    content: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreateAnnouncementResponse {
+    announcement_id: i32,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/contests/{contest_id}/announcements",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID")
+    ),
+    request_body = CreateAnnouncementRequest,
+    responses(
+        (status = 200, body = CreateAnnouncementResponse),
+    ),
+    security(("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn create_announcement(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+    Json(p): Json<CreateAnnouncementRequest>,
+) -> Result<Json<CreateAnnouncementResponse>> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::PutContest,
+        Resource::Contest(contest_id),
+    )
+    .await?;
+
+    if p.content.trim().is_empty() {
+        return Err(Error::invalid_field("content", "content is required"));
     }
 
-    // Count actual participation for each user
-    for user_entry in user_stats.values_mut() {
-        let user_id: i32 = user_entry.user_id;
-
-        let participated_count = sqlx::query_scalar!(
-            "SELECT COUNT(DISTINCT contest_id) FROM contest_participants WHERE user_id = $1 AND contest_id = ANY($2)",
-            user_id,
-            &contest_ids
-        )
-        .fetch_one(&state.pool)
-        .await
-        .map_err(|e| Error::msg(format!("database error: {}", e)))?
-        .unwrap_or(0);
-
-        user_entry.contest_count = participated_count as i32;
-    }
+    let announcement_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO contest_announcements (contest_id, author_id, content)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+        contest_id,
+        claims.sub,
+        p.content
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| {
+        if let sqlx::Error::Database(db_err) = &e {
+            if db_err.is_foreign_key_violation() {
+                return Error::msg("contest not found").status_code(StatusCode::NOT_FOUND);
+            }
+        }
+        Error::msg(format!("database error: {}", e))
+    })?;
 
-    let mut overall_rankings: Vec<OverallRankingItem> = user_stats.into_values().collect();
+    Ok(Json(CreateAnnouncementResponse { announcement_id }))
+}
 
-    // Sort by total_solved (desc), then by total_penalty (asc), then by contest_count (desc)
-    overall_rankings.sort_by(|a, b| {
-        b.total_solved
-            .cmp(&a.total_solved)
-            .then_with(|| a.total_penalty.cmp(&b.total_penalty))
-            .then_with(|| b.contest_count.cmp(&a.contest_count))
-    });
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AnnouncementItem {
+    announcement_id: i32,
+    content: String,
+    created_at: DateTime<Utc>,
+}
 
-    Ok(Json(GetOverallRankingResponse {
-        rankings: overall_rankings,
-    }))
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ListAnnouncementsResponse {
+    announcements: Vec<AnnouncementItem>,
 }
 
-pub async fn verify_contest_problem_access(
-    pool: &PgPool,
-    contest_id: i32,
-    problem_id: i32,
-    user_id: i32,
-) -> Result<()> {
-    // verify contest exists and is in valid time range
-    sqlx::query!(
+#[utoipa::path(
+    get,
+    path = "/api/contests/{contest_id}/announcements",
+    params(
+        ("contest_id" = i32, Path, description = "Contest ID")
+    ),
+    responses(
+        (status = 200, body = ListAnnouncementsResponse),
+    ),
+    security(("bearer_auth" = [])),
+    tag = "contest"
+)]
+async fn list_announcements(
+    state: State,
+    claims: Extension<Claims>,
+    Path(contest_id): Path<i32>,
+) -> Result<Json<ListAnnouncementsResponse>> {
+    let contest = sqlx::query!(
         r#"
-        SELECT id FROM contests 
-        WHERE id = $1 
-        AND status = 'active'
+        SELECT status as "status: ContestStatus" FROM contests WHERE id = $1
         "#,
         contest_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&state.pool)
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .ok_or_else(|| {
-        Error::msg("contest not in valid time range").status_code(StatusCode::FORBIDDEN)
-    })?;
-    // verify user participates in this contest
-    sqlx::query!(
+    .ok_or_else(|| Error::msg("contest not found").status_code(StatusCode::NOT_FOUND))?;
+
+    let user_role = role_of_claims(&state.pool, &claims).await?;
+    if !matches!(user_role, UserRole::Teacher | UserRole::Admin) {
+        if contest.status == ContestStatus::Hidden {
+            bail!(@NOT_FOUND "contest not found");
+        }
+
+        let is_participant = sqlx::query_scalar!(
+            r#"
+            SELECT (
+                EXISTS(
+                    SELECT 1 FROM contest_participants
+                    WHERE contest_id = $1 AND user_id = $2
+                ) OR EXISTS(
+                    SELECT 1 FROM contest_virtual_participants
+                    WHERE contest_id = $1 AND user_id = $2
+                )
+            ) as "exists!"
+            "#,
+            contest_id,
+            claims.sub
+        )
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+        if !is_participant {
+            bail!(@FORBIDDEN "user not participating in this contest");
+        }
+    }
+
+    let announcements = sqlx::query!(
         r#"
-        SELECT user_id FROM contest_participants WHERE contest_id = $1 AND user_id = $2
+        SELECT id, content, created_at FROM contest_announcements
+        WHERE contest_id = $1
+        ORDER BY created_at DESC, id DESC
         "#,
-        contest_id,
-        user_id
+        contest_id
     )
-    .fetch_optional(pool)
+    .fetch_all(&state.pool)
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .ok_or_else(|| {
-        Error::msg("user not participating in this contest").status_code(StatusCode::FORBIDDEN)
-    })?;
-    // verify problem is in this contest
-    sqlx::query!(
+    .into_iter()
+    .map(|row| AnnouncementItem {
+        announcement_id: row.id,
+        content: row.content,
+        created_at: row.created_at,
+    })
+    .collect();
+
+    Ok(Json(ListAnnouncementsResponse { announcements }))
+}
+
+/// Whether the problem may be read through the contest, even if the problem
+/// itself is hidden. Teachers and admins always may; anyone else has to take
+/// part in the contest while it runs, either officially within its time window
+/// (or past its end when upsolving is allowed) or virtually within their own.
+/// Every other case is reported as a missing problem, so that probing tells
+/// nothing about which problems a contest holds
+pub async fn verify_contest_problem_access(
+    pool: &PgPool,
+    claims: &Claims,
+    contest_id: i32,
+    problem_id: i32,
+) -> Result<()> {
+    if matches!(
+        role_of_claims(pool, claims).await?,
+        UserRole::Teacher | UserRole::Admin
+    ) {
+        return Ok(());
+    }
+
+    let allowed = sqlx::query_scalar!(
         r#"
-        SELECT contest_id FROM contest_problems 
-        WHERE contest_id = $1 AND problem_id = $2
+        SELECT EXISTS(
+            SELECT 1 FROM contests c
+            JOIN contest_problems cp ON cp.contest_id = c.id AND cp.problem_id = $2
+            WHERE c.id = $1 AND c.status = 'active'
+              AND (
+                  (NOW() >= c.begin_time AND (NOW() <= c.end_time OR c.allow_upsolving)
+                   AND EXISTS (
+                      SELECT 1 FROM contest_participants p
+                      WHERE p.contest_id = c.id AND p.user_id = $3
+                  ))
+                  OR EXISTS (
+                      SELECT 1 FROM contest_virtual_participants v
+                      WHERE v.contest_id = c.id AND v.user_id = $3
+                        AND NOW() BETWEEN v.started_at
+                                      AND v.started_at + (c.end_time - c.begin_time)
+                  )
+              )
+        ) as "exists!"
         "#,
         contest_id,
-        problem_id
+        problem_id,
+        claims.sub
     )
-    .fetch_optional(pool)
+    .fetch_one(pool)
     .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .ok_or_else(|| Error::msg("problem not in this contest").status_code(StatusCode::NOT_FOUND))?;
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if !allowed {
+        return Err(not_found("problem"));
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode, header};
+    use koioj_common::judge::{Language, SubmissionResult, TestCaseJudgeResult};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use crate::test_util::{TestApp, judge_result, test_result};
+
+    async fn join(app: &TestApp, contest_id: i32, user_id: i32) {
+        sqlx::query!(
+            "INSERT INTO contest_participants (contest_id, user_id) VALUES ($1, $2)",
+            contest_id,
+            user_id
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    }
+
+    /// a student who took part in an ended contest and then starts a virtual
+    /// participation in it
+    async fn virtual_participant(app: &TestApp) -> (i32, String, i32, i32) {
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -120, -60).await;
+        join(app, contest_id, student_id).await;
+
+        let response = app
+            .post(
+                &format!("/api/contests/{}/virtual", contest_id),
+                &student,
+                json!({}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        (student_id, student, contest_id, problem_id)
+    }
+
+    async fn submit_virtual(
+        app: &TestApp,
+        token: &str,
+        contest_id: i32,
+        problem_id: i32,
+    ) -> crate::test_util::Response {
+        app.post(
+            &format!("/api/problems/{}/submissions", problem_id),
+            token,
+            json!({
+                "code": "int main() { return 0; }",
+                "lang": "cpp",
+                "contestId": contest_id,
+                "virtual": true,
+            }),
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn virtual_submission_stays_out_of_official_ranking(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (student_id, student, contest_id, problem_id) = virtual_participant(&app).await;
+
+        let response = submit_virtual(&app, &student, contest_id, problem_id).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let submission_id = response.body["submissionId"].as_i64().unwrap() as i32;
+        app.judged(submission_id).await;
+
+        let official = app
+            .get(&format!("/api/contests/{}/ranking", contest_id), &student)
+            .await;
+        assert_eq!(official.status, StatusCode::OK, "{}", official.body);
+        assert_eq!(official.body["total"], 0, "{}", official.body);
+
+        let virtual_ranking = app
+            .get(
+                &format!("/api/contests/{}/virtual-ranking", contest_id),
+                &student,
+            )
+            .await;
+        assert_eq!(virtual_ranking.status, StatusCode::OK);
+        let rankings = virtual_ranking.body["rankings"].as_array().unwrap();
+        assert_eq!(rankings.len(), 1);
+        assert_eq!(rankings[0]["userId"], student_id);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn virtual_submission_to_hidden_contest_is_refused(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, student, contest_id, problem_id) = virtual_participant(&app).await;
+        sqlx::query!(
+            "UPDATE contests SET status = 'inactive' WHERE id = $1",
+            contest_id
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+        let response = submit_virtual(&app, &student, contest_id, problem_id).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND, "{}", response.body);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn announcements_reach_participants_newest_first(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let (_, outsider) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -10, 60).await;
+        join(&app, contest_id, student_id).await;
+        let uri = format!("/api/contests/{}/announcements", contest_id);
+
+        for content in ["first", "second"] {
+            let response = app
+                .post(&uri, &teacher, json!({ "content": content }))
+                .await;
+            assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        }
+        let response = app.post(&uri, &student, json!({ "content": "mine" })).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let contents: Vec<_> = response.body["announcements"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(contents, ["second", "first"]);
+
+        assert_eq!(app.get(&uri, &outsider).await.status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn deleting_a_contest_removes_its_file(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], 60, 120).await;
+        let path = app.state.get_contest_path(contest_id);
+        assert!(path.exists());
+
+        let uri = format!("/api/contests/{}", contest_id);
+        let response = app.send(Method::DELETE, &uri, Some(&teacher), None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert!(!path.exists());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn deleting_a_contest_without_its_file_succeeds(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], 60, 120).await;
+        std::fs::remove_file(app.state.get_contest_path(contest_id)).unwrap();
+
+        let uri = format!("/api/contests/{}", contest_id);
+        let response = app.send(Method::DELETE, &uri, Some(&teacher), None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        app.state.delete_contest_content(contest_id).await.unwrap();
+    }
+
+    /// a running contest of two problems and three participants, ranked in
+    /// the order they are returned
+    async fn ranked_contest(app: &TestApp) -> (String, i32, [i32; 2], [i32; 3]) {
+        let (_, teacher) = app.user("teacher").await;
+        let problems = [app.problem(&teacher).await, app.problem(&teacher).await];
+        let contest_id = app.contest(&teacher, &problems, -60, 60).await;
+        let mut users = [0; 3];
+        for user in &mut users {
+            *user = app.user("student").await.0;
+            join(app, contest_id, *user).await;
+        }
+
+        // first solves both, second solves one early, third the same one late
+        // after a wrong answer
+        app.seed_contest_submission(contest_id, users[0], problems[0], "accepted", 50)
+            .await;
+        app.seed_contest_submission(contest_id, users[0], problems[1], "accepted", 40)
+            .await;
+        app.seed_contest_submission(contest_id, users[1], problems[0], "accepted", 55)
+            .await;
+        app.seed_contest_submission(contest_id, users[2], problems[0], "wrong_answer", 30)
+            .await;
+        app.seed_contest_submission(contest_id, users[2], problems[0], "accepted", 20)
+            .await;
+        (teacher, contest_id, problems, users)
+    }
+
+    fn ranked_users(body: &serde_json::Value) -> Vec<i64> {
+        body["rankings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["userId"].as_i64().unwrap())
+            .collect()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn ranking_pages_are_slices_in_rank_order(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (teacher, contest_id, _, users) = ranked_contest(&app).await;
+        let uri = format!("/api/contests/{}/ranking", contest_id);
+
+        let full = app.get(&uri, &teacher).await;
+        assert_eq!(full.status, StatusCode::OK, "{}", full.body);
+        assert_eq!(ranked_users(&full.body), users.map(i64::from));
+
+        // once from the rebuilt ranking and once from the cache
+        for _ in 0..2 {
+            let page = app
+                .get(&format!("{}?page=2&pageSize=1", uri), &teacher)
+                .await;
+            assert_eq!(ranked_users(&page.body), [users[1] as i64]);
+            assert_eq!(page.body["total"], 3);
+
+            let page = app
+                .get(&format!("{}?page=2&pageSize=2", uri), &teacher)
+                .await;
+            assert_eq!(ranked_users(&page.body), [users[2] as i64]);
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn ties_go_to_the_earlier_last_solve_then_the_lower_id(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problems = [app.problem(&teacher).await, app.problem(&teacher).await];
+        let contest_id = app.contest(&teacher, &problems, -60, 60).await;
+        let mut users = [0; 3];
+        for user in &mut users {
+            *user = app.user("student").await.0;
+            join(&app, contest_id, *user).await;
+        }
+        // exactly this many minutes into the contest, so penalties tie
+        let solve = |user_id: i32, problem_id: i32, minute: i32| {
+            let app = &app;
+            async move {
+                let submission_id = app
+                    .seed_contest_submission(contest_id, user_id, problem_id, "accepted", 0)
+                    .await;
+                sqlx::query!(
+                    r#"
+                    UPDATE submissions
+                    SET created_at = (SELECT begin_time FROM contests WHERE id = $1)
+                        + make_interval(mins => $2)
+                    WHERE id = $3
+                    "#,
+                    contest_id,
+                    minute,
+                    submission_id
+                )
+                .execute(&app.pool)
+                .await
+                .unwrap();
+            }
+        };
+
+        // 10 + 40 and twice 20 + 30 minutes, the last two solved last at the
+        // same time
+        solve(users[0], problems[0], 10).await;
+        solve(users[0], problems[1], 40).await;
+        for user in &users[1..] {
+            solve(*user, problems[0], 20).await;
+            solve(*user, problems[1], 30).await;
+        }
+
+        // rebuilt, then twice from the cache
+        let uri = format!("/api/contests/{}/ranking", contest_id);
+        for _ in 0..3 {
+            let ranking = app.get(&uri, &teacher).await;
+            assert_eq!(ranking.status, StatusCode::OK, "{}", ranking.body);
+            let penalties: Vec<_> = ranking.body["rankings"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["totalPenalty"].as_i64().unwrap())
+                .collect();
+            assert!(penalties.iter().all(|penalty| *penalty == penalties[0]));
+            assert_eq!(
+                ranked_users(&ranking.body),
+                [users[1], users[2], users[0]].map(i64::from)
+            );
+        }
+    }
+
+    fn ranking_row(body: &serde_json::Value, user_id: i32) -> &serde_json::Value {
+        body["rankings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["userId"] == user_id)
+            .unwrap()
+    }
+
+    /// the problems `user_id` solved first, as the ranking shows them
+    fn first_solves(body: &serde_json::Value, user_id: i32) -> Vec<i64> {
+        ranking_row(body, user_id)["problemResults"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|result| result["firstSolve"] == true)
+            .map(|result| result["problemId"].as_i64().unwrap())
+            .collect()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn first_solve_goes_to_the_earliest_accepted(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (teacher, contest_id, problems, users) = ranked_contest(&app).await;
+        let uri = format!("/api/contests/{}/ranking", contest_id);
+
+        // rebuilt from the database
+        let ranking = app.get(&uri, &teacher).await;
+        assert_eq!(ranking.status, StatusCode::OK, "{}", ranking.body);
+        assert_eq!(first_solves(&ranking.body, users[0]), [problems[1] as i64]);
+        assert_eq!(first_solves(&ranking.body, users[1]), [problems[0] as i64]);
+        assert!(first_solves(&ranking.body, users[2]).is_empty());
+
+        // a later accepted that reaches the cache leaves the flag where it is
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let (late_id, late) = app.user("student").await;
+        join(&app, contest_id, late_id).await;
+        let body = json!({ "code": "int main() {}", "lang": "cpp", "contestId": contest_id });
+        let submissions = format!("/api/problems/{}/submissions", problems[1]);
+        let response = app.post(&submissions, &late, body).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let submission_id = response.body["submissionId"].as_i64().unwrap() as i32;
+        let test_case_id = judge.task().await.test_cases[0].id;
+        let accepted = vec![test_result(test_case_id, TestCaseJudgeResult::Accepted)];
+        judge
+            .send(judge_result(
+                submission_id,
+                SubmissionResult::Accepted,
+                accepted,
+            ))
+            .await;
+        assert_eq!(app.judged(submission_id).await, "accepted");
+
+        let ranking = app.get(&uri, &teacher).await;
+        assert!(first_solves(&ranking.body, late_id).is_empty());
+        assert_eq!(first_solves(&ranking.body, users[0]), [problems[1] as i64]);
+    }
+
+    /// a compile error, an accepted and a wrong answer after it, seeded for one
+    /// student and judged into the cached ranking for another
+    async fn compile_error_then_accepted(pool: PgPool, penalize: bool) {
+        let app =
+            TestApp::with_config(pool, |config| config.penalize_compile_errors = penalize).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -60, 60).await;
+        let attempts = if penalize { 2 } else { 1 };
+
+        let (seeded, _) = app.user("student").await;
+        join(&app, contest_id, seeded).await;
+        for (result, minutes_ago) in [
+            ("compile_error", 50),
+            ("accepted", 40),
+            ("wrong_answer", 30),
+        ] {
+            app.seed_contest_submission(contest_id, seeded, problem_id, result, minutes_ago)
+                .await;
+        }
+        let uri = format!("/api/contests/{}/ranking", contest_id);
+        let ranking = app.get(&uri, &teacher).await;
+        assert_eq!(ranking.status, StatusCode::OK, "{}", ranking.body);
+        let row = ranking_row(&ranking.body, seeded);
+        assert_eq!(row["problemResults"][0]["attempts"], attempts);
+        // solved 20 minutes in, plus 20 for each attempt before
+        let penalty = row["totalPenalty"].as_i64().unwrap();
+        assert!((penalty - attempts * 20 * 60).abs() <= 2, "{}", penalty);
+
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let (live_id, live) = app.user("student").await;
+        join(&app, contest_id, live_id).await;
+        let submissions = format!("/api/problems/{}/submissions", problem_id);
+        let body = json!({ "code": "int main() {}", "lang": "cpp", "contestId": contest_id });
+        for result in [SubmissionResult::CompileError, SubmissionResult::Accepted] {
+            let response = app.post(&submissions, &live, body.clone()).await;
+            assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+            let submission_id = response.body["submissionId"].as_i64().unwrap() as i32;
+            let test_case_id = judge.task().await.test_cases[0].id;
+            let test_results = match result {
+                SubmissionResult::Accepted => {
+                    vec![test_result(test_case_id, TestCaseJudgeResult::Accepted)]
+                }
+                _ => Vec::new(),
+            };
+            judge
+                .send(judge_result(submission_id, result, test_results))
+                .await;
+            app.judged(submission_id).await;
+        }
+        let ranking = app.get(&uri, &teacher).await;
+        let row = ranking_row(&ranking.body, live_id);
+        assert_eq!(row["problemResults"][0]["attempts"], attempts);
+
+        // and the cache agrees with the database
+        let response = app
+            .post(&format!("{}/rebuild", uri), &app.token(1), json!({}))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["discrepancies"], json!([]));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn compile_error_before_accepted_is_penalized_by_default(pool: PgPool) {
+        compile_error_then_accepted(pool, true).await;
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn compile_error_before_accepted_is_free_when_configured(pool: PgPool) {
+        compile_error_then_accepted(pool, false).await;
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn pending_submission_leaves_the_ranking_alone(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problems = [app.problem(&teacher).await, app.problem(&teacher).await];
+        let contest_id = app.contest(&teacher, &problems, -60, 60).await;
+        let (student_id, student) = app.user("student").await;
+        join(&app, contest_id, student_id).await;
+        app.seed_contest_submission(contest_id, student_id, problems[0], "pending", 50)
+            .await;
+        app.seed_contest_submission(contest_id, student_id, problems[0], "accepted", 40)
+            .await;
+
+        // rebuilt from the database, then read back from the cache
+        let uri = format!("/api/contests/{}/ranking", contest_id);
+        let rebuilt = app.get(&uri, &teacher).await.body;
+        let row = ranking_row(&rebuilt, student_id);
+        assert_eq!(row["problemResults"][0]["attempts"], 1);
+        let before = app.get(&uri, &teacher).await.body;
+
+        // and in the cache, until the verdict comes in
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let body = json!({ "code": "int main() {}", "lang": "cpp", "contestId": contest_id });
+        let submissions = format!("/api/problems/{}/submissions", problems[1]);
+        let response = app.post(&submissions, &student, body).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let submission_id = response.body["submissionId"].as_i64().unwrap() as i32;
+        let test_case_id = judge.task().await.test_cases[0].id;
+        assert_eq!(app.get(&uri, &teacher).await.body, before);
+
+        let wrong = vec![test_result(test_case_id, TestCaseJudgeResult::WrongAnswer)];
+        judge
+            .send(judge_result(
+                submission_id,
+                SubmissionResult::WrongAnswer,
+                wrong,
+            ))
+            .await;
+        assert_eq!(app.judged(submission_id).await, "wrong_answer");
+        let after = app.get(&uri, &teacher).await.body;
+        let row = ranking_row(&after, student_id);
+        assert_eq!(row["problemResults"][1]["attempts"], 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn overall_ranking_sums_the_contest_rankings(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (teacher, first, problems, users) = ranked_contest(&app).await;
+        let second = app.contest(&teacher, &problems, -60, 60).await;
+        for user in &users[1..] {
+            join(&app, second, *user).await;
+        }
+        app.seed_contest_submission(second, users[1], problems[1], "accepted", 58)
+            .await;
+        app.seed_contest_submission(second, users[2], problems[1], "wrong_answer", 50)
+            .await;
+
+        // what the per-user loop used to compute, one contest at a time
+        let mut expected = std::collections::BTreeMap::new();
+        for contest_id in [first, second] {
+            let ranking = app
+                .get(&format!("/api/contests/{}/ranking", contest_id), &teacher)
+                .await;
+            for item in ranking.body["rankings"].as_array().unwrap() {
+                let user_id = item["userId"].as_i64().unwrap();
+                let entry = expected.entry(user_id).or_insert((0, 0, 0));
+                entry.0 += item["solvedCount"].as_i64().unwrap();
+                entry.1 += item["totalPenalty"].as_i64().unwrap();
+            }
+        }
+        for (user_id, entry) in expected.iter_mut() {
+            entry.2 = sqlx::query_scalar!(
+                r#"
+                SELECT COUNT(*) as "count!" FROM contest_participants
+                WHERE user_id = $1 AND contest_id = ANY($2)
+                "#,
+                *user_id as i32,
+                &[first, second]
+            )
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+        }
+
+        let response = app
+            .get(
+                &format!(
+                    "/api/contests/overall-ranking?contestIds={}&contestIds={}",
+                    first, second
+                ),
+                &teacher,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let rankings = response.body["rankings"].as_array().unwrap();
+        let actual: std::collections::BTreeMap<_, _> = rankings
+            .iter()
+            .map(|item| {
+                (
+                    item["userId"].as_i64().unwrap(),
+                    (
+                        item["totalSolved"].as_i64().unwrap(),
+                        item["totalPenalty"].as_i64().unwrap(),
+                        item["contestCount"].as_i64().unwrap(),
+                    ),
+                )
+            })
+            .collect();
+        assert_eq!(actual, expected);
+
+        // two solved each for the first two, the faster of them first
+        let order: Vec<_> = rankings.iter().map(|item| item["userId"].clone()).collect();
+        assert_eq!(order, [json!(users[1]), json!(users[0]), json!(users[2])]);
+        let username = sqlx::query_scalar!("SELECT username FROM users WHERE id = $1", users[2])
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(rankings[2]["username"], username);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn rebuild_repairs_a_corrupted_ranking(pool: PgPool) {
+        use redis::AsyncCommands;
+
+        let app = TestApp::new(pool).await;
+        let (teacher, contest_id, _, users) = ranked_contest(&app).await;
+        let uri = format!("/api/contests/{}/ranking", contest_id);
+        // the first read builds the cache, the second comes from it
+        app.get(&uri, &teacher).await;
+        let expected = app.get(&uri, &teacher).await.body;
+
+        let mut redis = app.state.redis.clone();
+        let _: () = redis
+            .hset(
+                format!("contest:{}:user:{}", contest_id, users[0]),
+                "solved_count",
+                0,
+            )
+            .await
+            .unwrap();
+        let corrupted = app.get(&uri, &teacher).await.body;
+        assert_eq!(corrupted["rankings"][0]["solvedCount"], 0);
+
+        let response = app
+            .post(&format!("{}/rebuild", uri), &app.token(1), json!({}))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let discrepancies = response.body["discrepancies"].as_array().unwrap();
+        assert_eq!(discrepancies.len(), 1, "{}", response.body);
+        assert!(
+            discrepancies[0]
+                .as_str()
+                .unwrap()
+                .starts_with(&format!("user {}:", users[0]))
+        );
+        assert_eq!(app.get(&uri, &teacher).await.body, expected);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn ranking_exports_as_csv(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (teacher, contest_id, problems, users) = ranked_contest(&app).await;
+
+        let response = app
+            .get(
+                &format!("/api/contests/{}/ranking/export?format=csv", contest_id),
+                &teacher,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(
+            response.headers[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/csv")
+        );
+
+        let csv = response.body.as_str().unwrap();
+        let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), 1 + users.len());
+        assert_eq!(
+            rows[0],
+            [
+                "rank".to_string(),
+                "username".to_string(),
+                "solved".to_string(),
+                "penalty".to_string(),
+                format!("problem {} attempts", problems[0]),
+                format!("problem {} accepted at", problems[0]),
+                format!("problem {} attempts", problems[1]),
+                format!("problem {} accepted at", problems[1]),
+            ]
+        );
+
+        // the third solved one problem on the second try, 40 minutes in
+        let username = sqlx::query_scalar!("SELECT username FROM users WHERE id = $1", users[2])
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+        let third = &rows[3];
+        assert_eq!(third[..5], ["3", &username, "1", "3600", "2"]);
+        assert!(!third[5].is_empty());
+        assert_eq!(third[6..], ["0", ""]);
+    }
+
+    async fn create_contest(
+        app: &TestApp,
+        token: &str,
+        problem_ids: &[i32],
+    ) -> crate::test_util::Response {
+        let now = chrono::Utc::now();
+        app.post(
+            "/api/contests",
+            token,
+            json!({
+                "name": "a contest",
+                "description": "a contest",
+                "beginTime": now + chrono::Duration::minutes(60),
+                "endTime": now + chrono::Duration::minutes(120),
+                "type": "Public",
+                "problemIds": problem_ids,
+            }),
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn contest_of_existing_problems_is_created(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problems = [app.problem(&teacher).await, app.problem(&teacher).await];
+
+        let response = create_contest(&app, &teacher, &problems).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn contest_of_a_missing_problem_is_refused(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+
+        let response = create_contest(&app, &teacher, &[problem_id, 9999]).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "problemIds");
+        assert_eq!(
+            response.body["fields"][0]["reason"],
+            "problems not found: 9999"
+        );
+        let contests = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM contests"#)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(contests, 0);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn contest_problems_keep_the_submitted_order(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let mut problems = Vec::new();
+        for _ in 0..3 {
+            problems.push(app.problem(&teacher).await);
+        }
+        let order = [problems[2], problems[0], problems[1]];
+
+        let response = create_contest(&app, &teacher, &order).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let uri = format!("/api/contests/{}", response.body["contestId"]);
+        assert_eq!(
+            app.get(&uri, &teacher).await.body["problemIds"],
+            json!(order)
+        );
+
+        let reordered = [problems[1], problems[2]];
+        let response = app
+            .send(
+                Method::PUT,
+                &uri,
+                Some(&teacher),
+                Some(json!({ "problemIds": reordered })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(
+            app.get(&uri, &teacher).await.body["problemIds"],
+            json!(reordered)
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn contest_with_a_problem_twice_is_refused(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+
+        let response = create_contest(&app, &teacher, &[problem_id, problem_id]).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.body["fields"][0]["reason"],
+            "each problem can appear only once"
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn problem_aliases_round_trip(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problems = [app.problem(&teacher).await, app.problem(&teacher).await];
+        let contest_id = app.contest(&teacher, &problems, 60, 120).await;
+        let uri = format!("/api/contests/{}", contest_id);
+        let labels = |body: &serde_json::Value| -> Vec<String> {
+            body["problems"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|problem| problem["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(labels(&app.get(&uri, &teacher).await.body), ["A", "B"]);
+
+        let aliases = json!({ problems[1].to_string(): "Warm-up" });
+        let response = app
+            .send(
+                Method::PUT,
+                &uri,
+                Some(&teacher),
+                Some(json!({ "problemAliases": aliases })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let contest = app.get(&uri, &teacher).await.body;
+        assert_eq!(labels(&contest), ["A", "Warm-up"]);
+        assert_eq!(contest["problemIds"], json!(problems));
+
+        let aliases = json!({ problems[1].to_string(): "" });
+        app.send(
+            Method::PUT,
+            &uri,
+            Some(&teacher),
+            Some(json!({ "problemAliases": aliases })),
+        )
+        .await;
+        assert_eq!(labels(&app.get(&uri, &teacher).await.body), ["A", "B"]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn refused_update_leaves_the_contest_unchanged(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problems = [app.problem(&teacher).await, app.problem(&teacher).await];
+        let contest_id = app.contest(&teacher, &problems, 60, 120).await;
+        let uri = format!("/api/contests/{}", contest_id);
+        let before = app.get(&uri, &teacher).await.body;
+
+        // the alias names a problem the new list drops
+        let body = json!({
+            "name": "Renamed",
+            "description": "changed",
+            "problemIds": [problems[0]],
+            "problemAliases": { problems[1].to_string(): "Warm-up" },
+        });
+        let response = app
+            .send(Method::PUT, &uri, Some(&teacher), Some(body))
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "problemAliases");
+        assert_eq!(app.get(&uri, &teacher).await.body, before);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn contest_collaborators_edit_like_the_owner(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, owner) = app.user("teacher").await;
+        let (collaborator_id, collaborator) = app.user("teacher").await;
+        let (_, outsider) = app.user("teacher").await;
+        let problem_id = app.problem(&owner).await;
+        let contest_id = app.contest(&owner, &[problem_id], 60, 120).await;
+        let uri = format!("/api/contests/{}", contest_id);
+        let body = json!({ "description": "edited" });
+
+        let response = app
+            .send(Method::PUT, &uri, Some(&outsider), Some(body.clone()))
+            .await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+        let collaborators = format!("{}/collaborators/{}", uri, collaborator_id);
+        let response = app
+            .send(Method::PUT, &collaborators, Some(&owner), None)
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app
+            .send(Method::PUT, &uri, Some(&collaborator), Some(body))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app.send(Method::DELETE, &uri, Some(&outsider), None).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn open_ranking_is_public_and_private_one_is_not(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, outsider) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let open = app.contest(&teacher, &[problem_id], -10, 60).await;
+        let private = app.contest(&teacher, &[problem_id], -10, 60).await;
+        let response = app
+            .send(
+                Method::PUT,
+                &format!("/api/contests/{}", private),
+                Some(&teacher),
+                Some(json!({ "type": "Private" })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let ranking = |contest_id: i32| format!("/api/contests/{}/ranking", contest_id);
+
+        let response = app.send(Method::GET, &ranking(open), None, None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        // signing in without joining mustn't see less than a guest
+        let response = app.get(&ranking(open), &outsider).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let response = app.send(Method::GET, &ranking(private), None, None).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        let response = app.get(&ranking(private), &outsider).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn joining_follows_the_registration_window(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], 60, 120).await;
+        let uri = format!("/api/contests/{}", contest_id);
+        let join = format!("{}/join", uri);
+        let now = chrono::Utc::now();
+        let window = |begin: i64, end: i64| {
+            json!({
+                "registrationBegin": now + chrono::Duration::minutes(begin),
+                "registrationEnd": now + chrono::Duration::minutes(end),
+            })
+        };
+
+        let response = app
+            .send(Method::PUT, &uri, Some(&teacher), Some(window(10, 30)))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let (_, early) = app.user("student").await;
+        let response = app.post(&join, &early, json!({})).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        assert_eq!(response.body["message"], "registration is not open yet");
+
+        app.send(Method::PUT, &uri, Some(&teacher), Some(window(-30, -10)))
+            .await;
+        let (_, late) = app.user("student").await;
+        let response = app.post(&join, &late, json!({})).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        assert_eq!(response.body["message"], "registration is closed");
+
+        app.send(Method::PUT, &uri, Some(&teacher), Some(window(-10, 10)))
+            .await;
+        let contest = app.get(&uri, &teacher).await.body;
+        assert!(contest["registrationBegin"].is_string());
+        let (_, student) = app.user("student").await;
+        let response = app.post(&join, &student, json!({})).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        // without a window, joining is open until the contest ends
+        let body = json!({ "clearRegistrationWindow": true });
+        app.send(Method::PUT, &uri, Some(&teacher), Some(body))
+            .await;
+        let response = app.post(&join, &late, json!({})).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn submissions_stop_at_the_attempt_cap(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -10, 60).await;
+        let uri = format!("/api/contests/{}", contest_id);
+        let cap = |max: i32| json!({ "maxAttemptsPerProblem": max });
+        let response = app
+            .send(Method::PUT, &uri, Some(&teacher), Some(cap(2)))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let submissions = format!("/api/problems/{}/submissions", problem_id);
+        let body = json!({ "code": "int main() {}", "lang": "cpp", "contestId": contest_id });
+        let (student_id, student) = app.user("student").await;
+        join(&app, contest_id, student_id).await;
+        app.seed_contest_submission(contest_id, student_id, problem_id, "wrong_answer", 1)
+            .await;
+        let response = app.post(&submissions, &student, body.clone()).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app.post(&submissions, &student, body.clone()).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.body["message"],
+            "no attempts left for this problem"
+        );
+
+        // zero lifts the cap
+        app.send(Method::PUT, &uri, Some(&teacher), Some(cap(0)))
+            .await;
+        let (other_id, other) = app.user("student").await;
+        join(&app, contest_id, other_id).await;
+        for _ in 0..3 {
+            app.seed_contest_submission(contest_id, other_id, problem_id, "wrong_answer", 1)
+                .await;
+        }
+        let response = app.post(&submissions, &other, body).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn clone_copies_problems_but_not_participants(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, _) = app.user("student").await;
+        let problems = [app.problem(&teacher).await, app.problem(&teacher).await];
+        let contest_id = app.contest(&teacher, &problems, -10, 60).await;
+        join(&app, contest_id, student_id).await;
+
+        let now = chrono::Utc::now();
+        let body = json!({
+            "name": "next week",
+            "beginTime": now + chrono::Duration::days(7),
+            "endTime": now + chrono::Duration::days(7) + chrono::Duration::hours(2),
+        });
+        let uri = format!("/api/contests/{}/clone", contest_id);
+        let response = app.post(&uri, &teacher, body).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let clone_id = response.body["contestId"].as_i64().unwrap() as i32;
+        assert_ne!(clone_id, contest_id);
+
+        let original = app
+            .get(&format!("/api/contests/{}", contest_id), &teacher)
+            .await
+            .body;
+        let clone = app
+            .get(&format!("/api/contests/{}", clone_id), &teacher)
+            .await
+            .body;
+        assert_eq!(clone["name"], "next week");
+        assert_eq!(clone["problemIds"], json!(problems));
+        assert_eq!(clone["description"], original["description"]);
+        let participants = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM contest_participants WHERE contest_id = $1"#,
+            clone_id
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(participants, 0);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn ranking_stream_pushes_the_judged_row(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -10, 60).await;
+        join(&app, contest_id, student_id).await;
+        // an empty ranking has nothing cached, which the stream answers with a reset
+        app.seed_contest_submission(contest_id, student_id, problem_id, "wrong_answer", 5)
+            .await;
+        app.get(&format!("/api/contests/{}/ranking", contest_id), &teacher)
+            .await;
+
+        // subscribed by the time the headers are back
+        let addr = app.serve().await;
+        let mut stream = reqwest::Client::new()
+            .get(format!(
+                "http://{}/api/contests/{}/ranking/stream",
+                addr, contest_id
+            ))
+            .bearer_auth(&teacher)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(stream.status(), StatusCode::OK);
+        assert_eq!(stream.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let body = json!({ "code": "int main() {}", "lang": "cpp", "contestId": contest_id });
+        let submissions = format!("/api/problems/{}/submissions", problem_id);
+        let response = app.post(&submissions, &student, body).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let submission_id = response.body["submissionId"].as_i64().unwrap() as i32;
+        let test_case_id = judge.task().await.test_cases[0].id;
+        let accepted = vec![test_result(test_case_id, TestCaseJudgeResult::Accepted)];
+        judge
+            .send(judge_result(
+                submission_id,
+                SubmissionResult::Accepted,
+                accepted,
+            ))
+            .await;
+
+        // keep-alive comments may come first, the update is the first event
+        let mut received = String::new();
+        let event = 'read: loop {
+            while let Some((block, rest)) = received.split_once("\n\n") {
+                if block.lines().any(|line| line.starts_with("event:")) {
+                    break 'read block.to_string();
+                }
+                received = rest.to_string();
+            }
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), stream.chunk())
+                .await
+                .expect("no update was pushed")
+                .unwrap()
+                .expect("the stream ended");
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        };
+        assert!(
+            event.lines().any(|line| line == "event: update"),
+            "{}",
+            event
+        );
+        let data: String = event
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        let update: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(update["rank"], 1);
+        assert_eq!(update["item"]["userId"], student_id);
+        assert_eq!(update["item"]["solvedCount"], 1);
+        assert_eq!(update["item"]["problemResults"][0]["accepted"], true);
+        assert_eq!(update["item"]["problemResults"][0]["attempts"], 2);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn upsolve_is_accepted_after_the_end_and_leaves_the_standings(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -120, -60).await;
+        join(&app, contest_id, student_id).await;
+        app.seed_contest_submission(contest_id, student_id, problem_id, "wrong_answer", 90)
+            .await;
+
+        let uri = format!("/api/contests/{}/ranking", contest_id);
+        app.get(&uri, &teacher).await;
+        let before = app.get(&uri, &teacher).await.body;
+        assert_eq!(ranking_row(&before, student_id)["solvedCount"], 0);
+
+        let body = json!({ "code": "int main() {}", "lang": "cpp", "contestId": contest_id });
+        let submissions = format!("/api/problems/{}/submissions", problem_id);
+        let response = app.post(&submissions, &student, body.clone()).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+        let contest = format!("/api/contests/{}", contest_id);
+        let update = json!({ "allowUpsolving": true });
+        let response = app
+            .send(Method::PUT, &contest, Some(&teacher), Some(update))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let response = app.post(&submissions, &student, body).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let submission_id = response.body["submissionId"].as_i64().unwrap() as i32;
+        let test_case_id = judge.task().await.test_cases[0].id;
+        let accepted = vec![test_result(test_case_id, TestCaseJudgeResult::Accepted)];
+        judge
+            .send(judge_result(
+                submission_id,
+                SubmissionResult::Accepted,
+                accepted,
+            ))
+            .await;
+        assert_eq!(app.judged(submission_id).await, "accepted");
+        let is_upsolve = sqlx::query_scalar!(
+            "SELECT is_upsolve FROM submissions WHERE id = $1",
+            submission_id
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert!(is_upsolve);
+
+        // neither the cache nor a rebuild from the database counts it
+        assert_eq!(app.get(&uri, &teacher).await.body, before);
+        let response = app
+            .post(&format!("{}/rebuild", uri), &app.token(1), json!({}))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["discrepancies"], json!([]));
+        assert_eq!(app.get(&uri, &teacher).await.body, before);
+    }
+}
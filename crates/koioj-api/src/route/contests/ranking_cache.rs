@@ -3,7 +3,8 @@ use crate::{Error, Result};
 use chrono::{DateTime, Utc};
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::broadcast;
 use utoipa::ToSchema;
 
 use super::{ContestInfo, SubmissionResult};
@@ -25,11 +26,75 @@ pub struct ProblemResult {
     pub accepted: bool,
     pub attempts: i32,
     pub accepted_time: Option<DateTime<Utc>>,
+    /// solved it before anyone else, ties all count
+    pub first_solve: bool,
+}
+
+/// a change to the ranking of a contest, pushed to whoever streams it
+#[derive(Clone)]
+pub enum RankingEvent {
+    Update(RankingUpdate),
+    /// the cache was dropped, so the whole ranking has to be fetched again
+    Reset,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RankingUpdate {
+    /// position of the user in the ranking, counting from 1
+    pub rank: usize,
+    pub item: ContestRankingItem,
+}
+
+/// events a slow subscriber may fall behind by before it is told to reset
+const RANKING_STREAM_CAPACITY: usize = 64;
+
+impl AppState {
+    pub fn subscribe_ranking(&self, contest_id: i32) -> broadcast::Receiver<RankingEvent> {
+        self.ranking_streams
+            .lock()
+            .unwrap()
+            .entry(contest_id)
+            .or_insert_with(|| broadcast::channel(RANKING_STREAM_CAPACITY).0)
+            .subscribe()
+    }
+
+    fn has_ranking_subscribers(&self, contest_id: i32) -> bool {
+        self.ranking_streams
+            .lock()
+            .unwrap()
+            .get(&contest_id)
+            .is_some_and(|tx| tx.receiver_count() > 0)
+    }
+
+    /// the channel is dropped once its last subscriber is gone
+    fn publish_ranking(&self, contest_id: i32, event: RankingEvent) {
+        let mut streams = self.ranking_streams.lock().unwrap();
+        if let Some(tx) = streams.get(&contest_id) {
+            if tx.send(event).is_err() {
+                streams.remove(&contest_id);
+            }
+        }
+    }
+}
+
+/// added to the penalty for every counted attempt before the accepted one, in
+/// seconds
+const WRONG_ATTEMPT_PENALTY: i64 = 20 * 60;
+
+/// whether a verdict counts as an attempt on the way to solving a problem,
+/// submissions still being judged don't count yet
+fn is_penalized(result: SubmissionResult, penalize_compile_errors: bool) -> bool {
+    match result {
+        SubmissionResult::Pending | SubmissionResult::Accepted => false,
+        SubmissionResult::CompileError => penalize_compile_errors,
+        _ => true,
+    }
 }
 
 /// Redis key generators
 fn ranking_key(contest_id: i32) -> String {
-    format!("contest:{}:ranking", contest_id)
+    format!("contest:{}:ranking:order", contest_id)
 }
 
 fn user_key(contest_id: i32, user_id: i32) -> String {
@@ -40,16 +105,46 @@ fn version_key(contest_id: i32) -> String {
     format!("contest:{}:ranking:version", contest_id)
 }
 
-/// Calculate score for sorted set
+/// problem id -> timestamp of its earliest accepted submission
+fn first_solve_key(contest_id: i32) -> String {
+    format!("contest:{}:ranking:first_solve", contest_id)
+}
+
+/// Sorted set member, equal scores are ordered by it: zero-padded, so that the
+/// earlier last accepted problem (in seconds from the start) comes first, then
+/// the lower user id
+fn member(user_id: i32, last_accepted: i64) -> String {
+    format!("{:010}:{:010}", last_accepted.max(0), user_id)
+}
+
+fn member_user_id(member: &str) -> Option<i32> {
+    let (_, user_id) = member.rsplit_once(':')?;
+    user_id.parse().ok()
+}
+
+/// Calculate score for sorted set, lower ranks higher: more solved, then less
+/// penalty. Kept small, redis stores scores as doubles
 fn calculate_score(solved_count: i32, total_penalty: i64) -> i64 {
-    solved_count as i64 * 9999999 - total_penalty
+    total_penalty - solved_count as i64 * 9999999
 }
 
-/// Get ranking from Redis cache
+/// Get the full ranking from Redis cache
 pub async fn get_contest_ranking_cached(
     state: &Arc<AppState>,
     contest: &ContestInfo,
 ) -> Result<Vec<ContestRankingItem>> {
+    let (rankings, _) = get_contest_ranking_page(state, contest, 0, None).await?;
+    Ok(rankings)
+}
+
+/// Get a slice of the ranking from Redis cache, only the users inside it are
+/// hydrated. Returns the slice along with the total number of ranked users
+pub async fn get_contest_ranking_page(
+    state: &Arc<AppState>,
+    contest: &ContestInfo,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<(Vec<ContestRankingItem>, usize)> {
     let mut redis_conn = state.redis.clone();
 
     // Check if cache exists
@@ -58,36 +153,74 @@ pub async fn get_contest_ranking_cached(
         .await
         .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
-    if !exists {
-        tracing::info!("Cache miss for contest {}, rebuilding", contest.id);
-        return rebuild_ranking_cache(state, contest).await;
+    // a ranking without its version was written piecemeal after the last
+    // rebuild expired, so it can't be trusted
+    let has_version: bool = redis_conn
+        .exists(&version_key(contest.id))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+    if !exists || !has_version {
+        if exists {
+            tracing::warn!("Stale ranking cache for contest {}, rebuilding", contest.id);
+        } else {
+            tracing::info!("Cache miss for contest {}, rebuilding", contest.id);
+        }
+        let rankings = rebuild_ranking_cache(state, contest).await?;
+        let total = rankings.len();
+        let page = rankings
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        return Ok((page, total));
     }
 
-    // Get sorted user ids
-    let user_ids: Vec<String> = redis_conn
-        .zrevrange(&ranking_key(contest.id), 0, -1)
+    let total: usize = redis_conn
+        .zcard(&ranking_key(contest.id))
         .await
         .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
-    if user_ids.is_empty() {
-        return Ok(vec![]);
+    // Get sorted user ids of the requested slice
+    let stop = match limit {
+        Some(0) => return Ok((vec![], total)),
+        Some(limit) => (offset + limit - 1) as isize,
+        None => -1,
+    };
+    let members: Vec<String> = redis_conn
+        .zrange(&ranking_key(contest.id), offset as isize, stop)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+    if members.is_empty() {
+        return Ok((vec![], total));
     }
 
     // Get problem list
     let problem_ids = get_contest_problems(&state.pool, contest.id).await?;
 
-    // Batch get user data
-    let mut rankings = Vec::new();
-    for user_id_str in user_ids {
-        let user_id: i32 = user_id_str
-            .parse()
-            .map_err(|e| Error::msg(format!("invalid user_id in redis: {}", e)))?;
+    let user_ids = members
+        .iter()
+        .map(|member| {
+            member_user_id(member)
+                .ok_or_else(|| Error::msg(format!("invalid ranking member in redis: {}", member)))
+        })
+        .collect::<Result<Vec<i32>>>()?;
 
-        let user_data: std::collections::HashMap<String, String> = redis_conn
-            .hgetall(&user_key(contest.id, user_id))
-            .await
-            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let first_solves = get_first_solves(&mut redis_conn, contest.id).await?;
 
+    // Batch get user data
+    let mut pipe = redis::pipe();
+    for user_id in &user_ids {
+        pipe.hgetall(user_key(contest.id, *user_id));
+    }
+    let user_data: Vec<HashMap<String, String>> = pipe
+        .query_async(&mut redis_conn)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+    let mut rankings = Vec::new();
+    for (user_id, user_data) in user_ids.into_iter().zip(user_data) {
         if user_data.is_empty() {
             tracing::warn!(
                 "User data missing for user {} in contest {}",
@@ -97,46 +230,12 @@ pub async fn get_contest_ranking_cached(
             continue;
         }
 
-        let username = user_data.get("username").cloned().unwrap_or_default();
-        let solved_count: i32 = user_data
-            .get("solved_count")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0);
-        let total_penalty: i64 = user_data
-            .get("total_penalty")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0);
-
-        let mut problem_results = Vec::new();
-        for problem_id in &problem_ids {
-            let accepted = user_data
-                .get(&format!("problem:{}:accepted", problem_id))
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(false);
-            let attempts = user_data
-                .get(&format!("problem:{}:attempts", problem_id))
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0);
-            let accepted_time = user_data
-                .get(&format!("problem:{}:accepted_time", problem_id))
-                .and_then(|s| s.parse::<i64>().ok())
-                .and_then(|ts| DateTime::from_timestamp(ts, 0));
-
-            problem_results.push(ProblemResult {
-                problem_id: *problem_id,
-                accepted,
-                attempts,
-                accepted_time,
-            });
-        }
-
-        rankings.push(ContestRankingItem {
-            user_id: user_id,
-            username,
-            solved_count,
-            total_penalty,
-            problem_results,
-        });
+        rankings.push(parse_ranking_item(
+            user_id,
+            &user_data,
+            &problem_ids,
+            &first_solves,
+        ));
     }
 
     // Refresh TTL
@@ -145,8 +244,82 @@ pub async fn get_contest_ranking_cached(
         .expire(&ranking_key(contest.id), ttl)
         .await
         .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let _: () = redis_conn
+        .expire(&version_key(contest.id), ttl)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let _: () = redis_conn
+        .expire(&first_solve_key(contest.id), ttl)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
-    Ok(rankings)
+    Ok((rankings, total))
+}
+
+async fn get_first_solves(
+    redis_conn: &mut redis::aio::ConnectionManager,
+    contest_id: i32,
+) -> Result<HashMap<i32, i64>> {
+    let first_solves: HashMap<String, String> = redis_conn
+        .hgetall(&first_solve_key(contest_id))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    Ok(first_solves
+        .into_iter()
+        .filter_map(|(problem_id, time)| Some((problem_id.parse().ok()?, time.parse().ok()?)))
+        .collect())
+}
+
+/// Build a ranking item out of a cached user hash
+fn parse_ranking_item(
+    user_id: i32,
+    user_data: &HashMap<String, String>,
+    problem_ids: &[i32],
+    first_solves: &HashMap<i32, i64>,
+) -> ContestRankingItem {
+    let username = user_data.get("username").cloned().unwrap_or_default();
+    let solved_count: i32 = user_data
+        .get("solved_count")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+    let total_penalty: i64 = user_data
+        .get("total_penalty")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+
+    let mut problem_results = Vec::new();
+    for problem_id in problem_ids {
+        let accepted = user_data
+            .get(&format!("problem:{}:accepted", problem_id))
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(false);
+        let attempts = user_data
+            .get(&format!("problem:{}:attempts", problem_id))
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        let accepted_time = user_data
+            .get(&format!("problem:{}:accepted_time", problem_id))
+            .and_then(|s| s.parse::<i64>().ok())
+            .and_then(|ts| DateTime::from_timestamp(ts, 0));
+        let first_solve = accepted_time
+            .is_some_and(|time| first_solves.get(problem_id) == Some(&time.timestamp()));
+
+        problem_results.push(ProblemResult {
+            problem_id: *problem_id,
+            accepted,
+            attempts,
+            accepted_time,
+            first_solve,
+        });
+    }
+
+    ContestRankingItem {
+        user_id,
+        username,
+        solved_count,
+        total_penalty,
+        problem_results,
+    }
 }
 
 /// Rebuild ranking cache from database
@@ -154,7 +327,12 @@ pub async fn rebuild_ranking_cache(
     state: &Arc<AppState>,
     contest: &ContestInfo,
 ) -> Result<Vec<ContestRankingItem>> {
-    let rankings = calculate_contest_ranking_from_db(&state.pool, contest).await?;
+    let rankings = calculate_contest_ranking_from_db(
+        &state.pool,
+        contest,
+        state.config.penalize_compile_errors,
+    )
+    .await?;
 
     let mut redis_conn = state.redis.clone();
 
@@ -163,15 +341,30 @@ pub async fn rebuild_ranking_cache(
         .del(&ranking_key(contest.id))
         .await
         .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let _: () = redis_conn
+        .del(&first_solve_key(contest.id))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
     // Write to Redis
     for item in &rankings {
         let user_id: i32 = item.user_id;
 
         // Add to sorted set
+        let last_accepted = item
+            .problem_results
+            .iter()
+            .filter_map(|pr| pr.accepted_time)
+            .map(|time| (time - contest.begin_time).num_seconds())
+            .max()
+            .unwrap_or(0);
         let score = calculate_score(item.solved_count, item.total_penalty);
         let _: () = redis_conn
-            .zadd(&ranking_key(contest.id), &item.user_id, score)
+            .zadd(
+                &ranking_key(contest.id),
+                member(user_id, last_accepted),
+                score,
+            )
             .await
             .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
@@ -180,6 +373,7 @@ pub async fn rebuild_ranking_cache(
             ("username".to_string(), item.username.clone()),
             ("solved_count".to_string(), item.solved_count.to_string()),
             ("total_penalty".to_string(), item.total_penalty.to_string()),
+            ("last_accepted".to_string(), last_accepted.to_string()),
         ];
 
         for pr in &item.problem_results {
@@ -196,9 +390,24 @@ pub async fn rebuild_ranking_cache(
                     format!("problem:{}:accepted_time", pr.problem_id),
                     time.timestamp().to_string(),
                 ));
+                if pr.first_solve {
+                    let _: () = redis_conn
+                        .hset(
+                            &first_solve_key(contest.id),
+                            pr.problem_id,
+                            time.timestamp(),
+                        )
+                        .await
+                        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+                }
             }
         }
 
+        // replace rather than merge, so no stale field survives a rebuild
+        let _: () = redis_conn
+            .del(&user_key(contest.id, user_id))
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
         let _: () = redis_conn
             .hset_multiple(&user_key(contest.id, user_id), &fields)
             .await
@@ -211,6 +420,10 @@ pub async fn rebuild_ranking_cache(
         .expire(&ranking_key(contest.id), ttl)
         .await
         .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let _: () = redis_conn
+        .expire(&first_solve_key(contest.id), ttl)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
     // Set version
     let _: () = redis_conn
@@ -221,6 +434,125 @@ pub async fn rebuild_ranking_cache(
     Ok(rankings)
 }
 
+/// Compare the cached ranking against the database and log every mismatch,
+/// an empty result means the cache is consistent (or absent)
+pub async fn verify_ranking_cache(
+    state: &Arc<AppState>,
+    contest: &ContestInfo,
+) -> Result<Vec<String>> {
+    let mut redis_conn = state.redis.clone();
+
+    let exists: bool = redis_conn
+        .exists(&ranking_key(contest.id))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    if !exists {
+        return Ok(vec![]);
+    }
+
+    let mut discrepancies = Vec::new();
+
+    let version: Option<i64> = redis_conn
+        .get(&version_key(contest.id))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    if version.is_none() {
+        discrepancies.push("ranking version is missing".to_string());
+    }
+
+    // read the sorted set directly, going through the page helper would
+    // rebuild a stale cache before we get to look at it
+    let members: Vec<String> = redis_conn
+        .zrange(&ranking_key(contest.id), 0, -1)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let problem_ids = get_contest_problems(&state.pool, contest.id).await?;
+    let first_solves = get_first_solves(&mut redis_conn, contest.id).await?;
+
+    let mut cached = HashMap::new();
+    for member in members {
+        let Some(user_id) = member_user_id(&member) else {
+            discrepancies.push(format!("invalid member {:?} in ranking", member));
+            continue;
+        };
+        let user_data: HashMap<String, String> = redis_conn
+            .hgetall(&user_key(contest.id, user_id))
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+        if user_data.is_empty() {
+            discrepancies.push(format!("user {}: cached data missing", user_id));
+            continue;
+        }
+        cached.insert(
+            user_id,
+            parse_ranking_item(user_id, &user_data, &problem_ids, &first_solves),
+        );
+    }
+
+    let expected = calculate_contest_ranking_from_db(
+        &state.pool,
+        contest,
+        state.config.penalize_compile_errors,
+    )
+    .await?;
+    for item in &expected {
+        let Some(cached_item) = cached.remove(&item.user_id) else {
+            discrepancies.push(format!("user {}: missing from cache", item.user_id));
+            continue;
+        };
+
+        if cached_item.solved_count != item.solved_count
+            || cached_item.total_penalty != item.total_penalty
+        {
+            discrepancies.push(format!(
+                "user {}: cached solved={} penalty={}, expected solved={} penalty={}",
+                item.user_id,
+                cached_item.solved_count,
+                cached_item.total_penalty,
+                item.solved_count,
+                item.total_penalty
+            ));
+        }
+
+        for (cached_pr, pr) in cached_item
+            .problem_results
+            .iter()
+            .zip(&item.problem_results)
+        {
+            if cached_pr.accepted != pr.accepted || cached_pr.attempts != pr.attempts {
+                discrepancies.push(format!(
+                    "user {} problem {}: cached accepted={} attempts={}, expected accepted={} attempts={}",
+                    item.user_id,
+                    pr.problem_id,
+                    cached_pr.accepted,
+                    cached_pr.attempts,
+                    pr.accepted,
+                    pr.attempts
+                ));
+            }
+            if cached_pr.first_solve != pr.first_solve {
+                discrepancies.push(format!(
+                    "user {} problem {}: cached first_solve={}, expected first_solve={}",
+                    item.user_id, pr.problem_id, cached_pr.first_solve, pr.first_solve
+                ));
+            }
+        }
+    }
+    for user_id in cached.keys() {
+        discrepancies.push(format!("user {}: cached but has no submissions", user_id));
+    }
+
+    for discrepancy in &discrepancies {
+        tracing::warn!(
+            "Ranking cache mismatch in contest {}: {}",
+            contest.id,
+            discrepancy
+        );
+    }
+
+    Ok(discrepancies)
+}
+
 /// Update ranking cache when a submission is judged
 pub async fn update_ranking_on_submission(
     state: &Arc<AppState>,
@@ -230,6 +562,11 @@ pub async fn update_ranking_on_submission(
     result: SubmissionResult,
     created_at: DateTime<Utc>,
 ) -> Result<()> {
+    // not judged yet, the final verdict updates the ranking
+    if result == SubmissionResult::Pending {
+        return Ok(());
+    }
+
     let mut redis_conn = state.redis.clone();
 
     // Check if cache exists
@@ -243,6 +580,26 @@ pub async fn update_ranking_on_submission(
             "Cache doesn't exist for contest {}, skipping update",
             contest_id
         );
+        state.publish_ranking(contest_id, RankingEvent::Reset);
+        return Ok(());
+    }
+
+    // without a version the cache is already stale, drop it so the next read
+    // rebuilds from the database instead of building on top of it
+    let has_version: bool = redis_conn
+        .exists(&version_key(contest_id))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    if !has_version {
+        tracing::warn!(
+            "Ranking cache for contest {} has no version, invalidating",
+            contest_id
+        );
+        let _: () = redis_conn
+            .del(&ranking_key(contest_id))
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+        state.publish_ranking(contest_id, RankingEvent::Reset);
         return Ok(());
     }
 
@@ -265,23 +622,72 @@ pub async fn update_ranking_on_submission(
         .await
         .unwrap_or(false);
 
+    let penalize_compile_errors = state.config.penalize_compile_errors;
+    let counts =
+        result == SubmissionResult::Accepted || is_penalized(result, penalize_compile_errors);
+
     if accepted {
-        // Already solved, no need to update
+        // a result judged late for a submission made before the solve changes
+        // its penalty, let the next read rebuild rather than patch it up
+        let accepted_time: Option<i64> = redis_conn
+            .hget(&user_key, format!("{}accepted_time", problem_key_prefix))
+            .await
+            .unwrap_or(None);
+        if counts && accepted_time.is_some_and(|time| created_at.timestamp() < time) {
+            tracing::debug!(
+                "Out of order result for user {} in contest {}, invalidating",
+                user_id,
+                contest_id
+            );
+            let _: () = redis_conn
+                .del(&ranking_key(contest_id))
+                .await
+                .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+            state.publish_ranking(contest_id, RankingEvent::Reset);
+        }
         return Ok(());
     }
 
-    // Increment attempts
-    let _: () = redis_conn
-        .hincr(&user_key, format!("{}attempts", problem_key_prefix), 1)
-        .await
-        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    // an earlier solve moves the first solve mark off another user's row
+    let mut first_solve_moved = false;
+
+    if is_penalized(result, penalize_compile_errors) {
+        let _: () = redis_conn
+            .hincr(&user_key, format!("{}attempts", problem_key_prefix), 1)
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    }
 
     // If accepted, update ranking
     if result == SubmissionResult::Accepted {
-        let attempts: i32 = redis_conn
-            .hget(&user_key, format!("{}attempts", problem_key_prefix))
+        // attempts judged so far may have been made after this one, so only
+        // the ones before it are counted
+        let attempts_before = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!" FROM submissions
+            WHERE contest_id = $1 AND user_id = $2 AND problem_id = $3 AND NOT is_virtual
+              AND NOT is_upsolve
+              AND created_at < $4 AND result NOT IN ('pending', 'accepted')
+              AND ($5 OR result <> 'compile_error')
+            "#,
+            contest_id,
+            user_id,
+            problem_id,
+            created_at,
+            penalize_compile_errors
+        )
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+        let attempts = attempts_before as i32 + 1;
+        let _: () = redis_conn
+            .hset(
+                &user_key,
+                format!("{}attempts", problem_key_prefix),
+                attempts,
+            )
             .await
-            .unwrap_or(1);
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
         // Mark as accepted
         let _: () = redis_conn
@@ -299,9 +705,30 @@ pub async fn update_ranking_on_submission(
             .await
             .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
+        // results can arrive out of order, so an earlier solve may still come in
+        let first_solve: Option<i64> = redis_conn
+            .hget(&first_solve_key(contest_id), problem_id)
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+        if first_solve.is_none_or(|time| created_at.timestamp() < time) {
+            first_solve_moved = first_solve.is_some();
+            let _: () = redis_conn
+                .hset(
+                    &first_solve_key(contest_id),
+                    problem_id,
+                    created_at.timestamp(),
+                )
+                .await
+                .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+            let _: () = redis_conn
+                .expire(&first_solve_key(contest_id), calculate_ttl(&contest))
+                .await
+                .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+        }
+
         // Calculate penalty
         let solve_time = (created_at - contest.begin_time).num_seconds();
-        let penalty = solve_time + (attempts - 1) as i64 * 20 * 60;
+        let penalty = solve_time + (attempts - 1) as i64 * WRONG_ATTEMPT_PENALTY;
 
         // Update solved count and total penalty
         let _: () = redis_conn
@@ -325,10 +752,33 @@ pub async fn update_ranking_on_submission(
             .await
             .unwrap_or(0);
 
-        // Update sorted set score
+        // results can arrive out of order, keep the latest solve
+        let previous_last_accepted: i64 = redis_conn
+            .hget(&user_key, "last_accepted")
+            .await
+            .unwrap_or(0);
+        let last_accepted = previous_last_accepted.max(solve_time);
+        let _: () = redis_conn
+            .hset(&user_key, "last_accepted", last_accepted)
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+        // the member carries the last accepted time, so it is replaced rather
+        // than rescored
+        let _: () = redis_conn
+            .zrem(
+                &ranking_key(contest_id),
+                member(user_id, previous_last_accepted),
+            )
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
         let score = calculate_score(solved_count, total_penalty);
         let _: () = redis_conn
-            .zadd(&ranking_key(contest_id), user_id.to_string(), score)
+            .zadd(
+                &ranking_key(contest_id),
+                member(user_id, last_accepted),
+                score,
+            )
             .await
             .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
@@ -341,6 +791,57 @@ pub async fn update_ranking_on_submission(
         );
     }
 
+    if first_solve_moved {
+        state.publish_ranking(contest_id, RankingEvent::Reset);
+    } else if counts {
+        publish_user_update(state, &mut redis_conn, contest_id, user_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Push the new row of a user to the ranking stream, if anyone is watching
+async fn publish_user_update(
+    state: &Arc<AppState>,
+    redis_conn: &mut redis::aio::ConnectionManager,
+    contest_id: i32,
+    user_id: i32,
+) -> Result<()> {
+    if !state.has_ranking_subscribers(contest_id) {
+        return Ok(());
+    }
+
+    let user_data: HashMap<String, String> = redis_conn
+        .hgetall(&user_key(contest_id, user_id))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    if user_data.is_empty() {
+        return Ok(());
+    }
+
+    let last_accepted = user_data
+        .get("last_accepted")
+        .and_then(|time| time.parse().ok())
+        .unwrap_or(0);
+    let rank: Option<usize> = redis_conn
+        .zrank(&ranking_key(contest_id), member(user_id, last_accepted))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let Some(rank) = rank else {
+        return Ok(());
+    };
+
+    let problem_ids = get_contest_problems(&state.pool, contest_id).await?;
+    let first_solves = get_first_solves(redis_conn, contest_id).await?;
+
+    state.publish_ranking(
+        contest_id,
+        RankingEvent::Update(RankingUpdate {
+            rank: rank + 1,
+            item: parse_ranking_item(user_id, &user_data, &problem_ids, &first_solves),
+        }),
+    );
+
     Ok(())
 }
 
@@ -356,10 +857,10 @@ fn calculate_ttl(contest: &ContestInfo) -> i64 {
     }
 }
 
-/// Get contest problems
+/// Get contest problems in their display order
 async fn get_contest_problems(pool: &sqlx::PgPool, contest_id: i32) -> Result<Vec<i32>> {
     sqlx::query_scalar!(
-        "SELECT problem_id FROM contest_problems WHERE contest_id = $1 ORDER BY problem_id",
+        "SELECT problem_id FROM contest_problems WHERE contest_id = $1 ORDER BY number",
         contest_id
     )
     .fetch_all(pool)
@@ -371,6 +872,7 @@ async fn get_contest_problems(pool: &sqlx::PgPool, contest_id: i32) -> Result<Ve
 async fn calculate_contest_ranking_from_db(
     pool: &sqlx::PgPool,
     contest: &ContestInfo,
+    penalize_compile_errors: bool,
 ) -> Result<Vec<ContestRankingItem>> {
     let problem_ids = get_contest_problems(pool, contest.id).await?;
 
@@ -380,7 +882,9 @@ async fn calculate_contest_ranking_from_db(
                u.username
         FROM submissions s
         JOIN users u ON s.user_id = u.id
-        WHERE s.problem_id = ANY($1) AND s.contest_id = $2
+        WHERE s.problem_id = ANY($1) AND s.contest_id = $2 AND NOT s.is_virtual
+          AND NOT s.is_upsolve
+          AND s.result <> 'pending'
         ORDER BY s.user_id, s.problem_id, s.created_at
         "#,
         &problem_ids,
@@ -390,8 +894,86 @@ async fn calculate_contest_ranking_from_db(
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
+    Ok(build_rankings(
+        &problem_ids,
+        penalize_compile_errors,
+        submissions.into_iter().map(|sub| RankedSubmission {
+            user_id: sub.user_id,
+            username: sub.username,
+            problem_id: sub.problem_id,
+            result: sub.result,
+            created_at: sub.created_at,
+            start_time: contest.begin_time,
+        }),
+    ))
+}
+
+/// Calculate ranking of virtual participants, each timed from their own start
+pub async fn calculate_virtual_ranking_from_db(
+    pool: &sqlx::PgPool,
+    contest: &ContestInfo,
+    penalize_compile_errors: bool,
+) -> Result<Vec<ContestRankingItem>> {
+    let problem_ids = get_contest_problems(pool, contest.id).await?;
+    let duration = contest.end_time - contest.begin_time;
+
+    let submissions = sqlx::query!(
+        r#"
+        SELECT s.user_id, s.problem_id, s.result as "result: SubmissionResult", s.created_at,
+               u.username, v.started_at
+        FROM submissions s
+        JOIN users u ON s.user_id = u.id
+        JOIN contest_virtual_participants v
+            ON v.contest_id = s.contest_id AND v.user_id = s.user_id
+        WHERE s.problem_id = ANY($1) AND s.contest_id = $2 AND s.is_virtual
+          AND s.result <> 'pending'
+        ORDER BY s.user_id, s.problem_id, s.created_at
+        "#,
+        &problem_ids,
+        &contest.id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    Ok(build_rankings(
+        &problem_ids,
+        penalize_compile_errors,
+        submissions
+            .into_iter()
+            .filter(|sub| sub.created_at <= sub.started_at + duration)
+            .map(|sub| RankedSubmission {
+                user_id: sub.user_id,
+                username: sub.username,
+                problem_id: sub.problem_id,
+                result: sub.result,
+                created_at: sub.created_at,
+                start_time: sub.started_at,
+            }),
+    ))
+}
+
+struct RankedSubmission {
+    user_id: i32,
+    username: String,
+    problem_id: i32,
+    result: SubmissionResult,
+    created_at: DateTime<Utc>,
+    /// the moment the penalty clock started for this user
+    start_time: DateTime<Utc>,
+}
+
+/// Aggregate submissions ordered by user, problem and time into sorted rankings
+fn build_rankings(
+    problem_ids: &[i32],
+    penalize_compile_errors: bool,
+    submissions: impl Iterator<Item = RankedSubmission>,
+) -> Vec<ContestRankingItem> {
     let mut user_map: std::collections::HashMap<i32, ContestRankingItem> =
         std::collections::HashMap::new();
+    // (user, problem, seconds from the user's start) of every solve
+    let mut solves = Vec::new();
+    let mut last_accepted: HashMap<i32, i64> = HashMap::new();
 
     for sub in submissions {
         let entry = user_map
@@ -408,6 +990,7 @@ async fn calculate_contest_ranking_from_db(
                         accepted: false,
                         attempts: 0,
                         accepted_time: None,
+                        first_solve: false,
                     })
                     .collect(),
             });
@@ -422,30 +1005,61 @@ async fn calculate_contest_ranking_from_db(
             continue; // Already solved
         }
 
-        problem_result.attempts += 1;
-
         if sub.result == SubmissionResult::Accepted {
+            problem_result.attempts += 1;
             problem_result.accepted = true;
-            let solve_time = (sub.created_at - contest.begin_time).num_seconds();
+            let solve_time = (sub.created_at - sub.start_time).num_seconds();
             problem_result.accepted_time = Some(sub.created_at);
 
-            // Penalty: solve time + 20 minutes per wrong attempt
-            let penalty = solve_time + (problem_result.attempts - 1) as i64 * 20 * 60;
+            // Penalty: solve time + 20 minutes per counted attempt before it
+            let penalty = solve_time + (problem_result.attempts - 1) as i64 * WRONG_ATTEMPT_PENALTY;
             entry.total_penalty += penalty;
             entry.solved_count += 1;
+            solves.push((sub.user_id, sub.problem_id, solve_time));
+            let last = last_accepted.entry(sub.user_id).or_insert(0);
+            *last = (*last).max(solve_time);
+        } else if is_penalized(sub.result, penalize_compile_errors) {
+            problem_result.attempts += 1;
+        }
+    }
+
+    // timed from each user's start, which is what matters for virtual rankings
+    let mut fastest: HashMap<i32, i64> = HashMap::new();
+    for &(_, problem_id, solve_time) in &solves {
+        fastest
+            .entry(problem_id)
+            .and_modify(|fastest| *fastest = (*fastest).min(solve_time))
+            .or_insert(solve_time);
+    }
+    for (user_id, problem_id, solve_time) in solves {
+        if fastest.get(&problem_id) != Some(&solve_time) {
+            continue;
+        }
+        if let Some(problem_result) = user_map.get_mut(&user_id).and_then(|entry| {
+            entry
+                .problem_results
+                .iter_mut()
+                .find(|pr| pr.problem_id == problem_id)
+        }) {
+            problem_result.first_solve = true;
         }
     }
 
     let mut rankings: Vec<ContestRankingItem> = user_map.into_values().collect();
 
-    // Sort by solved_count (desc), then by total_penalty (asc)
+    // Sort by solved_count (desc), then by total_penalty (asc), then by the
+    // last accepted problem (earlier first) and user_id, like the cached order
+    let last_solve =
+        |item: &ContestRankingItem| last_accepted.get(&item.user_id).copied().unwrap_or(0);
     rankings.sort_by(|a, b| {
         b.solved_count
             .cmp(&a.solved_count)
             .then_with(|| a.total_penalty.cmp(&b.total_penalty))
+            .then_with(|| last_solve(a).cmp(&last_solve(b)))
+            .then_with(|| a.user_id.cmp(&b.user_id))
     });
 
-    Ok(rankings)
+    rankings
 }
 
 pub async fn clear_user_ranking_cache(state: &Arc<AppState>, user_id: i32) -> Result<()> {
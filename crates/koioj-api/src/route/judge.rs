@@ -5,21 +5,31 @@ use axum::{
         DefaultBodyLimit,
         ws::{Message, WebSocket, WebSocketUpgrade},
     },
+    http::{HeaderMap, header},
     response::Response,
 };
 use futures::{sink::SinkExt, stream::StreamExt};
 use koioj_common::judge::{
-    ApiToJudgeMessage, JudgeInfo, JudgeLoad, JudgeTask, JudgeToApiMessage, Language,
-    SubmissionResult, TestCaseJudgeResult,
+    ApiToJudgeMessage, JudgeInfo, JudgeLoad, JudgeResult, JudgeTask, JudgeTaskChunk,
+    JudgeToApiMessage, Language, SubmissionResult, TestCase, TestCaseJudgeResult,
 };
 use koioj_common::{bail, error::Context};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::{sync::Arc, time::Instant};
-use tokio::sync::{RwLock, mpsc};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, atomic::Ordering},
+    time::Instant,
+};
+use tokio::sync::{RwLock, mpsc, oneshot};
 use utoipa::ToSchema;
 
-use crate::{AppState, Result, State, error::Error};
+use crate::{
+    AppState, Result, State,
+    error::Error,
+    fingerprint,
+    webhook::{self, SubmissionJudged},
+};
 
 pub fn routes(_state: Arc<AppState>) -> Router<Arc<AppState>> {
     use axum::routing::*;
@@ -30,20 +40,73 @@ pub fn routes(_state: Arc<AppState>) -> Router<Arc<AppState>> {
                 .layer(DefaultBodyLimit::max(1024 * 1024 * 1024)),
         )
         .route("/supported-languages", get(get_supported_languages))
+        .route("/eta", get(get_judge_eta))
 }
 
+/// how many recently judged submissions the throughput estimate is based on
+const ETA_SAMPLE_SIZE: i64 = 50;
+
+/// submissions pending for longer are stuck rather than queued, e.g. after a
+/// crash, and would otherwise inflate every estimate for good
+const ETA_MAX_PENDING_MINUTES: i32 = 30;
+
+/// hands the outcome of a run back to the request waiting for it, judge errors
+/// come through as `Err`
+pub type RunSender = oneshot::Sender<std::result::Result<JudgeResult, String>>;
+
 #[derive(Clone)]
 pub struct JudgeConnection {
     pub info: JudgeInfo,
     pub load: JudgeLoad,
     pub sender: mpsc::UnboundedSender<ApiToJudgeMessage>,
     pub last_heartbeat: Arc<RwLock<Instant>>,
+    /// submissions sent over this connection that haven't come back yet, kept
+    /// here since the reported load lags behind by up to a heartbeat
+    dispatched: Arc<std::sync::Mutex<HashSet<i32>>>,
+    /// cleared once the judge says it's shutting down, it gets no new tasks
+    /// but still finishes the ones it has
+    accepting: bool,
 }
 impl JudgeConnection {
+    pub fn new(info: JudgeInfo, sender: mpsc::UnboundedSender<ApiToJudgeMessage>) -> Self {
+        Self {
+            info,
+            load: JudgeLoad {
+                running_tasks: 0,
+                cpu_usage: 0.0,
+                memory_usage: 0.0,
+            },
+            sender,
+            last_heartbeat: Arc::new(RwLock::new(Instant::now())),
+            dispatched: Default::default(),
+            accepting: true,
+        }
+    }
+
     pub fn load_score(&self) -> f32 {
-        (self.load.running_tasks as f32) * 100.0
-            + self.load.cpu_usage * 0.5
-            + self.load.memory_usage * 0.3
+        let tasks = self.load.running_tasks.max(self.in_flight());
+        (tasks as f32) * 100.0 + self.load.cpu_usage * 0.5 + self.load.memory_usage * 0.3
+    }
+
+    async fn is_available(&self, lang: Language, now: Instant) -> bool {
+        let last_heartbeat = *self.last_heartbeat.read().await;
+        self.accepting
+            && now.duration_since(last_heartbeat).as_secs() < 60
+            && self.info.languages.contains(&lang)
+    }
+
+    pub fn in_flight(&self) -> u32 {
+        self.dispatched.lock().unwrap().len() as u32
+    }
+
+    fn was_sent(&self, submission_id: i32) -> bool {
+        self.dispatched.lock().unwrap().contains(&submission_id)
+    }
+
+    /// results of tasks sent over an earlier connection, replayed after a
+    /// reconnect, or sent twice don't count
+    fn task_finished(&self, submission_id: i32) {
+        self.dispatched.lock().unwrap().remove(&submission_id);
     }
 }
 
@@ -59,10 +122,7 @@ impl crate::AppState {
         let now = Instant::now();
         let mut available_judges = Vec::new();
         for (id, conn) in judges.iter() {
-            let last_heartbeat = *conn.last_heartbeat.read().await;
-            if now.duration_since(last_heartbeat).as_secs() < 60
-                && conn.info.languages.contains(&lang)
-            {
+            if conn.is_available(lang, now).await {
                 available_judges.push((id, conn));
             }
         }
@@ -99,6 +159,20 @@ impl crate::AppState {
         Ok(candidates[selected_idx].clone())
     }
 
+    pub async fn live_judge_count(&self) -> usize {
+        let judges = self.judges.read().await;
+
+        let now = Instant::now();
+        let mut count = 0;
+        for conn in judges.values() {
+            let last_heartbeat = *conn.last_heartbeat.read().await;
+            if conn.accepting && now.duration_since(last_heartbeat).as_secs() < 60 {
+                count += 1;
+            }
+        }
+        count
+    }
+
     pub async fn send_judge_task(&self, judge_id: &str, task: JudgeTask) -> Result<()> {
         let judges = self.judges.read().await;
 
@@ -106,16 +180,140 @@ impl crate::AppState {
             .get(judge_id)
             .ok_or_else(|| Error::msg(format!("judge not found: {}", judge_id)))?;
 
+        let submission_id = task.submission_id;
         conn.sender
             .send(ApiToJudgeMessage::JudgeTask(task))
             .map_err(|e| Error::msg(format!("failed to send task: {}", e)))?;
+        conn.dispatched.lock().unwrap().insert(submission_id);
 
         Ok(())
     }
 
-    pub async fn submit_judge_task(&self, task: JudgeTask) -> Result<()> {
-        let judge_id = self.select_judge(task.lang).await?;
-        self.send_judge_task(&judge_id, task).await
+    async fn preferred_judge_available(&self, judge_id: &str, lang: Language) -> bool {
+        match self.judges.read().await.get(judge_id) {
+            Some(conn) => conn.is_available(lang, Instant::now()).await,
+            None => false,
+        }
+    }
+
+    /// Dispatch to `preferred_judge_id` if it can take the task, e.g. the one
+    /// that judged a submission before so that a rejudge gives comparable
+    /// numbers, and to the least loaded judge otherwise
+    pub async fn submit_judge_task(
+        &self,
+        task: JudgeTask,
+        preferred_judge_id: Option<&str>,
+    ) -> Result<()> {
+        let judge_id = match preferred_judge_id {
+            Some(id) if self.preferred_judge_available(id, task.lang).await => id.to_string(),
+            _ => self.select_judge(task.lang).await?,
+        };
+        let submission_id = task.submission_id;
+        self.send_judge_task(&judge_id, task).await?;
+
+        // runs have no submission to record it on, and the task is already
+        // out so failing to record it isn't worth failing the submission
+        if submission_id >= 0 {
+            if let Err(e) = sqlx::query!(
+                r#"
+                UPDATE submissions SET judge_id = $1 WHERE id = $2
+                "#,
+                judge_id,
+                submission_id
+            )
+            .execute(&self.pool)
+            .await
+            {
+                tracing::error!(
+                    "Failed to record judge of submission {}: {:?}",
+                    submission_id,
+                    e
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Judge a task that isn't backed by a submission and wait for the result.
+    /// Runs get negative ids so that judges can tell them apart from submissions
+    /// without any change to the protocol
+    pub async fn run_judge_task(&self, mut task: JudgeTask) -> Result<JudgeResult> {
+        let run_id = self.next_run_id.fetch_sub(1, Ordering::Relaxed);
+        task.submission_id = run_id;
+
+        let (tx, rx) = oneshot::channel();
+        self.runs.lock().unwrap().insert(run_id, tx);
+
+        let result = match self.submit_judge_task(task, None).await {
+            Ok(()) => {
+                let timeout = self.config.run_timeout.to_std().unwrap_or_default();
+                tokio::time::timeout(timeout, rx).await
+            }
+            Err(e) => {
+                self.runs.lock().unwrap().remove(&run_id);
+                return Err(e);
+            }
+        };
+        self.runs.lock().unwrap().remove(&run_id);
+
+        match result {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(msg))) => Err(Error::msg(format!("judge error: {}", msg))),
+            Ok(Err(_)) => Err(Error::msg("judge disconnected")),
+            Err(_) => bail!(@GATEWAY_TIMEOUT "run timed out"),
+        }
+    }
+
+    /// Hold back the test cases of a submission, to be handed out batch by batch
+    /// with `next_test_case_batch`, the first one going out with the task
+    pub fn queue_test_cases(&self, submission_id: i32, test_case_ids: Vec<i32>) {
+        self.queued_test_cases
+            .lock()
+            .unwrap()
+            .insert(submission_id, test_case_ids.into());
+    }
+
+    pub async fn next_test_case_batch(&self, submission_id: i32) -> Result<Vec<TestCase>> {
+        let ids: Vec<i32> = {
+            let mut queued = self.queued_test_cases.lock().unwrap();
+            let Some(queue) = queued.get_mut(&submission_id) else {
+                return Ok(Vec::new());
+            };
+            let batch_size = self.config.test_case_batch_size.max(1).min(queue.len());
+            queue.drain(..batch_size).collect()
+        };
+
+        let mut test_cases = Vec::with_capacity(ids.len());
+        for id in ids {
+            test_cases.push(TestCase {
+                id,
+                data: self.read_test_cases(id).await?,
+            });
+        }
+        Ok(test_cases)
+    }
+
+    pub fn forget_test_cases(&self, submission_id: i32) {
+        self.queued_test_cases
+            .lock()
+            .unwrap()
+            .remove(&submission_id);
+    }
+
+    /// Hand a judge message about a run to the request waiting for it, returns
+    /// false if `id` belongs to a submission
+    fn finish_run(&self, id: i32, result: std::result::Result<JudgeResult, String>) -> bool {
+        if id >= 0 {
+            return false;
+        }
+        match self.runs.lock().unwrap().remove(&id) {
+            Some(tx) => {
+                let _ = tx.send(result);
+            }
+            None => tracing::warn!("Result for unknown or expired run {}", id),
+        }
+        true
     }
 }
 
@@ -124,13 +322,34 @@ impl crate::AppState {
     path = "/api/judge/ws",
     responses(
         (status = 101, description = "WebSocket connection established"),
+        (status = 401, description = "missing or wrong judge token"),
     ),
     tag = "judge"
 )]
-pub async fn judge_ws(ws: WebSocketUpgrade, state: State) -> Response {
-    ws.max_message_size(1024 * 1024 * 1024)
+pub async fn judge_ws(ws: WebSocketUpgrade, state: State, headers: HeaderMap) -> Result<Response> {
+    // keep strangers from opening sockets at all, registering is still signed
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !token.is_some_and(|token| tokens_match(token, &state.config.judge_token)) {
+        bail!(@UNAUTHORIZED "invalid judge token");
+    }
+
+    Ok(ws
+        .max_message_size(1024 * 1024 * 1024)
         .max_frame_size(1024 * 1024 * 1024)
-        .on_upgrade(|socket| handle_socket(socket, state))
+        .on_upgrade(|socket| handle_socket(socket, state)))
+}
+
+/// compares in constant time, so that the token can't be guessed byte by byte
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
 }
 
 async fn handle_socket(socket: WebSocket, state: State) {
@@ -175,9 +394,7 @@ async fn handle_socket(socket: WebSocket, state: State) {
 
         // cleaning
         if let Some(id) = judge_id {
-            let mut judges = state.judges.write().await;
-            judges.remove(&id);
-            tracing::info!("Judge {} disconnected", id);
+            judge_disconnected(&state, &id).await;
         }
     });
 
@@ -186,7 +403,40 @@ async fn handle_socket(socket: WebSocket, state: State) {
         _ = &mut recv_task => send_task.abort(),
     }
 }
-async fn handle_judge_message(
+
+pub(crate) async fn judge_disconnected(state: &State, judge_id: &str) {
+    if let Some(conn) = state.judges.write().await.remove(judge_id) {
+        // nobody is left to ask for the rest of their test cases
+        for submission_id in conn.dispatched.lock().unwrap().iter() {
+            state.forget_test_cases(*submission_id);
+        }
+    }
+    tracing::info!("Judge {} disconnected", judge_id);
+}
+
+/// Whether the task of `submission_id` went out over this judge's connection
+/// and hasn't come back yet
+async fn was_sent_to(state: &State, judge_id: &Option<String>, submission_id: i32) -> bool {
+    match judge_id {
+        Some(id) => state
+            .judges
+            .read()
+            .await
+            .get(id)
+            .is_some_and(|conn| conn.was_sent(submission_id)),
+        None => false,
+    }
+}
+
+async fn mark_task_finished(state: &State, judge_id: &Option<String>, submission_id: i32) {
+    if let Some(id) = judge_id {
+        if let Some(conn) = state.judges.read().await.get(id) {
+            conn.task_finished(submission_id);
+        }
+    }
+}
+
+pub(crate) async fn handle_judge_message(
     text: &str,
     state: &State,
     judge_id: &mut Option<String>,
@@ -232,16 +482,7 @@ async fn handle_judge_message(
                 info.version
             );
 
-            let conn = JudgeConnection {
-                info: info.clone(),
-                load: JudgeLoad {
-                    running_tasks: 0,
-                    cpu_usage: 0.0,
-                    memory_usage: 0.0,
-                },
-                sender: tx.clone(),
-                last_heartbeat: Arc::new(RwLock::new(Instant::now())),
-            };
+            let conn = JudgeConnection::new(info.clone(), tx.clone());
 
             let mut judges = state.judges.write().await;
             judges.insert(info.judge_id.clone(), conn);
@@ -267,7 +508,22 @@ async fn handle_judge_message(
 
             tx.send(ApiToJudgeMessage::Pong)?;
         }
+        JudgeToApiMessage::ShuttingDown => {
+            // stop dispatching to it, but keep the connection for the results
+            // and test case requests of its running tasks
+            if let Some(id) = judge_id {
+                if let Some(conn) = state.judges.write().await.get_mut(id) {
+                    conn.accepting = false;
+                }
+                tracing::info!("Judge {} is shutting down", id);
+            }
+        }
         JudgeToApiMessage::JudgeProgress(progress) => {
+            if !*registered {
+                tracing::warn!("Received progress from unregistered judge");
+                return Ok(());
+            }
+
             tracing::debug!(
                 "Submission {} progress: {}/{}",
                 progress.submission_id,
@@ -275,59 +531,237 @@ async fn handle_judge_message(
                 progress.total_tests
             );
             // TODO: somehow broadcast to frontend?
+
+            // runs aren't stored
+            if progress.submission_id < 0 {
+                return Ok(());
+            }
+            if !was_sent_to(state, judge_id, progress.submission_id).await {
+                tracing::warn!(
+                    "Judge {:?} sent progress of submission {} it doesn't run",
+                    judge_id,
+                    progress.submission_id
+                );
+                return Ok(());
+            }
+            // progress arriving after the final result must not overwrite it
+            if let Some(test_result) = progress.test_result {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO submission_test_cases
+                    (submission_id, test_case_id, result, time_consumption, mem_consumption,
+                     message)
+                    SELECT $1, $2, $3, $4, $5, $6
+                    WHERE EXISTS (
+                        SELECT 1 FROM submissions WHERE id = $1 AND result = 'pending'
+                    )
+                    ON CONFLICT (submission_id, test_case_id) DO UPDATE
+                    SET result = EXCLUDED.result,
+                        time_consumption = EXCLUDED.time_consumption,
+                        mem_consumption = EXCLUDED.mem_consumption,
+                        message = EXCLUDED.message
+                    "#,
+                    progress.submission_id,
+                    test_result.test_case_id,
+                    test_result.result as TestCaseJudgeResult,
+                    test_result.time_consumption,
+                    test_result.memory_consumption,
+                    test_result.message
+                )
+                .execute(&state.pool)
+                .await?;
+            }
+        }
+        JudgeToApiMessage::NextTestCases(submission_id) => {
+            if !*registered {
+                tracing::warn!("Received test case request from unregistered judge");
+                return Ok(());
+            }
+
+            // the test cases of a submission only go to the judge running it
+            let sent_here = was_sent_to(state, judge_id, submission_id).await;
+            if !sent_here {
+                tracing::warn!(
+                    "Judge {:?} asked for test cases of submission {} it doesn't run",
+                    judge_id,
+                    submission_id
+                );
+            }
+
+            // an empty batch tells the judge the test cases are gone
+            let test_cases = if !sent_here {
+                Vec::new()
+            } else {
+                state
+                    .next_test_case_batch(submission_id)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::error!(
+                            "Failed to read test cases of submission {}: {:?}",
+                            submission_id,
+                            e
+                        );
+                        Vec::new()
+                    })
+            };
+            tx.send(ApiToJudgeMessage::JudgeTaskChunk(JudgeTaskChunk {
+                submission_id,
+                test_cases,
+            }))?;
         }
         JudgeToApiMessage::JudgeResult(result) => {
+            let submission_id = result.submission_id;
+            mark_task_finished(state, judge_id, submission_id).await;
+            state.forget_test_cases(submission_id);
+            if submission_id < 0 {
+                state.finish_run(submission_id, Ok(result));
+                let _ = tx.send(ApiToJudgeMessage::Ack(submission_id));
+                return Ok(());
+            }
+
             tracing::info!(
                 "Submission {} result: {:?}, time: {}ms, memory: {}KB",
-                result.submission_id,
+                submission_id,
                 result.result,
                 result.time_consumption,
                 result.memory_consumption
             );
 
-            let submission = sqlx::query!(
+            let Some(submission) = sqlx::query!(
                 r#"
-                SELECT user_id, problem_id, contest_id, created_at
+                SELECT user_id, problem_id, contest_id, lang, is_virtual, is_upsolve, created_at
                 FROM submissions
                 WHERE id = $1
                 "#,
-                result.submission_id
+                submission_id
             )
-            .fetch_one(&state.pool)
-            .await?;
+            .fetch_optional(&state.pool)
+            .await?
+            else {
+                tracing::warn!("Result for deleted submission {}", submission_id);
+                let _ = tx.send(ApiToJudgeMessage::Ack(submission_id));
+                return Ok(());
+            };
 
-            sqlx::query!(
+            // all or nothing, a resent result finds the submission still pending
+            let mut db_tx = state.pool.begin().await?;
+
+            // a result may arrive twice once resent, only the first one counts
+            let updated = sqlx::query!(
                 r#"
                 UPDATE submissions 
-                SET result = $1, time_consumption = $2, mem_consumption = $3, updated_at = NOW()
-                WHERE id = $4
+                SET result = $1, time_consumption = $2, mem_consumption = $3,
+                    failed_on_sample = $4, compile_message = $5,
+                    judge_id = COALESCE($6, judge_id), updated_at = NOW()
+                WHERE id = $7 AND result = 'pending'
                 "#,
                 result.result as SubmissionResult,
                 result.time_consumption,
                 result.memory_consumption,
-                result.submission_id
+                result.failed_on_sample,
+                result.compile_message,
+                judge_id.as_deref(),
+                submission_id
             )
-            .execute(&state.pool)
-            .await?;
+            .execute(&mut *db_tx)
+            .await?
+            .rows_affected();
+            if updated == 0 {
+                tracing::debug!("Duplicate result for submission {}", submission_id);
+                let _ = tx.send(ApiToJudgeMessage::Ack(submission_id));
+                return Ok(());
+            }
+
+            // at most one, the judge keeps only the first
+            let diff = match result.sample_diff {
+                Some(diff) => Some((None, diff)),
+                None => result
+                    .test_results
+                    .iter()
+                    .find_map(|r| Some((Some(r.test_case_id), r.diff.clone()?))),
+            };
+            if let Some((test_case_id, diff)) = diff {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO submission_diffs
+                    (submission_id, test_case_id, line, expected, actual)
+                    VALUES ($1, $2, $3, $4, $5)
+                    "#,
+                    submission_id,
+                    test_case_id,
+                    diff.line as i32,
+                    diff.expected,
+                    diff.actual
+                )
+                .execute(&mut *db_tx)
+                .await?;
+            }
+
+            for sample_result in result.sample_results {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO submission_samples
+                    (submission_id, sample_index, result, time_consumption, mem_consumption,
+                     message)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    "#,
+                    submission_id,
+                    sample_result.test_case_id,
+                    sample_result.result as TestCaseJudgeResult,
+                    sample_result.time_consumption,
+                    sample_result.memory_consumption,
+                    sample_result.message
+                )
+                .execute(&mut *db_tx)
+                .await?;
+            }
 
             for test_result in result.test_results {
                 sqlx::query!(
                     r#"
                     INSERT INTO submission_test_cases 
-                    (submission_id, test_case_id, result, time_consumption, mem_consumption)
-                    VALUES ($1, $2, $3, $4, $5)
+                    (submission_id, test_case_id, result, time_consumption, mem_consumption,
+                     message)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT (submission_id, test_case_id) DO UPDATE
+                    SET result = EXCLUDED.result,
+                        time_consumption = EXCLUDED.time_consumption,
+                        mem_consumption = EXCLUDED.mem_consumption,
+                        message = EXCLUDED.message
                     "#,
-                    result.submission_id,
+                    submission_id,
                     test_result.test_case_id,
                     test_result.result as TestCaseJudgeResult,
                     test_result.time_consumption,
-                    test_result.memory_consumption
+                    test_result.memory_consumption,
+                    test_result.message
                 )
-                .execute(&state.pool)
+                .execute(&mut *db_tx)
                 .await?;
             }
 
-            if let Some(contest_id) = submission.contest_id {
+            db_tx.commit().await?;
+
+            fingerprint::fingerprint_submission(state, submission_id, submission.lang);
+
+            webhook::submission_judged(
+                state,
+                SubmissionJudged {
+                    submission_id,
+                    user_id: submission.user_id,
+                    problem_id: submission.problem_id,
+                    contest_id: submission.contest_id,
+                    result: result.result,
+                    time_consumption: result.time_consumption,
+                    mem_consumption: result.memory_consumption,
+                    created_at: submission.created_at,
+                },
+            );
+
+            if let Some(contest_id) = submission
+                .contest_id
+                .filter(|_| !submission.is_virtual && !submission.is_upsolve)
+            {
                 if let Err(e) = crate::route::contests::ranking_cache::update_ranking_on_submission(
                     &state,
                     contest_id,
@@ -342,27 +776,41 @@ async fn handle_judge_message(
                     // Don't fail the whole operation if cache update fails
                 }
             }
+
+            // only now, so that the judge resends it if storing failed
+            let _ = tx.send(ApiToJudgeMessage::Ack(submission_id));
         }
         JudgeToApiMessage::Error(id, msg) => {
+            mark_task_finished(state, judge_id, id).await;
+            state.forget_test_cases(id);
+            if state.finish_run(id, Err(msg.clone())) {
+                tracing::warn!("Run {} judge error: {}", id, msg);
+                let _ = tx.send(ApiToJudgeMessage::Ack(id));
+                return Ok(());
+            }
             tracing::error!("Submission {} judge error: {}", id, msg);
 
             // Get submission info to check if it's in a contest
-            let submission = sqlx::query!(
+            let Some(submission) = sqlx::query!(
                 r#"
-                SELECT user_id, problem_id, contest_id, created_at
+                SELECT user_id, problem_id, contest_id, is_virtual, is_upsolve, created_at
                 FROM submissions
                 WHERE id = $1
                 "#,
                 id
             )
-            .fetch_one(&state.pool)
-            .await?;
+            .fetch_optional(&state.pool)
+            .await?
+            else {
+                let _ = tx.send(ApiToJudgeMessage::Ack(id));
+                return Ok(());
+            };
 
-            sqlx::query!(
+            let updated = sqlx::query!(
                 r#"
                 UPDATE submissions 
                 SET result = $1, time_consumption = $2, mem_consumption = $3, updated_at = NOW()
-                WHERE id = $4
+                WHERE id = $4 AND result = 'pending'
                 "#,
                 SubmissionResult::UnknownError as SubmissionResult,
                 0,
@@ -370,11 +818,34 @@ async fn handle_judge_message(
                 id
             )
             .execute(&state.pool)
-            .await?;
+            .await?
+            .rows_affected();
+            if updated == 0 {
+                tracing::debug!("Duplicate error for submission {}", id);
+                let _ = tx.send(ApiToJudgeMessage::Ack(id));
+                return Ok(());
+            }
+
+            webhook::submission_judged(
+                state,
+                SubmissionJudged {
+                    submission_id: id,
+                    user_id: submission.user_id,
+                    problem_id: submission.problem_id,
+                    contest_id: submission.contest_id,
+                    result: SubmissionResult::UnknownError,
+                    time_consumption: 0,
+                    mem_consumption: 0,
+                    created_at: submission.created_at,
+                },
+            );
 
             // Update ranking cache if this is a contest submission
             // UnknownError is treated as a failed attempt
-            if let Some(contest_id) = submission.contest_id {
+            if let Some(contest_id) = submission
+                .contest_id
+                .filter(|_| !submission.is_virtual && !submission.is_upsolve)
+            {
                 if let Err(e) = crate::route::contests::ranking_cache::update_ranking_on_submission(
                     &state,
                     contest_id,
@@ -389,6 +860,8 @@ async fn handle_judge_message(
                     // Don't fail the whole operation if cache update fails
                 }
             }
+
+            let _ = tx.send(ApiToJudgeMessage::Ack(id));
         }
     }
 
@@ -399,12 +872,13 @@ async fn handle_judge_message(
 #[serde(rename_all = "camelCase")]
 pub(crate) struct GetSupportedLanguagesResponse {
     languages: Vec<Language>,
+    /// versions reported by the judges, which may differ from judge to judge
+    versions: BTreeMap<Language, Vec<String>>,
 }
 
 #[utoipa::path(
     get,
     path = "/api/judge/supported-languages",
-    security(("bearer_auth" = [])),
     responses(
         (status = 200, body = GetSupportedLanguagesResponse),
     ),
@@ -413,21 +887,582 @@ pub(crate) struct GetSupportedLanguagesResponse {
 async fn get_supported_languages(state: State) -> Result<Json<GetSupportedLanguagesResponse>> {
     let judges = state.judges.read().await;
 
-    let mut languages = std::collections::HashSet::new();
+    let mut languages = HashSet::new();
+    let mut versions: BTreeMap<Language, Vec<String>> = BTreeMap::new();
 
     let now = tokio::time::Instant::now();
     for (_id, conn) in judges.iter() {
         let last_heartbeat = *conn.last_heartbeat.read().await;
-        // only count active judges (within 60 seconds)
-        if now.duration_since(last_heartbeat.into()).as_secs() < 60 {
+        // only count active judges (within 60 seconds) still taking tasks
+        if conn.accepting && now.duration_since(last_heartbeat.into()).as_secs() < 60 {
             for lang in &conn.info.languages {
                 languages.insert(lang.clone());
             }
+            for (lang, version) in &conn.info.language_versions {
+                let known = versions.entry(*lang).or_default();
+                if !known.contains(version) {
+                    known.push(version.clone());
+                }
+            }
         }
     }
 
     let mut languages: Vec<Language> = languages.into_iter().collect();
     languages.sort();
 
-    Ok(Json(GetSupportedLanguagesResponse { languages }))
+    for known in versions.values_mut() {
+        known.sort();
+    }
+
+    Ok(Json(GetSupportedLanguagesResponse {
+        languages,
+        versions,
+    }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetJudgeEtaResponse {
+    pending_submissions: i64,
+    live_judges: usize,
+    /// average time from submission to result over recent submissions, in ms
+    average_judge_time: i64,
+    /// estimated time until a new submission gets its result, in ms
+    estimated_wait: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/judge/eta",
+    responses(
+        (status = 200, body = GetJudgeEtaResponse),
+    ),
+    tag = "judge"
+)]
+async fn get_judge_eta(state: State) -> Result<Json<GetJudgeEtaResponse>> {
+    let pending_submissions: i64 = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!" FROM submissions
+        WHERE result = 'pending' AND created_at > NOW() - make_interval(mins => $1)
+        "#,
+        ETA_MAX_PENDING_MINUTES
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let average_judge_time: i64 = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(
+            AVG(EXTRACT(EPOCH FROM (updated_at - created_at)) * 1000)::BIGINT,
+            0
+        ) as "avg!"
+        FROM (
+            SELECT created_at, updated_at FROM submissions
+            WHERE result != 'pending'
+            ORDER BY updated_at DESC
+            LIMIT $1
+        ) recent
+        "#,
+        ETA_SAMPLE_SIZE
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let live_judges = state.live_judge_count().await;
+
+    // the queue is drained by all live judges in parallel,
+    // and the new submission itself needs one more round
+    let estimated_wait = (pending_submissions / live_judges.max(1) as i64 + 1) * average_judge_time;
+
+    Ok(Json(GetJudgeEtaResponse {
+        pending_submissions,
+        live_judges,
+        average_judge_time,
+        estimated_wait,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use koioj_common::judge::{
+        ApiToJudgeMessage, JudgeProgress, JudgeTask, JudgeToApiMessage, Language, SubmissionResult,
+        TestCaseJudgeResult,
+    };
+    use serde_json::json;
+
+    use crate::test_util::{TestApp, judge_result, test_result};
+
+    #[sqlx::test(migrations = false)]
+    async fn eta_ignores_stuck_pending_submissions(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        app.seed_submission(student_id, problem_id, "pending", 1)
+            .await;
+        app.seed_submission(student_id, problem_id, "pending", 2)
+            .await;
+        app.seed_submission(student_id, problem_id, "pending", 24 * 60)
+            .await;
+
+        let response = app.get("/api/judge/eta", &student).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body["pendingSubmissions"], 2);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn progress_shows_finished_test_cases_while_judging(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+        let task = judge.task().await;
+        let test_case_id = task.test_cases[0].id;
+
+        judge
+            .send(JudgeToApiMessage::JudgeProgress(JudgeProgress {
+                submission_id,
+                completed_tests: 1,
+                total_tests: 2,
+                test_result: Some(test_result(test_case_id, TestCaseJudgeResult::Accepted)),
+            }))
+            .await;
+        let uri = format!("/api/problems/{}/submissions/{}", problem_id, submission_id);
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.body["result"], "pending");
+        let results = response.body["testCaseResults"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["result"], "accepted");
+
+        // the final result overwrites what progress stored
+        judge
+            .send(judge_result(
+                submission_id,
+                SubmissionResult::WrongAnswer,
+                vec![test_result(test_case_id, TestCaseJudgeResult::WrongAnswer)],
+            ))
+            .await;
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.body["result"], "wrong_answer");
+        let results = response.body["testCaseResults"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["result"], "wrong_answer");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn late_or_stray_progress_leaves_stored_results_alone(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+        let task = judge.task().await;
+        let test_case_id = task.test_cases[0].id;
+        let progress = JudgeToApiMessage::JudgeProgress(JudgeProgress {
+            submission_id,
+            completed_tests: 1,
+            total_tests: 1,
+            test_result: Some(test_result(test_case_id, TestCaseJudgeResult::Accepted)),
+        });
+        let uri = format!("/api/problems/{}/submissions/{}", problem_id, submission_id);
+
+        // a judge that wasn't sent the task can't report on it
+        let other = app.judge("other", &[Language::Cpp]).await;
+        other.send(progress.clone()).await;
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.body["testCaseResults"], json!([]));
+
+        judge
+            .send(judge_result(
+                submission_id,
+                SubmissionResult::WrongAnswer,
+                vec![test_result(test_case_id, TestCaseJudgeResult::WrongAnswer)],
+            ))
+            .await;
+        judge.send(progress).await;
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.body["result"], "wrong_answer");
+        let results = response.body["testCaseResults"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["result"], "wrong_answer");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn dispatched_tasks_steer_away_from_the_busy_judge(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let mut judges = [
+            app.judge("first", &[Language::Cpp]).await,
+            app.judge("second", &[Language::Cpp]).await,
+        ];
+
+        // no judge has reported any load, only what was sent to it tells them
+        // apart
+        for _ in 0..2 {
+            app.submit(&student, problem_id, "int main() {}").await;
+            app.submit(&student, problem_id, "int main() {}").await;
+            for judge in judges.iter_mut() {
+                judge.task().await;
+            }
+        }
+        let connections = app.state.judges.read().await;
+        for conn in connections.values() {
+            assert_eq!(conn.in_flight(), 2);
+        }
+    }
+
+    fn task(submission_id: i32, lang: Language) -> JudgeTask {
+        JudgeTask {
+            submission_id,
+            lang,
+            code: String::new(),
+            time_limit: 1000,
+            memory_limit: 256,
+            test_cases: Vec::new(),
+            samples: Vec::new(),
+            continue_after_samples: false,
+            keep_output: false,
+            remaining_test_cases: 0,
+            reveal_diff: false,
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn rejudge_goes_to_the_preferred_judge_while_it_can(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, _) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let submission_id = app
+            .seed_submission(student_id, problem_id, "pending", 0)
+            .await;
+        let mut first = app.judge("first", &[Language::Cpp, Language::Python]).await;
+        let mut second = app.judge("second", &[Language::Cpp]).await;
+        let judge_of = || {
+            sqlx::query_scalar!(
+                "SELECT judge_id FROM submissions WHERE id = $1",
+                submission_id
+            )
+            .fetch_one(&app.pool)
+        };
+
+        // busier than the other, and still picked
+        for _ in 0..3 {
+            app.state
+                .submit_judge_task(task(submission_id, Language::Cpp), Some("second"))
+                .await
+                .unwrap();
+            assert_eq!(second.task().await.submission_id, submission_id);
+        }
+        assert_eq!(judge_of().await.unwrap().as_deref(), Some("second"));
+
+        // the preferred judge can't run python
+        app.state
+            .submit_judge_task(task(submission_id, Language::Python), Some("second"))
+            .await
+            .unwrap();
+        first.task().await;
+        assert_eq!(judge_of().await.unwrap().as_deref(), Some("first"));
+
+        second.disconnect().await;
+        app.state
+            .submit_judge_task(task(submission_id, Language::Cpp), Some("second"))
+            .await
+            .unwrap();
+        first.task().await;
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn reported_versions_show_up_with_the_languages(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        app.judge("first", &[Language::Cpp]).await;
+        app.judge("second", &[Language::Cpp, Language::Python])
+            .await;
+        let versions = [("first", "g++ 13.2"), ("second", "g++ 11.4")];
+        for (id, version) in versions {
+            app.state
+                .judges
+                .write()
+                .await
+                .get_mut(id)
+                .unwrap()
+                .info
+                .language_versions
+                .insert(Language::Cpp, version.to_string());
+        }
+
+        let response = app
+            .send(Method::GET, "/api/judge/supported-languages", None, None)
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.body["languages"],
+            serde_json::json!(["cpp", "python"])
+        );
+        assert_eq!(
+            response.body["versions"],
+            serde_json::json!({ "cpp": ["g++ 11.4", "g++ 13.2"] })
+        );
+    }
+
+    /// the status the app answers a websocket upgrade of the judge with
+    async fn upgrade_status(addr: std::net::SocketAddr, token: Option<&str>) -> u16 {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let authorization = token
+            .map(|token| format!("Authorization: Bearer {}\r\n", token))
+            .unwrap_or_default();
+        let request = format!(
+            "GET /api/judge/ws HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\n\
+             Upgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n{}\r\n",
+            addr, authorization
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        let mut buf = [0; 1024];
+        while !response.windows(2).any(|w| w == b"\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_ne!(n, 0, "connection closed without a response");
+            response.extend_from_slice(&buf[..n]);
+        }
+        let status_line = String::from_utf8_lossy(&response);
+        status_line.split(' ').nth(1).unwrap().parse().unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn upgrade_needs_the_judge_token(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let addr = app.serve().await;
+        let token = app.state.config.judge_token.clone();
+
+        assert_eq!(upgrade_status(addr, None).await, 401);
+        assert_eq!(upgrade_status(addr, Some("wrong-token")).await, 401);
+        assert_eq!(upgrade_status(addr, Some(&token)).await, 101);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn result_replayed_after_a_reconnect_is_stored(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let first = app.submit(&student, problem_id, "int main() {}").await;
+        let test_case_id = judge.task().await.test_cases[0].id;
+
+        // the result was on its way when the connection dropped
+        judge.disconnect().await;
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        app.state
+            .submit_judge_task(task(-7, Language::Cpp), None)
+            .await
+            .unwrap();
+        judge.task().await;
+        let accepted = vec![test_result(test_case_id, TestCaseJudgeResult::Accepted)];
+        judge
+            .send(judge_result(first, SubmissionResult::Accepted, accepted))
+            .await;
+        assert_eq!(app.judged(first).await, "accepted");
+
+        // it wasn't sent over this connection, so the one that was still counts
+        let in_flight = app.state.judges.read().await["judge"].in_flight();
+        assert_eq!(in_flight, 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn duplicate_result_is_stored_once(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -10, 60).await;
+        sqlx::query!(
+            "INSERT INTO contest_participants (contest_id, user_id) VALUES ($1, $2)",
+            contest_id,
+            student_id
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+        let ranking = format!("/api/contests/{}/ranking", contest_id);
+        app.get(&ranking, &student).await;
+        let mut events = app.state.subscribe_ranking(contest_id);
+
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let uri = format!("/api/problems/{}/submissions", problem_id);
+        let body =
+            serde_json::json!({ "code": "int main() {}", "lang": "cpp", "contestId": contest_id });
+        let response = app.post(&uri, &student, body).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let submission_id = response.body["submissionId"].as_i64().unwrap() as i32;
+        let test_case_id = judge.task().await.test_cases[0].id;
+        let wrong = || {
+            judge_result(
+                submission_id,
+                SubmissionResult::WrongAnswer,
+                vec![test_result(test_case_id, TestCaseJudgeResult::WrongAnswer)],
+            )
+        };
+        judge.send(wrong()).await;
+        judge.send(wrong()).await;
+
+        let rows = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM submission_test_cases WHERE submission_id = $1"#,
+            submission_id
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(rows, 1);
+        assert!(events.try_recv().is_ok());
+        assert!(events.try_recv().is_err());
+        let response = app.get(&ranking, &student).await;
+        assert_eq!(
+            response.body["rankings"][0]["problemResults"][0]["attempts"],
+            1
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn judge_of_a_submission_is_recorded_for_staff(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let mut judge = app.judge("judge-7", &[Language::Cpp]).await;
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+        let test_case_id = judge.task().await.test_cases[0].id;
+        let accepted = vec![test_result(test_case_id, TestCaseJudgeResult::Accepted)];
+        judge
+            .send(judge_result(
+                submission_id,
+                SubmissionResult::Accepted,
+                accepted,
+            ))
+            .await;
+
+        let uri = format!("/api/problems/{}/submissions/{}", problem_id, submission_id);
+        let response = app.get(&uri, &teacher).await;
+        assert_eq!(response.body["judgeId"], "judge-7");
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.body["result"], "accepted");
+        assert!(response.body.get("judgeId").is_none());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_cases_go_out_in_batches_to_the_judge_running_it(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| config.test_case_batch_size = 1).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}/test-cases", problem_id);
+        let more = json!({ "testCases": [
+            { "input": "2 3\n", "output": "5\n" },
+            { "input": "4 5\n", "output": "9\n" },
+        ] });
+        let response = app.post(&uri, &teacher, more).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+        let task = judge.task().await;
+        assert_eq!(task.test_cases.len(), 1);
+        assert_eq!(task.remaining_test_cases, 2);
+
+        // a judge that wasn't sent the task gets nothing, and takes nothing
+        // from the one that was
+        let mut other = app.judge("other", &[Language::Cpp]).await;
+        other
+            .send(JudgeToApiMessage::NextTestCases(submission_id))
+            .await;
+        let ApiToJudgeMessage::JudgeTaskChunk(chunk) = other.next().await else {
+            panic!("expected a chunk");
+        };
+        assert!(chunk.test_cases.is_empty());
+        other.disconnect().await;
+
+        // batch by batch, the judge gets what it would have in one go
+        let mut inputs: Vec<String> = task.test_cases.into_iter().map(|t| t.data.input).collect();
+        for _ in 0..3 {
+            judge
+                .send(JudgeToApiMessage::NextTestCases(submission_id))
+                .await;
+            let ApiToJudgeMessage::JudgeTaskChunk(chunk) = judge.next().await else {
+                panic!("expected a chunk");
+            };
+            inputs.extend(chunk.test_cases.into_iter().map(|t| t.data.input));
+        }
+        assert_eq!(inputs, ["1 2\n", "2 3\n", "4 5\n"]);
+
+        // the rest of an unfinished task is dropped with its judge
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+        judge.task().await;
+        judge.disconnect().await;
+        let queued = app.state.queued_test_cases.lock().unwrap();
+        assert!(!queued.contains_key(&submission_id));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn shutting_down_judge_drains_its_tasks_but_gets_no_new_ones(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| config.test_case_batch_size = 1).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}/test-cases", problem_id);
+        let more = json!({ "testCases": [
+            { "input": "2 3\n", "output": "5\n" },
+            { "input": "4 5\n", "output": "9\n" },
+        ] });
+        let response = app.post(&uri, &teacher, more).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+        let task = judge.task().await;
+        judge.send(JudgeToApiMessage::ShuttingDown).await;
+
+        let mut test_cases = task.test_cases;
+        for _ in 0..2 {
+            judge
+                .send(JudgeToApiMessage::NextTestCases(submission_id))
+                .await;
+            let ApiToJudgeMessage::JudgeTaskChunk(chunk) = judge.next().await else {
+                panic!("expected a chunk");
+            };
+            test_cases.extend(chunk.test_cases);
+        }
+        let inputs: Vec<&str> = test_cases.iter().map(|t| t.data.input.as_str()).collect();
+        assert_eq!(inputs, ["1 2\n", "2 3\n", "4 5\n"]);
+
+        // nothing new goes its way
+        assert!(app.state.select_judge(Language::Cpp).await.is_err());
+        assert_eq!(app.state.live_judge_count().await, 0);
+        let response = app.get("/api/judge/supported-languages", &student).await;
+        assert_eq!(response.body["languages"], json!([]));
+
+        // and the result of the task it kept running still counts
+        let accepted = test_cases
+            .iter()
+            .map(|t| test_result(t.id, TestCaseJudgeResult::Accepted))
+            .collect();
+        judge
+            .send(judge_result(
+                submission_id,
+                SubmissionResult::Accepted,
+                accepted,
+            ))
+            .await;
+        assert_eq!(app.judged(submission_id).await, "accepted");
+    }
 }
@@ -1,5 +1,10 @@
-use crate::{AppState, Result};
-use axum::{Json, Router};
+use crate::{AppState, Result, State};
+use axum::{
+    Json, Router,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 use std::sync::Arc;
 use utoipa::ToSchema;
@@ -9,6 +14,7 @@ pub fn top_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/ping", get(ping))
         .route("/version", get(version))
+        .route("/health", get(health))
 }
 
 #[utoipa::path(
@@ -27,6 +33,10 @@ async fn ping() -> Result<String> {
 #[serde(rename_all = "camelCase")]
 pub(crate) struct VersionResponse {
     api_version: String,
+    git_hash: String,
+    build_time: Option<DateTime<Utc>>,
+    /// seconds since the server started
+    uptime: u64,
 }
 
 #[utoipa::path(
@@ -37,10 +47,115 @@ pub(crate) struct VersionResponse {
     ),
     tag = "health"
 )]
-async fn version() -> Result<Json<VersionResponse>> {
+async fn version(state: State) -> Result<Json<VersionResponse>> {
     let version_info = VersionResponse {
         api_version: env!("CARGO_PKG_VERSION").to_string(),
+        git_hash: env!("GIT_HASH").to_string(),
+        build_time: env!("BUILD_TIME")
+            .parse()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+        uptime: state.started.elapsed().as_secs(),
     };
 
     Ok(Json(version_info))
 }
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HealthResponse {
+    healthy: bool,
+    database: bool,
+    redis: bool,
+    /// seconds since the server started
+    uptime: u64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    responses(
+        (status = 200, body = HealthResponse),
+        (status = 503, body = HealthResponse, description = "a dependency is down")
+    ),
+    tag = "health"
+)]
+async fn health(state: State) -> Response {
+    let database = sqlx::query("SELECT 1")
+        .execute(&state.pool)
+        .await
+        .inspect_err(|e| tracing::warn!("health check: database error: {}", e))
+        .is_ok();
+
+    let mut redis_conn = state.redis.clone();
+    let redis = redis::cmd("PING")
+        .query_async::<String>(&mut redis_conn)
+        .await
+        .inspect_err(|e| tracing::warn!("health check: redis error: {}", e))
+        .is_ok();
+
+    let healthy = database && redis;
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(HealthResponse {
+            healthy,
+            database,
+            redis,
+            uptime: state.started.elapsed().as_secs(),
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use sqlx::PgPool;
+    use std::time::Duration;
+
+    use crate::test_util::TestApp;
+
+    #[sqlx::test(migrations = false)]
+    async fn health_reports_each_dependency(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+
+        let res = app.send(Method::GET, "/api/health", None, None).await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert_eq!(res.body["healthy"], true);
+        assert_eq!(res.body["database"], true);
+        assert_eq!(res.body["redis"], true);
+
+        app.pool.close().await;
+        let res = app.send(Method::GET, "/api/health", None, None).await;
+        assert_eq!(res.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.body["healthy"], false);
+        assert_eq!(res.body["database"], false);
+        assert_eq!(res.body["redis"], true);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn version_reports_the_build_and_a_growing_uptime(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+
+        let first = app.send(Method::GET, "/api/version", None, None).await;
+        assert_eq!(first.status, StatusCode::OK);
+        assert!(
+            first.body["gitHash"]
+                .as_str()
+                .is_some_and(|hash| !hash.is_empty())
+        );
+        assert!(first.body["buildTime"].is_string());
+
+        // uptime counts whole seconds
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let second = app.send(Method::GET, "/api/version", None, None).await;
+        let uptime = |body: &serde_json::Value| body["uptime"].as_u64().unwrap();
+        assert!(uptime(&second.body) > uptime(&first.body));
+    }
+}
@@ -1,4 +1,6 @@
-mod contests;
+mod admin;
+mod audit;
+pub(crate) mod contests;
 pub mod judge;
 mod misc;
 mod problems;
@@ -8,11 +10,18 @@ mod users;
 #[cfg(feature = "embed-frontend")]
 mod web;
 
-use crate::{AppState, error::ErrorResponse};
-use axum::Router;
+use crate::{
+    AppState, Result,
+    auth::Claims,
+    error::{Error, ErrorResponse, FieldError},
+    perm::{UserRole, role_of_claims},
+};
+use axum::{Router, extract::DefaultBodyLimit, http::StatusCode};
+use koioj_common::judge::Language;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use utoipa::{
-    Modify, OpenApi,
+    Modify, OpenApi, ToSchema,
     openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
 };
 
@@ -30,7 +39,9 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
             .nest("/problems", problems::routes(state.clone()))
             .nest("/judge", judge::routes(state.clone()))
             .nest("/contests", contests::routes(state.clone()))
-            .nest("/training-plans", training_plans::routes(state.clone())),
+            .nest("/training-plans", training_plans::routes(state.clone()))
+            .nest("/audit", audit::routes(state.clone()))
+            .nest("/admin", admin::routes(state.clone())),
     );
     #[cfg(debug_assertions)]
     {
@@ -46,19 +57,105 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
     router
 }
 
+/// body limit for routes whose uploads outgrow `max_file_size_mb`
+pub(crate) fn body_limit(mb: f32) -> DefaultBodyLimit {
+    DefaultBodyLimit::max((mb * 1024. * 1024.) as usize)
+}
+
+/// A hidden resource looks exactly like a missing one to those who may not see
+/// it, so that probing ids tells nothing about what exists
+pub(crate) async fn check_visible(
+    pool: &sqlx::PgPool,
+    claims: &Claims,
+    hidden: bool,
+    what: &str,
+) -> Result<()> {
+    if hidden
+        && !matches!(
+            role_of_claims(pool, claims).await?,
+            UserRole::Teacher | UserRole::Admin
+        )
+    {
+        return Err(not_found(what));
+    }
+    Ok(())
+}
+
+/// pages past this are empty anyway, it keeps `page * page_size` in range
+pub(crate) const MAX_PAGE: i64 = 1_000_000;
+
+/// one page of a list, along with what it takes to page through the rest
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Page<T> {
+    items: Vec<T>,
+    total: i64,
+    page: i64,
+    page_size: i64,
+    has_next: bool,
+}
+
+impl<T> Page<T> {
+    pub(crate) fn new(items: Vec<T>, total: i64, page: i64, page_size: i64) -> Self {
+        Self {
+            items,
+            total,
+            page,
+            page_size,
+            has_next: page.saturating_mul(page_size) < total,
+        }
+    }
+}
+
+pub(crate) fn not_found(what: &str) -> Error {
+    Error::msg(format!("{} not found", what)).status_code(StatusCode::NOT_FOUND)
+}
+
+/// the language of a `submissions.lang` value, `None` for one that is no
+/// longer known, older rows may also differ in case or padding
+pub(crate) fn stored_language(lang: &str) -> Option<Language> {
+    let parsed = lang.trim().to_lowercase().parse().ok();
+    if parsed.is_none() {
+        tracing::warn!("Unknown stored submission language {:?}", lang);
+    }
+    parsed
+}
+
+/// escape `LIKE` wildcards so that user input only matches literally
+pub(crate) fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 #[derive(OpenApi)]
 #[openapi(
     paths(
         misc::ping,
         misc::version,
+        misc::health,
         users::register,
         users::login,
+        users::refresh,
+        users::logout,
         users::get_role,
+        users::get_my_role,
         users::put_role,
         users::get_profile,
+        users::get_my_profile,
         users::put_profile,
         users::change_password,
+        users::request_password_reset,
+        users::confirm_password_reset,
         users::delete_user,
+        users::list_users,
+        users::bulk_create_users,
+        users::list_bookmarks,
         problems::get_problem,
         problems::list_solutions,
         problems::get_solution,
@@ -66,22 +163,47 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
         problems::create_problem,
         problems::put_problem,
         problems::delete_problem,
+        problems::list_problem_collaborators,
+        problems::add_problem_collaborator,
+        problems::remove_problem_collaborator,
         problems::add_test_cases,
         problems::get_test_cases,
+        problems::export_problem,
+        problems::import_problem,
         problems::create_solution,
+        problems::put_solution,
         problems::delete_solution,
         problems::submit,
+        problems::submit_raw,
+        problems::run,
+        problems::custom_run,
         problems::list_submissions,
         problems::get_submission,
+        problems::download_submission_source,
+        problems::add_bookmark,
+        problems::remove_bookmark,
         problems::get_ac_status,
+        problems::get_problem_stats,
+        problems::get_similar_submissions,
         contests::list_contests,
         contests::get_contest,
         contests::create_contest,
         contests::put_contest,
         contests::delete_contest,
+        contests::clone_contest,
+        contests::list_contest_collaborators,
+        contests::add_contest_collaborator,
+        contests::remove_contest_collaborator,
         contests::join_contest,
         contests::get_is_joined,
         contests::get_contest_ranking,
+        contests::stream_contest_ranking,
+        contests::export_contest_ranking,
+        contests::rebuild_contest_ranking,
+        contests::start_virtual_contest,
+        contests::get_virtual_ranking,
+        contests::create_announcement,
+        contests::list_announcements,
         contests::get_overall_ranking,
         training_plans::get_training_plan,
         training_plans::list_training_plans,
@@ -90,7 +212,18 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
         training_plans::delete_training_plan,
         training_plans::set_participants,
         training_plans::set_contests,
-        judge::get_supported_languages
+        training_plans::get_training_plan_progress,
+        training_plans::reset_join_code,
+        training_plans::disable_join_code,
+        training_plans::join_training_plan,
+        audit::list_audit_log,
+        admin::prune_submissions,
+        admin::list_webhooks,
+        admin::create_webhook,
+        admin::put_webhook,
+        admin::delete_webhook,
+        judge::get_supported_languages,
+        judge::get_judge_eta
     ),
     modifiers(&JWTAuthAddon),
     tags(
@@ -99,9 +232,134 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
         (name = "problem"),
         (name = "contest"),
         (name = "training_plans"),
+        (name = "audit"),
+        (name = "admin"),
     ),
     components(
-        schemas(ErrorResponse),
+        schemas(
+            ErrorResponse,
+            FieldError,
+            UserRole,
+            problems::ProblemStatus,
+            problems::CreateProblemRequest,
+            problems::CreateProblemResponse,
+            problems::ProblemListItem,
+            problems::ProblemSort,
+            problems::SortOrder,
+            Page<problems::ProblemListItem>,
+            problems::GetProblemResponse,
+            problems::PutProblemRequest,
+            problems::CollaboratorItem,
+            problems::ListCollaboratorsResponse,
+            problems::AddTestCasesRequest,
+            problems::GetTestCasesResponse,
+            problems::CreateSolutionRequest,
+            problems::CreateSolutionResponse,
+            problems::SolutionListItem,
+            problems::ListSolutionsResponse,
+            problems::GetSolutionResponse,
+            problems::PutSolutionRequest,
+            problems::SubmitRequest,
+            problems::SubmitResponse,
+            problems::RunRequest,
+            problems::RunTestResult,
+            problems::RunResponse,
+            problems::CustomRunRequest,
+            problems::CustomRunResponse,
+            problems::SubmissionListItem,
+            Page<problems::SubmissionListItem>,
+            problems::TestCaseResultItem,
+            problems::SampleResultItem,
+            problems::SubmissionDiff,
+            problems::GetSubmissionResponse,
+            problems::GetAcStatusResponse,
+            problems::VerdictCount,
+            problems::GetProblemStatsResponse,
+            problems::SimilarSubmission,
+            problems::SimilarCluster,
+            problems::GetSimilarSubmissionsResponse,
+            users::UserStatus,
+            users::RegisterRequest,
+            users::RegisterResponse,
+            users::LoginRequest,
+            users::LoginResponse,
+            users::RefreshRequest,
+            users::RefreshResponse,
+            users::LogoutRequest,
+            users::PutRoleRequest,
+            users::GetRoleResponse,
+            users::GetProfileResponse,
+            users::PutProfileRequest,
+            users::ChangePasswordRequest,
+            users::RequestPasswordResetRequest,
+            users::ConfirmPasswordResetRequest,
+            users::BookmarkItem,
+            users::ListBookmarksResponse,
+            users::UserListItem,
+            Page<users::UserListItem>,
+            users::BulkUserItem,
+            users::BulkCreateUsersRequest,
+            users::BulkUserResult,
+            users::BulkCreateUsersResponse,
+            contests::ContestStatus,
+            contests::ContestType,
+            contests::CreateContestRequest,
+            contests::CreateContestResponse,
+            contests::ContestListItem,
+            Page<contests::ContestListItem>,
+            contests::GetContestResponse,
+            contests::ContestProblemItem,
+            contests::UpdateContestRequest,
+            contests::UpdateContestResponse,
+            contests::DeleteContestResponse,
+            contests::CloneContestRequest,
+            contests::JoinContestRequest,
+            contests::GetContestRankingResponse,
+            contests::RebuildContestRankingResponse,
+            contests::StartVirtualContestResponse,
+            contests::OverallRankingItem,
+            contests::GetOverallRankingResponse,
+            contests::CreateAnnouncementRequest,
+            contests::CreateAnnouncementResponse,
+            contests::AnnouncementItem,
+            contests::ListAnnouncementsResponse,
+            contests::ranking_cache::ContestRankingItem,
+            contests::ranking_cache::ProblemResult,
+            contests::ranking_cache::RankingUpdate,
+            training_plans::CreateTrainingPlanRequest,
+            training_plans::CreateTrainingPlanResponse,
+            training_plans::GetTrainingPlanResponse,
+            training_plans::ParticipantInfo,
+            training_plans::ContestInfo,
+            training_plans::TrainingPlanListItem,
+            Page<training_plans::TrainingPlanListItem>,
+            training_plans::PutTrainingPlanRequest,
+            training_plans::SetParticipantsRequest,
+            training_plans::SetParticipantsResponse,
+            training_plans::SetContestsRequest,
+            training_plans::SetContestsResponse,
+            training_plans::GetTrainingPlanProgressResponse,
+            training_plans::ResetJoinCodeResponse,
+            training_plans::JoinTrainingPlanRequest,
+            audit::AuditLogItem,
+            Page<audit::AuditLogItem>,
+            admin::PruneSubmissionsRequest,
+            admin::PruneSubmissionsResponse,
+            admin::WebhookItem,
+            admin::ListWebhooksResponse,
+            admin::CreateWebhookRequest,
+            admin::CreateWebhookResponse,
+            admin::PutWebhookRequest,
+            judge::GetSupportedLanguagesResponse,
+            judge::GetJudgeEtaResponse,
+            misc::VersionResponse,
+            misc::HealthResponse,
+            koioj_common::judge::Language,
+            koioj_common::judge::TestCaseData,
+            koioj_common::judge::SubmissionResult,
+            koioj_common::judge::TestCaseJudgeResult,
+            koioj_common::judge::OutputDiff,
+        ),
     )
 )]
 pub struct ApiDoc;
@@ -122,3 +380,80 @@ impl Modify for JWTAuthAddon {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use utoipa::OpenApi;
+
+    use super::{ApiDoc, Page};
+
+    fn collect_refs<'a>(value: &'a Value, refs: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value) {
+                        ("$ref", Value::String(r)) => refs.push(r),
+                        _ => collect_refs(value, refs),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|v| collect_refs(v, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn spec_registers_every_schema_it_references() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        for name in [
+            "CreateContestRequest",
+            "GetSubmissionResponse",
+            "ErrorResponse",
+        ] {
+            assert!(schemas.contains_key(name), "{} is not in the spec", name);
+        }
+
+        let mut refs = Vec::new();
+        collect_refs(&spec, &mut refs);
+        for r in refs {
+            let name = r.strip_prefix("#/components/schemas/").unwrap();
+            assert!(
+                schemas.contains_key(name),
+                "{} is referenced but missing",
+                r
+            );
+        }
+    }
+
+    #[test]
+    fn protected_paths_declare_bearer_auth() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let bearer = serde_json::json!([{ "bearer_auth": [] }]);
+        assert_eq!(
+            spec["paths"]["/api/problems/{problem_id}/submissions"]["post"]["security"],
+            bearer
+        );
+        assert_eq!(
+            spec["paths"]["/api/training-plans"]["post"]["security"],
+            bearer
+        );
+        assert!(spec["paths"]["/api/ping"]["get"]["security"].is_null());
+        assert!(spec["paths"]["/api/login"]["post"]["security"].is_null());
+    }
+
+    #[test]
+    fn only_pages_before_the_last_have_a_next() {
+        let middle = Page::new(vec![(); 10], 25, 2, 10);
+        assert!(middle.has_next);
+        let last = Page::new(vec![(); 5], 25, 3, 10);
+        assert!(!last.has_next);
+        let exactly_full = Page::new(vec![(); 10], 20, 2, 10);
+        assert!(!exactly_full.has_next);
+        let past_the_end = Page::new(Vec::<()>::new(), 20, 5, 10);
+        assert!(!past_the_end.has_next);
+        let huge = Page::new(Vec::<()>::new(), 20, i64::MAX, 100);
+        assert!(!huge.has_next);
+    }
+}
@@ -1,25 +1,31 @@
-use axum::extract::DefaultBodyLimit;
+use axum::body::Bytes;
 use axum::{
     Extension, Json, Router,
     extract::{Path, Query},
-    http::StatusCode,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     middleware,
+    response::{IntoResponse, Response},
 };
 use chrono::{DateTime, Utc};
-use koioj_common::judge::{JudgeTask, SubmissionResult, TestCase, TestCaseJudgeResult};
+use koioj_common::judge::{JudgeTask, OutputDiff, SubmissionResult, TestCase, TestCaseJudgeResult};
 use koioj_common::{bail, judge::Language};
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
 use serde::{Deserialize, Serialize};
 use sqlx::Row;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use utoipa::{IntoParams, ToSchema};
 
-use crate::route::contests::verify_contest_problem_access;
+use crate::route::{
+    MAX_PAGE, Page, body_limit, check_visible, contests::verify_contest_problem_access,
+    escape_like, not_found, stored_language,
+};
 use crate::{
-    AppState, Result, State,
+    AppState, Result, State, archive, audit,
     auth::{Claims, jwt_auth_accept_guest_middleware, jwt_auth_middleware},
-    error::Error,
+    error::{Error, ValidationError},
     models::*,
     perm::{Action, Resource, UserRole, check_permission, role_of_claims},
+    validation::check_max_length,
 };
 
 pub fn top_routes() -> Router<Arc<AppState>> {
@@ -30,12 +36,12 @@ pub fn top_routes() -> Router<Arc<AppState>> {
 pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
     use axum::routing::*;
     Router::new()
-        .route("/{problem_id}/solutions", get(list_solutions))
-        .route("/{problem_id}/solutions/{solution_id}", get(get_solution))
         .merge(
             Router::new()
                 .route("/{problem_id}", get(get_problem))
                 .route("/", get(list_problems))
+                .route("/{problem_id}/solutions", get(list_solutions))
+                .route("/{problem_id}/solutions/{solution_id}", get(get_solution))
                 .layer(middleware::from_fn_with_state(
                     state.clone(),
                     jwt_auth_accept_guest_middleware,
@@ -46,24 +52,60 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
                 .route("/", post(create_problem))
                 .route("/{problem_id}", put(put_problem))
                 .route("/{problem_id}", delete(delete_problem))
+                .route(
+                    "/{problem_id}/collaborators",
+                    get(list_problem_collaborators),
+                )
+                .route(
+                    "/{problem_id}/collaborators/{user_id}",
+                    put(add_problem_collaborator),
+                )
+                .route(
+                    "/{problem_id}/collaborators/{user_id}",
+                    delete(remove_problem_collaborator),
+                )
                 .merge(
                     Router::new()
                         .route("/{problem_id}/test-cases", post(add_test_cases))
-                        .layer(DefaultBodyLimit::max(256 * 1024 * 1024)),
+                        .layer(body_limit(state.config.max_testcase_upload_mb)),
+                )
+                .merge(
+                    Router::new()
+                        .route("/import", post(import_problem))
+                        .layer(body_limit(state.config.max_import_mb)),
                 )
                 .route("/{problem_id}/test-cases", get(get_test_cases))
+                .route("/{problem_id}/export", get(export_problem))
                 .route("/{problem_id}/solutions", post(create_solution))
+                .route("/{problem_id}/solutions/{solution_id}", put(put_solution))
                 .route(
                     "/{problem_id}/solutions/{solution_id}",
                     delete(delete_solution),
                 )
                 .route("/{problem_id}/submissions", post(submit))
+                .route("/{problem_id}/submissions/raw", post(submit_raw))
+                .route("/{problem_id}/run", post(run))
+                .route("/{problem_id}/custom-run", post(custom_run))
                 .route("/{problem_id}/submissions", get(list_submissions))
                 .route(
                     "/{problem_id}/submissions/{submission_id}",
                     get(get_submission),
                 )
+                .route(
+                    "/{problem_id}/submissions/{submission_id}/source",
+                    get(download_submission_source),
+                )
+                .route(
+                    "/{problem_id}/submissions/{submission_id}/bookmark",
+                    post(add_bookmark),
+                )
+                .route(
+                    "/{problem_id}/submissions/{submission_id}/bookmark",
+                    delete(remove_bookmark),
+                )
                 .route("/{problem_id}/ac-status", get(get_ac_status))
+                .route("/{problem_id}/stats", get(get_problem_stats))
+                .route("/{problem_id}/similar", get(get_similar_submissions))
                 .layer(middleware::from_fn_with_state(state, jwt_auth_middleware)),
         )
 }
@@ -76,6 +118,12 @@ pub enum ProblemStatus {
     Hidden,
 }
 
+const DIFFICULTY_RANGE_MESSAGE: &str = "difficulty must be between 1 and 10";
+
+fn is_valid_difficulty(difficulty: i32) -> bool {
+    (1..=10).contains(&difficulty)
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct CreateProblemRequest {
@@ -85,8 +133,28 @@ pub(crate) struct CreateProblemRequest {
     output_description: String,
     samples: Vec<TestCaseData>,
     note: Option<String>,
+    /// restrict submissions to these languages, empty allows all
+    #[serde(default)]
+    allowed_languages: Vec<Language>,
+    /// starter code by language
+    #[serde(default)]
+    templates: HashMap<Language, String>,
     time_limit: i32,
     mem_limit: i32,
+    /// judge the samples first and stop early if any of them fails
+    #[serde(default)]
+    sample_precheck: bool,
+    /// show where the output went wrong on hidden tests too, samples always do
+    #[serde(default)]
+    reveal_diff: bool,
+    /// judge the samples along with the hidden tests and show their results
+    #[serde(default)]
+    judge_samples: bool,
+    /// only users who solved the problem can read its solutions, off unless given
+    solutions_require_ac: Option<bool>,
+    /// longest accepted source in bytes, the configured default when absent
+    max_code_length: Option<i32>,
+    difficulty: Option<i32>,
     status: ProblemStatus,
 }
 
@@ -119,28 +187,49 @@ async fn create_problem(
     )
     .await?;
 
-    if p.name.is_empty()
-        || p.description.is_empty()
-        || p.input_description.is_empty()
-        || p.output_description.is_empty()
-    {
-        bail!(@BAD_REQUEST "required fields are missing");
-    }
+    let content = ProblemContent {
+        description: p.description,
+        input_description: p.input_description,
+        output_description: p.output_description,
+        samples: p.samples,
+        note: p.note,
+        allowed_languages: p.allowed_languages,
+        templates: p.templates,
+    };
 
-    if p.time_limit <= 0 || p.mem_limit <= 0 {
-        bail!(@BAD_REQUEST "time_limit and mem_limit must be positive");
+    let mut errors = ValidationError::new();
+    if p.name.is_empty() {
+        errors.add("name", "name is required");
     }
+    check_problem_content(&mut errors, &state, &content);
+    check_problem_limits(
+        &mut errors,
+        p.time_limit,
+        p.mem_limit,
+        p.max_code_length,
+        p.difficulty,
+    );
+    errors.check()?;
 
     let problem_id: i32 = sqlx::query_scalar!(
         r#"
-        INSERT INTO problems (name, time_limit, mem_limit, status)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO problems
+            (name, time_limit, mem_limit, sample_precheck, reveal_diff, judge_samples,
+             solutions_require_ac, max_code_length, difficulty, status, creator_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         RETURNING id
         "#,
         p.name,
         p.time_limit,
         p.mem_limit,
-        p.status as ProblemStatus
+        p.sample_precheck,
+        p.reveal_diff,
+        p.judge_samples,
+        p.solutions_require_ac.unwrap_or(false),
+        p.max_code_length,
+        p.difficulty,
+        p.status as ProblemStatus,
+        claims.sub
     )
     .fetch_one(&state.pool)
     .await
@@ -154,14 +243,6 @@ async fn create_problem(
         Error::msg(format!("database error: {}", e))
     })?;
 
-    let content = ProblemContent {
-        description: p.description,
-        input_description: p.input_description,
-        output_description: p.output_description,
-        samples: p.samples,
-        note: p.note,
-    };
-
     state.write_problem_content(problem_id, &content).await?;
 
     Ok(Json(CreateProblemResponse {
@@ -169,11 +250,112 @@ async fn create_problem(
     }))
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
+/// The checks on the statement shared by creating and importing a problem
+fn check_problem_content(errors: &mut ValidationError, state: &AppState, content: &ProblemContent) {
+    if content.description.is_empty() {
+        errors.add("description", "description is required");
+    }
+    if content.input_description.is_empty() {
+        errors.add("inputDescription", "input description is required");
+    }
+    if content.output_description.is_empty() {
+        errors.add("outputDescription", "output description is required");
+    }
+    let max_len = state.config.max_text_length;
+    check_max_length(errors, "description", &content.description, max_len);
+    check_max_length(
+        errors,
+        "inputDescription",
+        &content.input_description,
+        max_len,
+    );
+    check_max_length(
+        errors,
+        "outputDescription",
+        &content.output_description,
+        max_len,
+    );
+    if let Some(note) = &content.note {
+        check_max_length(errors, "note", note, max_len);
+    }
+    for template in content.templates.values() {
+        check_max_length(errors, "templates", template, state.config.max_code_length);
+    }
+}
+
+fn check_problem_limits(
+    errors: &mut ValidationError,
+    time_limit: i32,
+    mem_limit: i32,
+    max_code_length: Option<i32>,
+    difficulty: Option<i32>,
+) {
+    if time_limit <= 0 {
+        errors.add("timeLimit", "time_limit must be positive");
+    }
+    if mem_limit <= 0 {
+        errors.add("memLimit", "mem_limit must be positive");
+    }
+    if max_code_length.is_some_and(|len| len <= 0) {
+        errors.add("maxCodeLength", "max_code_length must be positive");
+    }
+    if let Some(difficulty) = difficulty {
+        if !is_valid_difficulty(difficulty) {
+            errors.add("difficulty", DIFFICULTY_RANGE_MESSAGE);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ProblemSort {
+    #[default]
+    Id,
+    Name,
+    UpdatedAt,
+    Difficulty,
+}
+
+impl ProblemSort {
+    /// the column to order by, never taken from the request itself
+    fn column(self) -> &'static str {
+        match self {
+            ProblemSort::Id => "p.id",
+            ProblemSort::Name => "p.name",
+            ProblemSort::UpdatedAt => "p.updated_at",
+            ProblemSort::Difficulty => "p.difficulty",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn keyword(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub(crate) struct ListProblemsQuery {
     page: Option<i64>,
     page_size: Option<i64>,
+    /// case-insensitive substring of the problem name
+    q: Option<String>,
+    /// `id` when left out, ties are broken by ascending id
+    sort: Option<ProblemSort>,
+    order: Option<SortOrder>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -181,24 +363,22 @@ pub(crate) struct ListProblemsQuery {
 pub(crate) struct ProblemListItem {
     problem_id: i32,
     name: String,
-}
-
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct ListProblemsResponse {
-    problems: Vec<ProblemListItem>,
-    total: i64,
+    difficulty: Option<i32>,
+    total_submissions: i64,
+    accepted_submissions: i64,
+    /// whether the current user has an accepted submission, `null` for guests
+    solved: Option<bool>,
+    /// whether the current user has submitted at all, `null` for guests
+    tried: Option<bool>,
 }
 
 #[utoipa::path(
     get,
     path = "/api/problems",
-    params(
-        ("page" = Option<i64>, Query),
-        ("pageSize" = Option<i64>, Query),
-    ),
+    security((), ("bearer_auth" = [])),
+    params(ListProblemsQuery),
     responses(
-        (status = 200, body = ListProblemsResponse),
+        (status = 200, body = Page<ProblemListItem>),
     ),
     tag = "problem"
 )]
@@ -206,43 +386,76 @@ async fn list_problems(
     state: State,
     claims: Extension<Claims>,
     Query(q): Query<ListProblemsQuery>,
-) -> Result<Json<ListProblemsResponse>> {
+) -> Result<Json<Page<ProblemListItem>>> {
     let user_role = role_of_claims(&state.pool, &claims).await?;
+    let is_guest = user_role == UserRole::Guest;
 
-    let page = q.page.unwrap_or(1).max(1);
+    let page = q.page.unwrap_or(1).clamp(1, MAX_PAGE);
     let page_size = q.page_size.unwrap_or(20).clamp(1, 100);
     let offset = (page - 1) * page_size;
+    let pattern = format!("%{}%", escape_like(q.q.as_deref().unwrap_or("").trim()));
 
-    let (count_query, select_query) = match user_role {
-        UserRole::Teacher | UserRole::Admin => (
-            "SELECT COUNT(*) FROM problems",
-            r#"
-            SELECT id, name
-            FROM problems
-            ORDER BY id
-            LIMIT $1 OFFSET $2
-            "#,
-        ),
-        _ => (
-            "SELECT COUNT(*) FROM problems WHERE status = 'active'",
-            r#"
-            SELECT id, name
-            FROM problems
-            WHERE status = 'active'
-            ORDER BY id
-            LIMIT $1 OFFSET $2
-            "#,
-        ),
+    let visibility_filter = match user_role {
+        UserRole::Teacher | UserRole::Admin => "",
+        _ => "p.status = 'active' AND",
     };
+    // problems without a difficulty come last either way
+    let order_by = format!(
+        "{} {} NULLS LAST, p.id",
+        q.sort.unwrap_or_default().column(),
+        q.order.unwrap_or_default().keyword()
+    );
+    let count_query = format!(
+        r#"SELECT COUNT(*) FROM problems p WHERE {} p.name ILIKE $1 ESCAPE '\'"#,
+        visibility_filter
+    );
+    // submission counts come from one grouped subquery instead of a query per
+    // problem, and only over the problems of the page
+    let select_query = format!(
+        r#"
+        WITH page AS (
+            SELECT p.id, p.name, p.difficulty, p.updated_at
+            FROM problems p
+            WHERE {} p.name ILIKE $3 ESCAPE '\'
+            ORDER BY {}
+            LIMIT $1 OFFSET $2
+        )
+        SELECT p.id, p.name, p.difficulty,
+               COALESCE(s.total, 0) AS total_submissions,
+               COALESCE(s.accepted, 0) AS accepted_submissions,
+               COALESCE(u.solved, FALSE) AS solved,
+               u.problem_id IS NOT NULL AS tried
+        FROM page p
+        LEFT JOIN (
+            SELECT problem_id,
+                   COUNT(*) AS total,
+                   COUNT(*) FILTER (WHERE result = 'accepted') AS accepted
+            FROM submissions
+            WHERE problem_id IN (SELECT id FROM page)
+            GROUP BY problem_id
+        ) s ON s.problem_id = p.id
+        LEFT JOIN (
+            SELECT problem_id, BOOL_OR(result = 'accepted') AS solved
+            FROM submissions
+            WHERE user_id = $4 AND problem_id IN (SELECT id FROM page)
+            GROUP BY problem_id
+        ) u ON u.problem_id = p.id
+        ORDER BY {}
+        "#,
+        visibility_filter, order_by, order_by
+    );
 
-    let total: i64 = sqlx::query_scalar(count_query)
+    let total: i64 = sqlx::query_scalar(&count_query)
+        .bind(&pattern)
         .fetch_one(&state.pool)
         .await
         .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    let problems = sqlx::query(select_query)
+    let problems = sqlx::query(&select_query)
         .bind(page_size)
         .bind(offset)
+        .bind(&pattern)
+        .bind(claims.sub)
         .fetch_all(&state.pool)
         .await
         .map_err(|e| Error::msg(format!("database error: {}", e)))?
@@ -250,15 +463,22 @@ async fn list_problems(
         .map(|row| ProblemListItem {
             problem_id: row.get::<i32, _>("id"),
             name: row.get::<String, _>("name"),
+            difficulty: row.get::<Option<i32>, _>("difficulty"),
+            total_submissions: row.get::<i64, _>("total_submissions"),
+            accepted_submissions: row.get::<i64, _>("accepted_submissions"),
+            solved: (!is_guest).then(|| row.get::<bool, _>("solved")),
+            tried: (!is_guest).then(|| row.get::<bool, _>("tried")),
         })
         .collect();
 
-    Ok(Json(ListProblemsResponse { problems, total }))
+    Ok(Json(Page::new(problems, total, page, page_size)))
 }
 
 #[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[into_params(parameter_in = Query)]
 struct GetProblemQuery {
-    #[serde(rename = "contestId")]
+    /// read the problem through a running contest, even while it is hidden
     contest_id: Option<i32>,
 }
 
@@ -272,20 +492,35 @@ pub(crate) struct GetProblemResponse {
     output_description: String,
     samples: Vec<TestCaseData>,
     note: Option<String>,
+    allowed_languages: Vec<Language>,
+    templates: HashMap<Language, String>,
     time_limit: i32,
     mem_limit: i32,
+    sample_precheck: bool,
+    reveal_diff: bool,
+    judge_samples: bool,
+    solutions_require_ac: bool,
+    max_code_length: Option<i32>,
+    difficulty: Option<i32>,
+    total_submissions: i64,
+    accepted_submissions: i64,
     status: ProblemStatus,
+    /// only shown to those who can edit the problem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warning: Option<String>,
 }
 
 #[utoipa::path(
     get,
     path = "/api/problems/{problem_id}",
+    security((), ("bearer_auth" = [])),
     params(
         ("problem_id" = i32, Path),
         GetProblemQuery
     ),
     responses(
         (status = 200, body = GetProblemResponse),
+        (status = 404, description = "problem not found, or not readable through the contest"),
     ),
     tag = "problem"
 )]
@@ -298,9 +533,7 @@ async fn get_problem(
     let user_role = role_of_claims(&state.pool, &claims).await?;
 
     let should_check_active = if let Some(cid) = query.contest_id {
-        if !matches!(user_role, UserRole::Teacher | UserRole::Admin) {
-            verify_contest_problem_access(&state.pool, cid, problem_id, claims.sub).await?;
-        }
+        verify_contest_problem_access(&state.pool, &claims, cid, problem_id).await?;
         false // don't check active for contest problems
     } else {
         !matches!(user_role, UserRole::Teacher | UserRole::Admin)
@@ -312,13 +545,21 @@ async fn get_problem(
         name: String,
         time_limit: i32,
         mem_limit: i32,
+        sample_precheck: bool,
+        reveal_diff: bool,
+        judge_samples: bool,
+        solutions_require_ac: bool,
+        max_code_length: Option<i32>,
+        difficulty: Option<i32>,
         status: ProblemStatus,
     }
     let problem = if should_check_active {
         sqlx::query_as!(
             ProblemRecord,
             r#"
-        SELECT id, name, time_limit, mem_limit, status as "status: ProblemStatus"
+        SELECT id, name, time_limit, mem_limit, sample_precheck, reveal_diff, judge_samples,
+               solutions_require_ac, max_code_length, difficulty,
+               status as "status: ProblemStatus"
         FROM problems
         WHERE id = $1 AND status = 'active'
         "#,
@@ -330,7 +571,9 @@ async fn get_problem(
         sqlx::query_as!(
             ProblemRecord,
             r#"
-        SELECT id, name, time_limit, mem_limit, status as "status: ProblemStatus"
+        SELECT id, name, time_limit, mem_limit, sample_precheck, reveal_diff, judge_samples,
+               solutions_require_ac, max_code_length, difficulty,
+               status as "status: ProblemStatus"
         FROM problems
         WHERE id = $1
         "#,
@@ -341,7 +584,35 @@ async fn get_problem(
     }
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
     .ok_or_else(|| Error::msg("problem not found").status_code(StatusCode::NOT_FOUND))?;
+    let stats = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "total!",
+               COUNT(*) FILTER (WHERE result = 'accepted') as "accepted!"
+        FROM submissions
+        WHERE problem_id = $1
+        "#,
+        problem_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
     let content = state.read_problem_content(problem_id).await?;
+
+    let is_editor = match user_role {
+        UserRole::Admin => true,
+        UserRole::Teacher => {
+            Resource::Problem(problem_id)
+                .is_editor(&state.pool, claims.sub)
+                .await?
+        }
+        _ => false,
+    };
+    let warning = if is_editor && !has_test_cases(&state, problem_id).await? {
+        Some("this problem has no test cases, submissions to it are refused".to_string())
+    } else {
+        None
+    };
+
     Ok(Json(GetProblemResponse {
         problem_id: problem.id,
         name: problem.name,
@@ -350,12 +621,35 @@ async fn get_problem(
         output_description: content.output_description,
         samples: content.samples,
         note: content.note,
+        allowed_languages: content.allowed_languages,
+        templates: content.templates,
         time_limit: problem.time_limit,
         mem_limit: problem.mem_limit,
+        sample_precheck: problem.sample_precheck,
+        reveal_diff: problem.reveal_diff,
+        judge_samples: problem.judge_samples,
+        solutions_require_ac: problem.solutions_require_ac,
+        max_code_length: problem.max_code_length,
+        difficulty: problem.difficulty,
+        total_submissions: stats.total,
+        accepted_submissions: stats.accepted,
         status: problem.status,
+        warning,
     }))
 }
 
+async fn has_test_cases(state: &AppState, problem_id: i32) -> Result<bool> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(SELECT 1 FROM test_cases WHERE problem_id = $1) as "exists!"
+        "#,
+        problem_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct PutProblemRequest {
@@ -365,8 +659,18 @@ pub(crate) struct PutProblemRequest {
     output_description: Option<String>,
     samples: Option<Vec<TestCaseData>>,
     note: Option<String>,
+    allowed_languages: Option<Vec<Language>>,
+    /// replaces all templates, an empty map removes them
+    templates: Option<HashMap<Language, String>>,
     time_limit: Option<i32>,
     mem_limit: Option<i32>,
+    sample_precheck: Option<bool>,
+    reveal_diff: Option<bool>,
+    judge_samples: Option<bool>,
+    solutions_require_ac: Option<bool>,
+    /// 0 goes back to the configured default
+    max_code_length: Option<i32>,
+    difficulty: Option<i32>,
     status: Option<ProblemStatus>,
 }
 
@@ -397,6 +701,28 @@ async fn put_problem(
     )
     .await?;
 
+    let mut errors = ValidationError::new();
+    let max_len = state.config.max_text_length;
+    for (field, value) in [
+        ("description", &p.description),
+        ("inputDescription", &p.input_description),
+        ("outputDescription", &p.output_description),
+        ("note", &p.note),
+    ] {
+        if let Some(value) = value {
+            check_max_length(&mut errors, field, value, max_len);
+        }
+    }
+    for template in p.templates.iter().flat_map(|t| t.values()) {
+        check_max_length(
+            &mut errors,
+            "templates",
+            template,
+            state.config.max_code_length,
+        );
+    }
+    errors.check()?;
+
     let mut content = state.read_problem_content(problem_id).await?;
 
     let mut tx = state
@@ -443,10 +769,19 @@ async fn put_problem(
     if let Some(note) = p.note {
         content.note = Some(note);
     }
+    if let Some(allowed_languages) = p.allowed_languages {
+        content.allowed_languages = allowed_languages;
+    }
+    if let Some(templates) = p.templates {
+        content.templates = templates;
+    }
 
     if let Some(time_limit) = p.time_limit {
         if time_limit <= 0 {
-            bail!(@BAD_REQUEST "time_limit must be positive");
+            return Err(Error::invalid_field(
+                "timeLimit",
+                "time_limit must be positive",
+            ));
         }
         sqlx::query!(
             r#"
@@ -462,7 +797,10 @@ async fn put_problem(
 
     if let Some(mem_limit) = p.mem_limit {
         if mem_limit <= 0 {
-            bail!(@BAD_REQUEST "mem_limit must be positive");
+            return Err(Error::invalid_field(
+                "memLimit",
+                "mem_limit must be positive",
+            ));
         }
         sqlx::query!(
             r#"
@@ -476,6 +814,93 @@ async fn put_problem(
         .map_err(|e| Error::msg(format!("database error: {}", e)))?;
     }
 
+    if let Some(sample_precheck) = p.sample_precheck {
+        sqlx::query!(
+            r#"
+            UPDATE problems SET sample_precheck = $1, updated_at = NOW() WHERE id = $2
+            "#,
+            sample_precheck,
+            problem_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    }
+
+    if let Some(reveal_diff) = p.reveal_diff {
+        sqlx::query!(
+            r#"
+            UPDATE problems SET reveal_diff = $1, updated_at = NOW() WHERE id = $2
+            "#,
+            reveal_diff,
+            problem_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    }
+
+    if let Some(judge_samples) = p.judge_samples {
+        sqlx::query!(
+            r#"
+            UPDATE problems SET judge_samples = $1, updated_at = NOW() WHERE id = $2
+            "#,
+            judge_samples,
+            problem_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    }
+
+    if let Some(solutions_require_ac) = p.solutions_require_ac {
+        sqlx::query!(
+            r#"
+            UPDATE problems SET solutions_require_ac = $1, updated_at = NOW() WHERE id = $2
+            "#,
+            solutions_require_ac,
+            problem_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    }
+
+    if let Some(max_code_length) = p.max_code_length {
+        if max_code_length < 0 {
+            return Err(Error::invalid_field(
+                "maxCodeLength",
+                "max_code_length must be positive",
+            ));
+        }
+        sqlx::query!(
+            r#"
+            UPDATE problems SET max_code_length = $1, updated_at = NOW() WHERE id = $2
+            "#,
+            (max_code_length > 0).then_some(max_code_length),
+            problem_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    }
+
+    if let Some(difficulty) = p.difficulty {
+        if !is_valid_difficulty(difficulty) {
+            return Err(Error::invalid_field("difficulty", DIFFICULTY_RANGE_MESSAGE));
+        }
+        sqlx::query!(
+            r#"
+            UPDATE problems SET difficulty = $1, updated_at = NOW() WHERE id = $2
+            "#,
+            difficulty,
+            problem_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    }
+
     if let Some(status) = p.status {
         sqlx::query!(
             r#"
@@ -498,6 +923,8 @@ async fn put_problem(
     Ok(())
 }
 
+/// Problems used in a contest or referenced by submissions can't be deleted,
+/// since that would break rankings and submission history; hide them instead.
 #[utoipa::path(
     delete,
     path = "/api/problems/{problem_id}",
@@ -537,6 +964,40 @@ async fn delete_problem(
         bail!(@BAD_REQUEST "can't delete a using problem")
     }
 
+    let has_submissions = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(SELECT 1 FROM submissions WHERE problem_id = $1) as "exists!"
+        "#,
+        problem_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if has_submissions {
+        bail!(@BAD_REQUEST "can't delete a problem with submissions, hide it instead")
+    }
+
+    let test_cases = sqlx::query!(
+        r#"
+        SELECT id, hash FROM test_cases WHERE problem_id = $1
+        "#,
+        problem_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let solution_ids = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM solutions WHERE problem_id = $1
+        "#,
+        problem_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
     sqlx::query!(
         r#"
         DELETE FROM problems WHERE id = $1
@@ -547,71 +1008,307 @@ async fn delete_problem(
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::DeleteProblem,
+        Resource::Problem(problem_id),
+        None,
+    )
+    .await;
+
+    // the rows are gone already, so leftover files are only worth a warning
+    if let Err(e) = state.delete_problem_content(problem_id).await {
+        tracing::warn!(
+            "Failed to delete content of problem {}: {:?}",
+            problem_id,
+            e
+        );
+    }
+    for test_case in test_cases {
+        if let Err(e) = state.delete_test_cases(test_case.id, &test_case.hash).await {
+            tracing::warn!("Failed to delete test case {}: {:?}", test_case.id, e);
+        }
+    }
+    for solution_id in solution_ids {
+        if let Err(e) = state.delete_solution_content(solution_id).await {
+            tracing::warn!("Failed to delete solution {}: {:?}", solution_id, e);
+        }
+    }
+
     Ok(())
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct AddTestCasesRequest {
-    test_cases: Vec<TestCaseData>,
+pub(crate) struct CollaboratorItem {
+    pub(crate) user_id: i32,
+    pub(crate) username: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ListCollaboratorsResponse {
+    pub(crate) collaborators: Vec<CollaboratorItem>,
+}
+
+/// Only active teachers and admins other than the owner can collaborate
+pub(crate) async fn check_collaborator(
+    state: &AppState,
+    owner_id: i32,
+    user_id: i32,
+) -> Result<()> {
+    let role = sqlx::query_scalar!(
+        r#"
+        SELECT user_role as "user_role: UserRole" FROM users
+        WHERE id = $1 AND status = 'active'
+        "#,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| not_found("user"))?;
+
+    if !matches!(role, UserRole::Teacher | UserRole::Admin) {
+        bail!("collaborators must be teachers");
+    }
+    if owner_id == user_id {
+        bail!("the owner can't be a collaborator");
+    }
+    Ok(())
+}
+
+async fn problem_owner(state: &AppState, problem_id: i32) -> Result<i32> {
+    sqlx::query_scalar!("SELECT creator_id FROM problems WHERE id = $1", problem_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?
+        .ok_or_else(|| not_found("problem"))
 }
 
 #[utoipa::path(
-    post,
-    path = "/api/problems/{problem_id}/test-cases",
-    request_body = AddTestCasesRequest,
+    get,
+    path = "/api/problems/{problem_id}/collaborators",
     params(
         ("problem_id" = i32, Path)
     ),
     security(("bearer_auth" = [])),
     responses(
-        (status = 200, body = ()),
+        (status = 200, body = ListCollaboratorsResponse),
     ),
     tag = "problem"
 )]
-async fn add_test_cases(
+async fn list_problem_collaborators(
     state: State,
     claims: Extension<Claims>,
     Path(problem_id): Path<i32>,
-    Json(p): Json<AddTestCasesRequest>,
-) -> Result<()> {
+) -> Result<Json<ListCollaboratorsResponse>> {
+    problem_owner(&state, problem_id).await?;
     check_permission(
         &state.pool,
         &claims,
-        Action::AddTestCases,
+        Action::PutProblem,
         Resource::Problem(problem_id),
     )
     .await?;
 
-    sqlx::query!(
+    let collaborators = sqlx::query_as!(
+        CollaboratorItem,
         r#"
-        SELECT id FROM problems WHERE id = $1
+        SELECT u.id as user_id, u.username
+        FROM problem_collaborators c
+        JOIN users u ON u.id = c.user_id
+        WHERE c.problem_id = $1
+        ORDER BY c.created_at
         "#,
         problem_id
     )
-    .fetch_optional(&state.pool)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    Ok(Json(ListCollaboratorsResponse { collaborators }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/problems/{problem_id}/collaborators/{user_id}",
+    params(
+        ("problem_id" = i32, Path),
+        ("user_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ()),
+    ),
+    tag = "problem"
+)]
+async fn add_problem_collaborator(
+    state: State,
+    claims: Extension<Claims>,
+    Path((problem_id, user_id)): Path<(i32, i32)>,
+) -> Result<()> {
+    let owner_id = problem_owner(&state, problem_id).await?;
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::ManageCollaborators,
+        Resource::Problem(problem_id),
+    )
+    .await?;
+    check_collaborator(&state, owner_id, user_id).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO problem_collaborators (problem_id, user_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        problem_id,
+        user_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::ManageCollaborators,
+        Resource::Problem(problem_id),
+        Some(format!("add: {}", user_id)),
+    )
+    .await;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/problems/{problem_id}/collaborators/{user_id}",
+    params(
+        ("problem_id" = i32, Path),
+        ("user_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ()),
+    ),
+    tag = "problem"
+)]
+async fn remove_problem_collaborator(
+    state: State,
+    claims: Extension<Claims>,
+    Path((problem_id, user_id)): Path<(i32, i32)>,
+) -> Result<()> {
+    problem_owner(&state, problem_id).await?;
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::ManageCollaborators,
+        Resource::Problem(problem_id),
+    )
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM problem_collaborators WHERE problem_id = $1 AND user_id = $2
+        RETURNING user_id
+        "#,
+        problem_id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| not_found("collaborator"))?;
+
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::ManageCollaborators,
+        Resource::Problem(problem_id),
+        Some(format!("remove: {}", user_id)),
+    )
+    .await;
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AddTestCasesRequest {
+    test_cases: Vec<TestCaseData>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/problems/{problem_id}/test-cases",
+    request_body = AddTestCasesRequest,
+    params(
+        ("problem_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ()),
+    ),
+    tag = "problem"
+)]
+async fn add_test_cases(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+    Json(p): Json<AddTestCasesRequest>,
+) -> Result<()> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::AddTestCases,
+        Resource::Problem(problem_id),
+    )
+    .await?;
+
+    sqlx::query!(
+        r#"
+        SELECT id FROM problems WHERE id = $1
+        "#,
+        problem_id
+    )
+    .fetch_optional(&state.pool)
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
     .ok_or_else(|| Error::msg("problem not found").status_code(StatusCode::NOT_FOUND))?;
 
     if p.test_cases.is_empty() {
-        bail!(@BAD_REQUEST "test_cases cannot be empty");
+        return Err(Error::invalid_field(
+            "testCases",
+            "test_cases cannot be empty",
+        ));
     }
 
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
     for test_case in p.test_cases.iter() {
-        let result = sqlx::query!(
+        // the file first, so that a row never points at a missing one
+        let hash = state.write_test_cases(&mut tx, test_case).await?;
+        sqlx::query!(
             r#"
-        INSERT INTO test_cases (problem_id) VALUES ($1) RETURNING id
+        INSERT INTO test_cases (problem_id, hash) VALUES ($1, $2)
         "#,
-            problem_id
+            problem_id,
+            hash
         )
-        .fetch_one(&state.pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| Error::msg(format!("database error: {}", e)))?;
-
-        let test_case_id = result.id;
-        state.write_test_cases(test_case_id, test_case).await?;
     }
+    tx.commit()
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
     Ok(())
 }
 
@@ -666,53 +1363,66 @@ async fn get_test_cases(
     }))
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct CreateSolutionRequest {
-    title: String,
-    content: String,
-}
+/// bumped whenever the layout of exported problem bundles changes
+const PROBLEM_BUNDLE_VERSION: u32 = 1;
+/// largest total size of the files unpacked from an imported bundle
+const MAX_BUNDLE_SIZE: u64 = 1024 * 1024 * 1024;
 
-#[derive(Serialize, Deserialize, ToSchema)]
+const BUNDLE_MANIFEST_PATH: &str = "manifest.json";
+const BUNDLE_CONTENT_PATH: &str = "content.json";
+const BUNDLE_TEST_CASES_DIR: &str = "test_cases/";
+
+/// problem metadata stored as `manifest.json` in a bundle
+#[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CreateSolutionResponse {
-    solution_id: i32,
+struct ProblemBundleManifest {
+    version: u32,
+    name: String,
+    time_limit: i32,
+    mem_limit: i32,
+    sample_precheck: bool,
+    #[serde(default)]
+    reveal_diff: bool,
+    #[serde(default)]
+    judge_samples: bool,
+    solutions_require_ac: bool,
+    max_code_length: Option<i32>,
+    difficulty: Option<i32>,
+    status: ProblemStatus,
 }
 
 #[utoipa::path(
-    post,
-    path = "/api/problems/{problem_id}/solutions",
-    request_body = CreateSolutionRequest,
+    get,
+    path = "/api/problems/{problem_id}/export",
     params(
         ("problem_id" = i32, Path)
     ),
     security(("bearer_auth" = [])),
     responses(
-        (status = 200, body = CreateSolutionResponse),
+        (status = 200, body = Vec<u8>, content_type = "application/zip"),
     ),
     tag = "problem"
 )]
-async fn create_solution(
+async fn export_problem(
     state: State,
     claims: Extension<Claims>,
     Path(problem_id): Path<i32>,
-    Json(p): Json<CreateSolutionRequest>,
-) -> Result<Json<CreateSolutionResponse>> {
+) -> Result<Response> {
     check_permission(
         &state.pool,
         &claims,
-        Action::CreateSolution,
+        Action::GetTestCases,
         Resource::Problem(problem_id),
     )
     .await?;
 
-    if p.title.is_empty() || p.content.is_empty() {
-        bail!(@BAD_REQUEST "title and content are required");
-    }
-
-    sqlx::query!(
+    let problem = sqlx::query!(
         r#"
-        SELECT id FROM problems WHERE id = $1
+        SELECT name, time_limit, mem_limit, sample_precheck, reveal_diff, judge_samples,
+               solutions_require_ac, max_code_length, difficulty,
+               status as "status: ProblemStatus"
+        FROM problems
+        WHERE id = $1
         "#,
         problem_id
     )
@@ -720,96 +1430,438 @@ async fn create_solution(
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
     .ok_or_else(|| Error::msg("problem not found").status_code(StatusCode::NOT_FOUND))?;
+    let manifest = ProblemBundleManifest {
+        version: PROBLEM_BUNDLE_VERSION,
+        name: problem.name,
+        time_limit: problem.time_limit,
+        mem_limit: problem.mem_limit,
+        sample_precheck: problem.sample_precheck,
+        reveal_diff: problem.reveal_diff,
+        judge_samples: problem.judge_samples,
+        solutions_require_ac: problem.solutions_require_ac,
+        max_code_length: problem.max_code_length,
+        difficulty: problem.difficulty,
+        status: problem.status,
+    };
 
-    let solution_id: i32 = sqlx::query_scalar!(
+    let content = state.read_problem_content(problem_id).await?;
+
+    let test_case_ids = sqlx::query_scalar!(
         r#"
-        INSERT INTO solutions (problem_id, author, title)
-        VALUES ($1, $2, $3)
-        RETURNING id
+        SELECT id FROM test_cases WHERE problem_id = $1 ORDER BY id
         "#,
-        problem_id,
-        claims.sub,
-        p.title
+        problem_id
     )
-    .fetch_one(&state.pool)
+    .fetch_all(&state.pool)
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    let solution_content = SolutionContent { content: p.content };
-
-    state
-        .write_solution_content(solution_id, &solution_content)
-        .await?;
-
-    Ok(Json(CreateSolutionResponse {
-        solution_id: solution_id,
-    }))
-}
+    let mut files = vec![
+        (
+            BUNDLE_MANIFEST_PATH.to_string(),
+            serde_json::to_vec_pretty(&manifest)?,
+        ),
+        (
+            BUNDLE_CONTENT_PATH.to_string(),
+            serde_json::to_vec_pretty(&content)?,
+        ),
+    ];
+    for (i, test_case_id) in test_case_ids.into_iter().enumerate() {
+        let test_case = state.read_test_cases(test_case_id).await?;
+        files.push((
+            format!("{}{}.json", BUNDLE_TEST_CASES_DIR, i),
+            serde_json::to_vec(&test_case)?,
+        ));
+    }
 
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct SolutionListItem {
-    solution_id: i32,
-    title: String,
-    author_id: i32,
-    author_name: String,
-    created_at: String,
-}
+    let bundle = archive::write_zip(files).await?;
 
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct ListSolutionsResponse {
-    solutions: Vec<SolutionListItem>,
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"problem_{}.zip\"", problem_id),
+        )
+        .body(bundle.into())?)
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/problems/{problem_id}/solutions",
-    params(
-        ("problem_id" = i32, Path)
-    ),
+    post,
+    path = "/api/problems/import",
+    request_body(content = Vec<u8>, content_type = "application/zip"),
+    security(("bearer_auth" = [])),
     responses(
-        (status = 200, body = ListSolutionsResponse),
+        (status = 200, body = CreateProblemResponse),
     ),
     tag = "problem"
 )]
-async fn list_solutions(
+async fn import_problem(
     state: State,
-    Path(problem_id): Path<i32>,
-) -> Result<Json<ListSolutionsResponse>> {
-    let _problem = sqlx::query!(
-        "SELECT id FROM problems WHERE id = $1 AND status = 'active'",
-        problem_id
-    )
-    .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .ok_or_else(|| Error::msg("invalid problem_id"))?;
-
-    let solutions = sqlx::query!(
-        r#"
-        SELECT s.id, s.title, s.author, s.created_at, u.username
-        FROM solutions s
-        JOIN users u ON s.author = u.id
-        WHERE s.problem_id = $1
-        ORDER BY s.created_at DESC
-        "#,
-        problem_id
+    claims: Extension<Claims>,
+    body: Bytes,
+) -> Result<Json<CreateProblemResponse>> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::CreateProblem,
+        Resource::Global,
     )
-    .fetch_all(&state.pool)
-    .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .into_iter()
-    .map(|row| SolutionListItem {
-        solution_id: row.id,
-        title: row.title,
-        author_id: row.author,
-        author_name: row.username,
-        created_at: row.created_at.to_rfc3339(),
-    })
-    .collect();
+    .await?;
 
-    Ok(Json(ListSolutionsResponse { solutions }))
+    // test cases are stored as they're unpacked, and taken back if the import
+    // fails
+    let mut stored = Vec::new();
+    let result = import_bundle(&state, &claims, body, &mut stored).await;
+    if result.is_err() {
+        for hash in stored {
+            if let Err(e) = state.discard_test_cases(&hash).await {
+                tracing::warn!("Failed to remove test case {}: {:?}", hash, e);
+            }
+        }
+    }
+
+    Ok(Json(CreateProblemResponse {
+        problem_id: result?,
+    }))
+}
+
+async fn import_bundle(
+    state: &AppState,
+    claims: &Claims,
+    body: Bytes,
+    stored: &mut Vec<String>,
+) -> Result<i32> {
+    // begun before the test cases are stored, which keeps them from being
+    // discarded until their rows are in
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let mut manifest = None;
+    let mut content = None;
+    let mut test_cases = Vec::new();
+    let mut entries = archive::read_zip(body, MAX_BUNDLE_SIZE);
+    while let Some(entry) = entries.recv().await {
+        let (path, data) = entry?;
+        let invalid = |e: serde_json::Error| {
+            Error::msg(format!("invalid bundle file {}: {}", path, e))
+                .status_code(StatusCode::BAD_REQUEST)
+        };
+        if path == BUNDLE_MANIFEST_PATH {
+            manifest =
+                Some(serde_json::from_slice::<ProblemBundleManifest>(&data).map_err(invalid)?);
+        } else if path == BUNDLE_CONTENT_PATH {
+            content = Some(serde_json::from_slice::<ProblemContent>(&data).map_err(invalid)?);
+        } else if let Some(index) = path
+            .strip_prefix(BUNDLE_TEST_CASES_DIR)
+            .and_then(|name| name.strip_suffix(".json"))
+            .and_then(|index| index.parse::<usize>().ok())
+        {
+            let test_case = serde_json::from_slice::<TestCaseData>(&data).map_err(invalid)?;
+            let hash = state.write_test_cases(&mut tx, &test_case).await?;
+            stored.push(hash.clone());
+            test_cases.push((index, hash));
+        }
+    }
+
+    let Some(manifest) = manifest else {
+        bail!("bundle has no {}", BUNDLE_MANIFEST_PATH);
+    };
+    if manifest.version != PROBLEM_BUNDLE_VERSION {
+        bail!("unsupported bundle version {}", manifest.version);
+    }
+    let Some(content) = content else {
+        bail!("bundle has no {}", BUNDLE_CONTENT_PATH);
+    };
+    test_cases.sort_by_key(|(index, _)| *index);
+
+    let mut errors = ValidationError::new();
+    if manifest.name.is_empty() {
+        errors.add("name", "name is required");
+    }
+    check_problem_content(&mut errors, state, &content);
+    check_problem_limits(
+        &mut errors,
+        manifest.time_limit,
+        manifest.mem_limit,
+        manifest.max_code_length,
+        manifest.difficulty,
+    );
+    errors.check()?;
+
+    let problem_id: i32 = sqlx::query_scalar!(
+        r#"
+        INSERT INTO problems
+            (name, time_limit, mem_limit, sample_precheck, reveal_diff, judge_samples,
+             solutions_require_ac, max_code_length, difficulty, status, creator_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING id
+        "#,
+        manifest.name,
+        manifest.time_limit,
+        manifest.mem_limit,
+        manifest.sample_precheck,
+        manifest.reveal_diff,
+        manifest.judge_samples,
+        manifest.solutions_require_ac,
+        manifest.max_code_length,
+        manifest.difficulty,
+        manifest.status as ProblemStatus,
+        claims.sub
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        if let sqlx::Error::Database(db_err) = &e {
+            if db_err.is_unique_violation() {
+                return Error::msg("problem name already exists")
+                    .status_code(StatusCode::BAD_REQUEST);
+            }
+        }
+        Error::msg(format!("database error: {}", e))
+    })?;
+
+    for (_, hash) in test_cases.iter() {
+        sqlx::query!(
+            r#"
+            INSERT INTO test_cases (problem_id, hash) VALUES ($1, $2)
+            "#,
+            problem_id,
+            hash
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    }
+
+    state.write_problem_content(problem_id, &content).await?;
+
+    if let Err(e) = tx.commit().await {
+        let _ = state.delete_problem_content(problem_id).await;
+        return Err(Error::msg(format!("database error: {}", e)));
+    }
+
+    Ok(problem_id)
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreateSolutionRequest {
+    title: String,
+    content: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreateSolutionResponse {
+    solution_id: i32,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/problems/{problem_id}/solutions",
+    request_body = CreateSolutionRequest,
+    params(
+        ("problem_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = CreateSolutionResponse),
+    ),
+    tag = "problem"
+)]
+async fn create_solution(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+    Json(p): Json<CreateSolutionRequest>,
+) -> Result<Json<CreateSolutionResponse>> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::CreateSolution,
+        Resource::Problem(problem_id),
+    )
+    .await?;
+
+    let mut errors = ValidationError::new();
+    if p.title.is_empty() {
+        errors.add("title", "title is required");
+    }
+    if p.content.is_empty() {
+        errors.add("content", "content is required");
+    }
+    check_max_length(
+        &mut errors,
+        "content",
+        &p.content,
+        state.config.max_solution_length,
+    );
+    errors.check()?;
+
+    sqlx::query!(
+        r#"
+        SELECT id FROM problems WHERE id = $1
+        "#,
+        problem_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("problem not found").status_code(StatusCode::NOT_FOUND))?;
+
+    let solution_id: i32 = sqlx::query_scalar!(
+        r#"
+        INSERT INTO solutions (problem_id, author, title)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+        problem_id,
+        claims.sub,
+        p.title
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let solution_content = SolutionContent { content: p.content };
+
+    state
+        .write_solution_content(solution_id, &solution_content)
+        .await?;
+
+    Ok(Json(CreateSolutionResponse {
+        solution_id: solution_id,
+    }))
+}
+
+/// Solutions spoil the problem, so a problem can opt in to showing them only
+/// to staff and users with an accepted submission
+async fn check_solution_access(
+    pool: &sqlx::PgPool,
+    claims: &Claims,
+    problem_id: i32,
+    require_ac: bool,
+) -> Result<()> {
+    if !require_ac {
+        return Ok(());
+    }
+
+    match role_of_claims(pool, claims).await? {
+        UserRole::Teacher | UserRole::Admin => return Ok(()),
+        UserRole::Guest => bail!(@FORBIDDEN "solve the problem first to see its solutions"),
+        UserRole::Student => {}
+    }
+
+    let solved = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM submissions
+            WHERE problem_id = $1 AND user_id = $2 AND result = 'accepted'
+        ) as "exists!"
+        "#,
+        problem_id,
+        claims.sub
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if !solved {
+        bail!(@FORBIDDEN "solve the problem first to see its solutions");
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SolutionListItem {
+    solution_id: i32,
+    title: String,
+    author_id: i32,
+    author_name: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ListSolutionsResponse {
+    solutions: Vec<SolutionListItem>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/problems/{problem_id}/solutions",
+    security((), ("bearer_auth" = [])),
+    params(
+        ("problem_id" = i32, Path)
+    ),
+    responses(
+        (status = 200, body = ListSolutionsResponse),
+        (status = 403, description = "The problem must be solved first"),
+    ),
+    tag = "problem"
+)]
+async fn list_solutions(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+) -> Result<Json<ListSolutionsResponse>> {
+    let problem = sqlx::query!(
+        r#"
+        SELECT solutions_require_ac, status as "status: ProblemStatus"
+        FROM problems
+        WHERE id = $1
+        "#,
+        problem_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| not_found("problem"))?;
+    check_visible(
+        &state.pool,
+        &claims,
+        problem.status != ProblemStatus::Active,
+        "problem",
+    )
+    .await?;
+
+    check_solution_access(
+        &state.pool,
+        &claims,
+        problem_id,
+        problem.solutions_require_ac,
+    )
+    .await?;
+
+    let solutions = sqlx::query!(
+        r#"
+        SELECT s.id, s.title, s.author, s.created_at, u.username
+        FROM solutions s
+        JOIN users u ON s.author = u.id
+        WHERE s.problem_id = $1
+        ORDER BY s.created_at DESC
+        "#,
+        problem_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .into_iter()
+    .map(|row| SolutionListItem {
+        solution_id: row.id,
+        title: row.title,
+        author_id: row.author,
+        author_name: row.username,
+        created_at: row.created_at,
+    })
+    .collect();
+
+    Ok(Json(ListSolutionsResponse { solutions }))
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -820,28 +1872,33 @@ pub(crate) struct GetSolutionResponse {
     content: String,
     author_id: i32,
     author_name: String,
-    created_at: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
 #[utoipa::path(
     get,
     path = "/api/problems/{problem_id}/solutions/{solution_id}",
+    security((), ("bearer_auth" = [])),
     params(
         ("problem_id" = i32, Path),
         ("solution_id" = i32, Path)
     ),
     responses(
         (status = 200, body = GetSolutionResponse),
+        (status = 403, description = "The problem must be solved first"),
     ),
     tag = "problem"
 )]
 async fn get_solution(
     state: State,
+    claims: Extension<Claims>,
     Path((problem_id, solution_id)): Path<(i32, i32)>,
 ) -> Result<Json<GetSolutionResponse>> {
     let solution = sqlx::query!(
         r#"
-        SELECT s.id, s.title, s.author, s.created_at, u.username
+        SELECT s.id, s.title, s.author, s.created_at, s.updated_at, u.username,
+               p.solutions_require_ac
         FROM solutions s
         JOIN users u ON s.author = u.id
         JOIN problems p ON s.problem_id = p.id
@@ -855,6 +1912,14 @@ async fn get_solution(
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
     .ok_or_else(|| Error::msg("solution not found").status_code(StatusCode::NOT_FOUND))?;
 
+    check_solution_access(
+        &state.pool,
+        &claims,
+        problem_id,
+        solution.solutions_require_ac,
+    )
+    .await?;
+
     let solution_content = state.read_solution_content(solution_id).await?;
 
     Ok(Json(GetSolutionResponse {
@@ -863,13 +1928,22 @@ async fn get_solution(
         content: solution_content.content,
         author_id: solution.author,
         author_name: solution.username,
-        created_at: solution.created_at.to_rfc3339(),
+        created_at: solution.created_at,
+        updated_at: solution.updated_at,
     }))
 }
 
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PutSolutionRequest {
+    title: Option<String>,
+    content: Option<String>,
+}
+
 #[utoipa::path(
-    delete,
+    put,
     path = "/api/problems/{problem_id}/solutions/{solution_id}",
+    request_body = PutSolutionRequest,
     params(
         ("problem_id" = i32, Path),
         ("solution_id" = i32, Path)
@@ -880,25 +1954,45 @@ async fn get_solution(
     ),
     tag = "problem"
 )]
-async fn delete_solution(
+async fn put_solution(
     state: State,
     claims: Extension<Claims>,
     Path((problem_id, solution_id)): Path<(i32, i32)>,
+    Json(p): Json<PutSolutionRequest>,
 ) -> Result<()> {
     check_permission(
         &state.pool,
         &claims,
-        Action::DeleteSolution,
+        Action::PutSolution,
         Resource::Solution(solution_id),
     )
     .await?;
 
-    let deleted = sqlx::query!(
+    let mut errors = ValidationError::new();
+    if p.title.as_ref().is_some_and(|title| title.is_empty()) {
+        errors.add("title", "title is required");
+    }
+    if let Some(content) = &p.content {
+        if content.is_empty() {
+            errors.add("content", "content is required");
+        }
+        check_max_length(
+            &mut errors,
+            "content",
+            content,
+            state.config.max_solution_length,
+        );
+    }
+    errors.check()?;
+
+    let updated = sqlx::query!(
         r#"
-        DELETE FROM solutions
-        WHERE id = $1 AND problem_id = $2
+        UPDATE solutions
+        SET title = COALESCE($1, title), updated_at = NOW()
+        WHERE id = $2 AND problem_id = $3
         RETURNING id
         "#,
+        p.title,
         solution_id,
         problem_id
     )
@@ -906,19 +2000,89 @@ async fn delete_solution(
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    if deleted.is_none() {
+    if updated.is_none() {
         bail!(@NOT_FOUND "solution not found");
     }
 
+    if let Some(content) = p.content {
+        state
+            .write_solution_content(solution_id, &SolutionContent { content })
+            .await?;
+    }
+
     Ok(())
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
+#[utoipa::path(
+    delete,
+    path = "/api/problems/{problem_id}/solutions/{solution_id}",
+    params(
+        ("problem_id" = i32, Path),
+        ("solution_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ()),
+    ),
+    tag = "problem"
+)]
+async fn delete_solution(
+    state: State,
+    claims: Extension<Claims>,
+    Path((problem_id, solution_id)): Path<(i32, i32)>,
+) -> Result<()> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::DeleteSolution,
+        Resource::Solution(solution_id),
+    )
+    .await?;
+
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM solutions
+        WHERE id = $1 AND problem_id = $2
+        RETURNING id
+        "#,
+        solution_id,
+        problem_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if deleted.is_none() {
+        bail!(@NOT_FOUND "solution not found");
+    }
+
+    // the row is gone already, so a leftover file is only worth a warning
+    if let Err(e) = state.delete_solution_content(solution_id).await {
+        tracing::warn!("Failed to delete solution {}: {:?}", solution_id, e);
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct SubmitRequest {
     code: String,
     lang: Language,
     contest_id: Option<i32>,
+    /// submit as part of a virtual participation in an ended contest
+    #[serde(default, rename = "virtual")]
+    is_virtual: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(crate) struct SubmitRawQuery {
+    lang: Language,
+    contest_id: Option<i32>,
+    #[serde(default, rename = "virtual")]
+    is_virtual: bool,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -927,16 +2091,135 @@ pub(crate) struct SubmitResponse {
     submission_id: i32,
 }
 
+/// how long a retried submission is recognized by its idempotency key
+const IDEMPOTENCY_KEY_TTL_SECS: u64 = 10 * 60;
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 128;
+/// stored under the key while the first request is still creating the submission
+const IDEMPOTENCY_PENDING: i32 = 0;
+
+fn submission_idempotency_key(user_id: i32, problem_id: i32, key: &str) -> String {
+    format!("submission_idempotency:{}:{}:{}", user_id, problem_id, key)
+}
+
+/// The submission an idempotency key already created, if any
+async fn replayed_submission(state: &AppState, redis_key: &str) -> Result<Option<i32>> {
+    let mut redis_conn = state.redis.clone();
+    let submission_id: Option<i32> = redis_conn
+        .get(redis_key)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+    match submission_id {
+        Some(IDEMPOTENCY_PENDING) => {
+            bail!(@CONFLICT "a submission with this idempotency key is in progress")
+        }
+        submission_id => Ok(submission_id),
+    }
+}
+
+/// Starts the caller's cooldown for `kind` of request, or returns how many
+/// seconds are left of the running one. Staff are never throttled
+async fn cooldown(state: &AppState, claims: &Claims, kind: &str) -> Result<Option<i64>> {
+    let cooldown_ms = state.config.submission_cooldown.num_milliseconds();
+    if cooldown_ms <= 0 {
+        return Ok(None);
+    }
+    if matches!(
+        role_of_claims(&state.pool, claims).await?,
+        UserRole::Teacher | UserRole::Admin
+    ) {
+        return Ok(None);
+    }
+
+    let key = format!("{}_cooldown:{}", kind, claims.sub);
+    let mut redis_conn = state.redis.clone();
+    let started: Option<String> = redis_conn
+        .set_options(
+            &key,
+            1,
+            SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::PX(cooldown_ms as u64)),
+        )
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    if started.is_some() {
+        return Ok(None);
+    }
+
+    let remaining_ms: i64 = redis_conn
+        .pttl(&key)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+    Ok(Some(((remaining_ms + 999) / 1000).max(1)))
+}
+
+fn too_many_requests(retry_after: i64) -> Response {
+    let mut res = Error::msg("submitting too often, please wait")
+        .status_code(StatusCode::TOO_MANY_REQUESTS)
+        .into_response();
+    res.headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    res
+}
+
+/// Checks code against the problem's length limit and language allow-list
+async fn check_submission_code(
+    state: &AppState,
+    problem_id: i32,
+    code: &str,
+    lang: Language,
+) -> Result<()> {
+    if code.is_empty() {
+        return Err(Error::invalid_field("code", "code is required"));
+    }
+
+    let max_code_length = sqlx::query_scalar!(
+        "SELECT max_code_length FROM problems WHERE id = $1",
+        problem_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .map(|len| len as usize)
+    .unwrap_or(state.config.max_code_length);
+    if code.len() > max_code_length {
+        return Err(Error::invalid_field(
+            "code",
+            format!("code can be at most {} bytes", max_code_length),
+        ));
+    }
+
+    let allowed_languages = state
+        .read_problem_content(problem_id)
+        .await?
+        .allowed_languages;
+    if !allowed_languages.is_empty() && !allowed_languages.contains(&lang) {
+        let allowed: Vec<String> = allowed_languages.iter().map(|l| l.to_string()).collect();
+        return Err(Error::invalid_field(
+            "lang",
+            format!("this problem only accepts {}", allowed.join(", ")),
+        ));
+    }
+
+    Ok(())
+}
+
 #[utoipa::path(
     post,
     path = "/api/problems/{problem_id}/submissions",
     request_body = SubmitRequest,
     params(
-        ("problem_id" = i32, Path)
+        ("problem_id" = i32, Path),
+        ("Idempotency-Key" = Option<String>, Header,
+            description = "retrying with the same key returns the first submission instead of judging again"),
     ),
     security(("bearer_auth" = [])),
     responses(
         (status = 200, body = SubmitResponse),
+        (status = 409, description = "the problem has no test cases"),
+        (status = 429, description = "submitting too often, see Retry-After"),
     ),
     tag = "problem"
 )]
@@ -944,48 +2227,148 @@ async fn submit(
     state: State,
     claims: Extension<Claims>,
     Path(problem_id): Path<i32>,
+    headers: HeaderMap,
     Json(p): Json<SubmitRequest>,
-) -> Result<Json<SubmitResponse>> {
-    if p.code.is_empty() {
-        bail!(@BAD_REQUEST "code and lang are required");
+) -> Result<Response> {
+    submit_code(state, claims, problem_id, headers, p).await
+}
+
+/// Same as `submit`, with the code as the plain body, which saves escaping and
+/// parsing it as json
+#[utoipa::path(
+    post,
+    path = "/api/problems/{problem_id}/submissions/raw",
+    request_body(content = String, content_type = "text/plain"),
+    params(
+        ("problem_id" = i32, Path),
+        SubmitRawQuery,
+        ("Idempotency-Key" = Option<String>, Header,
+            description = "retrying with the same key returns the first submission instead of judging again"),
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = SubmitResponse),
+        (status = 409, description = "the problem has no test cases"),
+        (status = 429, description = "submitting too often, see Retry-After"),
+    ),
+    tag = "problem"
+)]
+async fn submit_raw(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+    Query(query): Query<SubmitRawQuery>,
+    headers: HeaderMap,
+    code: String,
+) -> Result<Response> {
+    let p = SubmitRequest {
+        code,
+        lang: query.lang,
+        contest_id: query.contest_id,
+        is_virtual: query.is_virtual,
+    };
+    submit_code(state, claims, problem_id, headers, p).await
+}
+
+async fn submit_code(
+    state: State,
+    claims: Extension<Claims>,
+    problem_id: i32,
+    headers: HeaderMap,
+    p: SubmitRequest,
+) -> Result<Response> {
+    let idempotency_key = match headers.get("idempotency-key") {
+        Some(value) => {
+            let key = value
+                .to_str()
+                .ok()
+                .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH)
+                .ok_or_else(|| {
+                    Error::msg("invalid idempotency key").status_code(StatusCode::BAD_REQUEST)
+                })?;
+            Some(submission_idempotency_key(claims.sub, problem_id, key))
+        }
+        None => None,
+    };
+
+    if let Some(redis_key) = &idempotency_key {
+        if let Some(submission_id) = replayed_submission(&state, redis_key).await? {
+            return Ok(Json(SubmitResponse { submission_id }).into_response());
+        }
+    }
+
+    if state.live_judge_count().await < state.config.min_judges {
+        bail!(@SERVICE_UNAVAILABLE "judging temporarily unavailable");
     }
 
     let contest_id = p.contest_id;
+    let is_virtual = p.is_virtual;
+
+    if is_virtual && contest_id.is_none() {
+        bail!(@BAD_REQUEST "virtual submissions require a contest");
+    }
+
+    // made to an ended contest for practice, kept out of the ranking
+    let mut is_upsolve = false;
 
     // submitting to a contest's problem
     if let Some(cid) = contest_id {
-        // verify contest exists and is in valid time range
-        let _contest_exists = sqlx::query!(
+        // a hidden contest is reported as missing, to virtual participants
+        // too
+        let contest = sqlx::query!(
             r#"
-            SELECT id FROM contests 
+            SELECT begin_time, end_time, allow_upsolving FROM contests 
             WHERE id = $1 
             AND status = 'active'
-            AND begin_time <= NOW()
-            AND end_time >= NOW()
             "#,
             cid
         )
         .fetch_optional(&state.pool)
         .await
         .map_err(|e| Error::msg(format!("database error: {}", e)))?
-        .ok_or_else(|| {
-            Error::msg("contest not in valid time range").status_code(StatusCode::FORBIDDEN)
-        })?;
+        .ok_or_else(|| not_found("contest"))?;
 
-        // verify that this user participates in this contest
-        let participant = sqlx::query!(
-            r#"
-            SELECT user_id FROM contest_participants WHERE contest_id = $1 AND user_id = $2
-            "#,
-            cid,
-            claims.sub
-        )
-        .fetch_optional(&state.pool)
-        .await
-        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+        if is_virtual {
+            // verify that this user's virtual participation is still running
+            sqlx::query!(
+                r#"
+                SELECT v.started_at FROM contest_virtual_participants v
+                JOIN contests c ON v.contest_id = c.id
+                WHERE v.contest_id = $1 AND v.user_id = $2
+                AND v.started_at + (c.end_time - c.begin_time) >= NOW()
+                "#,
+                cid,
+                claims.sub
+            )
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))?
+            .ok_or_else(|| {
+                Error::msg("virtual participation not running").status_code(StatusCode::FORBIDDEN)
+            })?;
+        } else {
+            // verify contest is in valid time range
+            let now = Utc::now();
+            is_upsolve = contest.end_time < now;
+            if contest.begin_time > now || (is_upsolve && !contest.allow_upsolving) {
+                bail!(@FORBIDDEN "contest not in valid time range");
+            }
+
+            // verify that this user participates in this contest
+            let participant = sqlx::query!(
+                r#"
+                SELECT user_id FROM contest_participants WHERE contest_id = $1 AND user_id = $2
+                "#,
+                cid,
+                claims.sub
+            )
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-        if participant.is_none() {
-            bail!(@FORBIDDEN "user not participating in this contest");
+            if participant.is_none() {
+                bail!(@FORBIDDEN "user not participating in this contest");
+            }
         }
 
         // verify that this problem is in this contest
@@ -1004,6 +2387,31 @@ async fn submit(
             Error::msg("problem not in this contest").status_code(StatusCode::NOT_FOUND)
         })?;
 
+        // the ranking already counts every attempt, this only stops new ones,
+        // practice after the end is never limited
+        let attempts = sqlx::query!(
+            r#"
+            SELECT c.max_attempts_per_problem,
+                   (SELECT COUNT(*) FROM submissions s
+                    WHERE s.contest_id = c.id AND s.problem_id = $2 AND s.user_id = $3
+                    AND s.is_virtual = $4 AND NOT s.is_upsolve) as "used!"
+            FROM contests c
+            WHERE c.id = $1
+            "#,
+            cid,
+            problem_id,
+            claims.sub,
+            is_virtual
+        )
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+        if let Some(max) = attempts.max_attempts_per_problem.filter(|_| !is_upsolve) {
+            if attempts.used >= max as i64 {
+                bail!(@FORBIDDEN "no attempts left for this problem");
+            }
+        }
+
         // for contest submissions, we don't check if problem is active
         // just verify the problem exists
         sqlx::query!(
@@ -1030,31 +2438,81 @@ async fn submit(
         .ok_or_else(|| Error::msg("problem not found").status_code(StatusCode::NOT_FOUND))?;
     }
 
+    // nothing to judge against, it would come back as a vacuous accepted
+    if !has_test_cases(&state, problem_id).await? {
+        bail!(@CONFLICT "problem has no test cases");
+    }
+
+    check_submission_code(&state, problem_id, &p.code, p.lang).await?;
+
+    // only a submission that gets in starts the cooldown
+    if let Some(retry_after) = cooldown(&state, &claims, "submission").await? {
+        return Ok(too_many_requests(retry_after));
+    }
+
+    // claim the key before inserting, so concurrent retries can't both get through
+    if let Some(redis_key) = &idempotency_key {
+        let mut redis_conn = state.redis.clone();
+        let reserved: Option<String> = redis_conn
+            .set_options(
+                redis_key,
+                IDEMPOTENCY_PENDING,
+                SetOptions::default()
+                    .conditional_set(ExistenceCheck::NX)
+                    .with_expiration(SetExpiry::EX(IDEMPOTENCY_KEY_TTL_SECS)),
+            )
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+        if reserved.is_none() {
+            if let Some(submission_id) = replayed_submission(&state, redis_key).await? {
+                return Ok(Json(SubmitResponse { submission_id }).into_response());
+            }
+        }
+    }
+
     let submission = sqlx::query!(
         r#"
-        INSERT INTO submissions (user_id, contest_id, problem_id, lang, result)
-        VALUES ($1, $2, $3, $4, 'pending')
+        INSERT INTO submissions
+            (user_id, contest_id, problem_id, lang, result, is_virtual, is_upsolve)
+        VALUES ($1, $2, $3, $4, 'pending', $5, $6)
         RETURNING id, created_at
         "#,
         claims.sub,
         contest_id,
         problem_id,
-        p.lang.to_string()
+        p.lang.to_string(),
+        is_virtual,
+        is_upsolve
     )
     .fetch_one(&state.pool)
-    .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    .await;
 
-    let code_for_judge = p.code.clone();
-    let submission_code = SubmissionCode { code: p.code };
+    let submission = match submission {
+        Ok(submission) => submission,
+        Err(e) => {
+            if let Some(redis_key) = &idempotency_key {
+                let mut redis_conn = state.redis.clone();
+                let _: redis::RedisResult<()> = redis_conn.del(redis_key).await;
+            }
+            return Err(Error::msg(format!("database error: {}", e)));
+        }
+    };
 
-    state
-        .write_submission_code(submission.id, &submission_code)
-        .await?;
+    if let Some(redis_key) = &idempotency_key {
+        let mut redis_conn = state.redis.clone();
+        let _: () = redis_conn
+            .set_ex(redis_key, submission.id, IDEMPOTENCY_KEY_TTL_SECS)
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    }
+
+    let code = state.write_submission_code(submission.id, p.code).await?;
 
     let problem_limits = sqlx::query!(
         r#"
-        SELECT time_limit, mem_limit FROM problems WHERE id = $1
+        SELECT time_limit, mem_limit, sample_precheck, reveal_diff, judge_samples
+        FROM problems WHERE id = $1
         "#,
         problem_id
     )
@@ -1062,7 +2520,7 @@ async fn submit(
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    let test_case_records = sqlx::query!(
+    let test_case_ids = sqlx::query_scalar!(
         r#"
         SELECT id FROM test_cases WHERE problem_id = $1 ORDER BY id
         "#,
@@ -1071,26 +2529,39 @@ async fn submit(
     .fetch_all(&state.pool)
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?;
-    let mut test_cases = Vec::new();
-    for record in test_case_records {
-        let test_case_data = state.read_test_cases(record.id).await?;
-        test_cases.push(TestCase {
-            id: record.id,
-            data: test_case_data,
-        });
-    }
+    // only the first batch goes out with the task, the judge asks for the rest
+    let total_test_cases = test_case_ids.len() as u32;
+    state.queue_test_cases(submission.id, test_case_ids);
+    let test_cases = match state.next_test_case_batch(submission.id).await {
+        Ok(test_cases) => test_cases,
+        Err(e) => {
+            state.forget_test_cases(submission.id);
+            return Err(e);
+        }
+    };
+    let samples = if problem_limits.sample_precheck || problem_limits.judge_samples {
+        state.read_problem_content(problem_id).await?.samples
+    } else {
+        Vec::new()
+    };
     let task = JudgeTask {
         submission_id: submission.id,
         lang: p.lang,
-        code: code_for_judge,
+        code,
         time_limit: problem_limits.time_limit,
         memory_limit: problem_limits.mem_limit,
+        remaining_test_cases: total_test_cases - test_cases.len() as u32,
         test_cases,
+        samples,
+        continue_after_samples: !problem_limits.sample_precheck,
+        keep_output: false,
+        reveal_diff: problem_limits.reveal_diff,
     };
     let state_clone = state.clone();
     tokio::spawn(async move {
-        if let Err(e) = state_clone.submit_judge_task(task).await {
+        if let Err(e) = state_clone.submit_judge_task(task, None).await {
             tracing::error!("Failed to submit judge task: {:?}", e);
+            state_clone.forget_test_cases(submission.id);
 
             if let Err(update_err) = sqlx::query!(
                 r#"
@@ -1104,9 +2575,9 @@ async fn submit(
                 tracing::error!("Failed to update submission status: {:?}", update_err);
             }
 
-            // Update ranking cache if this is a contest submission
+            // Update ranking cache if this is an official contest submission
             // UnknownError is treated as a failed attempt
-            if let Some(contest_id) = contest_id {
+            if let Some(contest_id) = contest_id.filter(|_| !is_virtual && !is_upsolve) {
                 if let Err(e) = crate::route::contests::ranking_cache::update_ranking_on_submission(
                     &state,
                     contest_id,
@@ -1126,66 +2597,338 @@ async fn submit(
 
     Ok(Json(SubmitResponse {
         submission_id: submission.id,
-    }))
+    })
+    .into_response())
 }
 
-#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
+#[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct ListSubmissionsQuery {
-    page: Option<i64>,
-    page_size: Option<i64>,
+pub(crate) struct RunRequest {
+    code: String,
+    lang: Language,
+    /// also run the program on this, without checking its output
+    input: Option<String>,
+    /// the contest the problem is opened from
     contest_id: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct SubmissionListItem {
-    submission_id: i32,
-    user_id: i32,
-    username: String,
-    problem_id: i32,
-    problem_name: String,
-    lang: String,
-    result: SubmissionResult,
-    time_consumption: Option<i32>,
-    mem_consumption: Option<i32>,
-    created_at: String,
+pub(crate) struct RunTestResult {
+    result: TestCaseJudgeResult,
+    time_consumption: i32,
+    memory_consumption: i32,
+    output: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct ListSubmissionsResponse {
-    submissions: Vec<SubmissionListItem>,
-    total: i64,
+pub(crate) struct RunResponse {
+    compile_error: bool,
+    samples: Vec<RunTestResult>,
+    /// the run on `input`, accepted whenever it finished within the limits
+    custom: Option<RunTestResult>,
+}
+
+/// Checks that the caller can run code on the problem, and returns its time
+/// and memory limits
+async fn run_limits(
+    state: &AppState,
+    claims: &Claims,
+    problem_id: i32,
+    contest_id: Option<i32>,
+) -> Result<(i32, i32)> {
+    let problem = sqlx::query!(
+        r#"
+        SELECT time_limit, mem_limit, status as "status: ProblemStatus"
+        FROM problems
+        WHERE id = $1
+        "#,
+        problem_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| not_found("problem"))?;
+
+    match contest_id {
+        Some(cid) => {
+            verify_contest_problem_access(&state.pool, claims, cid, problem_id).await?;
+        }
+        None => {
+            check_visible(
+                &state.pool,
+                claims,
+                problem.status != ProblemStatus::Active,
+                "problem",
+            )
+            .await?
+        }
+    }
+
+    Ok((problem.time_limit, problem.mem_limit))
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/problems/{problem_id}/submissions",
+    post,
+    path = "/api/problems/{problem_id}/run",
+    request_body = RunRequest,
     params(
-        ("problem_id" = i32, Path, description = "Problem ID"),
-        ListSubmissionsQuery
+        ("problem_id" = i32, Path)
     ),
     security(("bearer_auth" = [])),
     responses(
-        (status = 200, body = ListSubmissionsResponse),
+        (status = 200, body = RunResponse),
+        (status = 429, description = "running too often, see Retry-After"),
     ),
     tag = "problem"
 )]
-async fn list_submissions(
+async fn run(
     state: State,
     claims: Extension<Claims>,
     Path(problem_id): Path<i32>,
-    Query(q): Query<ListSubmissionsQuery>,
-) -> Result<Json<ListSubmissionsResponse>> {
-    let page = q.page.unwrap_or(1).max(1);
-    let page_size = q.page_size.unwrap_or(20).clamp(1, 100);
-    let offset = (page - 1) * page_size;
+    Json(p): Json<RunRequest>,
+) -> Result<Response> {
+    let (time_limit, memory_limit) = run_limits(&state, &claims, problem_id, p.contest_id).await?;
+    check_submission_code(&state, problem_id, &p.code, p.lang).await?;
 
-    let requester_role = role_of_claims(&state.pool, &claims).await?;
+    let samples = state.read_problem_content(problem_id).await?.samples;
+    if samples.is_empty() && p.input.is_none() {
+        return Err(Error::invalid_field(
+            "input",
+            "this problem has no samples, input is required",
+        ));
+    }
 
-    // to unify records returnd by if branches
-    #[derive(Debug)]
+    if state.live_judge_count().await < state.config.min_judges {
+        bail!(@SERVICE_UNAVAILABLE "judging temporarily unavailable");
+    }
+
+    if let Some(retry_after) = cooldown(&state, &claims, "run").await? {
+        return Ok(too_many_requests(retry_after));
+    }
+
+    // samples are numbered from 0, the custom input comes right after them
+    let sample_count = samples.len();
+    let mut test_cases: Vec<TestCase> = samples
+        .into_iter()
+        .enumerate()
+        .map(|(i, data)| TestCase { id: i as i32, data })
+        .collect();
+    if let Some(input) = p.input {
+        test_cases.push(TestCase {
+            id: sample_count as i32,
+            data: TestCaseData {
+                input,
+                output: String::new(),
+            },
+        });
+    }
+
+    let result = state
+        .run_judge_task(JudgeTask {
+            submission_id: 0,
+            lang: p.lang,
+            code: p.code,
+            time_limit,
+            memory_limit,
+            test_cases,
+            samples: Vec::new(),
+            continue_after_samples: false,
+            keep_output: true,
+            remaining_test_cases: 0,
+            reveal_diff: false,
+        })
+        .await?;
+
+    let mut test_results = result.test_results;
+    test_results.sort_by_key(|r| r.test_case_id);
+    let mut samples = Vec::new();
+    let mut custom = None;
+    for r in test_results {
+        let mut run_result = RunTestResult {
+            result: r.result,
+            time_consumption: r.time_consumption,
+            memory_consumption: r.memory_consumption,
+            output: r.output,
+        };
+        if r.test_case_id as usize == sample_count {
+            // there's no expected output to compare with
+            if run_result.result == TestCaseJudgeResult::WrongAnswer {
+                run_result.result = TestCaseJudgeResult::Accepted;
+            }
+            custom = Some(run_result);
+        } else {
+            samples.push(run_result);
+        }
+    }
+
+    Ok(Json(RunResponse {
+        compile_error: result.result == SubmissionResult::CompileError,
+        samples,
+        custom,
+    })
+    .into_response())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CustomRunRequest {
+    code: String,
+    lang: Language,
+    stdin: String,
+    /// the contest the problem is opened from
+    contest_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CustomRunResponse {
+    compile_error: bool,
+    /// accepted when the program finished within the limits, there is no
+    /// expected output to check against
+    result: TestCaseJudgeResult,
+    stdout: String,
+    stderr: String,
+    time_consumption: i32,
+    memory_consumption: i32,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/problems/{problem_id}/custom-run",
+    request_body = CustomRunRequest,
+    params(
+        ("problem_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = CustomRunResponse),
+        (status = 429, description = "running too often, see Retry-After"),
+    ),
+    tag = "problem"
+)]
+async fn custom_run(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+    Json(p): Json<CustomRunRequest>,
+) -> Result<Response> {
+    let (time_limit, memory_limit) = run_limits(&state, &claims, problem_id, p.contest_id).await?;
+    check_submission_code(&state, problem_id, &p.code, p.lang).await?;
+
+    if state.live_judge_count().await < state.config.min_judges {
+        bail!(@SERVICE_UNAVAILABLE "judging temporarily unavailable");
+    }
+
+    if let Some(retry_after) = cooldown(&state, &claims, "run").await? {
+        return Ok(too_many_requests(retry_after));
+    }
+
+    let result = state
+        .run_judge_task(JudgeTask {
+            submission_id: 0,
+            lang: p.lang,
+            code: p.code,
+            time_limit,
+            memory_limit,
+            test_cases: vec![TestCase {
+                id: 0,
+                data: TestCaseData {
+                    input: p.stdin,
+                    output: String::new(),
+                },
+            }],
+            samples: Vec::new(),
+            continue_after_samples: false,
+            keep_output: true,
+            remaining_test_cases: 0,
+            reveal_diff: false,
+        })
+        .await?;
+
+    let compile_error = result.result == SubmissionResult::CompileError;
+    let response = match result.test_results.into_iter().next() {
+        Some(r) => CustomRunResponse {
+            compile_error,
+            result: match r.result {
+                TestCaseJudgeResult::WrongAnswer => TestCaseJudgeResult::Accepted,
+                result => result,
+            },
+            stdout: r.output.unwrap_or_default(),
+            stderr: r.error_output.unwrap_or_default(),
+            time_consumption: r.time_consumption,
+            memory_consumption: r.memory_consumption,
+        },
+        None => CustomRunResponse {
+            compile_error,
+            result: if compile_error {
+                TestCaseJudgeResult::CompileError
+            } else {
+                TestCaseJudgeResult::UnknownError
+            },
+            stdout: String::new(),
+            stderr: String::new(),
+            time_consumption: 0,
+            memory_consumption: 0,
+        },
+    };
+
+    Ok(Json(response).into_response())
+}
+
+#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ListSubmissionsQuery {
+    page: Option<i64>,
+    page_size: Option<i64>,
+    contest_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SubmissionListItem {
+    submission_id: i32,
+    user_id: i32,
+    username: String,
+    problem_id: i32,
+    problem_name: String,
+    /// unset for a language this server no longer knows
+    lang: Option<Language>,
+    result: SubmissionResult,
+    time_consumption: Option<i32>,
+    mem_consumption: Option<i32>,
+    created_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/problems/{problem_id}/submissions",
+    params(
+        ("problem_id" = i32, Path, description = "Problem ID"),
+        ListSubmissionsQuery
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = Page<SubmissionListItem>),
+    ),
+    tag = "problem"
+)]
+async fn list_submissions(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+    Query(q): Query<ListSubmissionsQuery>,
+) -> Result<Json<Page<SubmissionListItem>>> {
+    let page = q.page.unwrap_or(1).clamp(1, MAX_PAGE);
+    let page_size = q.page_size.unwrap_or(20).clamp(1, 100);
+    let offset = (page - 1) * page_size;
+
+    let requester_role = role_of_claims(&state.pool, &claims).await?;
+
+    // to unify records returnd by if branches
+    #[derive(Debug)]
     struct SubmissionWithDetails {
         id: i32,
         user_id: i32,
@@ -1344,21 +3087,15 @@ async fn list_submissions(
             username: row.username,
             problem_id: row.problem_id,
             problem_name: row.problem_name,
-            lang: row.lang,
+            lang: stored_language(&row.lang),
             result: row.result,
             time_consumption: row.time_consumption,
             mem_consumption: row.mem_consumption,
-            created_at: row
-                .created_at
-                .expect("created_at should not be null")
-                .to_rfc3339(),
+            created_at: row.created_at.expect("created_at should not be null"),
         })
         .collect();
 
-    Ok(Json(ListSubmissionsResponse {
-        submissions: submission_list,
-        total,
-    }))
+    Ok(Json(Page::new(submission_list, total, page, page_size)))
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -1366,6 +3103,26 @@ async fn list_submissions(
 pub(crate) struct TestCaseResultItem {
     test_case_id: i32,
     result: TestCaseJudgeResult,
+    /// how the program ended on a runtime error, e.g. "signal 11 (SIGSEGV)"
+    message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SampleResultItem {
+    /// position in the problem's samples
+    sample_index: i32,
+    result: TestCaseJudgeResult,
+    message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SubmissionDiff {
+    /// none when the failing case was a sample
+    test_case_id: Option<i32>,
+    #[serde(flatten)]
+    diff: OutputDiff,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -1376,13 +3133,27 @@ pub(crate) struct GetSubmissionResponse {
     username: String,
     problem_id: i32,
     problem_name: String,
-    lang: String,
+    /// unset for a language this server no longer knows
+    lang: Option<Language>,
     code: String,
     result: SubmissionResult,
     time_consumption: Option<i32>,
     mem_consumption: Option<i32>,
+    /// judging stopped on a failing sample, before any hidden test ran
+    failed_on_sample: bool,
+    /// set when compiling ran out of time or memory
+    compile_message: Option<String>,
+    /// the judge that ran it, only shown to teachers and admins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    judge_id: Option<String>,
+    /// expected and actual output of the first wrong answer, for a sample or
+    /// when the problem reveals diffs
+    diff: Option<SubmissionDiff>,
+    /// samples that were judged, empty unless the problem judges samples or
+    /// prechecks them
+    sample_results: Vec<SampleResultItem>,
     test_case_results: Vec<TestCaseResultItem>,
-    created_at: String,
+    created_at: DateTime<Utc>,
 }
 
 #[utoipa::path(
@@ -1415,8 +3186,9 @@ async fn get_submission(
         r#"
         SELECT s.id, s.user_id, s.problem_id, s.lang, 
                s.result as "result: SubmissionResult",
-               s.time_consumption, s.mem_consumption, s.created_at,
-               u.username, p.name as problem_name
+               s.time_consumption, s.mem_consumption, s.failed_on_sample, s.created_at,
+               s.compile_message, s.judge_id,
+               u.username, p.name as problem_name, p.reveal_diff
         FROM submissions s
         JOIN users u ON s.user_id = u.id
         JOIN problems p ON s.problem_id = p.id
@@ -1432,9 +3204,56 @@ async fn get_submission(
 
     let submission_code = state.read_submission_code(submission_id).await?;
 
+    let judge_id = match role_of_claims(&state.pool, &claims).await? {
+        UserRole::Teacher | UserRole::Admin => submission.judge_id,
+        _ => None,
+    };
+
+    // checked again here, so that turning the flag off hides stored diffs too
+    let diff = sqlx::query!(
+        r#"
+        SELECT test_case_id, line, expected, actual
+        FROM submission_diffs
+        WHERE submission_id = $1
+        "#,
+        submission_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .filter(|row| row.test_case_id.is_none() || submission.reveal_diff)
+    .map(|row| SubmissionDiff {
+        test_case_id: row.test_case_id,
+        diff: OutputDiff {
+            line: row.line as u32,
+            expected: row.expected,
+            actual: row.actual,
+        },
+    });
+
+    let sample_results = sqlx::query!(
+        r#"
+        SELECT sample_index, result as "result: TestCaseJudgeResult", message
+        FROM submission_samples
+        WHERE submission_id = $1
+        ORDER BY sample_index
+        "#,
+        submission_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .into_iter()
+    .map(|row| SampleResultItem {
+        sample_index: row.sample_index,
+        result: row.result,
+        message: row.message,
+    })
+    .collect();
+
     let test_case_results = sqlx::query!(
         r#"
-        SELECT test_case_id, result as "result: TestCaseJudgeResult"
+        SELECT test_case_id, result as "result: TestCaseJudgeResult", message
         FROM submission_test_cases
         WHERE submission_id = $1
         ORDER BY test_case_id
@@ -1448,6 +3267,7 @@ async fn get_submission(
     .map(|row| TestCaseResultItem {
         test_case_id: row.test_case_id,
         result: row.result,
+        message: row.message,
     })
     .collect();
 
@@ -1457,101 +3277,257 @@ async fn get_submission(
         username: submission.username,
         problem_id: submission.problem_id,
         problem_name: submission.problem_name,
-        lang: submission.lang,
+        lang: stored_language(&submission.lang),
         code: submission_code.code,
         result: submission.result,
         time_consumption: submission.time_consumption,
         mem_consumption: submission.mem_consumption,
+        failed_on_sample: submission.failed_on_sample,
+        compile_message: submission.compile_message,
+        judge_id,
+        diff,
+        sample_results,
         test_case_results,
-        created_at: submission.created_at.to_rfc3339(),
+        created_at: submission.created_at,
     }))
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct GetAcStatusResponse {
-    tried: bool,
-    status: Option<SubmissionResult>,
-}
+#[utoipa::path(
+    get,
+    path = "/api/problems/{problem_id}/submissions/{submission_id}/source",
+    params(
+        ("problem_id" = i32, Path),
+        ("submission_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = String, content_type = "text/plain"),
+    ),
+    tag = "problem"
+)]
+async fn download_submission_source(
+    state: State,
+    claims: Extension<Claims>,
+    Path((problem_id, submission_id)): Path<(i32, i32)>,
+) -> Result<Response> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::GetSubmission,
+        Resource::Submission(submission_id),
+    )
+    .await?;
 
-#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
-struct GetAcStatusQuery {
-    contest_id: Option<i32>,
+    let lang = sqlx::query_scalar!(
+        r#"
+        SELECT lang FROM submissions WHERE id = $1 AND problem_id = $2
+        "#,
+        submission_id,
+        problem_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("submission not found").status_code(StatusCode::NOT_FOUND))?;
+    let extension = stored_language(&lang).map_or("txt", |lang| lang.file_extension());
+
+    let submission_code = state.read_submission_code(submission_id).await?;
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"sub_{}.{}\"",
+                submission_id, extension
+            ),
+        )
+        .body(submission_code.code.into())?)
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/problems/{problem_id}/ac-status",
+    post,
+    path = "/api/problems/{problem_id}/submissions/{submission_id}/bookmark",
     params(
         ("problem_id" = i32, Path),
-        ("contest_id" = Option<i32>, Query, description = "Optional contest ID to filter submissions")
+        ("submission_id" = i32, Path)
     ),
     security(("bearer_auth" = [])),
     responses(
-        (status = 200, body = GetAcStatusResponse),
+        (status = 200, body = ()),
     ),
     tag = "problem"
 )]
-async fn get_ac_status(
+async fn add_bookmark(
     state: State,
     claims: Extension<Claims>,
-    Path(problem_id): Path<i32>,
-    Query(params): Query<GetAcStatusQuery>,
-) -> Result<Json<GetAcStatusResponse>> {
-    let accepted_count: i64 = if let Some(contest_id) = params.contest_id {
-        sqlx::query_scalar!(
-            r#"
-            SELECT COUNT(*) FROM submissions
-            WHERE problem_id = $1 AND user_id = $2 AND contest_id = $3 AND result = 'accepted'
-            "#,
-            problem_id,
-            claims.sub,
-            contest_id
-        )
-        .fetch_one(&state.pool)
-        .await
-        .map_err(|e| Error::msg(format!("database error: {}", e)))?
-        .unwrap_or(0)
-    } else {
-        sqlx::query_scalar!(
-            r#"
-            SELECT COUNT(*) FROM submissions
-            WHERE problem_id = $1 AND user_id = $2 AND result = 'accepted'
-            "#,
-            problem_id,
-            claims.sub
-        )
-        .fetch_one(&state.pool)
-        .await
-        .map_err(|e| Error::msg(format!("database error: {}", e)))?
-        .unwrap_or(0)
-    };
+    Path((problem_id, submission_id)): Path<(i32, i32)>,
+) -> Result<()> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::GetSubmission,
+        Resource::Submission(submission_id),
+    )
+    .await?;
 
-    let status = if accepted_count > 0 {
-        Some(SubmissionResult::Accepted)
-    } else {
-        if let Some(contest_id) = params.contest_id {
-            sqlx::query_scalar!(
-                r#"
-                SELECT result as "result: SubmissionResult" FROM submissions
-                WHERE problem_id = $1 AND user_id = $2 AND contest_id = $3
-                ORDER BY created_at DESC
-                LIMIT 1
-                "#,
-                problem_id,
-                claims.sub,
-                contest_id
-            )
-            .fetch_optional(&state.pool)
-            .await
-            .map_err(|e| Error::msg(format!("database error: {}", e)))?
-        } else {
-            sqlx::query_scalar!(
-                r#"
-                SELECT result as "result: SubmissionResult" FROM submissions
-                WHERE problem_id = $1 AND user_id = $2
-                ORDER BY created_at DESC
-                LIMIT 1
+    sqlx::query!(
+        r#"
+        SELECT id FROM submissions WHERE id = $1 AND problem_id = $2
+        "#,
+        submission_id,
+        problem_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("submission not found").status_code(StatusCode::NOT_FOUND))?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO submission_bookmarks (user_id, submission_id)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id, submission_id) DO NOTHING
+        "#,
+        claims.sub,
+        submission_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/problems/{problem_id}/submissions/{submission_id}/bookmark",
+    params(
+        ("problem_id" = i32, Path),
+        ("submission_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ()),
+    ),
+    tag = "problem"
+)]
+async fn remove_bookmark(
+    state: State,
+    claims: Extension<Claims>,
+    Path((problem_id, submission_id)): Path<(i32, i32)>,
+) -> Result<()> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM submission_bookmarks b
+        USING submissions s
+        WHERE b.submission_id = s.id
+        AND b.user_id = $1 AND b.submission_id = $2 AND s.problem_id = $3
+        RETURNING b.submission_id
+        "#,
+        claims.sub,
+        submission_id,
+        problem_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if deleted.is_none() {
+        bail!(@NOT_FOUND "bookmark not found");
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetAcStatusResponse {
+    tried: bool,
+    status: Option<SubmissionResult>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[into_params(parameter_in = Query)]
+struct GetAcStatusQuery {
+    /// only count submissions made in this contest
+    contest_id: Option<i32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/problems/{problem_id}/ac-status",
+    params(
+        ("problem_id" = i32, Path),
+        GetAcStatusQuery
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = GetAcStatusResponse),
+    ),
+    tag = "problem"
+)]
+async fn get_ac_status(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+    Query(params): Query<GetAcStatusQuery>,
+) -> Result<Json<GetAcStatusResponse>> {
+    let accepted_count: i64 = if let Some(contest_id) = params.contest_id {
+        sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) FROM submissions
+            WHERE problem_id = $1 AND user_id = $2 AND contest_id = $3 AND result = 'accepted'
+            "#,
+            problem_id,
+            claims.sub,
+            contest_id
+        )
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?
+        .unwrap_or(0)
+    } else {
+        sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) FROM submissions
+            WHERE problem_id = $1 AND user_id = $2 AND result = 'accepted'
+            "#,
+            problem_id,
+            claims.sub
+        )
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?
+        .unwrap_or(0)
+    };
+
+    let status = if accepted_count > 0 {
+        Some(SubmissionResult::Accepted)
+    } else {
+        if let Some(contest_id) = params.contest_id {
+            sqlx::query_scalar!(
+                r#"
+                SELECT result as "result: SubmissionResult" FROM submissions
+                WHERE problem_id = $1 AND user_id = $2 AND contest_id = $3
+                ORDER BY created_at DESC
+                LIMIT 1
+                "#,
+                problem_id,
+                claims.sub,
+                contest_id
+            )
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))?
+        } else {
+            sqlx::query_scalar!(
+                r#"
+                SELECT result as "result: SubmissionResult" FROM submissions
+                WHERE problem_id = $1 AND user_id = $2
+                ORDER BY created_at DESC
+                LIMIT 1
                 "#,
                 problem_id,
                 claims.sub
@@ -1567,3 +3543,1486 @@ async fn get_ac_status(
         status: status,
     }))
 }
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(crate) struct GetProblemStatsQuery {
+    /// only count submissions made in this contest
+    contest_id: Option<i32>,
+    /// split every verdict further by language
+    #[serde(default)]
+    by_language: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VerdictCount {
+    result: SubmissionResult,
+    /// only set with `byLanguage`
+    lang: Option<Language>,
+    count: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetProblemStatsResponse {
+    total: i64,
+    verdicts: Vec<VerdictCount>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/problems/{problem_id}/stats",
+    params(
+        ("problem_id" = i32, Path),
+        GetProblemStatsQuery
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = GetProblemStatsResponse),
+    ),
+    tag = "problem"
+)]
+async fn get_problem_stats(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+    Query(query): Query<GetProblemStatsQuery>,
+) -> Result<Json<GetProblemStatsResponse>> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM problems WHERE id = $1) as "exists!""#,
+        problem_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    if !exists {
+        return Err(not_found("problem"));
+    }
+
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::ViewProblemStats,
+        Resource::Problem(problem_id),
+    )
+    .await?;
+
+    let verdicts: Vec<VerdictCount> = sqlx::query!(
+        r#"
+        SELECT result as "result: SubmissionResult",
+               CASE WHEN $3 THEN lang END as "lang?",
+               COUNT(*) as "count!"
+        FROM submissions
+        WHERE problem_id = $1 AND ($2::INTEGER IS NULL OR contest_id = $2)
+        GROUP BY 1, 2
+        ORDER BY 1, 2
+        "#,
+        problem_id,
+        query.contest_id,
+        query.by_language
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .into_iter()
+    .map(|row| VerdictCount {
+        result: row.result,
+        lang: row.lang.as_deref().and_then(stored_language),
+        count: row.count,
+    })
+    .collect();
+
+    Ok(Json(GetProblemStatsResponse {
+        total: verdicts.iter().map(|v| v.count).sum(),
+        verdicts,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(crate) struct GetSimilarSubmissionsQuery {
+    /// only compare submissions made in this contest
+    contest_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SimilarSubmission {
+    submission_id: i32,
+    user_id: i32,
+    username: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SimilarCluster {
+    fingerprint: String,
+    submissions: Vec<SimilarSubmission>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetSimilarSubmissionsResponse {
+    clusters: Vec<SimilarCluster>,
+}
+
+/// submissions of different users whose code is the same once comments and
+/// whitespace are stripped
+#[utoipa::path(
+    get,
+    path = "/api/problems/{problem_id}/similar",
+    params(
+        ("problem_id" = i32, Path),
+        GetSimilarSubmissionsQuery
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = GetSimilarSubmissionsResponse),
+    ),
+    tag = "problem"
+)]
+async fn get_similar_submissions(
+    state: State,
+    claims: Extension<Claims>,
+    Path(problem_id): Path<i32>,
+    Query(query): Query<GetSimilarSubmissionsQuery>,
+) -> Result<Json<GetSimilarSubmissionsResponse>> {
+    // checked first, so that students can't probe which problems exist
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::ViewSimilarSubmissions,
+        Resource::Problem(problem_id),
+    )
+    .await?;
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM problems WHERE id = $1) as "exists!""#,
+        problem_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    if !exists {
+        return Err(not_found("problem"));
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT s.id, s.user_id, u.username, s.fingerprint as "fingerprint!", s.created_at
+        FROM submissions s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.problem_id = $1 AND ($2::INTEGER IS NULL OR s.contest_id = $2)
+          AND s.fingerprint IN (
+            SELECT fingerprint FROM submissions
+            WHERE problem_id = $1 AND ($2::INTEGER IS NULL OR contest_id = $2)
+              AND fingerprint IS NOT NULL
+            GROUP BY fingerprint
+            HAVING COUNT(DISTINCT user_id) > 1
+          )
+        ORDER BY s.fingerprint, s.created_at, s.id
+        "#,
+        problem_id,
+        query.contest_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let mut clusters: Vec<SimilarCluster> = Vec::new();
+    for row in rows {
+        let submission = SimilarSubmission {
+            submission_id: row.id,
+            user_id: row.user_id,
+            username: row.username,
+            created_at: row.created_at,
+        };
+        match clusters.last_mut() {
+            Some(cluster) if cluster.fingerprint == row.fingerprint => {
+                cluster.submissions.push(submission)
+            }
+            _ => clusters.push(SimilarCluster {
+                fingerprint: row.fingerprint,
+                submissions: vec![submission],
+            }),
+        }
+    }
+
+    Ok(Json(GetSimilarSubmissionsResponse { clusters }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode, header};
+    use serde_json::Value;
+
+    use koioj_common::judge::{JudgeToApiMessage, Language, SubmissionResult, TestCaseJudgeResult};
+
+    use crate::test_util::{TestApp, judge_result, test_result};
+
+    fn names(page: &Value) -> Vec<&str> {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["name"].as_str().unwrap())
+            .collect()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn search_matches_part_of_the_name(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        for name in ["Two Sum", "three sum", "Shortest Path", "100% Sum"] {
+            app.problem_named(&teacher, name).await;
+        }
+
+        let response = app.get("/api/problems?q=SUM", &teacher).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(names(&response.body), ["Two Sum", "three sum", "100% Sum"]);
+        assert_eq!(response.body["total"], 3);
+
+        // wildcards in the query are taken literally
+        let response = app.get("/api/problems?q=%25", &teacher).await;
+        assert_eq!(names(&response.body), ["100% Sum"]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn similar_submissions_are_refused_before_the_problem_is_looked_up(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+
+        for id in [problem_id, problem_id + 1000] {
+            let uri = format!("/api/problems/{}/similar", id);
+            let response = app.get(&uri, &student).await;
+            assert_eq!(response.status, StatusCode::FORBIDDEN);
+        }
+        let uri = format!("/api/problems/{}/similar", problem_id + 1000);
+        let response = app.get(&uri, &teacher).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn page_far_past_the_end_is_empty(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        app.problem(&teacher).await;
+
+        let uri = format!("/api/problems?page={}&pageSize=100", i64::MAX);
+        let response = app.get(&uri, &teacher).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert!(names(&response.body).is_empty());
+        assert_eq!(response.body["total"], 1);
+        assert_eq!(response.body["hasNext"], false);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn empty_search_lists_every_problem(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        for name in ["Two Sum", "Shortest Path"] {
+            app.problem_named(&teacher, name).await;
+        }
+
+        for uri in ["/api/problems", "/api/problems?q=", "/api/problems?q=%20"] {
+            let response = app.get(uri, &teacher).await;
+            assert_eq!(
+                names(&response.body),
+                ["Two Sum", "Shortest Path"],
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn list_counts_accepted_and_total_submissions(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (alice, _) = app.user("student").await;
+        let (bob, _) = app.user("student").await;
+        let first = app.problem(&teacher).await;
+        let second = app.problem(&teacher).await;
+        let untouched = app.problem(&teacher).await;
+        for (user, problem, result) in [
+            (alice, first, "wrong_answer"),
+            (alice, first, "accepted"),
+            (bob, first, "accepted"),
+            (bob, first, "compile_error"),
+            (bob, second, "wrong_answer"),
+        ] {
+            app.seed_submission(user, problem, result, 0).await;
+        }
+
+        let response = app.get("/api/problems", &teacher).await;
+        let counts: Vec<_> = response.body["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                (
+                    item["problemId"].as_i64().unwrap() as i32,
+                    item["acceptedSubmissions"].as_i64().unwrap(),
+                    item["totalSubmissions"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(counts, [(first, 2, 4), (second, 0, 1), (untouched, 0, 0)]);
+
+        let response = app.get(&format!("/api/problems/{}", first), &teacher).await;
+        assert_eq!(response.body["acceptedSubmissions"], 2);
+        assert_eq!(response.body["totalSubmissions"], 4);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn accepted_submission_marks_only_its_problem_solved(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let solved = app.problem(&teacher).await;
+        let tried = app.problem(&teacher).await;
+        app.problem(&teacher).await;
+        app.seed_submission(student_id, solved, "wrong_answer", 1)
+            .await;
+        app.seed_submission(student_id, solved, "accepted", 0).await;
+        app.seed_submission(student_id, tried, "wrong_answer", 0)
+            .await;
+
+        let response = app.get("/api/problems", &student).await;
+        let flags: Vec<_> = response.body["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| (item["solved"].clone(), item["tried"].clone()))
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                (Value::Bool(true), Value::Bool(true)),
+                (Value::Bool(false), Value::Bool(true)),
+                (Value::Bool(false), Value::Bool(false)),
+            ]
+        );
+
+        let response = app.send(Method::GET, "/api/problems", None, None).await;
+        assert_eq!(response.status, StatusCode::OK);
+        for item in response.body["items"].as_array().unwrap() {
+            assert!(item["solved"].is_null() && item["tried"].is_null());
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn source_downloads_as_file_named_by_language(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let code = "int main() { return 0; }";
+        let submission_id = app.submit(&student, problem_id, code).await;
+
+        let response = app
+            .get(
+                &format!(
+                    "/api/problems/{}/submissions/{}/source",
+                    problem_id, submission_id
+                ),
+                &teacher,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.headers[header::CONTENT_DISPOSITION],
+            format!("attachment; filename=\"sub_{}.cpp\"", submission_id)
+        );
+        assert_eq!(response.body, code);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn problem_with_submissions_is_kept(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, _) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        app.seed_submission(student_id, problem_id, "accepted", 1)
+            .await;
+
+        let uri = format!("/api/problems/{}", problem_id);
+        let response = app.send(Method::DELETE, &uri, Some(&teacher), None).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(app.get(&uri, &teacher).await.status, StatusCode::OK);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn problem_without_submissions_is_deleted(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+
+        let uri = format!("/api/problems/{}", problem_id);
+        let response = app.send(Method::DELETE, &uri, Some(&teacher), None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(app.get(&uri, &teacher).await.status, StatusCode::NOT_FOUND);
+    }
+
+    /// a problem with one solution written by `teacher`
+    async fn problem_with_solution(app: &TestApp, teacher: &str) -> i32 {
+        let problem_id = app.problem(teacher).await;
+        let response = app
+            .post(
+                &format!("/api/problems/{}/solutions", problem_id),
+                teacher,
+                serde_json::json!({ "title": "greedy", "content": "sort first" }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        problem_id
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn solutions_are_open_by_default(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = problem_with_solution(&app, &teacher).await;
+
+        let uri = format!("/api/problems/{}/solutions", problem_id);
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn required_ac_lets_only_solvers_read_solutions(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (solver_id, solver) = app.user("student").await;
+        let (trier_id, trier) = app.user("student").await;
+        let problem_id = problem_with_solution(&app, &teacher).await;
+        let response = app
+            .send(
+                Method::PUT,
+                &format!("/api/problems/{}", problem_id),
+                Some(&teacher),
+                Some(serde_json::json!({ "solutionsRequireAc": true })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        app.seed_submission(solver_id, problem_id, "accepted", 0)
+            .await;
+        app.seed_submission(trier_id, problem_id, "wrong_answer", 0)
+            .await;
+
+        let uri = format!("/api/problems/{}/solutions", problem_id);
+        let response = app.get(&uri, &solver).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["solutions"].as_array().unwrap().len(), 1);
+        assert_eq!(app.get(&uri, &trier).await.status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn editing_a_solution_keeps_its_id(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}/solutions", problem_id);
+        let response = app
+            .post(
+                &uri,
+                &teacher,
+                serde_json::json!({ "title": "greedy", "content": "sort frist" }),
+            )
+            .await;
+        let solution_id = response.body["solutionId"].as_i64().unwrap();
+        let uri = format!("{}/{}", uri, solution_id);
+        let before = app.get(&uri, &teacher).await.body;
+
+        let response = app
+            .send(
+                Method::PUT,
+                &uri,
+                Some(&teacher),
+                Some(serde_json::json!({ "content": "sort first" })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let after = app.get(&uri, &teacher).await.body;
+        assert_eq!(after["solutionId"], solution_id);
+        assert_eq!(after["title"], "greedy");
+        assert_eq!(after["content"], "sort first");
+        assert_eq!(after["createdAt"], before["createdAt"]);
+        assert_ne!(after["updatedAt"], before["updatedAt"]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn text_fields_are_limited_to_the_configured_length(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| {
+            config.max_text_length = 20;
+            config.max_solution_length = 5;
+        })
+        .await;
+        let (_, teacher) = app.user("teacher").await;
+        let create = |description: String| {
+            app.post(
+                "/api/problems",
+                &teacher,
+                serde_json::json!({
+                    "name": "long",
+                    "description": description,
+                    "inputDescription": "two numbers",
+                    "outputDescription": "their sum",
+                    "samples": [],
+                    "timeLimit": 1000,
+                    "memLimit": 256,
+                    "status": "Active",
+                }),
+            )
+        };
+
+        let response = create("题".repeat(20)).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = create("题".repeat(21)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "description");
+
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}/solutions", problem_id);
+        let response = app
+            .post(
+                &uri,
+                &teacher,
+                serde_json::json!({ "title": "t", "content": "12345" }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app
+            .post(
+                &uri,
+                &teacher,
+                serde_json::json!({ "title": "t", "content": "123456" }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "content");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn retried_submission_returns_the_first_one(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let first = app.problem(&teacher).await;
+        let second = app.problem(&teacher).await;
+        let submit = |problem_id: i32| {
+            let request =
+                axum::http::Request::post(format!("/api/problems/{}/submissions", problem_id))
+                    .header(header::AUTHORIZATION, format!("Bearer {}", student))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header("idempotency-key", "retry-me")
+                    .body(axum::body::Body::from(
+                        serde_json::json!({ "code": "int main() {}", "lang": "cpp" }).to_string(),
+                    ))
+                    .unwrap();
+            app.request(request)
+        };
+
+        let response = submit(first).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let submission_id = response.body["submissionId"].clone();
+        let response = submit(first).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["submissionId"], submission_id);
+
+        // the key only stands for a retry of the same problem
+        let response = submit(second).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_ne!(response.body["submissionId"], submission_id);
+
+        let counts = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!" FROM submissions
+            WHERE user_id = $1 GROUP BY problem_id ORDER BY problem_id
+            "#,
+            student_id
+        )
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(counts, [1, 1]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn rapid_resubmission_waits_for_the_cooldown(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| {
+            config.submission_cooldown = chrono::Duration::milliseconds(500);
+            config.max_code_length = 100;
+        })
+        .await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}/submissions", problem_id);
+        let submit = || {
+            app.post(
+                &uri,
+                &student,
+                serde_json::json!({ "code": "int main() {}", "lang": "cpp" }),
+            )
+        };
+
+        // a rejected submission doesn't start the cooldown
+        let response = app
+            .post(
+                &uri,
+                &student,
+                serde_json::json!({ "code": "x".repeat(101), "lang": "cpp" }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+        assert_eq!(submit().await.status, StatusCode::OK);
+        let response = submit().await;
+        assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers[header::RETRY_AFTER], "1");
+
+        tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+        assert_eq!(submit().await.status, StatusCode::OK);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn python_only_problem_rejects_cpp(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}", problem_id);
+        let response = app
+            .send(
+                Method::PUT,
+                &uri,
+                Some(&teacher),
+                Some(serde_json::json!({ "allowedLanguages": ["python"] })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app.get(&uri, &student).await;
+        assert_eq!(
+            response.body["allowedLanguages"],
+            serde_json::json!(["python"])
+        );
+
+        let submit_uri = format!("{}/submissions", uri);
+        let submit = |lang: &'static str| {
+            app.post(
+                &submit_uri,
+                &student,
+                serde_json::json!({ "code": "print(3)", "lang": lang }),
+            )
+        };
+        let response = submit("cpp").await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "lang");
+        let response = submit("python").await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn templates_round_trip(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let response = app
+            .post(
+                "/api/problems",
+                &teacher,
+                serde_json::json!({
+                    "name": "with templates",
+                    "description": "add two numbers",
+                    "inputDescription": "two numbers",
+                    "outputDescription": "their sum",
+                    "samples": [{ "input": "1 2\n", "output": "3\n" }],
+                    "templates": { "cpp": "int main() {\n}\n" },
+                    "timeLimit": 1000,
+                    "memLimit": 256,
+                    "status": "Active",
+                }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let uri = format!("/api/problems/{}", response.body["problemId"]);
+
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(
+            response.body["templates"],
+            serde_json::json!({ "cpp": "int main() {\n}\n" })
+        );
+
+        // a put replaces them all
+        let templates = serde_json::json!({ "python": "print()\n" });
+        let response = app
+            .send(
+                Method::PUT,
+                &uri,
+                Some(&teacher),
+                Some(serde_json::json!({ "templates": templates })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.body["templates"], templates);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn stats_count_submissions_by_verdict(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -10, 60).await;
+        for result in ["accepted", "accepted", "wrong_answer", "compile_error"] {
+            app.seed_submission(student_id, problem_id, result, 5).await;
+        }
+        app.seed_contest_submission(contest_id, student_id, problem_id, "wrong_answer", 1)
+            .await;
+        let stats = |query: &str| {
+            let uri = format!("/api/problems/{}/stats{}", problem_id, query);
+            let teacher = teacher.to_string();
+            let app = &app;
+            async move {
+                let response = app.get(&uri, &teacher).await;
+                assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+                response.body
+            }
+        };
+        let histogram = |body: &Value| -> Vec<(String, i64)> {
+            let mut verdicts: Vec<_> = body["verdicts"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| {
+                    let key = match v["lang"].as_str() {
+                        Some(lang) => format!("{} {}", v["result"].as_str().unwrap(), lang),
+                        None => v["result"].as_str().unwrap().to_string(),
+                    };
+                    (key, v["count"].as_i64().unwrap())
+                })
+                .collect();
+            verdicts.sort();
+            verdicts
+        };
+
+        let body = stats("").await;
+        assert_eq!(body["total"], 5);
+        assert_eq!(
+            histogram(&body),
+            [
+                ("accepted".to_string(), 2),
+                ("compile_error".to_string(), 1),
+                ("wrong_answer".to_string(), 2),
+            ]
+        );
+
+        let body = stats(&format!("?contestId={}", contest_id)).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(histogram(&body), [("wrong_answer".to_string(), 1)]);
+
+        sqlx::query!("UPDATE submissions SET lang = 'python' WHERE result = 'compile_error'")
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        let body = stats("?byLanguage=true").await;
+        assert_eq!(
+            histogram(&body),
+            [
+                ("accepted cpp".to_string(), 2),
+                ("compile_error python".to_string(), 1),
+                ("wrong_answer cpp".to_string(), 2),
+            ]
+        );
+
+        let uri = format!("/api/problems/{}/stats", problem_id);
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn sample_results_show_without_hidden_inputs(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let response = app
+            .post(
+                &format!("/api/problems/{}/test-cases", problem_id),
+                &teacher,
+                serde_json::json!({ "testCases": [{ "input": "hidden 40 2\n", "output": "42\n" }] }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app
+            .send(
+                Method::PUT,
+                &format!("/api/problems/{}", problem_id),
+                Some(&teacher),
+                Some(serde_json::json!({ "judgeSamples": true })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+        let task = judge.task().await;
+        assert_eq!(task.samples.len(), 1);
+        let test_results = task
+            .test_cases
+            .iter()
+            .map(|test_case| test_result(test_case.id, TestCaseJudgeResult::WrongAnswer))
+            .collect();
+        let mut result = judge_result(submission_id, SubmissionResult::WrongAnswer, test_results);
+        if let JudgeToApiMessage::JudgeResult(result) = &mut result {
+            result.sample_results = vec![test_result(0, TestCaseJudgeResult::Accepted)];
+        }
+        judge.send(result).await;
+        app.judged(submission_id).await;
+
+        let uri = format!("/api/problems/{}/submissions/{}", problem_id, submission_id);
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let samples = response.body["sampleResults"].as_array().unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0]["sampleIndex"], 0);
+        assert_eq!(samples[0]["result"], "accepted");
+        assert_eq!(
+            response.body["testCaseResults"].as_array().unwrap().len(),
+            2
+        );
+        assert!(!response.body.to_string().contains("hidden 40 2"));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn submission_times_are_rfc3339_with_an_offset(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+
+        let uri = format!("/api/problems/{}/submissions/{}", problem_id, submission_id);
+        let response = app.get(&uri, &student).await;
+        let single = response.body["createdAt"].clone();
+        let uri = format!("/api/problems/{}/submissions", problem_id);
+        let response = app.get(&uri, &student).await;
+        let listed = response.body["items"][0]["createdAt"].clone();
+
+        for created_at in [single, listed] {
+            let created_at = created_at.as_str().unwrap();
+            let parsed = chrono::DateTime::parse_from_rfc3339(created_at).unwrap();
+            assert_eq!(parsed.offset().local_minus_utc(), 0);
+            assert!(created_at.ends_with('Z') || created_at.ends_with("+00:00"));
+            let age = chrono::Utc::now() - parsed.to_utc();
+            assert!(age < chrono::Duration::minutes(1));
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn ac_status_takes_contest_id_in_camel_case(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -10, 60).await;
+        app.seed_submission(student_id, problem_id, "accepted", 0)
+            .await;
+
+        let uri = format!("/api/problems/{}/ac-status", problem_id);
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.body["status"], "accepted");
+
+        let uri = format!(
+            "/api/problems/{}/ac-status?contestId={}",
+            problem_id, contest_id
+        );
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body["tried"], false);
+
+        let uri = format!(
+            "/api/problems/{}/ac-status?contest_id={}",
+            problem_id, contest_id
+        );
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn problems_sort_by_name_descending(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        for name in ["banana", "apple", "cherry"] {
+            app.problem_named(&teacher, name).await;
+        }
+
+        for token in [&teacher, &student] {
+            let response = app.get("/api/problems?sort=name&order=desc", token).await;
+            assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+            assert_eq!(names(&response.body), ["cherry", "banana", "apple"]);
+        }
+
+        let response = app.get("/api/problems?sort=p.name", &teacher).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn hidden_problem_reads_through_a_running_contest_only_for_participants(
+        pool: sqlx::PgPool,
+    ) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (participant_id, participant) = app.user("student").await;
+        let (_, outsider) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], -10, 60).await;
+        sqlx::query!(
+            "INSERT INTO contest_participants (contest_id, user_id) VALUES ($1, $2)",
+            contest_id,
+            participant_id
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "UPDATE problems SET status = 'hidden' WHERE id = $1",
+            problem_id
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+        let through_contest = format!("/api/problems/{}?contestId={}", problem_id, contest_id);
+        let response = app.get(&through_contest, &participant).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert!(response.body["name"].is_string());
+
+        let response = app.get(&through_contest, &outsider).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        let directly = format!("/api/problems/{}", problem_id);
+        let response = app.get(&directly, &participant).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn stored_languages_read_back_as_the_enum(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let submission_id = app.submit(&student, problem_id, "int main() {}").await;
+        let uri = format!("/api/problems/{}/submissions/{}", problem_id, submission_id);
+        let set_lang = |lang: String| {
+            let pool = app.pool.clone();
+            async move {
+                sqlx::query!(
+                    "UPDATE submissions SET lang = $1 WHERE id = $2",
+                    lang,
+                    submission_id
+                )
+                .execute(&pool)
+                .await
+                .unwrap();
+            }
+        };
+
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.body["lang"], "cpp");
+        for lang in [
+            Language::C,
+            Language::Cpp,
+            Language::Java,
+            Language::Python,
+            Language::Go,
+            Language::Rust,
+            Language::JavaScript,
+            Language::TypeScript,
+            Language::CSharp,
+            Language::Php,
+            Language::Ruby,
+            Language::Swift,
+            Language::Kotlin,
+            Language::Scala,
+            Language::Haskell,
+            Language::Lua,
+            Language::Perl,
+            Language::R,
+            Language::Dart,
+            Language::ObjectiveC,
+        ] {
+            set_lang(lang.to_string()).await;
+            let response = app.get(&uri, &student).await;
+            let read: Language = serde_json::from_value(response.body["lang"].clone()).unwrap();
+            assert_eq!(read, lang);
+        }
+
+        // older rows may be written differently or name a language since dropped
+        set_lang(" CPP ".to_string()).await;
+        assert_eq!(app.get(&uri, &student).await.body["lang"], "cpp");
+        set_lang("cobol".to_string()).await;
+        let response = app.get(&uri, &student).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert!(response.body["lang"].is_null());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn multi_megabyte_code_round_trips(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| {
+            config.max_code_length = 8 << 20;
+            config.max_file_size_mb = 16.0;
+        })
+        .await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let code: String = (0..300_000).map(|i| format!("// line {}\n", i)).collect();
+        assert!(code.len() > 4 << 20);
+
+        let request = axum::http::Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "/api/problems/{}/submissions/raw?lang=cpp",
+                problem_id
+            ))
+            .header(header::AUTHORIZATION, format!("Bearer {}", student))
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(axum::body::Body::from(code.clone()))
+            .unwrap();
+        let response = app.request(request).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let raw = response.body["submissionId"].as_i64().unwrap();
+        let json = app.submit(&student, problem_id, &code).await;
+
+        for submission_id in [raw, json as i64] {
+            let uri = format!("/api/problems/{}/submissions/{}", problem_id, submission_id);
+            let response = app.get(&uri, &student).await;
+            assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+            assert!(response.body["code"] == code.as_str());
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn code_is_accepted_up_to_the_problem_limit(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| config.max_code_length = 100).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}/submissions", problem_id);
+        let submit = |len: usize| {
+            app.post(
+                &uri,
+                &student,
+                serde_json::json!({ "code": "x".repeat(len), "lang": "cpp" }),
+            )
+        };
+
+        // the configured default applies until the problem sets its own
+        assert_eq!(submit(100).await.status, StatusCode::OK);
+        assert_eq!(submit(101).await.status, StatusCode::BAD_REQUEST);
+
+        let response = app
+            .send(
+                Method::PUT,
+                &format!("/api/problems/{}", problem_id),
+                Some(&teacher),
+                Some(serde_json::json!({ "maxCodeLength": 10 })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(submit(10).await.status, StatusCode::OK);
+        let response = submit(11).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "code");
+    }
+
+    /// the files of a problem bundle, parsed
+    async fn unpack(bundle: axum::body::Bytes) -> std::collections::BTreeMap<String, Value> {
+        let mut files = std::collections::BTreeMap::new();
+        let mut entries = crate::archive::read_zip(bundle, super::MAX_BUNDLE_SIZE);
+        while let Some(entry) = entries.recv().await {
+            let (path, data) = entry.unwrap();
+            files.insert(path, serde_json::from_slice(&data).unwrap());
+        }
+        files
+    }
+
+    fn import_request(token: &str, bundle: Vec<u8>) -> axum::http::Request<axum::body::Body> {
+        axum::http::Request::post("/api/problems/import")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/zip")
+            .body(axum::body::Body::from(bundle))
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn exported_problem_imports_unchanged(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}", problem_id);
+        let response = app
+            .send(
+                Method::PUT,
+                &uri,
+                Some(&teacher),
+                Some(serde_json::json!({
+                    "allowedLanguages": ["cpp"],
+                    "difficulty": 3,
+                    "revealDiff": true,
+                })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app
+            .post(
+                &format!("{}/test-cases", uri),
+                &teacher,
+                serde_json::json!({ "testCases": [{ "input": "5 7\n", "output": "12\n" }] }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let exported = app.get(&format!("{}/export", uri), &teacher).await;
+        assert_eq!(exported.status, StatusCode::OK);
+        // the name is unique, so make room for the copy
+        let response = app
+            .send(
+                Method::PUT,
+                &uri,
+                Some(&teacher),
+                Some(serde_json::json!({ "name": "renamed" })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let response = app
+            .request(import_request(&teacher, exported.raw.to_vec()))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let imported_id = response.body["problemId"].as_i64().unwrap();
+        assert_ne!(imported_id, problem_id as i64);
+
+        let reexported = app
+            .get(&format!("/api/problems/{}/export", imported_id), &teacher)
+            .await;
+        let files = unpack(reexported.raw).await;
+        assert_eq!(files.len(), 4);
+        assert_eq!(files, unpack(exported.raw).await);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn invalid_bundle_leaves_nothing_behind(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let exported = app
+            .get(&format!("/api/problems/{}/export", problem_id), &teacher)
+            .await;
+        let mut files = unpack(exported.raw).await;
+        files.get_mut("content.json").unwrap()["description"] = "".into();
+        files.insert(
+            "test_cases/1.json".to_string(),
+            serde_json::json!({ "input": "only in the bundle", "output": "" }),
+        );
+        let files = files
+            .into_iter()
+            .map(|(path, value)| (path, serde_json::to_vec(&value).unwrap()))
+            .collect();
+        let bundle = crate::archive::write_zip(files).await.unwrap();
+
+        let response = app.request(import_request(&teacher, bundle)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "description");
+
+        // only the test case the first problem uses is left
+        let dir = std::path::Path::new(&app.state.config.data_dir).join("test_cases");
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn run_gives_verdicts_without_a_submission(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let mut judge = app.judge("judge", &[Language::Cpp]).await;
+        let uri = format!("/api/problems/{}/run", problem_id);
+
+        let judging = async {
+            let task = judge.task().await;
+            assert!(task.submission_id < 0);
+            // the one sample, then the custom input
+            assert_eq!(task.test_cases.len(), 2);
+            assert_eq!(task.test_cases[1].data.input, "4 5\n");
+            let mut custom = test_result(1, TestCaseJudgeResult::WrongAnswer);
+            custom.output = Some("9\n".to_string());
+            judge
+                .send(judge_result(
+                    task.submission_id,
+                    SubmissionResult::WrongAnswer,
+                    vec![test_result(0, TestCaseJudgeResult::Accepted), custom],
+                ))
+                .await;
+        };
+        let (response, ()) = tokio::join!(
+            app.post(
+                &uri,
+                &student,
+                serde_json::json!({ "code": "int main() {}", "lang": "cpp", "input": "4 5\n" }),
+            ),
+            judging
+        );
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["samples"][0]["result"], "accepted");
+        // nothing to compare the custom input's output with
+        assert_eq!(response.body["custom"]["result"], "accepted");
+        assert_eq!(response.body["custom"]["output"], "9\n");
+
+        let submissions = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM submissions"#)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(submissions, 0);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn custom_run_echoes_the_program_output(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let mut judge = app.judge("judge", &[Language::Python]).await;
+        let uri = format!("/api/problems/{}/custom-run", problem_id);
+
+        // the judge plays a program that echoes its input
+        let judging = async {
+            let task = judge.task().await;
+            let mut echoed = test_result(0, TestCaseJudgeResult::WrongAnswer);
+            echoed.output = Some(task.test_cases[0].data.input.clone());
+            echoed.error_output = Some("done\n".to_string());
+            judge
+                .send(judge_result(
+                    task.submission_id,
+                    SubmissionResult::WrongAnswer,
+                    vec![echoed],
+                ))
+                .await;
+        };
+        let (response, ()) = tokio::join!(
+            app.post(
+                &uri,
+                &student,
+                serde_json::json!({
+                    "code": "print(input())",
+                    "lang": "python",
+                    "stdin": "hello judge\n",
+                }),
+            ),
+            judging
+        );
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["stdout"], "hello judge\n");
+        assert_eq!(response.body["stderr"], "done\n");
+        assert_eq!(response.body["result"], "accepted");
+        assert_eq!(response.body["compileError"], false);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn solutions_of_a_hidden_problem_look_missing(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (_, student) = app.user("student").await;
+        let problem_id = problem_with_solution(&app, &teacher).await;
+        let response = app
+            .send(
+                Method::PUT,
+                &format!("/api/problems/{}", problem_id),
+                Some(&teacher),
+                Some(serde_json::json!({ "status": "Hidden" })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let hidden = app
+            .get(&format!("/api/problems/{}/solutions", problem_id), &student)
+            .await;
+        let missing = app.get("/api/problems/999999/solutions", &student).await;
+        assert_eq!(hidden.status, StatusCode::NOT_FOUND);
+        assert_eq!((hidden.status, hidden.body), (missing.status, missing.body));
+
+        // staff still see them
+        let response = app
+            .get(&format!("/api/problems/{}/solutions", problem_id), &teacher)
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_case_upload_is_limited_by_its_body_limit(pool: sqlx::PgPool) {
+        let app = TestApp::with_config(pool, |config| config.max_testcase_upload_mb = 0.01).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let uri = format!("/api/problems/{}/test-cases", problem_id);
+        let upload = |size: usize| {
+            app.post(
+                &uri,
+                &teacher,
+                serde_json::json!({ "testCases": [{ "input": "1".repeat(size), "output": "1" }] }),
+            )
+        };
+
+        let response = upload(5 * 1024).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = upload(20 * 1024).await;
+        assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn teachers_edit_only_their_own_problems(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, owner) = app.user("teacher").await;
+        let (_, other) = app.user("teacher").await;
+        let (_, admin) = app.user("admin").await;
+        let problem_id = app.problem(&owner).await;
+        let uri = format!("/api/problems/{}", problem_id);
+        let rename = |token: &str, name: &str| {
+            let body = serde_json::json!({ "name": name });
+            let token = token.to_string();
+            let (app, uri) = (&app, &uri);
+            async move {
+                app.send(Method::PUT, uri, Some(&token), Some(body))
+                    .await
+                    .status
+            }
+        };
+
+        assert_eq!(rename(&owner, "renamed").await, StatusCode::OK);
+        assert_eq!(rename(&other, "taken over").await, StatusCode::FORBIDDEN);
+        let test_cases = format!("/api/problems/{}/test-cases", problem_id);
+        let body = serde_json::json!({ "testCases": [{ "input": "1", "output": "1" }] });
+        let response = app.post(&test_cases, &other, body).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        let response = app.send(Method::DELETE, &uri, Some(&other), None).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+        assert_eq!(rename(&admin, "by admin").await, StatusCode::OK);
+        let response = app.get(&uri, &owner).await;
+        assert_eq!(response.body["name"], "by admin");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn collaborators_edit_like_the_owner(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, owner) = app.user("teacher").await;
+        let (collaborator_id, collaborator) = app.user("teacher").await;
+        let (outsider_id, outsider) = app.user("teacher").await;
+        let problem_id = app.problem(&owner).await;
+        let uri = format!("/api/problems/{}", problem_id);
+        let collaborators = |user_id: i32| format!("{}/collaborators/{}", uri, user_id);
+        let rename = |token: &str| {
+            let body = serde_json::json!({ "name": "renamed" });
+            let token = token.to_string();
+            let (app, uri) = (&app, &uri);
+            async move {
+                app.send(Method::PUT, uri, Some(&token), Some(body))
+                    .await
+                    .status
+            }
+        };
+
+        let response = app
+            .send(
+                Method::PUT,
+                &collaborators(collaborator_id),
+                Some(&owner),
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(rename(&collaborator).await, StatusCode::OK);
+        let test_cases = format!("{}/test-cases", uri);
+        let body = serde_json::json!({ "testCases": [{ "input": "1", "output": "1" }] });
+        let response = app.post(&test_cases, &collaborator, body).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        assert_eq!(rename(&outsider).await, StatusCode::FORBIDDEN);
+        // only the owner brings others in
+        let response = app
+            .send(
+                Method::PUT,
+                &collaborators(outsider_id),
+                Some(&collaborator),
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+        let response = app
+            .send(
+                Method::DELETE,
+                &collaborators(collaborator_id),
+                Some(&owner),
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(rename(&collaborator).await, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn problem_without_test_cases_refuses_submissions(pool: sqlx::PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let response = app
+            .post(
+                "/api/problems",
+                &teacher,
+                serde_json::json!({
+                    "name": "untested",
+                    "description": "add two numbers",
+                    "inputDescription": "two numbers",
+                    "outputDescription": "their sum",
+                    "samples": [],
+                    "timeLimit": 1000,
+                    "memLimit": 256,
+                    "status": "Active",
+                }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let problem_id = response.body["problemId"].as_i64().unwrap();
+
+        let uri = format!("/api/problems/{}/submissions", problem_id);
+        let body = serde_json::json!({ "code": "int main() {}", "lang": "cpp" });
+        let response = app.post(&uri, &student, body).await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        let submitted = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM submissions WHERE user_id = $1"#,
+            student_id
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(submitted, 0);
+
+        let uri = format!("/api/problems/{}", problem_id);
+        let response = app.get(&uri, &teacher).await;
+        assert!(response.body["warning"].is_string());
+        let response = app.get(&uri, &student).await;
+        assert!(response.body.get("warning").is_none());
+    }
+}
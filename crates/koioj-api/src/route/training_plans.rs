@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use axum::{
     Extension, Json, Router,
@@ -14,10 +14,15 @@ use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     AppState, Result, State,
-    auth::{Claims, jwt_auth_accept_guest_middleware, jwt_auth_middleware},
-    error::Error,
+    auth::{Claims, generate_join_code, jwt_auth_accept_guest_middleware, jwt_auth_middleware},
+    error::{Error, ValidationError},
     models::TrainingPlanContent,
     perm::{Action, Resource, check_permission},
+    route::{
+        MAX_PAGE, Page,
+        contests::{OverallRankingItem, aggregate_contest_rankings, sort_overall_rankings},
+    },
+    validation::check_max_length,
 };
 
 pub fn top_routes() -> Router<Arc<AppState>> {
@@ -28,7 +33,6 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
     use axum::routing::*;
     Router::new()
         .route("/{plan_id}", get(get_training_plan))
-        .route("/", get(list_training_plans))
         .merge(
             Router::new()
                 .route("/", post(create_training_plan))
@@ -36,15 +40,18 @@ pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
                 .route("/{plan_id}", delete(delete_training_plan))
                 .route("/{plan_id}/participants", put(set_participants))
                 .route("/{plan_id}/contests", put(set_contests))
+                .route("/{plan_id}/progress", get(get_training_plan_progress))
+                .route("/{plan_id}/join-code", post(reset_join_code))
+                .route("/{plan_id}/join-code", delete(disable_join_code))
+                .route("/{plan_id}/join", post(join_training_plan))
                 .layer(middleware::from_fn_with_state(
                     state.clone(),
                     jwt_auth_middleware,
                 )),
         )
-        .merge(Router::new().layer(middleware::from_fn_with_state(
-            state.clone(),
-            jwt_auth_accept_guest_middleware,
-        )))
+        .merge(Router::new().route("/", get(list_training_plans)).layer(
+            middleware::from_fn_with_state(state.clone(), jwt_auth_accept_guest_middleware),
+        ))
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -83,9 +90,17 @@ async fn create_training_plan(
     )
     .await?;
 
+    let mut errors = ValidationError::new();
     if p.name.trim().is_empty() {
-        bail!(@BAD_REQUEST "name cannot be empty");
+        errors.add("name", "name cannot be empty");
     }
+    check_max_length(
+        &mut errors,
+        "description",
+        &p.description,
+        state.config.max_text_length,
+    );
+    errors.check()?;
 
     let plan_id: i32 = sqlx::query_scalar!(
         r#"
@@ -143,8 +158,8 @@ pub(crate) struct ParticipantInfo {
 pub(crate) struct ContestInfo {
     contest_id: i32,
     name: String,
-    begin_time: String,
-    end_time: String,
+    begin_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
 }
 
 #[utoipa::path(
@@ -153,7 +168,6 @@ pub(crate) struct ContestInfo {
     params(
         ("plan_id" = i32, Path)
     ),
-    security(("bearer_auth" = [])),
     responses(
         (status = 200, body = GetTrainingPlanResponse),
     ),
@@ -226,8 +240,8 @@ async fn get_training_plan(
     .map(|row| ContestInfo {
         contest_id: row.contest_id,
         name: row.name,
-        begin_time: row.begin_time.to_rfc3339(),
-        end_time: row.end_time.to_rfc3339(),
+        begin_time: row.begin_time,
+        end_time: row.end_time,
     })
     .collect();
 
@@ -252,109 +266,84 @@ pub(crate) struct TrainingPlanListItem {
 }
 #[derive(Serialize, Deserialize, ToSchema, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub(crate) struct ListTrainingPlansQuery {
     page: Option<i64>,
     page_size: Option<i64>,
     end_after: Option<DateTime<Utc>>,
+    /// only the plans the caller participates in
+    #[serde(default)]
+    mine: bool,
 }
-#[derive(Serialize, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct ListTrainingPlansResponse {
-    plans: Vec<TrainingPlanListItem>,
-    total: i64,
-}
+
 #[utoipa::path(
     get,
     path = "/api/training-plans",
-    security(("bearer_auth" = [])),
+    security((), ("bearer_auth" = [])),
     params(ListTrainingPlansQuery),
     responses(
-        (status = 200, body = ListTrainingPlansResponse),
+        (status = 200, body = Page<TrainingPlanListItem>),
     ),
     tag = "training_plan"
 )]
 async fn list_training_plans(
     state: State,
+    claims: Extension<Claims>,
     Query(q): Query<ListTrainingPlansQuery>,
-) -> Result<Json<ListTrainingPlansResponse>> {
-    let page = q.page.unwrap_or(1).max(1);
+) -> Result<Json<Page<TrainingPlanListItem>>> {
+    let page = q.page.unwrap_or(1).clamp(1, MAX_PAGE);
     let page_size = q.page_size.unwrap_or(20).clamp(1, 100);
     let offset = (page - 1) * page_size;
 
-    let (count_query, list_query, bind_end_after) = if let Some(end_after) = q.end_after {
-        (
-            "SELECT COUNT(*) FROM training_plans tp 
-             WHERE tp.id != 0 AND EXISTS (
-                 SELECT 1 FROM training_plan_contests tpc2
-                 JOIN contests c ON tpc2.contest_id = c.id
-                 WHERE tpc2.plan_id = tp.id AND c.end_time > $1
-             )",
-            r#"
-            SELECT 
-                tp.id, tp.creator_id, tp.name,
-                COUNT(DISTINCT tpp.user_id) as participant_count,
-                COUNT(DISTINCT tpc.contest_id) as contest_count
-            FROM training_plans tp
-            LEFT JOIN training_plan_participants tpp ON tp.id = tpp.plan_id
-            LEFT JOIN training_plan_contests tpc ON tp.id = tpc.plan_id
-            WHERE tp.id != 0 AND EXISTS (
-                SELECT 1 FROM training_plan_contests tpc2
-                JOIN contests c ON tpc2.contest_id = c.id
-                WHERE tpc2.plan_id = tp.id AND c.end_time > $1
-            )
-            GROUP BY tp.id, tp.creator_id, tp.name
-            ORDER BY tp.created_at ASC
-            LIMIT $2 OFFSET $3
-            "#,
-            Some(end_after),
-        )
-    } else {
-        (
-            "SELECT COUNT(*) FROM training_plans tp WHERE tp.id != 0",
-            r#"
-            SELECT 
-                tp.id, tp.creator_id, tp.name, 
-                COUNT(DISTINCT tpp.user_id) as participant_count,
-                COUNT(DISTINCT tpc.contest_id) as contest_count
-            FROM training_plans tp
-            LEFT JOIN training_plan_participants tpp ON tp.id = tpp.plan_id
-            LEFT JOIN training_plan_contests tpc ON tp.id = tpc.plan_id
-            WHERE tp.id != 0
-            GROUP BY tp.id, tp.creator_id, tp.name
-            ORDER BY tp.created_at ASC
-            LIMIT $1 OFFSET $2
-            "#,
-            None,
-        )
-    };
+    // guests have no plans of their own, their id never matches
+    let member_id = q.mine.then_some(claims.sub);
+
+    let filter = r#"
+        tp.id != 0
+        AND ($1::TIMESTAMPTZ IS NULL OR EXISTS (
+            SELECT 1 FROM training_plan_contests tpc2
+            JOIN contests c ON tpc2.contest_id = c.id
+            WHERE tpc2.plan_id = tp.id AND c.end_time > $1
+        ))
+        AND ($2::INTEGER IS NULL OR EXISTS (
+            SELECT 1 FROM training_plan_participants tpp2
+            WHERE tpp2.plan_id = tp.id AND tpp2.user_id = $2
+        ))
+    "#;
 
     // Count query
-    let total: i64 = if let Some(end_after) = bind_end_after {
-        sqlx::query_scalar(count_query)
-            .bind(end_after)
-            .fetch_one(&state.pool)
-            .await?
-    } else {
-        sqlx::query_scalar(count_query)
-            .fetch_one(&state.pool)
-            .await?
-    };
+    let total: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM training_plans tp WHERE {}",
+        filter
+    ))
+    .bind(q.end_after)
+    .bind(member_id)
+    .fetch_one(&state.pool)
+    .await?;
 
     // List query
-    let rows = if let Some(end_after) = bind_end_after {
-        sqlx::query(list_query)
-            .bind(end_after)
-            .bind(page_size)
-            .bind(offset)
-            .fetch_all(&state.pool)
-            .await?
-    } else {
-        sqlx::query(list_query)
-            .bind(page_size)
-            .bind(offset)
-            .fetch_all(&state.pool)
-            .await?
-    };
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT 
+            tp.id, tp.creator_id, tp.name,
+            COUNT(DISTINCT tpp.user_id) as participant_count,
+            COUNT(DISTINCT tpc.contest_id) as contest_count
+        FROM training_plans tp
+        LEFT JOIN training_plan_participants tpp ON tp.id = tpp.plan_id
+        LEFT JOIN training_plan_contests tpc ON tp.id = tpc.plan_id
+        WHERE {}
+        GROUP BY tp.id, tp.creator_id, tp.name
+        ORDER BY tp.created_at ASC
+        LIMIT $3 OFFSET $4
+        "#,
+        filter
+    ))
+    .bind(q.end_after)
+    .bind(member_id)
+    .bind(page_size)
+    .bind(offset)
+    .fetch_all(&state.pool)
+    .await?;
 
     let plans = rows
         .into_iter()
@@ -367,7 +356,7 @@ async fn list_training_plans(
         })
         .collect();
 
-    Ok(Json(ListTrainingPlansResponse { plans, total }))
+    Ok(Json(Page::new(plans, total, page, page_size)))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -417,6 +406,17 @@ async fn put_training_plan(
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
     .ok_or_else(|| Error::msg("training plan not found").status_code(StatusCode::NOT_FOUND))?;
 
+    if let Some(description) = &req.description {
+        let mut errors = ValidationError::new();
+        check_max_length(
+            &mut errors,
+            "description",
+            description,
+            state.config.max_text_length,
+        );
+        errors.check()?;
+    }
+
     if let Some(name) = req.name {
         sqlx::query!(
             r#"
@@ -529,7 +529,14 @@ async fn delete_training_plan(
     .await
     .map_err(|e| Error::msg(format!("database error: {}", e)))?;
 
-    // state.delete_training_plan_content(plan_id).await?;
+    // the row is gone already, so a leftover file is only worth a warning
+    if let Err(e) = state.delete_training_plan_content(plan_id).await {
+        tracing::warn!(
+            "Failed to delete content of training plan {}: {:?}",
+            plan_id,
+            e
+        );
+    }
 
     Ok(())
 }
@@ -958,3 +965,532 @@ async fn set_contests(
 
     Ok(Json(SetContestsResponse { added, removed }))
 }
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GetTrainingPlanProgressResponse {
+    progress: Vec<OverallRankingItem>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/training-plans/{plan_id}/progress",
+    params(
+        ("plan_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = GetTrainingPlanProgressResponse),
+    ),
+    tag = "training_plan"
+)]
+async fn get_training_plan_progress(
+    state: State,
+    claims: Extension<Claims>,
+    Path(plan_id): Path<i32>,
+) -> Result<Json<GetTrainingPlanProgressResponse>> {
+    let exists = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM training_plans WHERE id = $1)",
+        plan_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .unwrap_or(false);
+
+    if !exists {
+        bail!(@NOT_FOUND "training plan not found");
+    }
+
+    // the owner sees everyone, a participant only their own row
+    let only_user = match check_permission(
+        &state.pool,
+        &claims,
+        Action::PutTrainingPlan,
+        Resource::TrainingPlan(plan_id),
+    )
+    .await
+    {
+        Ok(()) => None,
+        Err(e) => {
+            let is_participant = sqlx::query_scalar!(
+                r#"
+                SELECT EXISTS(
+                    SELECT 1 FROM training_plan_participants
+                    WHERE plan_id = $1 AND user_id = $2
+                ) as "exists!"
+                "#,
+                plan_id,
+                claims.sub
+            )
+            .fetch_one(&state.pool)
+            .await
+            .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+            if !is_participant {
+                return Err(e);
+            }
+            Some(claims.sub)
+        }
+    };
+
+    let participants = sqlx::query!(
+        r#"
+        SELECT tpp.user_id, u.username
+        FROM training_plan_participants tpp
+        JOIN users u ON tpp.user_id = u.id
+        WHERE tpp.plan_id = $1 AND ($2::INTEGER IS NULL OR tpp.user_id = $2)
+        "#,
+        plan_id,
+        only_user
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let contest_ids: Vec<i32> = sqlx::query_scalar!(
+        "SELECT contest_id FROM training_plan_contests WHERE plan_id = $1",
+        plan_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let mut stats: HashMap<i32, OverallRankingItem> = if contest_ids.is_empty() {
+        HashMap::new()
+    } else {
+        aggregate_contest_rankings(&state, &contest_ids)
+            .await?
+            .into_iter()
+            .map(|item| (item.user_id, item))
+            .collect()
+    };
+
+    // participants who haven't submitted anything still get a row
+    let mut progress: Vec<OverallRankingItem> = participants
+        .into_iter()
+        .map(|row| {
+            stats
+                .remove(&row.user_id)
+                .unwrap_or_else(|| OverallRankingItem {
+                    user_id: row.user_id,
+                    username: row.username,
+                    contest_count: 0,
+                    total_solved: 0,
+                    total_penalty: 0,
+                })
+        })
+        .collect();
+    sort_overall_rankings(&mut progress);
+
+    Ok(Json(GetTrainingPlanProgressResponse { progress }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ResetJoinCodeResponse {
+    join_code: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/training-plans/{plan_id}/join-code",
+    params(
+        ("plan_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ResetJoinCodeResponse),
+    ),
+    tag = "training_plan"
+)]
+async fn reset_join_code(
+    state: State,
+    claims: Extension<Claims>,
+    Path(plan_id): Path<i32>,
+) -> Result<Json<ResetJoinCodeResponse>> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::PutTrainingPlan,
+        Resource::TrainingPlan(plan_id),
+    )
+    .await?;
+
+    // a new code replaces the old one, so a leaked code can be revoked
+    let join_code = generate_join_code();
+    let updated = sqlx::query!(
+        r#"
+        UPDATE training_plans
+        SET join_code = $1, updated_at = NOW()
+        WHERE id = $2
+        "#,
+        join_code,
+        plan_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if updated.rows_affected() == 0 {
+        bail!(@NOT_FOUND "training plan not found");
+    }
+
+    Ok(Json(ResetJoinCodeResponse { join_code }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/training-plans/{plan_id}/join-code",
+    params(
+        ("plan_id" = i32, Path)
+    ),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ()),
+    ),
+    tag = "training_plan"
+)]
+async fn disable_join_code(
+    state: State,
+    claims: Extension<Claims>,
+    Path(plan_id): Path<i32>,
+) -> Result<()> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::PutTrainingPlan,
+        Resource::TrainingPlan(plan_id),
+    )
+    .await?;
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE training_plans
+        SET join_code = NULL, updated_at = NOW()
+        WHERE id = $1
+        "#,
+        plan_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if updated.rows_affected() == 0 {
+        bail!(@NOT_FOUND "training plan not found");
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JoinTrainingPlanRequest {
+    join_code: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/training-plans/{plan_id}/join",
+    params(
+        ("plan_id" = i32, Path)
+    ),
+    request_body = JoinTrainingPlanRequest,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ()),
+        (status = 403, description = "Wrong join code"),
+        (status = 409, description = "Already joined"),
+    ),
+    tag = "training_plan"
+)]
+async fn join_training_plan(
+    state: State,
+    claims: Extension<Claims>,
+    Path(plan_id): Path<i32>,
+    Json(req): Json<JoinTrainingPlanRequest>,
+) -> Result<()> {
+    // the phantom plan only exists to back direct contest joins
+    if plan_id == 0 {
+        bail!(@NOT_FOUND "training plan not found");
+    }
+
+    let join_code = sqlx::query_scalar!(
+        "SELECT join_code FROM training_plans WHERE id = $1",
+        plan_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .ok_or_else(|| Error::msg("training plan not found").status_code(StatusCode::NOT_FOUND))?;
+
+    match join_code {
+        Some(code) if code.eq_ignore_ascii_case(req.join_code.trim()) => {}
+        Some(_) => bail!(@FORBIDDEN "wrong join code"),
+        None => bail!(@FORBIDDEN "this training plan can't be joined with a code"),
+    }
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO training_plan_participants (plan_id, user_id)
+        VALUES ($1, $2)
+        ON CONFLICT (plan_id, user_id) DO NOTHING
+        "#,
+        plan_id,
+        claims.sub
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if inserted.rows_affected() == 0 {
+        bail!(@CONFLICT "already joined this training plan");
+    }
+
+    // same as set_participants, the plan's contests are joined along with it
+    sqlx::query!(
+        r#"
+        INSERT INTO contest_participants (contest_id, user_id, training_plan_id)
+        SELECT contest_id, $2, $1 FROM training_plan_contests WHERE plan_id = $1
+        ON CONFLICT (contest_id, user_id, training_plan_id) DO NOTHING
+        "#,
+        plan_id,
+        claims.sub
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use crate::test_util::TestApp;
+
+    async fn plan(app: &TestApp, token: &str) -> i32 {
+        let response = app
+            .post(
+                "/api/training-plans",
+                token,
+                json!({ "name": "a plan", "description": "a plan" }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        response.body["planId"].as_i64().unwrap() as i32
+    }
+
+    async fn put(app: &TestApp, token: &str, uri: &str, body: serde_json::Value) {
+        let response = app.send(Method::PUT, uri, Some(token), Some(body)).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn progress_sums_up_the_plan_contests(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (first_id, first) = app.user("student").await;
+        let (second_id, _) = app.user("student").await;
+        let (_, outsider) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let plan_id = plan(&app, &teacher).await;
+        let uri = format!("/api/training-plans/{}", plan_id);
+
+        // contests can only join a plan before they start
+        let contests = [
+            app.contest(&teacher, &[problem_id], 60, 120).await,
+            app.contest(&teacher, &[problem_id], 60, 120).await,
+        ];
+        put(
+            &app,
+            &teacher,
+            &format!("{}/participants", uri),
+            json!({ "userIds": [first_id, second_id] }),
+        )
+        .await;
+        put(
+            &app,
+            &teacher,
+            &format!("{}/contests", uri),
+            json!({ "contestIds": contests }),
+        )
+        .await;
+        sqlx::query!(
+            r#"
+            UPDATE contests SET begin_time = NOW() - INTERVAL '1 hour',
+                                end_time = NOW() + INTERVAL '1 hour'
+            WHERE id = ANY($1)
+            "#,
+            &contests
+        )
+        .execute(&app.pool)
+        .await
+        .unwrap();
+        for contest_id in contests {
+            app.seed_contest_submission(contest_id, first_id, problem_id, "accepted", 50)
+                .await;
+        }
+
+        let progress = app.get(&format!("{}/progress", uri), &teacher).await;
+        assert_eq!(progress.status, StatusCode::OK, "{}", progress.body);
+        let rows = progress.body["progress"].as_array().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["userId"], first_id);
+        assert_eq!(rows[0]["totalSolved"], 2);
+        assert_eq!(rows[0]["totalPenalty"], 2 * 10 * 60);
+        assert_eq!(rows[0]["contestCount"], 2);
+        assert_eq!(rows[1]["userId"], second_id);
+        assert_eq!(rows[1]["totalSolved"], 0);
+
+        let own = app.get(&format!("{}/progress", uri), &first).await;
+        assert_eq!(own.body["progress"].as_array().unwrap().len(), 1);
+        assert_eq!(own.body["progress"][0]["userId"], first_id);
+        assert_eq!(
+            app.get(&format!("{}/progress", uri), &outsider)
+                .await
+                .status,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn mine_lists_only_the_joined_plans(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let joined = plan(&app, &teacher).await;
+        plan(&app, &teacher).await;
+        put(
+            &app,
+            &teacher,
+            &format!("/api/training-plans/{}/participants", joined),
+            json!({ "userIds": [student_id] }),
+        )
+        .await;
+
+        let all = app.get("/api/training-plans", &student).await;
+        assert_eq!(all.body["total"], 2);
+        let mine = app.get("/api/training-plans?mine=true", &student).await;
+        assert_eq!(mine.status, StatusCode::OK, "{}", mine.body);
+        assert_eq!(mine.body["total"], 1);
+        assert_eq!(mine.body["items"][0]["id"], joined);
+
+        let guest = app
+            .send(Method::GET, "/api/training-plans?mine=true", None, None)
+            .await;
+        assert_eq!(guest.status, StatusCode::OK, "{}", guest.body);
+        assert_eq!(guest.body["total"], 0);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn join_code_enrolls_once(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let (student_id, student) = app.user("student").await;
+        let problem_id = app.problem(&teacher).await;
+        let contest_id = app.contest(&teacher, &[problem_id], 60, 120).await;
+        let plan_id = plan(&app, &teacher).await;
+        let uri = format!("/api/training-plans/{}", plan_id);
+        put(
+            &app,
+            &teacher,
+            &format!("{}/contests", uri),
+            json!({ "contestIds": [contest_id] }),
+        )
+        .await;
+
+        let join_uri = format!("{}/join", uri);
+        let join =
+            |code: serde_json::Value| app.post(&join_uri, &student, json!({ "joinCode": code }));
+        assert_eq!(join(json!("anything")).await.status, StatusCode::FORBIDDEN);
+
+        let response = app
+            .post(&format!("{}/join-code", uri), &teacher, json!({}))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let code = response.body["joinCode"].clone();
+
+        assert_eq!(
+            join(json!("wrong-code")).await.status,
+            StatusCode::FORBIDDEN
+        );
+        let response = join(code.clone()).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(join(code).await.status, StatusCode::CONFLICT);
+
+        let joined = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM contest_participants
+                WHERE contest_id = $1 AND user_id = $2 AND training_plan_id = $3
+            ) as "exists!"
+            "#,
+            contest_id,
+            student_id,
+            plan_id
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert!(joined);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn plan_contest_cant_be_moved_into_the_past(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, teacher) = app.user("teacher").await;
+        let problem_id = app.problem(&teacher).await;
+        let plan_id = plan(&app, &teacher).await;
+        let linked = app.contest(&teacher, &[problem_id], 60, 120).await;
+        let unlinked = app.contest(&teacher, &[problem_id], 60, 120).await;
+        put(
+            &app,
+            &teacher,
+            &format!("/api/training-plans/{}/contests", plan_id),
+            json!({ "contestIds": [linked] }),
+        )
+        .await;
+
+        let now = chrono::Utc::now();
+        let past = json!({
+            "beginTime": now - chrono::Duration::hours(2),
+            "endTime": now - chrono::Duration::hours(1),
+        });
+        let uri = format!("/api/contests/{}", linked);
+        let response = app
+            .send(Method::PUT, &uri, Some(&teacher), Some(past.clone()))
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let fields: Vec<_> = response.body["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["beginTime", "endTime"]);
+        assert!(
+            response.body["fields"][0]["reason"]
+                .as_str()
+                .unwrap()
+                .contains("a plan")
+        );
+
+        // contests outside any plan are free to move
+        let uri = format!("/api/contests/{}", unlinked);
+        put(&app, &teacher, &uri, past).await;
+    }
+}
@@ -1,17 +1,37 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use axum::{Extension, Json, Router, extract::Path, http::StatusCode, middleware};
-use koioj_common::bail;
-use regex::Regex;
+use axum::{
+    Extension, Json, Router,
+    extract::{ConnectInfo, Path, Query},
+    http::{HeaderValue, StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use koioj_common::{
+    bail,
+    judge::{Language, SubmissionResult},
+};
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
 
 use crate::{
-    AppState, Result, State,
-    auth::{Claims, generate_jwt_token, hash_password, jwt_auth_middleware, verify_password},
-    error::Error,
+    AppState, Result, State, audit,
+    auth::{
+        Claims, generate_jwt_token, generate_strong_password, hash_password, issue_refresh_token,
+        jwt_auth_middleware, revoke_jwt_token, revoke_refresh_tokens, verify_password,
+        verify_refresh_token,
+    },
+    error::{Error, ValidationError},
     perm::{Action, Resource, UserRole, check_permission, role_of_claims},
-    route::contests::ranking_cache::clear_user_ranking_cache,
+    route::{
+        MAX_PAGE, Page, contests::ranking_cache::clear_user_ranking_cache, escape_like,
+        stored_language,
+    },
+    validation::{check_email, check_phone, check_user_code, check_username, user_write_error},
+    webhook::{self, PasswordResetRequested},
 };
 
 pub fn top_routes() -> Router<Arc<AppState>> {
@@ -24,26 +44,28 @@ pub fn top_routes() -> Router<Arc<AppState>> {
 pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
     use axum::routing::*;
     Router::new()
+        .route("/", get(list_users))
+        .route("/bulk", post(bulk_create_users))
         .route("/{user_id}", delete(delete_user))
         .route("/{user_id}/role", put(put_role))
         .route("/{user_id}/role", get(get_role))
         .route("/{user_id}/profile", put(put_profile))
         .route("/{user_id}/profile", get(get_profile))
         .route("/change-password", post(change_password))
+        .route("/me", get(get_my_profile))
+        .route("/me/role", get(get_my_role))
+        .route("/me/bookmarks", get(list_bookmarks))
+        .route("/logout", post(logout))
         .layer(middleware::from_fn_with_state(state, jwt_auth_middleware))
+        .route("/refresh", post(refresh))
+        .route("/reset-password/request", post(request_password_reset))
+        .route("/reset-password/confirm", post(confirm_password_reset))
 }
 
-fn is_valid_email(email: &str) -> bool {
-    let email_regex = Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$").unwrap();
-    email_regex.is_match(email)
-}
-fn is_all_digit(phone: &str) -> bool {
-    phone.chars().all(|c| c.is_ascii_digit())
-}
-
-#[derive(Debug, sqlx::Type, PartialEq)]
+#[derive(Debug, sqlx::Type, PartialEq, Serialize, Deserialize, ToSchema)]
 #[sqlx(type_name = "user_status_enum")]
 #[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum UserStatus {
     Active,
     Inactive,
@@ -64,6 +86,7 @@ pub(crate) struct RegisterRequest {
 pub(crate) struct RegisterResponse {
     user_id: i32,
     token: String,
+    refresh_token: String,
 }
 
 #[utoipa::path(
@@ -76,24 +99,15 @@ pub(crate) struct RegisterResponse {
     tag = "user"
 )]
 async fn register(state: State, Json(p): Json<RegisterRequest>) -> Result<Json<RegisterResponse>> {
-    if p.phone.is_empty()
-        || p.email.is_empty()
-        || p.username.is_empty()
-        || p.user_code.is_empty()
-        || p.password.is_empty()
-    {
-        bail!(@BAD_REQUEST "all fields are required");
-    }
-
-    if !is_valid_email(&p.email) {
-        bail!(@BAD_REQUEST "invalid email");
-    }
-    if !is_all_digit(&p.phone) {
-        bail!(@BAD_REQUEST "invalid phone");
-    }
-    if !is_all_digit(&p.user_code) {
-        bail!(@BAD_REQUEST "invalid user code");
+    let mut errors = ValidationError::new();
+    check_phone(&mut errors, &p.phone);
+    check_email(&mut errors, &p.email);
+    check_username(&mut errors, &p.username);
+    check_user_code(&mut errors, &p.user_code);
+    if p.password.is_empty() {
+        errors.add("password", "password is required");
     }
+    errors.check()?;
 
     let password_hash = hash_password(p.password)?;
 
@@ -112,15 +126,7 @@ async fn register(state: State, Json(p): Json<RegisterRequest>) -> Result<Json<R
     )
     .fetch_one(&state.pool)
     .await
-    .map_err(|e| {
-        if let sqlx::Error::Database(db_err) = &e {
-            if db_err.is_unique_violation() {
-                return Error::msg("phone, email, username, or user_code already exists")
-                    .status_code(StatusCode::BAD_REQUEST);
-            }
-        }
-        Error::msg(format!("database error: {}", e))
-    })?;
+    .map_err(user_write_error)?;
 
     let token = generate_jwt_token(
         &user_id,
@@ -128,8 +134,13 @@ async fn register(state: State, Json(p): Json<RegisterRequest>) -> Result<Json<R
         state.config.jwt_secret.clone(),
     )
     .map_err(|e| Error::msg(format!("Token generation failed: {}", e)))?;
+    let refresh_token = issue_refresh_token(&state, user_id).await?;
 
-    Ok(Json(RegisterResponse { user_id, token }))
+    Ok(Json(RegisterResponse {
+        user_id,
+        token,
+        refresh_token,
+    }))
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -145,6 +156,70 @@ pub(crate) struct LoginRequest {
 pub(crate) struct LoginResponse {
     user_id: i32,
     token: String,
+    refresh_token: String,
+}
+
+fn login_attempts_key(identifier: &str, ip: &SocketAddr) -> String {
+    format!("login_attempts:{}:{}", ip.ip(), identifier)
+}
+
+/// Sliding window over recorded attempts; returns how many seconds to wait if
+/// `max_attempts` of them are within the window.
+async fn retry_after(
+    state: &AppState,
+    key: &str,
+    max_attempts: u32,
+    window: chrono::Duration,
+) -> Result<Option<i64>> {
+    let window_ms = window.num_milliseconds();
+    let now_ms = chrono::Utc::now().timestamp_millis();
+
+    let mut redis_conn = state.redis.clone();
+    let _: () = redis_conn
+        .zrembyscore(key, "-inf", now_ms - window_ms)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let attempts: u32 = redis_conn
+        .zcard(key)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+    if attempts < max_attempts {
+        return Ok(None);
+    }
+
+    let oldest: Vec<(String, i64)> = redis_conn
+        .zrange_withscores(key, 0, 0)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let oldest_ms = oldest.first().map(|(_, score)| *score).unwrap_or(now_ms);
+
+    Ok(Some(((oldest_ms + window_ms - now_ms) / 1000).max(1)))
+}
+
+async fn record_attempt(state: &AppState, key: &str, window: chrono::Duration) -> Result<()> {
+    let now_ms = chrono::Utc::now().timestamp_millis();
+
+    let mut redis_conn = state.redis.clone();
+    let _: () = redis_conn
+        .zadd(key, Uuid::new_v4().to_string(), now_ms)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let _: () = redis_conn
+        .expire(key, window.num_seconds().max(1))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+    Ok(())
+}
+
+fn too_many_requests(message: &str, retry_after: i64) -> Response {
+    let mut res = Error::msg(message)
+        .status_code(StatusCode::TOO_MANY_REQUESTS)
+        .into_response();
+    res.headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    res
 }
 
 #[utoipa::path(
@@ -153,14 +228,31 @@ pub(crate) struct LoginResponse {
     request_body = LoginRequest,
     responses(
         (status = 200, body = LoginResponse),
+        (status = 429, description = "too many failed attempts, see Retry-After"),
     ),
     tag = "user"
 )]
-async fn login(state: State, Json(p): Json<LoginRequest>) -> Result<Json<LoginResponse>> {
+async fn login(
+    state: State,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(p): Json<LoginRequest>,
+) -> Result<Response> {
     if p.identifier.is_empty() || p.password.is_empty() {
         bail!("identifier and password are required");
     }
 
+    let attempts_key = login_attempts_key(&p.identifier, &addr);
+    let (max_attempts, window) = (
+        state.config.login_max_attempts,
+        state.config.login_attempt_window,
+    );
+    if let Some(retry_after) = retry_after(&state, &attempts_key, max_attempts, window).await? {
+        return Ok(too_many_requests(
+            "too many failed login attempts",
+            retry_after,
+        ));
+    }
+
     let user = sqlx::query!(
         r#"
     SELECT id, password, status as "status: UserStatus"
@@ -171,14 +263,26 @@ async fn login(state: State, Json(p): Json<LoginRequest>) -> Result<Json<LoginRe
     )
     .fetch_optional(&state.pool)
     .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
-    .ok_or_else(|| Error::msg("invalid credentials").status_code(StatusCode::UNAUTHORIZED))?;
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    let Some(user) = user else {
+        record_attempt(&state, &attempts_key, window).await?;
+        bail!(@UNAUTHORIZED "invalid credentials");
+    };
 
     if user.status != UserStatus::Active {
         bail!("account is not active");
     }
 
-    verify_password(p.password, user.password)?;
+    if let Err(e) = verify_password(p.password, user.password) {
+        record_attempt(&state, &attempts_key, window).await?;
+        return Err(e);
+    }
+
+    let mut redis_conn = state.redis.clone();
+    let _: () = redis_conn
+        .del(&attempts_key)
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
 
     let token = generate_jwt_token(
         &user.id,
@@ -186,11 +290,103 @@ async fn login(state: State, Json(p): Json<LoginRequest>) -> Result<Json<LoginRe
         state.config.jwt_secret.clone(),
     )
     .map_err(|e| Error::msg(format!("token generation failed: {}", e)))?;
+    let refresh_token = issue_refresh_token(&state, user.id).await?;
 
     Ok(Json(LoginResponse {
         user_id: user.id,
         token,
-    }))
+        refresh_token,
+    })
+    .into_response())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RefreshRequest {
+    refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RefreshResponse {
+    token: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/refresh",
+    request_body = RefreshRequest,
+    responses(
+        (status = 200, body = RefreshResponse),
+    ),
+    tag = "user"
+)]
+async fn refresh(state: State, Json(p): Json<RefreshRequest>) -> Result<Json<RefreshResponse>> {
+    let (_, user_id) = verify_refresh_token(&state, &p.refresh_token).await?;
+
+    let active = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND status = 'active') as "exists!"
+        "#,
+        user_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    if !active {
+        bail!(@UNAUTHORIZED "account is not active");
+    }
+
+    let token = generate_jwt_token(
+        &user_id,
+        state.config.jwt_expiry,
+        state.config.jwt_secret.clone(),
+    )
+    .map_err(|e| Error::msg(format!("token generation failed: {}", e)))?;
+
+    Ok(Json(RefreshResponse { token }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LogoutRequest {
+    /// also revoke this refresh token
+    refresh_token: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/logout",
+    request_body = LogoutRequest,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ()),
+    ),
+    tag = "user"
+)]
+async fn logout(
+    state: State,
+    claims: Extension<Claims>,
+    Json(p): Json<LogoutRequest>,
+) -> Result<()> {
+    if let Some(refresh_token) = p.refresh_token {
+        let (id, user_id) = verify_refresh_token(&state, &refresh_token).await?;
+        if user_id != claims.sub {
+            bail!(@FORBIDDEN "refresh token belongs to another user");
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE refresh_tokens SET revoked = TRUE WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&state.pool)
+        .await
+        .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+    }
+
+    revoke_jwt_token(&state, &claims).await
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -241,6 +437,15 @@ async fn put_role(
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
     .ok_or_else(|| Error::msg("user not found").status_code(StatusCode::NOT_FOUND))?;
 
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::PutRole,
+        Resource::User(user_id),
+        Some(format!("role: {:?}", p.user_role).to_lowercase()),
+    )
+    .await;
+
     Ok(())
 }
 
@@ -275,6 +480,27 @@ async fn get_role(
     )
     .await?;
 
+    let role = fetch_role(&state, user_id).await?;
+
+    Ok(Json(GetRoleResponse { role }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me/role",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = GetRoleResponse),
+    ),
+    tag = "user",
+)]
+async fn get_my_role(state: State, claims: Extension<Claims>) -> Result<Json<GetRoleResponse>> {
+    let role = fetch_role(&state, claims.sub).await?;
+
+    Ok(Json(GetRoleResponse { role }))
+}
+
+async fn fetch_role(state: &AppState, user_id: i32) -> Result<UserRole> {
     let role = sqlx::query!(
         r#"
         SELECT user_role as "user_role: UserRole" FROM users
@@ -288,7 +514,7 @@ async fn get_role(
     .ok_or_else(|| Error::msg("user not found").status_code(StatusCode::NOT_FOUND))?
     .user_role;
 
-    Ok(Json(GetRoleResponse { role }))
+    Ok(role)
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -326,8 +552,33 @@ async fn get_profile(
     )
     .await?;
 
+    Ok(Json(build_profile(&state, &claims, user_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = GetProfileResponse),
+    ),
+    tag = "user",
+)]
+async fn get_my_profile(
+    state: State,
+    claims: Extension<Claims>,
+) -> Result<Json<GetProfileResponse>> {
+    Ok(Json(build_profile(&state, &claims, claims.sub).await?))
+}
+
+/// profile of `user_id` as seen by the requester, with fields hidden by role
+async fn build_profile(
+    state: &AppState,
+    claims: &Claims,
+    user_id: i32,
+) -> Result<GetProfileResponse> {
     let requester_id: i32 = claims.sub;
-    let requester_role = role_of_claims(&state.pool, &claims).await?;
+    let requester_role = role_of_claims(&state.pool, claims).await?;
 
     let user = sqlx::query!(
         r#"
@@ -369,7 +620,7 @@ async fn get_profile(
         }
     };
 
-    Ok(Json(response))
+    Ok(response)
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -377,6 +628,10 @@ async fn get_profile(
 pub(crate) struct PutProfileRequest {
     username: String,
     email: String,
+    /// left unchanged if omitted
+    phone: Option<String>,
+    /// left unchanged if omitted
+    user_code: Option<String>,
 }
 
 #[utoipa::path(
@@ -406,27 +661,35 @@ async fn put_profile(
     )
     .await?;
 
-    if p.email.is_empty() || p.username.is_empty() {
-        bail!(@BAD_REQUEST "all fields are required");
+    let mut errors = ValidationError::new();
+    check_email(&mut errors, &p.email);
+    check_username(&mut errors, &p.username);
+    if let Some(phone) = &p.phone {
+        check_phone(&mut errors, phone);
     }
-    if !is_valid_email(&p.email) {
-        bail!(@BAD_REQUEST "invalid email");
+    if let Some(user_code) = &p.user_code {
+        check_user_code(&mut errors, user_code);
     }
+    errors.check()?;
 
     let _updated = sqlx::query!(
         r#"
         UPDATE users
-        SET username = $1, email = $2, updated_at = NOW()
-        WHERE id = $3 AND status = 'active'
+        SET username = $1, email = $2,
+            phone = COALESCE($3, phone), user_code = COALESCE($4, user_code),
+            updated_at = NOW()
+        WHERE id = $5 AND status = 'active'
         RETURNING id
         "#,
         p.username,
         p.email,
+        p.phone,
+        p.user_code,
         user_id
     )
     .fetch_optional(&state.pool)
     .await
-    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .map_err(user_write_error)?
     .ok_or_else(|| Error::msg("user not found").status_code(StatusCode::NOT_FOUND))?;
 
     clear_user_ranking_cache(&state, user_id).await?;
@@ -457,9 +720,14 @@ async fn change_password(
     claims: Extension<Claims>,
     Json(p): Json<ChangePasswordRequest>,
 ) -> Result<()> {
-    if p.old_password.is_empty() || p.new_password.is_empty() {
-        bail!(@BAD_REQUEST "all fields are required");
+    let mut errors = ValidationError::new();
+    if p.old_password.is_empty() {
+        errors.add("oldPassword", "old password is required");
+    }
+    if p.new_password.is_empty() {
+        errors.add("newPassword", "new password is required");
     }
+    errors.check()?;
 
     let new_password_hash = hash_password(p.new_password)?;
 
@@ -494,6 +762,187 @@ async fn change_password(
         bail!(@NOT_FOUND "user not found or inactive");
     }
 
+    revoke_refresh_tokens(&mut *tx, claims.sub).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| Error::msg(format!("transaction commit error: {}", e)))?;
+
+    Ok(())
+}
+
+fn password_reset_key(token: &str) -> String {
+    format!("password_reset:{}", token)
+}
+
+fn password_reset_requests_key(scope: &str, value: &str) -> String {
+    format!("password_reset_requests:{}:{}", scope, value)
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RequestPasswordResetRequest {
+    /// username, phone or email
+    identifier: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/reset-password/request",
+    request_body = RequestPasswordResetRequest,
+    responses(
+        (status = 200, body = (), description = "the same whether or not the account exists"),
+        (status = 404, description = "password reset is not enabled"),
+        (status = 429, description = "too many reset requests, see Retry-After"),
+    ),
+    tag = "user"
+)]
+async fn request_password_reset(
+    state: State,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(p): Json<RequestPasswordResetRequest>,
+) -> Result<Response> {
+    let identifier = p.identifier.trim();
+    if identifier.is_empty() {
+        return Err(Error::invalid_field("identifier", "identifier is required"));
+    }
+    // the token must only ever reach the account owner, so without a way to
+    // deliver it there's no resetting
+    let Some(hook) = &state.config.password_reset_hook else {
+        bail!(@NOT_FOUND "password reset is not enabled");
+    };
+
+    // counted whether or not the account exists, so hitting the limit tells
+    // nothing about it
+    let window = state.config.password_reset_window;
+    let limits = [
+        (
+            password_reset_requests_key("identifier", &identifier.to_lowercase()),
+            state.config.password_reset_max_requests,
+        ),
+        (
+            password_reset_requests_key("ip", &addr.ip().to_string()),
+            state.config.password_reset_max_requests_per_ip,
+        ),
+    ];
+    for (key, max_requests) in &limits {
+        if let Some(retry_after) = retry_after(&state, key, *max_requests, window).await? {
+            return Ok(too_many_requests(
+                "too many password reset requests",
+                retry_after,
+            ));
+        }
+    }
+    for (key, _) in &limits {
+        record_attempt(&state, key, window).await?;
+    }
+
+    let user = sqlx::query!(
+        r#"
+        SELECT id, username, email, phone FROM users
+        WHERE (username = $1 OR phone = $1 OR email = $1) AND status = 'active'
+        "#,
+        identifier
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    if let Some(user) = user {
+        let token = Uuid::new_v4().simple().to_string();
+        let expiry = state.config.password_reset_expiry;
+
+        let mut redis_conn = state.redis.clone();
+        let _: () = redis_conn
+            .set_ex(
+                password_reset_key(&token),
+                user.id,
+                expiry.num_seconds().max(1) as u64,
+            )
+            .await
+            .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+
+        webhook::password_reset_requested(
+            hook,
+            PasswordResetRequested {
+                user_id: user.id,
+                username: user.username,
+                email: user.email,
+                phone: user.phone,
+                token,
+                expires_at: Utc::now() + expiry,
+            },
+        );
+    }
+
+    Ok(().into_response())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ConfirmPasswordResetRequest {
+    token: String,
+    new_password: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/reset-password/confirm",
+    request_body = ConfirmPasswordResetRequest,
+    responses(
+        (status = 200, body = ()),
+    ),
+    tag = "user"
+)]
+async fn confirm_password_reset(
+    state: State,
+    Json(p): Json<ConfirmPasswordResetRequest>,
+) -> Result<()> {
+    let mut errors = ValidationError::new();
+    if p.token.is_empty() {
+        errors.add("token", "token is required");
+    }
+    if p.new_password.is_empty() {
+        errors.add("newPassword", "new password is required");
+    }
+    errors.check()?;
+
+    // GETDEL makes the token single-use even under concurrent requests
+    let mut redis_conn = state.redis.clone();
+    let user_id: Option<i32> = redis_conn
+        .get_del(password_reset_key(&p.token))
+        .await
+        .map_err(|e| Error::msg(format!("redis error: {}", e)))?;
+    let user_id = user_id.ok_or_else(|| {
+        Error::msg("invalid or expired reset token").status_code(StatusCode::BAD_REQUEST)
+    })?;
+
+    let new_password_hash = hash_password(p.new_password)?;
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| Error::msg(format!("transaction error: {}", e)))?;
+
+    let rows_affected = sqlx::query!(
+        r#"UPDATE users SET password = $1, updated_at = NOW()
+           WHERE id = $2 AND status = 'active'"#,
+        new_password_hash,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        bail!(@NOT_FOUND "user not found or inactive");
+    }
+
+    // whoever knew the old password may still hold a session
+    revoke_refresh_tokens(&mut *tx, user_id).await?;
+
     tx.commit()
         .await
         .map_err(|e| Error::msg(format!("transaction commit error: {}", e)))?;
@@ -541,5 +990,740 @@ async fn delete_user(
     .map_err(|e| Error::msg(format!("database error: {}", e)))?
     .ok_or_else(|| Error::msg("user not found").status_code(StatusCode::NOT_FOUND))?;
 
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::DeleteUser,
+        Resource::User(user_id),
+        None,
+    )
+    .await;
+
     Ok(())
 }
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BookmarkItem {
+    submission_id: i32,
+    problem_id: i32,
+    problem_name: String,
+    /// unset for a language this server no longer knows
+    lang: Option<Language>,
+    result: SubmissionResult,
+    bookmarked_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ListBookmarksResponse {
+    bookmarks: Vec<BookmarkItem>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me/bookmarks",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = ListBookmarksResponse),
+    ),
+    tag = "user",
+)]
+async fn list_bookmarks(
+    state: State,
+    claims: Extension<Claims>,
+) -> Result<Json<ListBookmarksResponse>> {
+    let bookmarks = sqlx::query!(
+        r#"
+        SELECT s.id, s.problem_id, p.name as problem_name, s.lang,
+               s.result as "result: SubmissionResult", b.created_at
+        FROM submission_bookmarks b
+        JOIN submissions s ON b.submission_id = s.id
+        JOIN problems p ON s.problem_id = p.id
+        WHERE b.user_id = $1
+        ORDER BY b.created_at DESC
+        "#,
+        claims.sub
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .into_iter()
+    .map(|row| BookmarkItem {
+        submission_id: row.id,
+        problem_id: row.problem_id,
+        problem_name: row.problem_name,
+        lang: stored_language(&row.lang),
+        result: row.result,
+        bookmarked_at: row.created_at,
+    })
+    .collect();
+
+    Ok(Json(ListBookmarksResponse { bookmarks }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ListUsersQuery {
+    page: Option<i64>,
+    page_size: Option<i64>,
+    /// case-insensitive substring of username, user code or email
+    q: Option<String>,
+    role: Option<UserRole>,
+    /// also list the internal `admin` account
+    #[serde(default)]
+    include_admin: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UserListItem {
+    user_id: i32,
+    username: String,
+    user_code: String,
+    role: UserRole,
+    status: UserStatus,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users",
+    params(ListUsersQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = Page<UserListItem>),
+    ),
+    tag = "user",
+)]
+async fn list_users(
+    state: State,
+    claims: Extension<Claims>,
+    Query(q): Query<ListUsersQuery>,
+) -> Result<Json<Page<UserListItem>>> {
+    check_permission(&state.pool, &claims, Action::ListUsers, Resource::Global).await?;
+
+    let page = q.page.unwrap_or(1).clamp(1, MAX_PAGE);
+    let page_size = q.page_size.unwrap_or(20).clamp(1, 100);
+    let offset = (page - 1) * page_size;
+    let pattern = format!("%{}%", escape_like(q.q.as_deref().unwrap_or("").trim()));
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!" FROM users
+        WHERE ($1::user_role_enum IS NULL OR user_role = $1)
+        AND (username ILIKE $2 ESCAPE '\' OR user_code ILIKE $2 ESCAPE '\'
+             OR email ILIKE $2 ESCAPE '\')
+        AND ($3 OR username <> 'admin')
+        "#,
+        q.role as Option<UserRole>,
+        pattern,
+        q.include_admin
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?;
+
+    let users = sqlx::query!(
+        r#"
+        SELECT id, username, user_code,
+               user_role as "user_role: UserRole", status as "status: UserStatus"
+        FROM users
+        WHERE ($1::user_role_enum IS NULL OR user_role = $1)
+        AND (username ILIKE $2 ESCAPE '\' OR user_code ILIKE $2 ESCAPE '\'
+             OR email ILIKE $2 ESCAPE '\')
+        AND ($3 OR username <> 'admin')
+        ORDER BY id
+        LIMIT $4 OFFSET $5
+        "#,
+        q.role as Option<UserRole>,
+        pattern,
+        q.include_admin,
+        page_size,
+        offset
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| Error::msg(format!("database error: {}", e)))?
+    .into_iter()
+    .map(|row| UserListItem {
+        user_id: row.id,
+        username: row.username,
+        user_code: row.user_code,
+        role: row.user_role,
+        status: row.status,
+    })
+    .collect();
+
+    Ok(Json(Page::new(users, total, page, page_size)))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BulkUserItem {
+    username: String,
+    user_code: String,
+    email: String,
+    phone: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BulkCreateUsersRequest {
+    users: Vec<BulkUserItem>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BulkUserResult {
+    username: String,
+    user_code: String,
+    user_id: Option<i32>,
+    /// generated password, only present if the user was created
+    password: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BulkCreateUsersResponse {
+    results: Vec<BulkUserResult>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/bulk",
+    request_body = BulkCreateUsersRequest,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, body = BulkCreateUsersResponse),
+    ),
+    tag = "user",
+)]
+async fn bulk_create_users(
+    state: State,
+    claims: Extension<Claims>,
+    Json(p): Json<BulkCreateUsersRequest>,
+) -> Result<Json<BulkCreateUsersResponse>> {
+    check_permission(
+        &state.pool,
+        &claims,
+        Action::BulkCreateUsers,
+        Resource::Global,
+    )
+    .await?;
+
+    if p.users.is_empty() {
+        return Err(Error::invalid_field("users", "users cannot be empty"));
+    }
+
+    let mut results = Vec::with_capacity(p.users.len());
+    for user in p.users {
+        // a bad row is reported back instead of failing the whole batch
+        let outcome = create_bulk_user(&state, &user).await;
+        results.push(match outcome {
+            Ok((user_id, password)) => BulkUserResult {
+                username: user.username,
+                user_code: user.user_code,
+                user_id: Some(user_id),
+                password: Some(password),
+                error: None,
+            },
+            Err(e) => BulkUserResult {
+                username: user.username,
+                user_code: user.user_code,
+                user_id: None,
+                password: None,
+                error: Some(e.1.map(|e| e.to_string()).unwrap_or_default()),
+            },
+        });
+    }
+
+    let created = results.iter().filter(|r| r.user_id.is_some()).count();
+    audit::record(
+        &state.pool,
+        &claims,
+        Action::BulkCreateUsers,
+        Resource::Global,
+        Some(format!("created: {}", created)),
+    )
+    .await;
+
+    Ok(Json(BulkCreateUsersResponse { results }))
+}
+
+async fn create_bulk_user(state: &AppState, user: &BulkUserItem) -> Result<(i32, String)> {
+    let mut errors = ValidationError::new();
+    check_phone(&mut errors, &user.phone);
+    check_email(&mut errors, &user.email);
+    check_username(&mut errors, &user.username);
+    check_user_code(&mut errors, &user.user_code);
+    errors.check()?;
+
+    let password = generate_strong_password();
+    let password_hash = hash_password(password.clone())?;
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| Error::msg(format!("transaction error: {}", e)))?;
+
+    let user_id: i32 = sqlx::query_scalar!(
+        r#"
+        INSERT INTO users (phone, email, username, user_code, user_role, password, status)
+        VALUES ($1, $2, $3, $4, $5, $6, 'active')
+        RETURNING id
+        "#,
+        user.phone,
+        user.email,
+        user.username,
+        user.user_code,
+        UserRole::Student as UserRole,
+        password_hash
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(user_write_error)?;
+
+    tx.commit()
+        .await
+        .map_err(|e| Error::msg(format!("transaction commit error: {}", e)))?;
+
+    Ok((user_id, password))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::PasswordResetHook,
+        test_util::{HookServer, PASSWORD, TestApp},
+        webhook::{SIGNATURE_HEADER, sign},
+    };
+
+    async fn app_with_hook(pool: PgPool) -> (TestApp, HookServer) {
+        let hook = HookServer::start().await;
+        let url = hook.url.clone();
+        let app = TestApp::with_config(pool, |config| {
+            config.password_reset_hook = Some(PasswordResetHook {
+                url,
+                secret: "hook-secret".to_string(),
+            });
+        })
+        .await;
+        (app, hook)
+    }
+
+    async fn request_reset(app: &TestApp, identifier: &str) -> crate::test_util::Response {
+        app.post_as_guest(
+            "/api/users/reset-password/request",
+            json!({ "identifier": identifier }),
+        )
+        .await
+    }
+
+    async fn login(app: &TestApp, identifier: &str, password: &str) -> crate::test_util::Response {
+        app.post_as_guest(
+            "/api/login",
+            json!({ "identifier": identifier, "password": password }),
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn reset_token_goes_to_the_hook_and_never_to_the_caller(pool: PgPool) {
+        let (app, mut hook) = app_with_hook(pool).await;
+        let (user_id, _) = app.user("student").await;
+
+        let known = request_reset(&app, "user1").await;
+        let unknown = request_reset(&app, "nobody").await;
+        assert_eq!(known.status, StatusCode::OK);
+        assert_eq!(unknown.status, known.status);
+        assert_eq!(unknown.body, known.body);
+        assert!(!known.body.to_string().contains("token"));
+
+        let request = hook.next().await;
+        assert_eq!(request.body["event"], "password_reset.requested");
+        assert_eq!(request.body["userId"], user_id);
+        assert_eq!(request.body["email"], "user1@example.com");
+        assert!(
+            request.body["token"]
+                .as_str()
+                .is_some_and(|t| !t.is_empty())
+        );
+        assert_eq!(
+            request.headers[SIGNATURE_HEADER],
+            format!("sha256={}", sign("hook-secret", &request.raw)).as_str()
+        );
+        assert_eq!(hook.remaining().await, 0);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn reset_changes_the_password_once_and_ends_sessions(pool: PgPool) {
+        let (app, mut hook) = app_with_hook(pool).await;
+        app.user("student").await;
+        let session = login(&app, "user1", PASSWORD).await;
+        assert_eq!(session.status, StatusCode::OK);
+
+        request_reset(&app, "user1@example.com").await;
+        let token = hook.next().await.body["token"].clone();
+        let confirm = json!({ "token": token, "newPassword": "a-new-password" });
+        let response = app
+            .post_as_guest("/api/users/reset-password/confirm", confirm.clone())
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        assert_eq!(
+            login(&app, "user1", PASSWORD).await.status,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            login(&app, "user1", "a-new-password").await.status,
+            StatusCode::OK
+        );
+        let refresh = app
+            .post_as_guest(
+                "/api/users/refresh",
+                json!({ "refreshToken": session.body["refreshToken"] }),
+            )
+            .await;
+        assert_eq!(refresh.status, StatusCode::UNAUTHORIZED);
+
+        let reused = app
+            .post_as_guest("/api/users/reset-password/confirm", confirm)
+            .await;
+        assert_eq!(reused.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn reset_is_off_without_a_hook(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        app.user("student").await;
+
+        assert_eq!(
+            request_reset(&app, "user1").await.status,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn reset_requests_are_limited_per_identifier(pool: PgPool) {
+        let (app, _hook) = app_with_hook(pool).await;
+
+        for _ in 0..app.state.config.password_reset_max_requests {
+            assert_eq!(request_reset(&app, "nobody").await.status, StatusCode::OK);
+        }
+        let response = request_reset(&app, "NOBODY").await;
+        assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers.contains_key("retry-after"));
+        assert_eq!(request_reset(&app, "someone").await.status, StatusCode::OK);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn reset_requests_are_limited_per_client(pool: PgPool) {
+        let (app, _hook) = app_with_hook(pool).await;
+
+        for n in 0..app.state.config.password_reset_max_requests_per_ip {
+            let response = request_reset(&app, &format!("nobody{}", n)).await;
+            assert_eq!(response.status, StatusCode::OK);
+        }
+        assert_eq!(
+            request_reset(&app, "somebody").await.status,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    async fn refresh(
+        app: &TestApp,
+        refresh_token: &serde_json::Value,
+    ) -> crate::test_util::Response {
+        app.post_as_guest(
+            "/api/users/refresh",
+            json!({ "refreshToken": refresh_token }),
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn refresh_token_issues_access_tokens_until_revoked(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        app.user("student").await;
+        let session = login(&app, "user1", PASSWORD).await;
+        assert_eq!(session.status, StatusCode::OK);
+
+        let response = refresh(&app, &session.body["refreshToken"]).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let token = response.body["token"].as_str().unwrap().to_string();
+        let me = app.get("/api/users/me", &token).await;
+        assert_eq!(me.status, StatusCode::OK);
+        assert_eq!(me.body["username"], "user1");
+
+        let response = app
+            .post(
+                "/api/users/logout",
+                &token,
+                json!({ "refreshToken": session.body["refreshToken"] }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(
+            refresh(&app, &session.body["refreshToken"]).await.status,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            refresh(&app, &json!("not-a-token")).await.status,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn changing_the_password_ends_other_sessions(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, token) = app.user("student").await;
+        let session = login(&app, "user1", PASSWORD).await;
+
+        let response = app
+            .post(
+                "/api/users/change-password",
+                &token,
+                json!({ "oldPassword": PASSWORD, "newPassword": "a-new-password" }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(
+            refresh(&app, &session.body["refreshToken"]).await.status,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    async fn app_with_login_limit(pool: PgPool, max_attempts: u32) -> TestApp {
+        TestApp::with_config(pool, |config| config.login_max_attempts = max_attempts).await
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn login_is_blocked_after_too_many_failures(pool: PgPool) {
+        let app = app_with_login_limit(pool, 3).await;
+        app.user("student").await;
+
+        for _ in 0..3 {
+            assert_eq!(
+                login(&app, "user1", "wrong").await.status,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        let response = login(&app, "user1", PASSWORD).await;
+        assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers.contains_key("retry-after"));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn successful_login_clears_failures(pool: PgPool) {
+        let app = app_with_login_limit(pool, 3).await;
+        app.user("student").await;
+
+        for _ in 0..2 {
+            login(&app, "user1", "wrong").await;
+        }
+        assert_eq!(login(&app, "user1", PASSWORD).await.status, StatusCode::OK);
+        for _ in 0..2 {
+            assert_eq!(
+                login(&app, "user1", "wrong").await.status,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        assert_eq!(login(&app, "user1", PASSWORD).await.status, StatusCode::OK);
+    }
+
+    fn usernames(body: &serde_json::Value) -> Vec<&str> {
+        body["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|user| user["username"].as_str().unwrap())
+            .collect()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn users_are_searched_and_filtered_by_role(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        app.user("student").await;
+        let (_, teacher) = app.user("teacher").await;
+        app.user("student").await;
+
+        let response = app.get("/api/users", &teacher).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(usernames(&response.body), ["user1", "user2", "user3"]);
+
+        let response = app.get("/api/users?q=USER3", &teacher).await;
+        assert_eq!(usernames(&response.body), ["user3"]);
+        let response = app.get("/api/users?q=202400000002", &teacher).await;
+        assert_eq!(usernames(&response.body), ["user2"]);
+
+        let response = app.get("/api/users?role=student", &teacher).await;
+        assert_eq!(usernames(&response.body), ["user1", "user3"]);
+        assert_eq!(response.body["total"], 2);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn students_cannot_list_users(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (_, student) = app.user("student").await;
+
+        assert_eq!(
+            app.get("/api/users", &student).await.status,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    fn bulk_user(n: u32) -> serde_json::Value {
+        json!({
+            "username": format!("bulk{}", n),
+            "userCode": format!("2025{:08}", n),
+            "email": format!("bulk{}@example.com", n),
+            "phone": format!("1390000{:04}", n),
+        })
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn bulk_creation_keeps_the_valid_rows_of_a_batch(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        app.user("student").await;
+        let admin = app.token(1);
+
+        let mut duplicate = bulk_user(2);
+        duplicate["username"] = json!("user1");
+        let response = app
+            .post(
+                "/api/users/bulk",
+                &admin,
+                json!({ "users": [bulk_user(1), duplicate, bulk_user(3)] }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let results = response.body["results"].as_array().unwrap();
+        assert!(results[0]["userId"].is_i64());
+        assert!(results[0]["password"].is_string());
+        assert!(results[1]["userId"].is_null());
+        assert!(results[1]["error"].is_string());
+        assert!(results[2]["userId"].is_i64());
+
+        let created = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM users WHERE username LIKE 'bulk%'"#
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(created, 2);
+        assert_eq!(
+            login(&app, "bulk1", results[0]["password"].as_str().unwrap())
+                .await
+                .status,
+            StatusCode::OK
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn own_profile_is_the_same_under_me(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (user_id, token) = app.user("student").await;
+
+        let me = app.get("/api/users/me", &token).await;
+        let by_id = app
+            .get(&format!("/api/users/{}/profile", user_id), &token)
+            .await;
+        assert_eq!(me.status, StatusCode::OK, "{}", me.body);
+        assert_eq!(me.body, by_id.body);
+        assert_eq!(me.body["phone"], "13800000001");
+
+        let role = app.get("/api/users/me/role", &token).await;
+        let role_by_id = app
+            .get(&format!("/api/users/{}/role", user_id), &token)
+            .await;
+        assert_eq!(role.status, StatusCode::OK, "{}", role.body);
+        assert_eq!(role.body, role_by_id.body);
+    }
+
+    async fn put_profile(
+        app: &TestApp,
+        token: &str,
+        user_id: i32,
+        body: serde_json::Value,
+    ) -> crate::test_util::Response {
+        app.send(
+            Method::PUT,
+            &format!("/api/users/{}/profile", user_id),
+            Some(token),
+            Some(body),
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn profile_update_changes_only_the_given_phone(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (user_id, token) = app.user("student").await;
+
+        let response = put_profile(
+            &app,
+            &token,
+            user_id,
+            json!({ "username": "user1", "email": "user1@example.com", "phone": "13912345678" }),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let me = app.get("/api/users/me", &token).await;
+        assert_eq!(me.body["phone"], "13912345678");
+        assert_eq!(me.body["userCode"], "202400000001");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn profile_update_to_a_taken_phone_is_refused(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (user_id, token) = app.user("student").await;
+        app.user("student").await;
+
+        let response = put_profile(
+            &app,
+            &token,
+            user_id,
+            json!({ "username": "user1", "email": "user1@example.com", "phone": "13800000002" }),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "phone");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn profile_update_to_a_taken_email_is_refused(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let (user_id, token) = app.user("student").await;
+        app.user("student").await;
+
+        let response = put_profile(
+            &app,
+            &token,
+            user_id,
+            json!({ "username": "user1", "email": "user2@example.com" }),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["field"], "email");
+        assert_eq!(response.body["fields"][0]["reason"], "email already exists");
+
+        let response = put_profile(
+            &app,
+            &token,
+            user_id,
+            json!({ "username": "user1", "email": "user2" }),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["fields"][0]["reason"], "invalid email");
+    }
+}
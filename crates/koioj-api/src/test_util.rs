@@ -0,0 +1,535 @@
+//! An app on a fresh database for the tests that go through the routes.
+//! `#[sqlx::test]` hands out the database, redis is the one at `REDIS_URL`
+//! and gets flushed, so those tests take turns.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::{Body, Bytes, to_bytes},
+    extract::connect_info::MockConnectInfo,
+    http::{HeaderMap, Method, Request, StatusCode, header},
+    routing::post,
+};
+use koioj_common::judge::{
+    ApiToJudgeMessage, JudgeInfo, JudgeResult, JudgeTask, JudgeToApiMessage, Language,
+    SubmissionResult, TestCaseJudgeResult, TestCaseResult,
+};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use tempfile::TempDir;
+use tokio::{
+    net::TcpListener,
+    sync::{Mutex, MutexGuard, mpsc},
+};
+use tower::ServiceExt;
+
+use crate::{
+    AppState,
+    auth::{generate_jwt_token, hash_password},
+    config::Config,
+    route::{
+        self,
+        judge::{self, JudgeConnection},
+    },
+};
+
+static REDIS_LOCK: Mutex<()> = Mutex::const_new(());
+
+const CONFIG: &str = r#"
+listen: "127.0.0.1:0"
+maxWorkers: 1
+logFile: "koioj.log"
+logLevel: "Debug"
+maxConnections: 4
+maxFileSizeMb: 8
+jwtSecret: "test-jwt-secret-0123456789abcdef0123456789"
+jwtExpiry: [3600, 0]
+adminPassword: "test-admin-password"
+dataDir: "."
+judgeToken: "test-judge-token-0123456789abcdef0123456789"
+minJudges: 0
+submissionCooldown: [0, 0]
+judgers: {}
+"#;
+
+pub const PASSWORD: &str = "test-password";
+
+/// argon2 takes long in debug builds, every seeded user shares one hash
+fn password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password(PASSWORD.to_string()).unwrap())
+}
+
+pub fn test_config(data_dir: &TempDir) -> Config {
+    let mut config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    config.data_dir = data_dir.path().to_string_lossy().into_owned();
+    config
+}
+
+/// an http server that keeps whatever is posted to it
+pub struct HookServer {
+    pub url: String,
+    requests: mpsc::UnboundedReceiver<HookRequest>,
+}
+
+pub struct HookRequest {
+    pub headers: HeaderMap,
+    /// as sent, which is what the signature covers
+    pub raw: Bytes,
+    pub body: Value,
+}
+
+impl HookServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, requests) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, raw: Bytes| async move {
+                let body = serde_json::from_slice(&raw).unwrap_or(Value::Null);
+                let _ = sender.send(HookRequest { headers, raw, body });
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Self { url, requests }
+    }
+
+    /// the next request, failing the test if none comes
+    pub async fn next(&mut self) -> HookRequest {
+        tokio::time::timeout(Duration::from_secs(5), self.requests.recv())
+            .await
+            .expect("no request reached the hook")
+            .unwrap()
+    }
+
+    /// how many more requests arrive, after giving deliveries in flight a moment
+    pub async fn remaining(&mut self) -> usize {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let mut count = 0;
+        while self.requests.try_recv().is_ok() {
+            count += 1;
+        }
+        count
+    }
+}
+
+/// a judge put straight into the state, whose messages go through the same
+/// handler as a connected judge's
+pub struct FakeJudge {
+    pub id: String,
+    state: Arc<AppState>,
+    sender: mpsc::UnboundedSender<ApiToJudgeMessage>,
+    messages: mpsc::UnboundedReceiver<ApiToJudgeMessage>,
+}
+
+impl FakeJudge {
+    /// handles the message as if the judge had sent it
+    pub async fn send(&self, msg: JudgeToApiMessage) {
+        let text = serde_json::to_string(&msg).unwrap();
+        judge::handle_judge_message(
+            &text,
+            &axum::extract::State(self.state.clone()),
+            &mut Some(self.id.clone()),
+            &mut true,
+            &self.sender,
+        )
+        .await
+        .unwrap();
+    }
+
+    /// the next message to the judge, failing the test if none comes
+    pub async fn next(&mut self) -> ApiToJudgeMessage {
+        tokio::time::timeout(Duration::from_secs(5), self.messages.recv())
+            .await
+            .expect("nothing was sent to the judge")
+            .unwrap()
+    }
+
+    /// the next task sent to the judge, skipping anything else
+    pub async fn task(&mut self) -> JudgeTask {
+        loop {
+            if let ApiToJudgeMessage::JudgeTask(task) = self.next().await {
+                return task;
+            }
+        }
+    }
+
+    /// drops the connection the way a closed socket does
+    pub async fn disconnect(&self) {
+        judge::judge_disconnected(&axum::extract::State(self.state.clone()), &self.id).await;
+    }
+}
+
+/// a test result with nothing but the verdict
+pub fn test_result(test_case_id: i32, result: TestCaseJudgeResult) -> TestCaseResult {
+    TestCaseResult {
+        test_case_id,
+        result,
+        time_consumption: 10,
+        memory_consumption: 1024,
+        output: None,
+        error_output: None,
+        message: None,
+        diff: None,
+    }
+}
+
+/// the result of a task, 10ms and 1MB per test like `test_result`
+pub fn judge_result(
+    submission_id: i32,
+    result: SubmissionResult,
+    test_results: Vec<TestCaseResult>,
+) -> JudgeToApiMessage {
+    JudgeToApiMessage::JudgeResult(JudgeResult {
+        submission_id,
+        result,
+        time_consumption: 10,
+        memory_consumption: 1024,
+        test_results,
+        failed_on_sample: false,
+        compile_message: None,
+        sample_diff: None,
+        sample_results: Vec::new(),
+    })
+}
+
+pub struct TestApp {
+    pub state: Arc<AppState>,
+    pub pool: PgPool,
+    router: Router,
+    /// keeps seeded names unique
+    serial: AtomicU32,
+    _data_dir: TempDir,
+    _redis: MutexGuard<'static, ()>,
+}
+
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+    /// the body as sent, for the ones that aren't text
+    pub raw: Bytes,
+}
+
+impl TestApp {
+    pub async fn new(pool: PgPool) -> Self {
+        Self::with_config(pool, |_| {}).await
+    }
+
+    pub async fn with_config(pool: PgPool, configure: impl FnOnce(&mut Config)) -> Self {
+        let redis_guard = REDIS_LOCK.lock().await;
+        let data_dir = TempDir::new().unwrap();
+        let mut config = test_config(&data_dir);
+        configure(&mut config);
+        let cors = crate::cors_layer(&config).unwrap();
+        let body_limit = route::body_limit(config.max_file_size_mb);
+
+        sqlx::raw_sql(include_str!("../../../docs/schema.sql"))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let redis_url =
+            std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379/".to_string());
+        let mut redis = redis::aio::ConnectionManager::new(redis::Client::open(redis_url).unwrap())
+            .await
+            .unwrap();
+        let _: () = redis::cmd("FLUSHDB").query_async(&mut redis).await.unwrap();
+
+        let state = Arc::new(AppState::with_connections(
+            Arc::new(config),
+            pool.clone(),
+            redis,
+        ));
+        sqlx::query!(
+            r#"
+            INSERT INTO users (phone, email, username, user_code, user_role, password)
+            VALUES ('00000000000', 'admin@admin.admin', 'admin', '000000000000', 'admin', $1)
+            "#,
+            password_hash()
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        state.setup_phantom_training_plan().await.unwrap();
+
+        let router = route::routes(state.clone())
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))))
+            .layer(cors)
+            .layer(body_limit)
+            .with_state(state.clone());
+
+        Self {
+            state,
+            pool,
+            router,
+            serial: AtomicU32::new(0),
+            _data_dir: data_dir,
+            _redis: redis_guard,
+        }
+    }
+
+    /// a judge for the languages, registered as `id`
+    pub async fn judge(&self, id: &str, languages: &[Language]) -> FakeJudge {
+        let (sender, messages) = mpsc::unbounded_channel();
+        let info = JudgeInfo {
+            judge_id: id.to_string(),
+            version: "test".to_string(),
+            timestamp: 0,
+            signature: String::new(),
+            languages: languages.to_vec(),
+            language_versions: Default::default(),
+        };
+        self.state
+            .judges
+            .write()
+            .await
+            .insert(id.to_string(), JudgeConnection::new(info, sender.clone()));
+        FakeJudge {
+            id: id.to_string(),
+            state: self.state.clone(),
+            sender,
+            messages,
+        }
+    }
+
+    /// an active user of the role and an access token of theirs
+    pub async fn user(&self, role: &str) -> (i32, String) {
+        let n = self.serial.fetch_add(1, Ordering::Relaxed) + 1;
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO users (phone, email, username, user_code, user_role, password)
+            VALUES ($1, $2, $3, $4, $5::text::user_role_enum, $6)
+            RETURNING id
+            "#,
+            format!("1380000{:04}", n),
+            format!("user{}@example.com", n),
+            format!("user{}", n),
+            format!("2024{:08}", n),
+            role,
+            password_hash()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap();
+        (id, self.token(id))
+    }
+
+    pub fn token(&self, user_id: i32) -> String {
+        generate_jwt_token(
+            &user_id,
+            self.state.config.jwt_expiry,
+            self.state.config.jwt_secret.clone(),
+        )
+        .unwrap()
+    }
+
+    pub async fn send(
+        &self,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> Response {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        self.request(request).await
+    }
+
+    /// serves the app on a local port, for what needs a real connection like
+    /// websocket upgrades
+    pub async fn serve(&self) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = self.router.clone();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        addr
+    }
+
+    /// sends a request built by the test, for the headers `send` doesn't set
+    pub async fn request(&self, request: Request<Body>) -> Response {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+        Response {
+            status,
+            headers,
+            body,
+            raw: bytes,
+        }
+    }
+
+    pub async fn get(&self, uri: &str, token: &str) -> Response {
+        self.send(Method::GET, uri, Some(token), None).await
+    }
+
+    pub async fn post(&self, uri: &str, token: &str, body: Value) -> Response {
+        self.send(Method::POST, uri, Some(token), Some(body)).await
+    }
+
+    /// a submission row made `minutes_ago`, without code or judging
+    pub async fn seed_submission(
+        &self,
+        user_id: i32,
+        problem_id: i32,
+        result: &str,
+        minutes_ago: i32,
+    ) -> i32 {
+        sqlx::query_scalar!(
+            r#"
+            INSERT INTO submissions (user_id, problem_id, lang, result, created_at, updated_at)
+            VALUES ($1, $2, 'cpp', $3::text::submission_result_enum,
+                    NOW() - make_interval(mins => $4), NOW() - make_interval(mins => $4))
+            RETURNING id
+            "#,
+            user_id,
+            problem_id,
+            result,
+            minutes_ago
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap()
+    }
+
+    /// like `seed_submission`, but made in the contest
+    pub async fn seed_contest_submission(
+        &self,
+        contest_id: i32,
+        user_id: i32,
+        problem_id: i32,
+        result: &str,
+        minutes_ago: i32,
+    ) -> i32 {
+        let submission_id = self
+            .seed_submission(user_id, problem_id, result, minutes_ago)
+            .await;
+        sqlx::query!(
+            "UPDATE submissions SET contest_id = $1 WHERE id = $2",
+            contest_id,
+            submission_id
+        )
+        .execute(&self.pool)
+        .await
+        .unwrap();
+        submission_id
+    }
+
+    /// submit the code to the problem outside of any contest
+    pub async fn submit(&self, token: &str, problem_id: i32, code: &str) -> i32 {
+        let response = self
+            .post(
+                &format!("/api/problems/{}/submissions", problem_id),
+                token,
+                json!({ "code": code, "lang": "cpp" }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        response.body["submissionId"].as_i64().unwrap() as i32
+    }
+
+    /// a request without a token, as a guest would send it
+    pub async fn post_as_guest(&self, uri: &str, body: Value) -> Response {
+        self.send(Method::POST, uri, None, Some(body)).await
+    }
+
+    /// the result of a submission once it's no longer pending, without a
+    /// judge connected that's `unknown_error`
+    pub async fn judged(&self, submission_id: i32) -> String {
+        for _ in 0..100 {
+            let result = sqlx::query_scalar!(
+                r#"SELECT result::text as "result!" FROM submissions WHERE id = $1"#,
+                submission_id
+            )
+            .fetch_one(&self.pool)
+            .await
+            .unwrap();
+            if result != "pending" {
+                return result;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("submission {} still pending", submission_id);
+    }
+
+    /// an active problem with one sample, which is also its only test case
+    pub async fn problem(&self, token: &str) -> i32 {
+        let n = self.serial.fetch_add(1, Ordering::Relaxed) + 1;
+        self.problem_named(token, &format!("problem {}", n)).await
+    }
+
+    pub async fn problem_named(&self, token: &str, name: &str) -> i32 {
+        let response = self
+            .post(
+                "/api/problems",
+                token,
+                json!({
+                    "name": name,
+                    "description": "add two numbers",
+                    "inputDescription": "two numbers",
+                    "outputDescription": "their sum",
+                    "samples": [{ "input": "1 2\n", "output": "3\n" }],
+                    "timeLimit": 1000,
+                    "memLimit": 256,
+                    "status": "Active",
+                }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let problem_id = response.body["problemId"].as_i64().unwrap() as i32;
+
+        let response = self
+            .post(
+                &format!("/api/problems/{}/test-cases", problem_id),
+                token,
+                json!({ "testCases": [{ "input": "1 2\n", "output": "3\n" }] }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        problem_id
+    }
+
+    /// a public contest of the problems, running from `begin` to `end` minutes
+    /// from now
+    pub async fn contest(&self, token: &str, problem_ids: &[i32], begin: i64, end: i64) -> i32 {
+        let n = self.serial.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = chrono::Utc::now();
+        let response = self
+            .post(
+                "/api/contests",
+                token,
+                json!({
+                    "name": format!("contest {}", n),
+                    "description": "a contest",
+                    "beginTime": now + chrono::Duration::minutes(begin),
+                    "endTime": now + chrono::Duration::minutes(end),
+                    "type": "Public",
+                    "problemIds": problem_ids,
+                }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        response.body["contestId"].as_i64().unwrap() as i32
+    }
+}
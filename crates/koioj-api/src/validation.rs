@@ -0,0 +1,124 @@
+use std::sync::LazyLock;
+
+use axum::http::StatusCode;
+use regex::Regex;
+
+use crate::error::{Error, ValidationError};
+
+static EMAIL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$").unwrap());
+
+pub(crate) fn is_valid_email(email: &str) -> bool {
+    EMAIL_REGEX.is_match(email)
+}
+
+fn is_all_digit(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_digit())
+}
+
+pub(crate) fn check_email(errors: &mut ValidationError, email: &str) {
+    if email.is_empty() {
+        errors.add("email", "email is required");
+    } else if !is_valid_email(email) {
+        errors.add("email", "invalid email");
+    }
+}
+
+pub(crate) fn check_phone(errors: &mut ValidationError, phone: &str) {
+    if phone.is_empty() {
+        errors.add("phone", "phone is required");
+    } else if !is_all_digit(phone) {
+        errors.add("phone", "invalid phone");
+    }
+}
+
+pub(crate) fn check_user_code(errors: &mut ValidationError, user_code: &str) {
+    if user_code.is_empty() {
+        errors.add("userCode", "user code is required");
+    } else if !is_all_digit(user_code) {
+        errors.add("userCode", "invalid user code");
+    }
+}
+
+pub(crate) fn check_username(errors: &mut ValidationError, username: &str) {
+    if username.is_empty() {
+        errors.add("username", "username is required");
+    }
+}
+
+/// Rejects user written text longer than `max` characters.
+pub(crate) fn check_max_length(errors: &mut ValidationError, field: &str, value: &str, max: usize) {
+    if value.chars().count() > max {
+        errors.add(field, format!("can have at most {} characters", max));
+    }
+}
+
+/// Maps an error from writing to `users`, naming the field whose value is already taken.
+pub(crate) fn user_write_error(e: sqlx::Error) -> Error {
+    if let sqlx::Error::Database(db_err) = &e {
+        if db_err.is_unique_violation() {
+            let (field, reason) = match db_err.constraint() {
+                Some("users_phone_key") => ("phone", "phone already exists"),
+                Some("users_email_key") => ("email", "email already exists"),
+                Some("users_username_key") => ("username", "username already exists"),
+                Some("users_user_code_key") => ("userCode", "user code already exists"),
+                _ => {
+                    return Error::msg("phone, email, username, or user_code already exists")
+                        .status_code(StatusCode::BAD_REQUEST);
+                }
+            };
+            return Error::invalid_field(field, reason);
+        }
+    }
+    Error::msg(format!("database error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(check: impl FnOnce(&mut ValidationError)) -> Vec<String> {
+        let mut errors = ValidationError::new();
+        check(&mut errors);
+        errors.0.into_iter().map(|e| e.reason).collect()
+    }
+
+    #[test]
+    fn email_format() {
+        assert!(is_valid_email("someone@example.com"));
+        assert!(is_valid_email("a.b+c@mail.example.org"));
+        assert!(!is_valid_email("someone"));
+        assert!(!is_valid_email("someone@example"));
+        assert!(!is_valid_email("some one@example.com"));
+
+        assert!(fields(|e| check_email(e, "someone@example.com")).is_empty());
+        assert_eq!(fields(|e| check_email(e, "")), ["email is required"]);
+        assert_eq!(fields(|e| check_email(e, "someone")), ["invalid email"]);
+    }
+
+    #[test]
+    fn digits_only_fields() {
+        assert!(fields(|e| check_phone(e, "13800000000")).is_empty());
+        assert_eq!(fields(|e| check_phone(e, "")), ["phone is required"]);
+        assert_eq!(fields(|e| check_phone(e, "+86 138")), ["invalid phone"]);
+
+        assert!(fields(|e| check_user_code(e, "202400000001")).is_empty());
+        assert_eq!(
+            fields(|e| check_user_code(e, "")),
+            ["user code is required"]
+        );
+        assert_eq!(
+            fields(|e| check_user_code(e, "2024a")),
+            ["invalid user code"]
+        );
+    }
+
+    #[test]
+    fn max_length_counts_characters() {
+        assert!(fields(|e| check_max_length(e, "name", "题目名", 3)).is_empty());
+        assert_eq!(
+            fields(|e| check_max_length(e, "name", "题目名称", 3)),
+            ["can have at most 3 characters"]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use koioj_common::judge::SubmissionResult;
+use reqwest::{Client, Url, header::CONTENT_TYPE, redirect};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{AppState, Result, config::PasswordResetHook, error::Error};
+
+/// tries per webhook before a notification is dropped
+const DELIVERY_ATTEMPTS: u32 = 5;
+/// wait before the first retry, doubled on each following one
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+/// for a single try, from connecting to reading the response
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// redirects aren't followed, the signed body goes only where it was configured to
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .user_agent(concat!("koioj/", env!("CARGO_PKG_VERSION")))
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(redirect::Policy::none())
+        .build()
+        .expect("webhook client uses no settings that can fail")
+});
+
+pub const SIGNATURE_HEADER: &str = "X-Koioj-Signature";
+pub const EVENT_HEADER: &str = "X-Koioj-Event";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionJudged {
+    pub submission_id: i32,
+    pub user_id: i32,
+    pub problem_id: i32,
+    pub contest_id: Option<i32>,
+    pub result: SubmissionResult,
+    pub time_consumption: i32,
+    pub mem_consumption: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// a reset token for the password reset hook to deliver to the account owner
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordResetRequested {
+    pub user_id: i32,
+    pub username: String,
+    pub email: String,
+    pub phone: String,
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Payload<'a, T> {
+    event: &'a str,
+    #[serde(flatten)]
+    data: T,
+}
+
+/// an http or https url a webhook can be sent to, without credentials and
+/// without whitespace or control characters the url parser would quietly drop
+pub fn parse_url(url: &str) -> Option<Url> {
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+    let url = Url::parse(url).ok()?;
+    let usable = matches!(url.scheme(), "http" | "https")
+        && url.host_str().is_some_and(|host| !host.is_empty())
+        && url.username().is_empty()
+        && url.password().is_none();
+    usable.then_some(url)
+}
+
+/// hex HMAC-SHA256 of the body, sent as `sha256=<signature>`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// notify every enabled webhook in the background, the judge isn't kept waiting
+pub fn submission_judged(state: &Arc<AppState>, event: SubmissionJudged) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = dispatch(&state, "submission.judged", event).await {
+            tracing::error!("Failed to dispatch webhooks: {:?}", e);
+        }
+    });
+}
+
+/// only the password reset hook gets these, never the webhooks, they carry a
+/// token to someone's account
+pub fn password_reset_requested(hook: &PasswordResetHook, event: PasswordResetRequested) {
+    let hook = hook.clone();
+    tokio::spawn(async move {
+        let event_name = "password_reset.requested";
+        let body = match serde_json::to_vec(&Payload {
+            event: event_name,
+            data: event,
+        }) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to serialize {}: {:?}", event_name, e);
+                return;
+            }
+        };
+        let target = "Password reset hook";
+        deliver(target, &hook.url, &hook.secret, event_name, &body).await;
+    });
+}
+
+async fn dispatch<T: Serialize>(state: &AppState, event: &str, data: T) -> Result<()> {
+    let webhooks = sqlx::query!(
+        r#"
+        SELECT id, url, secret FROM webhooks WHERE enabled
+        "#
+    )
+    .fetch_all(&state.pool)
+    .await?;
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+
+    let body: Arc<[u8]> = serde_json::to_vec(&Payload { event, data })?.into();
+    for webhook in webhooks {
+        let body = body.clone();
+        let event = event.to_string();
+        tokio::spawn(async move {
+            let target = format!("Webhook {}", webhook.id);
+            deliver(&target, &webhook.url, &webhook.secret, &event, &body).await;
+        });
+    }
+
+    Ok(())
+}
+
+/// `target` names the receiver in logs
+async fn deliver(target: &str, url: &str, secret: &str, event: &str, body: &[u8]) {
+    let headers = [
+        (EVENT_HEADER, event.to_string()),
+        (SIGNATURE_HEADER, format!("sha256={}", sign(secret, body))),
+    ];
+
+    for attempt in 0..DELIVERY_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+        }
+
+        match post(url, &headers, body).await {
+            Ok(status) if (200..300).contains(&status) => return,
+            Ok(status) => {
+                tracing::warn!("{} answered {} to {}", target, status, event);
+            }
+            Err(e) => {
+                tracing::warn!("{} failed to deliver {}: {:?}", target, event, e);
+            }
+        }
+    }
+
+    tracing::error!(
+        "Gave up delivering {} to {} after {} attempts",
+        event,
+        target,
+        DELIVERY_ATTEMPTS
+    );
+}
+
+/// POST the body, returning the status code of the response
+async fn post(url: &str, headers: &[(&str, String)], body: &[u8]) -> Result<u16> {
+    let url = parse_url(url).ok_or_else(|| Error::msg(format!("invalid webhook url {}", url)))?;
+
+    let mut request = CLIENT
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_vec());
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| Error::msg(format!("webhook request failed: {}", e)))?;
+
+    Ok(response.status().as_u16())
+}
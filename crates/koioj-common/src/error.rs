@@ -55,8 +55,59 @@ impl Error {
     pub fn status_code(self, code: StatusCode) -> Self {
         Self(code, self.1)
     }
+
+    pub fn invalid_field<F: Into<String>, R: Into<String>>(field: F, reason: R) -> Self {
+        let mut errors = ValidationError::new();
+        errors.add(field, reason);
+        errors.into_error()
+    }
 }
 
+/// a request field that failed validation, reported so that forms can highlight it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+/// collects every invalid field of a request before bailing out
+#[derive(Debug, Default)]
+pub struct ValidationError(pub Vec<FieldError>);
+
+impl ValidationError {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<F: Into<String>, R: Into<String>>(&mut self, field: F, reason: R) {
+        self.0.push(FieldError {
+            field: field.into(),
+            reason: reason.into(),
+        });
+    }
+
+    pub fn check(self) -> Result<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self.into_error())
+        }
+    }
+
+    fn into_error(self) -> Error {
+        Error(StatusCode::BAD_REQUEST, Some(anyhow::Error::new(self)))
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reasons: Vec<&str> = self.0.iter().map(|e| e.reason.as_str()).collect();
+        write!(f, "{}", reasons.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
 impl<E> From<E> for Error
 where
     E: std::error::Error + Send + Sync + 'static,
@@ -74,7 +125,13 @@ impl IntoResponse for Error {
         (
             self.0,
             self.1
-                .map(|it| json!({ "message": format!("{it}") }).to_string())
+                .map(|it| {
+                    let mut body = json!({ "message": format!("{it}") });
+                    if let Some(errors) = it.downcast_ref::<ValidationError>() {
+                        body["fields"] = json!(errors.0);
+                    }
+                    body.to_string()
+                })
                 .unwrap_or_default(),
         )
             .into_response()
@@ -84,6 +141,8 @@ impl IntoResponse for Error {
 #[derive(Serialize, ToSchema)]
 pub struct ErrorResponse {
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<FieldError>>,
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -1,6 +1,6 @@
 use core::fmt;
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 use utoipa::ToSchema;
 
 #[derive(
@@ -30,6 +30,33 @@ pub enum Language {
     ObjectiveC,
 }
 
+impl Language {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::Java => "java",
+            Language::Python => "py",
+            Language::Go => "go",
+            Language::Rust => "rs",
+            Language::JavaScript => "js",
+            Language::TypeScript => "ts",
+            Language::CSharp => "cs",
+            Language::Php => "php",
+            Language::Ruby => "rb",
+            Language::Swift => "swift",
+            Language::Kotlin => "kt",
+            Language::Scala => "scala",
+            Language::Haskell => "hs",
+            Language::Lua => "lua",
+            Language::Perl => "pl",
+            Language::R => "r",
+            Language::Dart => "dart",
+            Language::ObjectiveC => "m",
+        }
+    }
+}
+
 impl fmt::Display for Language {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = serde_plain::to_string(self).map_err(|_| fmt::Error)?;
@@ -50,6 +77,9 @@ pub struct JudgeInfo {
     pub timestamp: i64,
     pub signature: String,
     pub languages: Vec<Language>,
+    /// compiler or runtime version of each language, as configured on the judge
+    #[serde(default)]
+    pub language_versions: HashMap<Language, String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -66,6 +96,12 @@ pub enum ApiToJudgeMessage {
     JudgeTask(JudgeTask),
     #[serde(rename = "pong")]
     Pong,
+    /// the result or error of this submission is stored, the judge can forget it
+    #[serde(rename = "ack")]
+    Ack(i32),
+    /// the next batch of test cases of a task, empty if there are none left
+    #[serde(rename = "judge_task_chunk")]
+    JudgeTaskChunk(JudgeTaskChunk),
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -76,6 +112,30 @@ pub struct JudgeTask {
     pub time_limit: i32,   // ms
     pub memory_limit: i32, // MB
     pub test_cases: Vec<TestCase>,
+    /// run before `test_cases`; a failure here short-circuits judging unless
+    /// `continue_after_samples` is set
+    #[serde(default)]
+    pub samples: Vec<TestCaseData>,
+    /// judge the hidden tests even when a sample fails, the samples still count
+    /// towards the verdict
+    #[serde(default)]
+    pub continue_after_samples: bool,
+    /// send the program's stdout and stderr back with every test result
+    #[serde(default)]
+    pub keep_output: bool,
+    /// test cases held back by the api, the judge asks for them in batches with
+    /// `NextTestCases` once `test_cases` are done
+    #[serde(default)]
+    pub remaining_test_cases: u32,
+    /// record a diff for failing hidden tests too, not only for samples
+    #[serde(default)]
+    pub reveal_diff: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct JudgeTaskChunk {
+    pub submission_id: i32,
+    pub test_cases: Vec<TestCase>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -104,6 +164,12 @@ pub enum JudgeToApiMessage {
     Register(JudgeInfo),
     #[serde(rename = "error")]
     Error(i32, String),
+    /// the judge takes no more tasks and disconnects once the running ones finish
+    #[serde(rename = "shutting_down")]
+    ShuttingDown,
+    /// ask for the next batch of test cases of a submission
+    #[serde(rename = "next_test_cases")]
+    NextTestCases(i32),
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -113,6 +179,17 @@ pub struct JudgeResult {
     pub time_consumption: i32,   // ms
     pub memory_consumption: i32, // KB
     pub test_results: Vec<TestCaseResult>,
+    #[serde(default)]
+    pub failed_on_sample: bool,
+    /// why compiling failed, when it wasn't the compiler rejecting the code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compile_message: Option<String>,
+    /// the diff of the first failing sample, kept out of `sample_results`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_diff: Option<OutputDiff>,
+    /// one per sample that ran, `test_case_id` is the index of the sample
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sample_results: Vec<TestCaseResult>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -120,6 +197,9 @@ pub struct JudgeProgress {
     pub submission_id: i32,
     pub completed_tests: u32,
     pub total_tests: u32,
+    /// the test case that just finished
+    #[serde(default)]
+    pub test_result: Option<TestCaseResult>,
 }
 
 #[derive(PartialEq, Clone, Copy, Debug, sqlx::Type, Serialize, Deserialize, ToSchema)]
@@ -160,4 +240,40 @@ pub struct TestCaseResult {
     pub result: TestCaseJudgeResult,
     pub time_consumption: i32,
     pub memory_consumption: i32,
+    /// only filled in when the task asked for `keep_output`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_output: Option<String>,
+    /// how a crashed program ended, e.g. "signal 11 (SIGSEGV)"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// where the output went wrong, only on the first wrong answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<OutputDiff>,
+}
+
+/// the expected and actual output from the first differing line on, both
+/// truncated
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputDiff {
+    /// 1-based
+    pub line: u32,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_extension_of_languages() {
+        assert_eq!(Language::C.file_extension(), "c");
+        assert_eq!(Language::Cpp.file_extension(), "cpp");
+        assert_eq!(Language::Python.file_extension(), "py");
+        assert_eq!(Language::Rust.file_extension(), "rs");
+        assert_eq!(Language::ObjectiveC.file_extension(), "m");
+    }
 }
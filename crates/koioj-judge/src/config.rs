@@ -17,10 +17,58 @@ pub struct Config {
     #[serde(deserialize_with = "deserialize_log_level")]
     pub log_level: Level,
     pub private_key_path: String,
+    /// the `judgeToken` of the api
+    pub judge_token: String,
     pub judger_bin_path: PathBuf,
     pub rootfs_path: PathBuf,
     pub cgroup_base: PathBuf,
     pub languages: HashMap<Language, LanguageConfig>,
     pub rootfs_base: String,
     pub rootfs_install: Vec<String>,
+    #[serde(default)]
+    pub comparison_mode: ComparisonMode,
+    /// how long to wait for running tasks on shutdown, in seconds
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    /// wall-clock cap on a whole task in seconds, on top of `task_timeout_per_test`
+    /// for each of its tests
+    #[serde(default = "default_task_timeout")]
+    pub task_timeout: u64,
+    #[serde(default = "default_task_timeout_per_test")]
+    pub task_timeout_per_test: u64,
+    /// first delay before reconnecting in seconds, doubled on every failure in a row
+    #[serde(default = "default_reconnect_base_delay")]
+    pub reconnect_base_delay: u64,
+    #[serde(default = "default_reconnect_max_delay")]
+    pub reconnect_max_delay: u64,
+}
+
+fn default_shutdown_timeout() -> u64 {
+    300
+}
+
+fn default_task_timeout() -> u64 {
+    60
+}
+
+fn default_task_timeout_per_test() -> u64 {
+    10
+}
+
+fn default_reconnect_base_delay() -> u64 {
+    1
+}
+
+fn default_reconnect_max_delay() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ComparisonMode {
+    /// only trim leading and trailing whitespace of the whole output
+    Trim,
+    /// also normalize line endings and ignore trailing whitespace on each line
+    #[default]
+    Normalize,
 }
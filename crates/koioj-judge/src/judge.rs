@@ -1,29 +1,48 @@
-use crate::config::Config;
+use crate::config::{ComparisonMode, Config};
 use crate::judger::{FileInput, JudgerResult, run_judger_async};
+use crate::sandbox::LanguageConfig;
+use crate::websocket::Outbox;
 use futures::future::join_all;
 use koioj_common::judge::{
-    JudgeLoad, JudgeResult, JudgeToApiMessage, Language, SubmissionResult, TestCase,
-    TestCaseJudgeResult, TestCaseResult,
+    JudgeLoad, JudgeProgress, JudgeResult, JudgeTask, JudgeTaskChunk, JudgeToApiMessage,
+    OutputDiff, SubmissionResult, TestCase, TestCaseJudgeResult, TestCaseResult,
 };
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::vec;
 use sysinfo::System;
-use tokio::sync::{RwLock, Semaphore};
+use tokio::sync::{RwLock, Semaphore, oneshot, watch};
+
+/// longest expected or actual output snippet in a diff, in bytes
+const DIFF_SNIPPET_SIZE: usize = 256;
+
+/// tasks waiting for their next batch of test cases, by submission id
+type BatchWaiters = Arc<Mutex<HashMap<i32, oneshot::Sender<Vec<TestCase>>>>>;
 
 pub struct JudgeExecutor {
     config: Config,
     running_tasks: Arc<RwLock<u32>>,
     semaphore: Arc<Semaphore>,
+    /// accepted tasks whose result hasn't been sent yet, including the ones
+    /// still waiting for a permit
+    in_flight: Arc<watch::Sender<u32>>,
+    /// where results wait for the api to acknowledge them
+    outbox: Arc<Outbox>,
+    batch_waiters: BatchWaiters,
 
     system_info: Arc<RwLock<System>>,
     cached_load: Arc<RwLock<JudgeLoad>>,
 }
 impl JudgeExecutor {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config, outbox: Arc<Outbox>) -> Self {
         let executor = Self {
             config,
             running_tasks: Arc::new(RwLock::new(0)),
             semaphore: Arc::new(Semaphore::new(64)),
+            in_flight: Arc::new(watch::Sender::new(0)),
+            outbox,
+            batch_waiters: Arc::new(Mutex::new(HashMap::new())),
             system_info: Arc::new(RwLock::new(System::new_all())),
             cached_load: Arc::new(RwLock::new(JudgeLoad {
                 running_tasks: 0,
@@ -69,38 +88,83 @@ impl JudgeExecutor {
         self.cached_load.read().await.clone()
     }
 
-    pub async fn execute_task(
-        &mut self,
-        submission_id: i32,
-        lang: Language,
-        code: String,
-        time_limit: i32,
-        memory_limit: i32,
-        test_cases: Vec<TestCase>,
-        tx: tokio::sync::mpsc::UnboundedSender<JudgeToApiMessage>,
-    ) {
-        let permit = self.semaphore.clone().acquire_owned().await.unwrap();
+    pub fn in_flight(&self) -> u32 {
+        *self.in_flight.borrow()
+    }
 
+    /// Wait until every accepted task has sent its result, returns false if
+    /// `timeout` ran out first
+    pub async fn drain(&self, timeout: std::time::Duration) -> bool {
+        let mut in_flight = self.in_flight.subscribe();
+        tokio::time::timeout(timeout, in_flight.wait_for(|n| *n == 0))
+            .await
+            .is_ok()
+    }
+
+    /// hand a batch of test cases to the task that asked for it
+    pub fn deliver_test_cases(&self, chunk: JudgeTaskChunk) {
+        match self
+            .batch_waiters
+            .lock()
+            .unwrap()
+            .remove(&chunk.submission_id)
         {
-            let mut running = self.running_tasks.write().await;
-            *running += 1;
+            Some(waiter) => {
+                let _ = waiter.send(chunk.test_cases);
+            }
+            None => tracing::warn!(
+                "Received test cases nobody asked for, submission {}",
+                chunk.submission_id
+            ),
         }
+    }
 
+    pub fn execute_task(
+        &self,
+        task: JudgeTask,
+        tx: tokio::sync::mpsc::UnboundedSender<JudgeToApiMessage>,
+    ) {
+        self.in_flight.send_modify(|n| *n += 1);
+
+        let semaphore = self.semaphore.clone();
         let running_tasks = self.running_tasks.clone();
+        let in_flight = self.in_flight.clone();
+        let outbox = self.outbox.clone();
+        let batch_waiters = self.batch_waiters.clone();
         let config = self.config.clone();
 
         tokio::spawn(async move {
-            let result = judge_submission(
-                submission_id,
-                lang,
-                code,
-                time_limit,
-                memory_limit,
-                test_cases,
-                &config,
+            let permit = semaphore.acquire_owned().await.unwrap();
+
+            {
+                let mut running = running_tasks.write().await;
+                *running += 1;
+            }
+
+            let submission_id = task.submission_id;
+            let test_count = (task.test_cases.len() + task.samples.len()) as u64
+                + u64::from(task.remaining_test_cases);
+            let compile_time = config
+                .languages
+                .get(&task.lang)
+                .map_or(0, |l| l.compile_time_limit_ms.max(0) as u64 / 1000);
+            let timeout = std::time::Duration::from_secs(
+                config.task_timeout + compile_time + config.task_timeout_per_test * test_count,
+            );
+            // a wedged judger keeps its blocking thread, but at least the
+            // permit is freed and the submission doesn't stay pending
+            let result = tokio::time::timeout(
+                timeout,
+                judge_submission(task, &tx, &batch_waiters, &config),
             )
-            .await;
+            .await
+            .unwrap_or_else(|_| {
+                tracing::error!("Submission {} timed out after {:?}", submission_id, timeout);
+                JudgeToApiMessage::Error(submission_id, "judge timeout".to_string())
+            });
 
+            // held first, the connection may be gone by now
+            outbox.hold(&result);
             let _ = tx.send(result);
 
             {
@@ -109,19 +173,30 @@ impl JudgeExecutor {
             }
 
             drop(permit);
+            in_flight.send_modify(|n| *n -= 1);
         });
     }
 }
 
 async fn judge_submission(
-    submission_id: i32,
-    lang: Language,
-    code: String,
-    time_limit: i32,
-    memory_limit: i32,
-    test_cases: Vec<TestCase>,
+    task: JudgeTask,
+    progress: &tokio::sync::mpsc::UnboundedSender<JudgeToApiMessage>,
+    batch_waiters: &BatchWaiters,
     config: &Config,
 ) -> JudgeToApiMessage {
+    let JudgeTask {
+        submission_id,
+        lang,
+        code,
+        time_limit,
+        memory_limit,
+        test_cases,
+        samples,
+        continue_after_samples,
+        keep_output,
+        remaining_test_cases,
+        reveal_diff,
+    } = task;
     let lang_config = config.languages.get(&lang);
 
     let judger_bin_path = config.judger_bin_path.to_string_lossy().to_string();
@@ -129,6 +204,7 @@ async fn judge_submission(
     let cgroup_base = config.cgroup_base.to_string_lossy().to_string();
     let tmpfs_size = "256M";
     let pids_limit = 16;
+    let comparison_mode = config.comparison_mode;
 
     if lang_config.is_none() {
         return JudgeToApiMessage::Error(submission_id, format!("Unsupported language {:?}", lang));
@@ -145,8 +221,8 @@ async fn judge_submission(
             tmpfs_size,
             &cgroup_base,
             &format!("koioj_judge_{}_compile", submission_id),
-            5000,
-            512,
+            lang_config.compile_time_limit_ms,
+            lang_config.compile_memory_limit_mb,
             512 * 1024 * 1024,
             128,
             "",
@@ -175,12 +251,27 @@ async fn judge_submission(
                     res.verdict,
                     res.time
                 );
+                let compile_message = match res.verdict {
+                    crate::judger::Verdict::Tle => Some(format!(
+                        "compilation exceeded the time limit of {} ms",
+                        lang_config.compile_time_limit_ms
+                    )),
+                    crate::judger::Verdict::Mle => Some(format!(
+                        "compilation exceeded the memory limit of {} MB",
+                        lang_config.compile_memory_limit_mb
+                    )),
+                    _ => None,
+                };
                 return JudgeToApiMessage::JudgeResult(JudgeResult {
                     submission_id,
                     result: SubmissionResult::CompileError,
                     time_consumption: 0,
                     memory_consumption: 0,
                     test_results: vec![],
+                    failed_on_sample: false,
+                    compile_message,
+                    sample_diff: None,
+                    sample_results: vec![],
                 });
             }
         }
@@ -188,91 +279,275 @@ async fn judge_submission(
         compile_result = None;
     }
 
-    // test
-    let test_futures = test_cases.iter().map(|test_case| {
-        let run_cmd = lang_config.run.clone();
-        let compiled = lang_config.compiled.clone();
-        let input = test_case.data.input.clone();
-        let expected_output = test_case.data.output.clone();
+    let ctx = RunContext {
+        submission_id,
+        lang_config,
+        compile_result: compile_result.as_ref(),
+        judger_bin_path: &judger_bin_path,
+        rootfs_path: &rootfs_path,
+        cgroup_base: &cgroup_base,
+        tmpfs_size,
+        pids_limit,
+        time_limit,
+        memory_limit,
+        comparison_mode,
+        keep_output,
+        reveal_diff,
+        progress,
+        completed_tests: AtomicU32::new(0),
+        total_tests: test_cases.len() as u32 + remaining_test_cases,
+    };
+
+    // samples first, so that obviously wrong submissions skip the hidden tests
+    let mut sample_results = vec![];
+    let mut sample_diff = None;
+    if !samples.is_empty() {
+        let sample_cases: Vec<TestCase> = samples
+            .into_iter()
+            .enumerate()
+            .map(|(i, data)| TestCase { id: i as i32, data })
+            .collect();
+        sample_results = run_test_cases(&ctx, "sample", &sample_cases).await;
+        sample_diff = sample_results.iter_mut().find_map(|r| r.diff.take());
+        for sample_result in &mut sample_results {
+            sample_result.diff = None;
+        }
+        let sample_result = final_result(&sample_results);
+        if sample_result != SubmissionResult::Accepted && !continue_after_samples {
+            tracing::debug!(
+                "Submission {} failed on sample: {:?}",
+                submission_id,
+                sample_result
+            );
+            return JudgeToApiMessage::JudgeResult(JudgeResult {
+                submission_id,
+                result: sample_result,
+                time_consumption: sample_results.iter().map(|r| r.time_consumption).sum(),
+                memory_consumption: sample_results
+                    .iter()
+                    .map(|r| r.memory_consumption)
+                    .max()
+                    .unwrap_or(0),
+                failed_on_sample: true,
+                compile_message: None,
+                sample_diff,
+                sample_results,
+                test_results: vec![],
+            });
+        }
+    }
+
+    // test, batch by batch so that only one of them is held at a time
+    let mut test_results = run_test_cases(&ctx, "test", &test_cases).await;
+    drop(test_cases);
+    let mut remaining = remaining_test_cases;
+    while remaining > 0 {
+        let batch = match next_test_cases(submission_id, progress, batch_waiters).await {
+            Some(batch) if !batch.is_empty() && batch.len() as u32 <= remaining => batch,
+            _ => {
+                return JudgeToApiMessage::Error(
+                    submission_id,
+                    "failed to fetch the remaining test cases".to_string(),
+                );
+            }
+        };
+        remaining -= batch.len() as u32;
+        test_results.extend(run_test_cases(&ctx, "test", &batch).await);
+    }
+
+    // only the first failing test shows where it went wrong, samples come first
+    let first_diff = match sample_diff {
+        Some(_) => None,
+        None => test_results.iter().position(|r| r.diff.is_some()),
+    };
+    for (i, test_result) in test_results.iter_mut().enumerate() {
+        if Some(i) != first_diff {
+            test_result.diff = None;
+        }
+    }
+
+    // the samples all passed unless `continue_after_samples`, where they count
+    let final_result = final_result(&[sample_results.as_slice(), &test_results].concat());
+
+    let total_time = test_results.iter().map(|r| r.time_consumption).sum();
+    let max_memory = test_results
+        .iter()
+        .map(|r| r.memory_consumption)
+        .max()
+        .unwrap_or(0);
+
+    JudgeToApiMessage::JudgeResult(JudgeResult {
+        submission_id,
+        result: final_result,
+        time_consumption: total_time,
+        memory_consumption: max_memory,
+        test_results,
+        failed_on_sample: false,
+        compile_message: None,
+        sample_diff,
+        sample_results,
+    })
+}
+
+struct RunContext<'a> {
+    submission_id: i32,
+    lang_config: &'a LanguageConfig,
+    compile_result: Option<&'a JudgerResult>,
+    judger_bin_path: &'a str,
+    rootfs_path: &'a str,
+    cgroup_base: &'a str,
+    tmpfs_size: &'a str,
+    pids_limit: i32,
+    time_limit: i32,
+    memory_limit: i32,
+    comparison_mode: ComparisonMode,
+    keep_output: bool,
+    /// diff failing hidden tests too, samples always are
+    reveal_diff: bool,
+    progress: &'a tokio::sync::mpsc::UnboundedSender<JudgeToApiMessage>,
+    /// progress over all batches of hidden tests
+    completed_tests: AtomicU32,
+    total_tests: u32,
+}
+
+async fn next_test_cases(
+    submission_id: i32,
+    progress: &tokio::sync::mpsc::UnboundedSender<JudgeToApiMessage>,
+    batch_waiters: &BatchWaiters,
+) -> Option<Vec<TestCase>> {
+    let (tx, rx) = oneshot::channel();
+    batch_waiters.lock().unwrap().insert(submission_id, tx);
+    if progress
+        .send(JudgeToApiMessage::NextTestCases(submission_id))
+        .is_err()
+    {
+        batch_waiters.lock().unwrap().remove(&submission_id);
+        return None;
+    }
+    rx.await.ok()
+}
+
+async fn run_test_cases(
+    ctx: &RunContext<'_>,
+    kind: &str,
+    test_cases: &[TestCase],
+) -> Vec<TestCaseResult> {
+    let test_futures = test_cases.iter().map(|test_case| async move {
         let test_id = test_case.id;
-        let compile_result_ref = compile_result.as_ref();
-        let rootfs_path = rootfs_path.clone();
-        let judger_bin_path = judger_bin_path.clone();
-        let cgroup_base = cgroup_base.clone();
-        let submission_id = submission_id;
-
-        async move {
-            let input_files: Vec<FileInput> = match compile_result_ref {
-                Some(res) => match res.output_files.iter().find(|(name, _)| name == &compiled) {
-                    Some((_, content)) => vec![FileInput {
-                        filename: compiled.clone(),
-                        content: content.to_vec(),
-                        mode: 0o775,
-                    }],
-                    None => {
-                        return TestCaseResult {
-                            test_case_id: test_id,
-                            result: TestCaseJudgeResult::UnknownError,
-                            time_consumption: 0,
-                            memory_consumption: 0,
-                        };
-                    }
-                },
-                None => vec![],
-            };
-
-            let run_result = run_judger_async(
-                &judger_bin_path,
-                &rootfs_path,
-                tmpfs_size,
-                &cgroup_base,
-                &format!("koioj_judge_{}_test_{}", submission_id, test_id),
-                time_limit.into(),
-                memory_limit.into(),
-                32 * 1024,
-                pids_limit,
-                &input,
-                &run_cmd.iter().map(|s| s.as_str()).collect::<Vec<&str>>(),
-                &input_files,
-                &[],
-            )
-            .await;
+        let compiled = &ctx.lang_config.compiled;
 
-            match run_result {
-                Err(_) => TestCaseResult {
-                    test_case_id: test_id,
-                    result: TestCaseJudgeResult::UnknownError,
-                    time_consumption: 0,
-                    memory_consumption: 0,
-                },
-                Ok(res) => {
-                    let result = match res.verdict {
-                        crate::judger::Verdict::Ok => {
-                            if res.stdout.trim() == expected_output.trim() {
-                                TestCaseJudgeResult::Accepted
-                            } else {
-                                TestCaseJudgeResult::WrongAnswer
-                            }
-                        }
-                        crate::judger::Verdict::Tle => TestCaseJudgeResult::TimeLimitExceeded,
-                        crate::judger::Verdict::Mle => TestCaseJudgeResult::MemoryLimitExceeded,
-                        crate::judger::Verdict::Re => TestCaseJudgeResult::RuntimeError,
-                        _ => TestCaseJudgeResult::UnknownError,
-                    };
-                    TestCaseResult {
+        let input_files: Vec<FileInput> = match ctx.compile_result {
+            Some(res) => match res.output_files.iter().find(|(name, _)| name == compiled) {
+                Some((_, content)) => vec![FileInput {
+                    filename: compiled.clone(),
+                    content: content.to_vec(),
+                    mode: 0o775,
+                }],
+                None => {
+                    return TestCaseResult {
                         test_case_id: test_id,
-                        result,
-                        time_consumption: res.time,
-                        memory_consumption: res.memory as i32,
+                        result: TestCaseJudgeResult::UnknownError,
+                        time_consumption: 0,
+                        memory_consumption: 0,
+                        output: None,
+                        error_output: None,
+                        message: None,
+                        diff: None,
+                    };
+                }
+            },
+            None => vec![],
+        };
+
+        let run_result = run_judger_async(
+            ctx.judger_bin_path,
+            ctx.rootfs_path,
+            ctx.tmpfs_size,
+            ctx.cgroup_base,
+            &format!("koioj_judge_{}_{}_{}", ctx.submission_id, kind, test_id),
+            ctx.time_limit.into(),
+            ctx.memory_limit.into(),
+            32 * 1024,
+            ctx.pids_limit,
+            &test_case.data.input,
+            &ctx.lang_config
+                .run
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>(),
+            &input_files,
+            &[],
+        )
+        .await;
+
+        match run_result {
+            Err(_) => TestCaseResult {
+                test_case_id: test_id,
+                result: TestCaseJudgeResult::UnknownError,
+                time_consumption: 0,
+                memory_consumption: 0,
+                output: None,
+                error_output: None,
+                message: None,
+                diff: None,
+            },
+            Ok(res) => {
+                let result = match res.verdict {
+                    crate::judger::Verdict::Ok => {
+                        if outputs_match(&res.stdout, &test_case.data.output, ctx.comparison_mode) {
+                            TestCaseJudgeResult::Accepted
+                        } else {
+                            TestCaseJudgeResult::WrongAnswer
+                        }
                     }
+                    crate::judger::Verdict::Tle => TestCaseJudgeResult::TimeLimitExceeded,
+                    crate::judger::Verdict::Mle => TestCaseJudgeResult::MemoryLimitExceeded,
+                    crate::judger::Verdict::Re => TestCaseJudgeResult::RuntimeError,
+                    _ => TestCaseJudgeResult::UnknownError,
+                };
+                let message =
+                    (result == TestCaseJudgeResult::RuntimeError).then(|| res.exit_status());
+                let diff = shows_diff(&result, kind, ctx.reveal_diff)
+                    .then(|| output_diff(&res.stdout, &test_case.data.output));
+                TestCaseResult {
+                    test_case_id: test_id,
+                    result,
+                    time_consumption: res.time,
+                    memory_consumption: res.memory as i32,
+                    output: ctx.keep_output.then_some(res.stdout),
+                    error_output: ctx.keep_output.then_some(res.stderr),
+                    message,
+                    diff,
                 }
             }
         }
     });
 
-    let test_results: Vec<TestCaseResult> = join_all(test_futures).await;
+    // report each hidden test as it finishes, sample ids aren't real test case ids
+    let test_futures = test_futures.map(|test_future| async move {
+        let result = test_future.await;
+        if kind == "test" {
+            let completed_tests = ctx.completed_tests.fetch_add(1, Ordering::Relaxed) + 1;
+            let _ = ctx
+                .progress
+                .send(JudgeToApiMessage::JudgeProgress(JudgeProgress {
+                    submission_id: ctx.submission_id,
+                    completed_tests,
+                    total_tests: ctx.total_tests,
+                    test_result: Some(result.clone()),
+                }));
+        }
+        result
+    });
+
+    join_all(test_futures).await
+}
 
-    let final_result = if test_results
+fn final_result(test_results: &[TestCaseResult]) -> SubmissionResult {
+    // nothing ran, so nothing was accepted either
+    if test_results.is_empty() {
+        SubmissionResult::UnknownError
+    } else if test_results
         .iter()
         .all(|r| r.result == TestCaseJudgeResult::Accepted)
     {
@@ -294,20 +569,370 @@ async fn judge_submission(
         SubmissionResult::MemoryLimitExceeded
     } else {
         SubmissionResult::RuntimeError
-    };
+    }
+}
 
-    let total_time = test_results.iter().map(|r| r.time_consumption).sum();
-    let max_memory = test_results
-        .iter()
-        .map(|r| r.memory_consumption)
-        .max()
+fn outputs_match(actual: &str, expected: &str, mode: ComparisonMode) -> bool {
+    match mode {
+        ComparisonMode::Trim => actual.trim() == expected.trim(),
+        ComparisonMode::Normalize => normalize_output(actual) == normalize_output(expected),
+    }
+}
+
+/// `\r\n` -> `\n`, strip trailing whitespace per line, then trim the whole output
+fn normalize_output(output: &str) -> String {
+    output
+        .lines()
+        .map(|line| line.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// samples always show where a wrong answer went wrong, hidden tests only
+/// when the problem reveals it
+fn shows_diff(result: &TestCaseJudgeResult, kind: &str, reveal_diff: bool) -> bool {
+    *result == TestCaseJudgeResult::WrongAnswer && (kind == "sample" || reveal_diff)
+}
+
+/// where `actual` first departs from `expected`, line by line and ignoring
+/// trailing whitespace
+fn output_diff(actual: &str, expected: &str) -> OutputDiff {
+    let actual_lines: Vec<&str> = actual.trim().lines().collect();
+    let expected_lines: Vec<&str> = expected.trim().lines().collect();
+    let line = (0..actual_lines.len().max(expected_lines.len()))
+        .find(|&i| {
+            actual_lines.get(i).map(|l| l.trim_end()) != expected_lines.get(i).map(|l| l.trim_end())
+        })
         .unwrap_or(0);
 
-    JudgeToApiMessage::JudgeResult(JudgeResult {
-        submission_id,
-        result: final_result,
-        time_consumption: total_time,
-        memory_consumption: max_memory,
-        test_results,
-    })
+    OutputDiff {
+        line: line as u32 + 1,
+        expected: snippet(expected_lines.get(line..).unwrap_or_default()),
+        actual: snippet(actual_lines.get(line..).unwrap_or_default()),
+    }
+}
+
+/// the first `DIFF_SNIPPET_SIZE` bytes of `lines`, cut at a char boundary
+fn snippet(lines: &[&str]) -> String {
+    let mut snippet = String::new();
+    for line in lines {
+        if !snippet.is_empty() {
+            snippet.push('\n');
+        }
+        snippet.push_str(line);
+        if snippet.len() > DIFF_SNIPPET_SIZE {
+            let mut end = DIFF_SNIPPET_SIZE;
+            while !snippet.is_char_boundary(end) {
+                end -= 1;
+            }
+            snippet.truncate(end);
+            snippet.push_str("...");
+            break;
+        }
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, path::PathBuf, time::Duration};
+
+    use koioj_common::judge::{Language, TestCaseData};
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    /// a judger that takes `seconds` and then exits without answering
+    fn slow_judger(seconds: u32) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("koioj_judger_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, format!("#!/bin/sh\nsleep {}\n", seconds)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn test_config(judger: PathBuf) -> Config {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+judgeId: "test"
+apiUrl: "ws://127.0.0.1:0"
+logFile: "judge.log"
+logLevel: "Debug"
+privateKeyPath: "judge.pem"
+judgeToken: "test-judge-token"
+judgerBinPath: "judger"
+rootfsPath: "rootfs"
+cgroupBase: "koioj"
+languages:
+  python:
+    source: "main.py"
+    compiled: "main.py"
+    run: ["python3", "main.py"]
+rootfsBase: ""
+rootfsInstall: []
+"#,
+        )
+        .unwrap();
+        config.judger_bin_path = judger;
+        config
+    }
+
+    /// hands the executor a python task with one test, returns what it sends
+    fn execute(
+        executor: &JudgeExecutor,
+        submission_id: i32,
+    ) -> mpsc::UnboundedReceiver<JudgeToApiMessage> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let test_case = TestCase {
+            id: 1,
+            data: TestCaseData {
+                input: String::new(),
+                output: "1\n".to_string(),
+            },
+        };
+        let task = JudgeTask {
+            submission_id,
+            lang: Language::Python,
+            code: "print(1)".to_string(),
+            time_limit: 1000,
+            memory_limit: 256,
+            test_cases: vec![test_case],
+            samples: Vec::new(),
+            continue_after_samples: false,
+            keep_output: false,
+            remaining_test_cases: 0,
+            reveal_diff: false,
+        };
+        executor.execute_task(task, tx);
+        rx
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_the_running_task() {
+        let executor = JudgeExecutor::new(test_config(slow_judger(1)), Default::default());
+        let mut messages = execute(&executor, 1);
+        assert_eq!(executor.in_flight(), 1);
+
+        assert!(!executor.drain(Duration::from_millis(100)).await);
+        assert!(executor.drain(Duration::from_secs(10)).await);
+        assert_eq!(executor.in_flight(), 0);
+
+        // the outcome went out before draining finished
+        let mut last = None;
+        while let Ok(msg) = messages.try_recv() {
+            last = Some(msg);
+        }
+        assert!(matches!(
+            last,
+            Some(JudgeToApiMessage::JudgeResult(JudgeResult {
+                submission_id: 1,
+                ..
+            })) | Some(JudgeToApiMessage::Error(1, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn wedged_judger_times_out_and_frees_the_permit() {
+        let mut config = test_config(slow_judger(5));
+        config.task_timeout = 1;
+        config.task_timeout_per_test = 0;
+        config
+            .languages
+            .get_mut(&Language::Python)
+            .unwrap()
+            .compile_time_limit_ms = 0;
+        let executor = JudgeExecutor::new(config, Default::default());
+        let mut messages = execute(&executor, 1);
+
+        let started = std::time::Instant::now();
+        let msg = tokio::time::timeout(Duration::from_secs(10), messages.recv())
+            .await
+            .unwrap();
+        assert!(matches!(
+            msg,
+            Some(JudgeToApiMessage::Error(1, ref reason)) if reason == "judge timeout"
+        ));
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(executor.drain(Duration::from_secs(1)).await);
+        assert_eq!(executor.semaphore.available_permits(), 64);
+    }
+
+    #[tokio::test]
+    async fn compile_runs_under_the_configured_limits() {
+        let judger = crate::judger::tests::answering_judger(crate::judger::Verdict::Tle, 0, 9);
+        let mut config = test_config(judger.clone());
+        let python: LanguageConfig = serde_yaml::from_str(
+            r#"
+source: "main.py"
+compile: ["python3", "-m", "py_compile", "main.py"]
+compiled: "main.py"
+run: ["python3", "main.py"]
+compileTimeLimitMs: 30000
+compileMemoryLimitMb: 2048
+"#,
+        )
+        .unwrap();
+        config.languages.insert(Language::Python, python);
+        let executor = JudgeExecutor::new(config, Default::default());
+        let mut messages = execute(&executor, 1);
+
+        let msg = tokio::time::timeout(Duration::from_secs(10), messages.recv())
+            .await
+            .unwrap();
+        let Some(JudgeToApiMessage::JudgeResult(result)) = msg else {
+            panic!("expected a result, got {:?}", msg);
+        };
+        assert_eq!(result.result, SubmissionResult::CompileError);
+        assert_eq!(
+            result.compile_message.as_deref(),
+            Some("compilation exceeded the time limit of 30000 ms")
+        );
+
+        // the request opens with the time and memory limits
+        let request = std::fs::read(judger.with_extension("in")).unwrap();
+        let time_limit = i32::from_le_bytes(request[0..4].try_into().unwrap());
+        let memory_limit = i64::from_le_bytes(request[4..12].try_into().unwrap());
+        assert_eq!((time_limit, memory_limit), (30000, 2048));
+    }
+
+    #[test]
+    fn normalize_output_drops_carriage_returns_and_trailing_spaces() {
+        assert_eq!(normalize_output("1 2 \r\n3\t\r\n\r\n"), "1 2\n3");
+        assert_eq!(normalize_output("  a\n b  \n"), "a\n b");
+    }
+
+    #[test]
+    fn normalize_mode_ignores_line_endings_and_trailing_spaces() {
+        let mode = ComparisonMode::Normalize;
+        assert!(outputs_match("1\r\n2\r\n", "1\n2\n", mode));
+        assert!(outputs_match("1 \n2  \n", "1\n2", mode));
+        assert!(!outputs_match("1 2\n", "12\n", mode));
+        assert!(!outputs_match("1\n2\n", "2\n1\n", mode));
+    }
+
+    #[test]
+    fn trim_mode_only_trims_the_ends() {
+        let mode = ComparisonMode::Trim;
+        assert!(outputs_match("\n1\n2\n\n", "1\n2", mode));
+        assert!(!outputs_match("1\r\n2\r\n", "1\n2\n", mode));
+        assert!(!outputs_match("1 \n2", "1\n2", mode));
+    }
+
+    #[test]
+    fn wrong_answer_on_sample_has_diff() {
+        assert!(shows_diff(
+            &TestCaseJudgeResult::WrongAnswer,
+            "sample",
+            false
+        ));
+    }
+
+    #[test]
+    fn wrong_answer_on_hidden_test_has_no_diff() {
+        assert!(!shows_diff(
+            &TestCaseJudgeResult::WrongAnswer,
+            "test",
+            false
+        ));
+        assert!(shows_diff(&TestCaseJudgeResult::WrongAnswer, "test", true));
+    }
+
+    #[test]
+    fn other_verdicts_have_no_diff() {
+        assert!(!shows_diff(&TestCaseJudgeResult::Accepted, "sample", true));
+        assert!(!shows_diff(
+            &TestCaseJudgeResult::RuntimeError,
+            "sample",
+            true
+        ));
+    }
+
+    #[test]
+    fn no_test_results_are_not_accepted() {
+        assert_eq!(final_result(&[]), SubmissionResult::UnknownError);
+
+        let accepted = TestCaseResult {
+            test_case_id: 1,
+            result: TestCaseJudgeResult::Accepted,
+            time_consumption: 10,
+            memory_consumption: 1024,
+            output: None,
+            error_output: None,
+            message: None,
+            diff: None,
+        };
+        assert_eq!(final_result(&[accepted]), SubmissionResult::Accepted);
+    }
+
+    /// judges three python tests, at most `batch_size` of them handed over at a
+    /// time, and returns the verdicts by test case
+    async fn judge_in_batches(
+        batch_size: usize,
+    ) -> (SubmissionResult, Vec<(i32, TestCaseJudgeResult)>) {
+        let judger = crate::judger::tests::answering_judger(crate::judger::Verdict::Ok, 0, 0);
+        let executor = JudgeExecutor::new(test_config(judger), Default::default());
+        let mut test_cases: Vec<TestCase> = ["", "1\n", ""]
+            .iter()
+            .enumerate()
+            .map(|(i, output)| TestCase {
+                id: i as i32 + 1,
+                data: TestCaseData {
+                    input: String::new(),
+                    output: output.to_string(),
+                },
+            })
+            .collect();
+        let rest = test_cases.split_off(batch_size);
+
+        let (tx, mut messages) = mpsc::unbounded_channel();
+        let task = JudgeTask {
+            submission_id: 1,
+            lang: Language::Python,
+            code: "print()".to_string(),
+            time_limit: 1000,
+            memory_limit: 256,
+            test_cases,
+            samples: Vec::new(),
+            continue_after_samples: false,
+            keep_output: false,
+            remaining_test_cases: rest.len() as u32,
+            reveal_diff: false,
+        };
+        executor.execute_task(task, tx);
+
+        let mut rest = rest.into_iter();
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(10), messages.recv())
+                .await
+                .unwrap();
+            match msg {
+                Some(JudgeToApiMessage::NextTestCases(submission_id)) => {
+                    executor.deliver_test_cases(JudgeTaskChunk {
+                        submission_id,
+                        test_cases: rest.by_ref().take(batch_size).collect(),
+                    });
+                }
+                Some(JudgeToApiMessage::JudgeResult(result)) => {
+                    let mut verdicts: Vec<_> = result
+                        .test_results
+                        .into_iter()
+                        .map(|t| (t.test_case_id, t.result))
+                        .collect();
+                    verdicts.sort_by_key(|(id, _)| *id);
+                    return (result.result, verdicts);
+                }
+                Some(JudgeToApiMessage::JudgeProgress(_)) => {}
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn batched_test_cases_judge_like_a_single_batch() {
+        let single = judge_in_batches(3).await;
+        assert_eq!(single.0, SubmissionResult::WrongAnswer);
+        assert_eq!(single.1.len(), 3);
+        assert_eq!(judge_in_batches(1).await, single);
+    }
 }
@@ -61,6 +61,8 @@ struct JudgeResult {
   int verdict;
   int time;         // ms
   long long memory; // KB -> MB later
+  int exit_code;    // of the program, 0 if killed
+  int signal;       // that killed the program, 0 if exited
   std::string stdout_content;
   std::string stderr_content;
   std::vector<FileInfo> output_files;
@@ -183,12 +185,14 @@ struct RunContext {
   JudgeConfig *cfg;
   int child_pipe[2]; // barrier
   int result_pipe[2];
+  int status_pipe[2]; // exit code and signal of the program
   std::string sandbox_root;
 };
 
 int sandbox_executor(RunContext *ctx) {
   close(ctx->result_pipe[0]);
   close(ctx->result_pipe[1]);
+  close(ctx->status_pipe[0]);
 
   // mount proc first
   if (mount("proc", "/proc", "proc", 0, nullptr))
@@ -261,6 +265,9 @@ int sandbox_executor(RunContext *ctx) {
     if (ret == -1)
       return 1;
     // process exited
+    int codes[2] = {WIFEXITED(status) ? WEXITSTATUS(status) : 0,
+                    WIFSIGNALED(status) ? WTERMSIG(status) : 0};
+    write(ctx->status_pipe[1], codes, sizeof(codes));
     return (WIFEXITED(status) ? (WEXITSTATUS(status) == 0 ? 0 : 1) : 3);
   }
 
@@ -316,6 +323,8 @@ int container_init(void *arg) {
   close(ctx->child_pipe[0]);
   if (pipe2(ctx->child_pipe, O_CLOEXEC) == -1)
     return 1;
+  if (pipe2(ctx->status_pipe, O_CLOEXEC) == -1)
+    return 1;
 
   // restrict resource
   try {
@@ -356,6 +365,14 @@ int container_init(void *arg) {
   JudgeResult res;
   res.verdict = VERDICT_UKE;
 
+  close(ctx->status_pipe[1]);
+  int codes[2] = {0, 0};
+  if (read(ctx->status_pipe[0], codes, sizeof(codes)) != sizeof(codes))
+    codes[0] = codes[1] = 0;
+  close(ctx->status_pipe[0]);
+  res.exit_code = codes[0];
+  res.signal = codes[1];
+
   int exit_code = 255;
   if (WIFEXITED(status))
     exit_code = WEXITSTATUS(status);
@@ -402,6 +419,8 @@ int container_init(void *arg) {
   write_full(ctx->result_pipe[1], &res.verdict, sizeof(int));
   write_full(ctx->result_pipe[1], &res.time, sizeof(int));
   write_full(ctx->result_pipe[1], &res.memory, sizeof(long long));
+  write_full(ctx->result_pipe[1], &res.exit_code, sizeof(int));
+  write_full(ctx->result_pipe[1], &res.signal, sizeof(int));
   write_proto_str(ctx->result_pipe[1], res.stdout_content);
   write_proto_str(ctx->result_pipe[1], res.stderr_content);
 
@@ -494,12 +513,14 @@ int main() {
 
     close(ctx.result_pipe[1]);
 
-    int verdict, time, file_cnt;
+    int verdict, time, exit_code, sig, file_cnt;
     long long memory;
 
     read_full(ctx.result_pipe[0], &verdict, sizeof(int));
     read_full(ctx.result_pipe[0], &time, sizeof(int));
     read_full(ctx.result_pipe[0], &memory, sizeof(long long));
+    read_full(ctx.result_pipe[0], &exit_code, sizeof(int));
+    read_full(ctx.result_pipe[0], &sig, sizeof(int));
     std::string stdout_str = read_proto_str(ctx.result_pipe[0]);
     std::string stderr_str = read_proto_str(ctx.result_pipe[0]);
 
@@ -520,6 +541,8 @@ int main() {
     write_full(1, &verdict, sizeof(int));
     write_full(1, &time, sizeof(int));
     write_full(1, &memory, sizeof(long long));
+    write_full(1, &exit_code, sizeof(int));
+    write_full(1, &sig, sizeof(int));
     write_proto_str(1, stdout_str);
     write_proto_str(1, stderr_str);
     write_full(1, &file_cnt, sizeof(int));
@@ -538,6 +561,8 @@ int main() {
     write_full(1, &v, sizeof(int));
     write_full(1, &t, sizeof(int));
     write_full(1, &m, sizeof(long long));
+    write_full(1, &t, sizeof(int)); // exit code
+    write_full(1, &t, sizeof(int)); // signal
     write_proto_str(1, "");
     write_proto_str(1, msg); // Stderr
     int zero = 0;
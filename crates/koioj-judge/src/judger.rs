@@ -27,11 +27,14 @@ impl From<i32> for Verdict {
 }
 
 #[derive(Debug, Clone)]
-#[allow(dead_code)] // stderr unused
 pub struct JudgerResult {
     pub verdict: Verdict,
     pub time: i32,
     pub memory: i64,
+    /// of the program, 0 if it was killed
+    pub exit_code: i32,
+    /// that killed the program, 0 if it exited
+    pub signal: i32,
     pub stdout: String,
     pub stderr: String,
     pub output_files: Vec<(String, Vec<u8>)>,
@@ -169,6 +172,8 @@ pub fn run_judger(
     let verdict = Verdict::from(read_i32(&mut cursor)?);
     let time = read_i32(&mut cursor)?;
     let memory = read_i64(&mut cursor)?;
+    let exit_code = read_i32(&mut cursor)?;
+    let signal = read_i32(&mut cursor)?;
     let stdout = read_string(&mut cursor)?;
     let stderr = read_string(&mut cursor)?;
 
@@ -184,12 +189,47 @@ pub fn run_judger(
         verdict,
         time,
         memory,
+        exit_code,
+        signal,
         stdout,
         stderr,
         output_files,
     })
 }
 
+fn signal_name(signal: i32) -> Option<&'static str> {
+    Some(match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        24 => "SIGXCPU",
+        25 => "SIGXFSZ",
+        31 => "SIGSYS",
+        _ => return None,
+    })
+}
+
+impl JudgerResult {
+    /// short description of how the program ended, e.g. "signal 11 (SIGSEGV)"
+    pub fn exit_status(&self) -> String {
+        match (self.signal, signal_name(self.signal)) {
+            (0, _) => format!("exit code {}", self.exit_code),
+            (signal, Some(name)) => format!("signal {} ({})", signal, name),
+            (signal, None) => format!("signal {}", signal),
+        }
+    }
+}
+
 pub async fn run_judger_async(
     judger_bin_path: &str,
     rootfs: &str,
@@ -237,3 +277,67 @@ pub async fn run_judger_async(
     })
     .await?
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use super::*;
+
+    /// a judger that answers every run with `verdict`, ended in `exit_code` or
+    /// `signal`; the last request it got is kept next to it with an `in`
+    /// extension
+    pub(crate) fn answering_judger(verdict: Verdict, exit_code: i32, signal: i32) -> PathBuf {
+        let mut answer = Vec::new();
+        write_i32(&mut answer, verdict as i32).unwrap();
+        write_i32(&mut answer, 10).unwrap();
+        write_i64(&mut answer, 1024).unwrap();
+        write_i32(&mut answer, exit_code).unwrap();
+        write_i32(&mut answer, signal).unwrap();
+        write_str(&mut answer, "").unwrap();
+        write_str(&mut answer, "").unwrap();
+        write_i32(&mut answer, 0).unwrap();
+
+        let base = std::env::temp_dir().join(format!("koioj_judger_{}", uuid::Uuid::new_v4()));
+        let answer_path = base.with_extension("out");
+        std::fs::write(&answer_path, answer).unwrap();
+        let script = format!(
+            "#!/bin/sh\ncat > {}\ncat {}\n",
+            base.with_extension("in").display(),
+            answer_path.display()
+        );
+        std::fs::write(&base, script).unwrap();
+        std::fs::set_permissions(&base, std::fs::Permissions::from_mode(0o755)).unwrap();
+        base
+    }
+
+    fn run(judger: PathBuf) -> JudgerResult {
+        run_judger(
+            judger.to_str().unwrap(),
+            "rootfs",
+            "64m",
+            "koioj",
+            "test",
+            1000,
+            256,
+            64,
+            16,
+            "",
+            &["./main"],
+            &[],
+            &[],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn segfault_and_nonzero_exit_read_differently() {
+        let segfault = run(answering_judger(Verdict::Re, 0, 11));
+        assert_eq!(segfault.verdict, Verdict::Re);
+        assert_eq!(segfault.exit_status(), "signal 11 (SIGSEGV)");
+
+        let nonzero = run(answering_judger(Verdict::Re, 3, 0));
+        assert_eq!(nonzero.verdict, Verdict::Re);
+        assert_eq!(nonzero.exit_status(), "exit code 3");
+    }
+}
@@ -11,12 +11,19 @@ use std::process::Command;
 use crate::config::Config;
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct LanguageConfig {
     pub install: Option<Vec<String>>,
     pub source: String,
     pub compile: Option<Vec<String>>,
     pub compiled: String,
     pub run: Vec<String>,
+    /// shown to users, e.g. "gcc 14.2.0"
+    pub version: Option<String>,
+    #[serde(default = "default_compile_time_limit_ms")]
+    pub compile_time_limit_ms: i32,
+    #[serde(default = "default_compile_memory_limit_mb")]
+    pub compile_memory_limit_mb: i64,
 }
 
 const CHROOT_PATH: &str = "/sbin:/bin:/usr/sbin:/usr/bin:/usr/local/sbin:/usr/local/bin";
@@ -99,3 +106,11 @@ pub fn install_sandbox(config: &Config) -> Result<()> {
     tracing::info!("Sandbox installation completed successfully!");
     Ok(())
 }
+
+fn default_compile_time_limit_ms() -> i32 {
+    5000
+}
+
+fn default_compile_memory_limit_mb() -> i64 {
+    512
+}
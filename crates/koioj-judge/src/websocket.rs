@@ -2,12 +2,69 @@ use crate::{config::Config, judge::JudgeExecutor};
 use futures::{SinkExt, StreamExt};
 use koioj_common::error::{Context, Result};
 use koioj_common::judge::{ApiToJudgeMessage, JudgeInfo, JudgeTask, JudgeToApiMessage};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use rand::Rng;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{RwLock, watch};
 use tokio_tungstenite::{
-    connect_async_with_config, tungstenite::Message, tungstenite::protocol::WebSocketConfig,
+    connect_async_with_config,
+    tungstenite::{
+        Message, client::IntoClientRequest, http::HeaderValue, protocol::WebSocketConfig,
+    },
 };
 
+/// unacknowledged results are sent again after this long
+const RESEND_AFTER: Duration = Duration::from_secs(30);
+
+/// results the api hasn't acknowledged yet, kept across reconnects and resent
+/// until it does
+#[derive(Default)]
+pub struct Outbox {
+    pending: Mutex<BTreeMap<i32, (JudgeToApiMessage, Instant)>>,
+}
+
+impl Outbox {
+    /// keep a result or error, anything else isn't acknowledged
+    pub fn hold(&self, msg: &JudgeToApiMessage) {
+        let submission_id = match msg {
+            JudgeToApiMessage::JudgeResult(result) => result.submission_id,
+            JudgeToApiMessage::Error(submission_id, _) => *submission_id,
+            _ => return,
+        };
+        // runs (negative ids) only live as long as the request waiting for
+        // them, and a restarted api hands the same ids out again
+        if submission_id < 0 {
+            return;
+        }
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(submission_id, (msg.clone(), Instant::now()));
+    }
+
+    fn ack(&self, submission_id: i32) {
+        self.pending.lock().unwrap().remove(&submission_id);
+    }
+
+    /// the messages last sent at least `age` ago, which count as sent again
+    fn due(&self, age: Duration) -> Vec<JudgeToApiMessage> {
+        let now = Instant::now();
+        self.pending
+            .lock()
+            .unwrap()
+            .values_mut()
+            .filter(|(_, sent)| now.duration_since(*sent) >= age)
+            .map(|(msg, sent)| {
+                *sent = now;
+                msg.clone()
+            })
+            .collect()
+    }
+}
+
 pub async fn run(config: Config) -> Result<()> {
     let ws_url = config
         .api_url
@@ -15,29 +72,106 @@ pub async fn run(config: Config) -> Result<()> {
         .replace("https://", "wss://");
     let ws_url = format!("{}/api/judge/ws", ws_url);
 
+    let (shutdown_tx, mut shutdown) = watch::channel(false);
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        tracing::info!("Shutdown requested");
+        let _ = shutdown_tx.send(true);
+    });
+
+    let outbox = Arc::new(Outbox::default());
+    let mut failures = 0;
     loop {
         tracing::info!("Connecting to {}", ws_url);
 
-        match connect_and_handle(&ws_url, &config).await {
+        match connect_and_handle(&ws_url, &config, &outbox, shutdown.clone()).await {
             Ok(_) => {
                 tracing::info!("Connection closed normally");
+                failures = 0;
             }
             Err(e) => {
                 tracing::error!("Connection error: {:?}", e);
+                failures += 1;
+            }
+        }
+
+        if *shutdown.borrow() {
+            break;
+        }
+
+        let delay = reconnect_delay(
+            failures,
+            config.reconnect_base_delay,
+            config.reconnect_max_delay,
+        );
+        tracing::info!("Reconnecting in {:?}...", delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.changed() => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// exponential backoff from `base` up to `max` seconds
+fn backoff_secs(failures: u32, base: u64, max: u64) -> u64 {
+    base.saturating_mul(1u64.checked_shl(failures).unwrap_or(u64::MAX))
+        .min(max)
+}
+
+/// the backoff with its upper half randomized, so that judges dropped at the
+/// same time don't all come back at once
+fn reconnect_delay(failures: u32, base: u64, max: u64) -> std::time::Duration {
+    let millis = backoff_secs(failures, base, max).saturating_mul(1000);
+    let jitter = rand::rng().random_range(0..=millis / 2);
+    std::time::Duration::from_millis(millis - millis / 2 + jitter)
+}
+
+async fn wait_for_signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = ctrl_c => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {:?}", e);
+                let _ = ctrl_c.await;
             }
         }
+    }
 
-        tracing::info!("Reconnecting in 5 seconds...");
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+    #[cfg(not(unix))]
+    {
+        let _ = ctrl_c.await;
     }
 }
 
-async fn connect_and_handle(url: &str, config: &Config) -> Result<()> {
+async fn connect_and_handle(
+    url: &str,
+    config: &Config,
+    outbox: &Arc<Outbox>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
     let mut ws_config = WebSocketConfig::default();
     ws_config.max_message_size = Some(1024 * 1024 * 1024);
     ws_config.max_frame_size = Some(1024 * 1024 * 1024);
 
-    let (ws_stream, _) = connect_async_with_config(url, Some(ws_config), false)
+    let mut request = url.into_client_request().context("Invalid WebSocket url")?;
+    request.headers_mut().insert(
+        "Authorization",
+        HeaderValue::from_str(&format!("Bearer {}", config.judge_token))
+            .context("Invalid judge token")?,
+    );
+
+    let (ws_stream, _) = connect_async_with_config(request, Some(ws_config), false)
         .await
         .context("Failed to connect to WebSocket")?;
 
@@ -45,7 +179,10 @@ async fn connect_and_handle(url: &str, config: &Config) -> Result<()> {
 
     let (mut write, mut read) = ws_stream.split();
 
-    let executor = Arc::new(RwLock::new(JudgeExecutor::new(config.clone())));
+    let executor = Arc::new(RwLock::new(JudgeExecutor::new(
+        config.clone(),
+        outbox.clone(),
+    )));
 
     let private_key = koioj_common::auth::load_private_key(&config.private_key_path)
         .context("Failed to load private key")?;
@@ -61,6 +198,11 @@ async fn connect_and_handle(url: &str, config: &Config) -> Result<()> {
         timestamp,
         signature,
         languages: config.languages.keys().copied().collect(),
+        language_versions: config
+            .languages
+            .iter()
+            .filter_map(|(lang, lang_config)| Some((*lang, lang_config.version.clone()?)))
+            .collect(),
     });
 
     // send register
@@ -71,20 +213,27 @@ async fn connect_and_handle(url: &str, config: &Config) -> Result<()> {
 
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<JudgeToApiMessage>();
 
-    // send
-    let send_task = tokio::spawn(async move {
+    // send, until every sender is gone
+    let mut send_task = tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
             if let Ok(json) = serde_json::to_string(&msg) {
                 if write.send(Message::Text(json.into())).await.is_err() {
-                    break;
+                    return;
                 }
             }
         }
+        let _ = write.close().await;
     });
 
+    // results that may not have made it over the previous connection
+    for msg in outbox.due(Duration::ZERO) {
+        let _ = tx.send(msg);
+    }
+
     // heartbeat
     let tx_clone = tx.clone();
     let executor_clone = executor.clone();
+    let outbox_clone = outbox.clone();
     let heartbeat_send_task = tokio::spawn(async move {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(8));
 
@@ -97,33 +246,82 @@ async fn connect_and_handle(url: &str, config: &Config) -> Result<()> {
             };
 
             let _ = tx_clone.send(JudgeToApiMessage::Ping(load));
+
+            for msg in outbox_clone.due(RESEND_AFTER) {
+                let _ = tx_clone.send(msg);
+            }
         }
     });
 
     // recv
-    let executor_clone = executor.clone();
-    let tx_clone = tx.clone();
-    while let Some(msg) = read.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                if let Err(e) = handle_message(&text, &executor_clone, &tx_clone).await {
-                    tracing::error!("Failed to handle message: {:?}", e);
+    let mut drain: Option<tokio::task::JoinHandle<bool>> = None;
+    loop {
+        tokio::select! {
+            msg = read.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        let accepting = drain.is_none();
+                        if let Err(e) =
+                            handle_message(&text, &executor, outbox, &tx, accepting).await
+                        {
+                            tracing::error!("Failed to handle message: {:?}", e);
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) => {
+                        tracing::info!("Received close message");
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        tracing::error!("WebSocket error: {:?}", e);
+                        break;
+                    }
+                    None => break,
+                    _ => {}
                 }
             }
-            Ok(Message::Close(_)) => {
-                tracing::info!("Received close message");
-                break;
+            _ = shutdown.changed(), if drain.is_none() => {
+                // keep the connection so that running tasks can still report
+                tracing::info!(
+                    "Shutting down, waiting for {} tasks",
+                    executor.read().await.in_flight()
+                );
+                let _ = tx.send(JudgeToApiMessage::ShuttingDown);
+                let executor = executor.clone();
+                let timeout = std::time::Duration::from_secs(config.shutdown_timeout);
+                drain = Some(tokio::spawn(async move {
+                    executor.read().await.drain(timeout).await
+                }));
             }
-            Err(e) => {
-                tracing::error!("WebSocket error: {:?}", e);
+            drained = async { drain.as_mut().unwrap().await }, if drain.is_some() => {
+                if drained.unwrap_or(false) {
+                    tracing::info!("All tasks finished");
+                } else {
+                    tracing::warn!(
+                        "Shutdown timeout, abandoning {} tasks",
+                        executor.read().await.in_flight()
+                    );
+                }
                 break;
             }
-            _ => {}
         }
     }
 
     heartbeat_send_task.abort();
-    send_task.abort();
+
+    if drain.is_some() {
+        // final status, then let the send task flush and close the socket
+        let load = executor.read().await.get_load().await;
+        let _ = tx.send(JudgeToApiMessage::Ping(load));
+        drop(tx);
+        if tokio::time::timeout(tokio::time::Duration::from_secs(5), &mut send_task)
+            .await
+            .is_err()
+        {
+            send_task.abort();
+        }
+    } else {
+        send_task.abort();
+    }
 
     Ok(())
 }
@@ -131,7 +329,9 @@ async fn connect_and_handle(url: &str, config: &Config) -> Result<()> {
 async fn handle_message(
     text: &str,
     executor: &Arc<RwLock<JudgeExecutor>>,
+    outbox: &Outbox,
     tx: &tokio::sync::mpsc::UnboundedSender<JudgeToApiMessage>,
+    accepting: bool,
 ) -> Result<()> {
     let msg: ApiToJudgeMessage = serde_json::from_str(text).context("Failed to parse message")?;
 
@@ -139,34 +339,79 @@ async fn handle_message(
         ApiToJudgeMessage::Pong => {
             tracing::debug!("Received pong");
         }
-        ApiToJudgeMessage::JudgeTask(JudgeTask {
-            submission_id,
-            lang,
-            code,
-            time_limit,
-            memory_limit,
-            test_cases,
-        }) => {
-            tracing::info!("Received judge task for submission {}", submission_id);
-
-            let executor = executor.clone();
-            let tx = tx.clone();
-
-            tokio::spawn(async move {
-                let mut exec = executor.write().await;
-                exec.execute_task(
-                    submission_id,
-                    lang,
-                    code,
-                    time_limit,
-                    memory_limit,
-                    test_cases,
-                    tx,
-                )
-                .await;
-            });
+        ApiToJudgeMessage::Ack(submission_id) => {
+            outbox.ack(submission_id);
+        }
+        ApiToJudgeMessage::JudgeTaskChunk(chunk) => {
+            executor.read().await.deliver_test_cases(chunk);
+        }
+        ApiToJudgeMessage::JudgeTask(JudgeTask { submission_id, .. }) if !accepting => {
+            tracing::warn!(
+                "Refused task for submission {}, shutting down",
+                submission_id
+            );
+            let refusal =
+                JudgeToApiMessage::Error(submission_id, "judge is shutting down".to_string());
+            outbox.hold(&refusal);
+            let _ = tx.send(refusal);
+        }
+        ApiToJudgeMessage::JudgeTask(task) => {
+            tracing::info!("Received judge task for submission {}", task.submission_id);
+
+            executor.read().await.execute_task(task, tx.clone());
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_max() {
+        let delays: Vec<_> = (0..8)
+            .map(|failures| backoff_secs(failures, 1, 60))
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(backoff_secs(200, 1, 60), 60);
+        assert_eq!(backoff_secs(3, 5, 30), 30);
+    }
+
+    #[test]
+    fn jitter_stays_in_the_upper_half() {
+        for _ in 0..100 {
+            let delay = reconnect_delay(3, 1, 60);
+            assert!(delay >= Duration::from_secs(4), "{:?}", delay);
+            assert!(delay <= Duration::from_secs(8), "{:?}", delay);
+        }
+    }
+
+    fn result(submission_id: i32) -> JudgeToApiMessage {
+        JudgeToApiMessage::Error(submission_id, "judge timeout".to_string())
+    }
+
+    #[test]
+    fn held_result_is_resent_until_acknowledged() {
+        let outbox = Outbox::default();
+        outbox.hold(&result(1));
+
+        // the connection dropped before the api acknowledged it
+        let resent = outbox.due(Duration::ZERO);
+        assert!(matches!(resent[..], [JudgeToApiMessage::Error(1, _)]));
+        // not again until it's been waiting a while
+        assert!(outbox.due(RESEND_AFTER).is_empty());
+        assert_eq!(outbox.due(Duration::ZERO).len(), 1);
+
+        outbox.ack(1);
+        assert!(outbox.due(Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn run_results_are_not_held() {
+        let outbox = Outbox::default();
+        outbox.hold(&result(-1));
+        assert!(outbox.due(Duration::ZERO).is_empty());
+    }
+}
@@ -26,12 +26,28 @@ CREATE TABLE users (
     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
 
+CREATE TABLE refresh_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(255) NOT NULL,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
 CREATE TABLE problems (
     id SERIAL PRIMARY KEY,
     name VARCHAR(255) UNIQUE NOT NULL,
     time_limit INTEGER NOT NULL,
     mem_limit INTEGER NOT NULL,
+    sample_precheck BOOLEAN NOT NULL DEFAULT FALSE,
+    reveal_diff BOOLEAN NOT NULL DEFAULT FALSE,
+    judge_samples BOOLEAN NOT NULL DEFAULT FALSE,
+    solutions_require_ac BOOLEAN NOT NULL DEFAULT FALSE,
+    max_code_length INTEGER CHECK (max_code_length > 0),
+    difficulty INTEGER CHECK (difficulty BETWEEN 1 AND 10),
     status problem_status_enum NOT NULL DEFAULT 'active',
+    creator_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
@@ -39,9 +55,13 @@ CREATE TABLE problems (
 CREATE TABLE test_cases (
     id SERIAL PRIMARY KEY,
     problem_id INTEGER NOT NULL REFERENCES problems(id) ON DELETE CASCADE,
+    -- sha256 of the content, which is stored once per hash
+    hash CHAR(64) NOT NULL,
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
 
+CREATE INDEX idx_test_cases_hash ON test_cases(hash);
+
 CREATE TABLE solutions (
     id SERIAL PRIMARY KEY,
     problem_id INTEGER NOT NULL REFERENCES problems(id) ON DELETE CASCADE,
@@ -60,6 +80,10 @@ CREATE TABLE contests (
     password VARCHAR(255),
     type contest_type_enum NOT NULL,
     status contest_status_enum NOT NULL DEFAULT 'active',
+    registration_begin TIMESTAMP WITH TIME ZONE,
+    registration_end TIMESTAMP WITH TIME ZONE,
+    max_attempts_per_problem INTEGER CHECK (max_attempts_per_problem > 0),
+    allow_upsolving BOOLEAN NOT NULL DEFAULT FALSE,
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
@@ -73,6 +97,13 @@ CREATE TABLE submissions (
     result submission_result_enum NOT NULL DEFAULT 'pending',
     time_consumption INTEGER,
     mem_consumption INTEGER,
+    is_virtual BOOLEAN NOT NULL DEFAULT FALSE,
+    is_upsolve BOOLEAN NOT NULL DEFAULT FALSE,
+    failed_on_sample BOOLEAN NOT NULL DEFAULT FALSE,
+    compile_message TEXT,
+    judge_id VARCHAR(64),
+    -- hash of the code without comments and whitespace, set after judging
+    fingerprint VARCHAR(64),
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
@@ -83,21 +114,53 @@ CREATE TABLE submission_test_cases (
     result test_case_result_enum NOT NULL DEFAULT 'pending',
     time_consumption INTEGER,
     mem_consumption INTEGER,
+    message TEXT,
     PRIMARY KEY (submission_id, test_case_id)
 );
 
+-- results of the judged samples, which aren't test cases
+CREATE TABLE submission_samples (
+    submission_id INTEGER NOT NULL REFERENCES submissions(id) ON DELETE CASCADE,
+    sample_index INTEGER NOT NULL,
+    result test_case_result_enum NOT NULL,
+    time_consumption INTEGER,
+    mem_consumption INTEGER,
+    message TEXT,
+    PRIMARY KEY (submission_id, sample_index)
+);
+
+-- where the first wrong answer of a submission went wrong
+CREATE TABLE submission_diffs (
+    submission_id INTEGER PRIMARY KEY REFERENCES submissions(id) ON DELETE CASCADE,
+    -- null when the failing case was a sample
+    test_case_id INTEGER REFERENCES test_cases(id) ON DELETE CASCADE,
+    line INTEGER NOT NULL,
+    expected TEXT NOT NULL,
+    actual TEXT NOT NULL
+);
+
+CREATE TABLE submission_bookmarks (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    submission_id INTEGER NOT NULL REFERENCES submissions(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, submission_id)
+);
+
 CREATE TABLE contest_problems (
     contest_id INTEGER NOT NULL REFERENCES contests(id) ON DELETE CASCADE,
     problem_id INTEGER NOT NULL REFERENCES problems(id) ON DELETE CASCADE,
     number INTEGER NOT NULL,
+    alias VARCHAR(32),
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
-    PRIMARY KEY (contest_id, problem_id)
+    PRIMARY KEY (contest_id, problem_id),
+    UNIQUE (contest_id, number)
 );
 
 CREATE TABLE training_plans (
     id SERIAL PRIMARY KEY,
     creator_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
     name VARCHAR(255) NOT NULL,
+    join_code VARCHAR(32),
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
@@ -124,12 +187,62 @@ CREATE TABLE contest_participants (
     PRIMARY KEY (contest_id, user_id, training_plan_id)
 );
 
+CREATE TABLE contest_virtual_participants (
+    contest_id INTEGER NOT NULL REFERENCES contests(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (contest_id, user_id)
+);
+
+CREATE TABLE contest_announcements (
+    id SERIAL PRIMARY KEY,
+    contest_id INTEGER NOT NULL REFERENCES contests(id) ON DELETE CASCADE,
+    author_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE problem_collaborators (
+    problem_id INTEGER NOT NULL REFERENCES problems(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (problem_id, user_id)
+);
+
+CREATE TABLE contest_collaborators (
+    contest_id INTEGER NOT NULL REFERENCES contests(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (contest_id, user_id)
+);
+
+CREATE TABLE audit_log (
+    id SERIAL PRIMARY KEY,
+    actor_id INTEGER NOT NULL REFERENCES users(id),
+    action VARCHAR(32) NOT NULL,
+    resource_type VARCHAR(32) NOT NULL,
+    resource_id INTEGER,
+    detail TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE webhooks (
+    id SERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
 CREATE INDEX idx_submissions_problem_user_contest_result 
 ON submissions(problem_id, user_id, contest_id, result);
 
 CREATE INDEX idx_submissions_problem_user_contest_created 
 ON submissions(problem_id, user_id, contest_id, created_at DESC);
 
+CREATE INDEX idx_submissions_problem_fingerprint 
+ON submissions(problem_id, fingerprint);
+
 CREATE INDEX idx_contest_problems_problem_id 
 ON contest_problems(problem_id);
 
@@ -143,4 +256,7 @@ CREATE INDEX idx_solutions_problem_id
 ON solutions(problem_id);
 
 CREATE INDEX idx_problems_status 
-ON problems(status);
\ No newline at end of file
+ON problems(status);
+
+CREATE INDEX idx_audit_log_created 
+ON audit_log(created_at DESC);
\ No newline at end of file